@@ -1,9 +1,13 @@
 #[cfg(test)]
 mod tests;
 
-use crate::TlpError;
+use crate::{Field, TlpError, DWORD_LEN};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Address {
     Addr32(u32),
     Addr64(u64),
@@ -43,7 +47,12 @@ macro_rules! impl_addr_try_from {
                 if Self::is_valid_addr(v) {
                     Ok(Self::new(v))
                 } else {
-                    Err(TlpError::NotAligned)
+                    Err(TlpError::NotAligned {
+                        field: Field::Address,
+                        value: v,
+                        align: DWORD_LEN as u64,
+                        offset: 8,
+                    })
                 }
             }
         })+
@@ -53,9 +62,17 @@ macro_rules! impl_addr_try_from {
 
 impl_addr_try_from!(u8, u16, u32, u64, usize);
 
+impl From<Address> for u64 {
+    fn from(addr: Address) -> Self {
+        match addr {
+            Address::Addr32(a) => a.into(),
+            Address::Addr64(a) => a,
+        }
+    }
+}
+
 impl Default for Address {
     fn default() -> Self {
         Self::Addr32(0)
     }
 }
-
@@ -25,14 +25,14 @@ proptest! {
 
     #[test]
     fn into_addr_32_u64(addr in (0..=(u32::MAX as u64)).prop_filter("Address must be dword aligned",
-            |&x| (x as u64) & !ADDR64_MASK == 0)) {
+            |&x| x & !ADDR64_MASK == 0)) {
         let a: Address = addr.try_into().unwrap();
         assert!(matches!(a, Address::Addr32 { .. }))
     }
 
     #[test]
     fn into_addr_64_u64(addr in ((u32::MAX as u64 + 1)..=u64::MAX)
-        .prop_filter("Address must be dword aligned",|&x| (x as u64) & !ADDR64_MASK == 0)) {
+        .prop_filter("Address must be dword aligned",|&x| x & !ADDR64_MASK == 0)) {
         let a: Address = addr.try_into().unwrap();
         assert!(matches!(a, Address::Addr64 { .. }))
     }
@@ -50,7 +50,8 @@ proptest! {
         assert!(!Address::is_valid_addr(addr));
         let a: Result<Address, TlpError> = addr.try_into();
         assert!(a.is_err());
-        assert_eq!(TlpError::NotAligned, a.unwrap_err())
+        let e = TlpError::NotAligned { field: Field::Address, value: addr.into(), align: 4, offset: 8 };
+        assert_eq!(e, a.unwrap_err())
     }
 
     #[test]
@@ -66,6 +67,7 @@ proptest! {
         assert!(!Address::is_valid_addr(addr));
         let a: Result<Address, TlpError> = addr.try_into();
         assert!(a.is_err());
-        assert_eq!(TlpError::NotAligned, a.unwrap_err())
+        let e = TlpError::NotAligned { field: Field::Address, value: addr, align: 4, offset: 8 };
+        assert_eq!(e, a.unwrap_err())
     }
 }
@@ -0,0 +1,59 @@
+//! Helpers shared by the command-line tools
+
+/// Parses hex written as bytes, or as dwords in wire order if any token has
+/// more than 2 digits
+pub fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    let tokens: Vec<String> = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .map(|t| {
+            let t = t
+                .strip_prefix("0x")
+                .or_else(|| t.strip_prefix("0X"))
+                .unwrap_or(t);
+            t.replace('_', "")
+        })
+        .collect();
+
+    if let Some(bad) = tokens
+        .iter()
+        .find(|t| t.is_empty() || !t.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return Err(format!("'{}' is not hex", bad));
+    }
+
+    // Left pad each token to whole bytes, or to whole dwords
+    let width = if tokens.iter().all(|t| t.len() <= 2) {
+        2
+    } else {
+        8
+    };
+    let mut digits = String::new();
+    for t in &tokens {
+        let padded = t.len().next_multiple_of(width);
+        digits.extend(std::iter::repeat_n('0', padded - t.len()));
+        digits.push_str(t);
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_bytes_and_dwords() {
+        let expect = vec![0x40, 0, 0, 1, 0, 0, 0, 0xF];
+        assert_eq!(Ok(expect.clone()), parse_hex("40 00 00 01 00 00 00 0f"));
+        assert_eq!(Ok(expect.clone()), parse_hex("0x40,0x0,0x0,0x1,0,0,0,F"));
+        assert_eq!(Ok(expect.clone()), parse_hex("40000001 0000000F"));
+        assert_eq!(Ok(expect.clone()), parse_hex("0x4000_0001 0xF"));
+        assert_eq!(Ok(expect), parse_hex("400000010000000f"));
+        assert!(parse_hex("40 0g").is_err());
+        assert!(parse_hex("0x").is_err());
+    }
+}
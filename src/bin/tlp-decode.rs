@@ -0,0 +1,335 @@
+//! Decodes TLPs given as hex and prints an analyzer style summary or JSON
+//!
+//! Run `tlp-decode --help` for usage.
+
+mod common;
+
+use common::parse_hex;
+use rust_pcie_tlp::{Address, RequestHeader, Tlp, TlpHeader};
+use std::{
+    env, fmt, fs,
+    io::{self, Read},
+    process::ExitCode,
+};
+
+const USAGE: &str = "\
+Usage: tlp-decode [OPTIONS] [HEX]...
+
+Decodes PCIe TLPs given as hex. With HEX arguments they form a single TLP,
+otherwise each non-empty line of the file or stdin is one TLP. Lines starting
+with '#' are skipped.
+
+Hex is read as bytes when every token has at most 2 digits, and as dwords in
+wire order otherwise. Tokens are separated by whitespace or commas and may
+carry a 0x prefix and '_' separators.
+
+Options:
+  -f, --file <PATH>  Read TLPs from PATH instead of stdin
+  -v, --verbose      Print every header field and the payload
+      --json         Print one JSON object per TLP
+  -h, --help         Print this help
+
+Exit status is 0 when every TLP decodes, 1 when any fails to decode and 2 for
+usage, input or I/O errors.";
+
+/// Exit status when a TLP fails to decode
+const EXIT_DECODE: u8 = 1;
+
+/// Exit status for usage, input and I/O errors
+const EXIT_USAGE: u8 = 2;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Output {
+    #[default]
+    Summary,
+    Verbose,
+    Json,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Options {
+    output: Output,
+    file: Option<String>,
+    hex: Vec<String>,
+}
+
+fn parse_args<I>(args: I) -> Result<Option<Options>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut opts = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-v" | "--verbose" => opts.output = Output::Verbose,
+            "--json" => opts.output = Output::Json,
+            "-f" | "--file" => {
+                opts.file = Some(args.next().ok_or(format!("{} needs a path", arg))?);
+            }
+            a if a.starts_with("--") => return Err(format!("unknown option {}", a)),
+            _ => opts.hex.push(arg),
+        }
+    }
+    if opts.file.is_some() && !opts.hex.is_empty() {
+        return Err("give either --file or HEX arguments, not both".into());
+    }
+    Ok(Some(opts))
+}
+
+/// Writes `s` as a JSON string
+struct JsonStr<'a>(&'a str);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Fields of a JSON object, written in insertion order
+#[derive(Default)]
+struct JsonObject(Vec<(&'static str, String)>);
+
+impl JsonObject {
+    fn str(&mut self, key: &'static str, value: impl fmt::Display) -> &mut Self {
+        self.0.push((key, JsonStr(&value.to_string()).to_string()));
+        self
+    }
+
+    fn num(&mut self, key: &'static str, value: impl Into<u64>) -> &mut Self {
+        self.0.push((key, value.into().to_string()));
+        self
+    }
+
+    fn bool(&mut self, key: &'static str, value: bool) -> &mut Self {
+        self.0.push((key, value.to_string()));
+        self
+    }
+}
+
+impl fmt::Display for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, (k, v)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}:{}", JsonStr(k), v)?;
+        }
+        f.write_str("}")
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn json_common(obj: &mut JsonObject, hdr: &TlpHeader) {
+    obj.str("type", hdr.tlp_type)
+        .num("fmt_type", u8::from(hdr.tlp_type))
+        .num("tc", hdr.tc as u8)
+        .bool("ro", hdr.ro)
+        .bool("ns", hdr.ns)
+        .bool("ido", hdr.ibo)
+        .bool("ep", hdr.ep)
+        .bool("td", hdr.td)
+        .bool("th", hdr.th)
+        .bool("ln", hdr.ln)
+        .num("at", hdr.at as u8)
+        .num("length", hdr.length);
+}
+
+fn json_req(obj: &mut JsonObject, hdr: &RequestHeader) {
+    obj.str("req_id", hdr.req_id)
+        .num("tag", hdr.tag)
+        .num("first_be", hdr.first_be)
+        .num("last_be", hdr.last_be);
+}
+
+fn json_addr(obj: &mut JsonObject, addr: Address) {
+    obj.str("addr", format_args!("{:#x}", u64::from(addr)));
+}
+
+/// Describes a decoded TLP as a JSON object
+fn json(tlp: &Tlp) -> JsonObject {
+    let mut obj = JsonObject::default();
+    json_common(&mut obj, &tlp.header());
+    match tlp {
+        Tlp::MRd(t) => {
+            json_req(&mut obj, &t.hdr);
+            json_addr(&mut obj, t.addr);
+        }
+        Tlp::MWr(t) => {
+            json_req(&mut obj, &t.hdr);
+            json_addr(&mut obj, t.addr);
+        }
+        Tlp::DMWr(t) => {
+            json_req(&mut obj, &t.hdr);
+            json_addr(&mut obj, t.addr);
+        }
+        Tlp::CfgRd(t) => {
+            json_req(&mut obj, &t.hdr);
+            obj.str("target", t.target).num("reg", t.reg);
+        }
+        Tlp::CfgWr(t) => {
+            json_req(&mut obj, &t.hdr);
+            obj.str("target", t.target).num("reg", t.reg);
+        }
+        Tlp::Cpl(t) => {
+            obj.str("cpl_id", t.hdr.cpl_id)
+                .str("status", t.hdr.status)
+                .num("byte_count", t.hdr.bc)
+                .str("req_id", t.hdr.req_id)
+                .num("tag", t.hdr.tag)
+                .num("lower_addr", t.hdr.addr_low);
+        }
+        Tlp::Msg(t) => {
+            if let Some(prefix) = t.prefix {
+                obj.num("pasid", prefix.pasid)
+                    .bool("exe", prefix.exe)
+                    .bool("privileged", prefix.privileged);
+            }
+            obj.str("req_id", t.hdr.req_id)
+                .num("tag", t.hdr.tag)
+                .num("code", t.hdr.code);
+            if let Some(routing) = t.hdr.routing() {
+                obj.str("routing", format_args!("{:?}", routing));
+            }
+            if let Some(target) = t.hdr.target_id() {
+                obj.str("target", target);
+            }
+        }
+        Tlp::Other(_, bytes) => {
+            obj.str("raw", hex_string(bytes));
+        }
+    }
+    obj.str("data", hex_string(tlp.data())).str("summary", tlp);
+    obj
+}
+
+/// Decodes and prints one TLP, returning false if it failed to decode
+fn decode(line: &str, output: Output) -> Result<bool, String> {
+    let bytes = parse_hex(line)?;
+    let tlp = match Tlp::from_bytes(&bytes) {
+        Ok(tlp) => tlp,
+        Err(e) => {
+            match output {
+                Output::Json => {
+                    let mut obj = JsonObject::default();
+                    obj.str("error", e).str("input", hex_string(&bytes));
+                    println!("{}", obj);
+                }
+                _ => eprintln!("error: {}: {}", hex_string(&bytes), e),
+            }
+            return Ok(false);
+        }
+    };
+
+    match output {
+        Output::Summary => println!("{}", tlp),
+        Output::Verbose => println!("{:#}\n", tlp),
+        Output::Json => println!("{}", json(&tlp)),
+    }
+    Ok(true)
+}
+
+fn run(opts: &Options) -> Result<bool, String> {
+    if !opts.hex.is_empty() {
+        return decode(&opts.hex.join(" "), opts.output);
+    }
+
+    let mut input = String::new();
+    match &opts.file {
+        Some(path) => input = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => {
+            io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let mut ok = true;
+    for (n, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        ok &= decode(line, opts.output).map_err(|e| format!("line {}: {}", n + 1, e))?;
+    }
+    Ok(ok)
+}
+
+fn main() -> ExitCode {
+    let opts = match parse_args(env::args().skip(1)) {
+        Ok(Some(opts)) => opts,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    match run(&opts) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_DECODE),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_pcie_tlp::{DeviceID, MWr};
+
+    #[test]
+    fn args() {
+        let args = |a: &[&str]| parse_args(a.iter().map(|s| s.to_string()));
+        assert_eq!(Ok(None), args(&["-v", "--help"]));
+        assert_eq!(
+            Ok(Some(Options {
+                output: Output::Json,
+                file: None,
+                hex: vec!["40".into(), "01".into()],
+            })),
+            args(&["--json", "40", "01"])
+        );
+        assert!(args(&["--bogus"]).is_err());
+        assert!(args(&["-f"]).is_err());
+        assert!(args(&["-f", "x", "40"]).is_err());
+    }
+
+    #[test]
+    fn json_mwr() {
+        let data = [1, 2, 3, 4];
+        let mwr = MWr::new(DeviceID::new(1, 0, 0).unwrap(), 5, 0x1000, &data).unwrap();
+        let out = json(&Tlp::MWr(mwr)).to_string();
+        assert!(out.starts_with("{\"type\":\"MWr32\",\"fmt_type\":64,"));
+        assert!(out.contains("\"req_id\":\"01:00.0\",\"tag\":5,"));
+        assert!(out.contains("\"addr\":\"0x1000\",\"data\":\"01020304\""));
+        assert!(out.ends_with("LBE=0 TC0\"}"));
+    }
+
+    #[test]
+    fn json_escape() {
+        assert_eq!(
+            "\"a\\\"b\\\\c\\u0001\"",
+            JsonStr("a\"b\\c\u{1}").to_string()
+        );
+    }
+}
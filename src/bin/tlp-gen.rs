@@ -0,0 +1,534 @@
+//! Builds a TLP from command-line flags and prints it for testbenches
+//!
+//! Run `tlp-gen --help` for usage.
+
+mod common;
+
+use common::parse_hex;
+use num_traits::FromPrimitive;
+use rust_pcie_tlp::{
+    Address, CfgRd, CfgWr, CompletionStatus, Cpl, CplHeader, DMWr, DeviceID, MRd, MWr, Msg,
+    MsgHeader, MsgRouting, PasidPrefix, RequestHeader, Tlp, TlpHeader, TlpType, TrafficClass,
+    DWORD_LEN,
+};
+use std::{
+    env, fs,
+    io::{self, Write},
+    process::ExitCode,
+};
+
+const USAGE: &str = "\
+Usage: tlp-gen --type TYPE [OPTIONS]
+
+Builds a PCIe TLP and prints it as hex dwords, $readmemh lines or raw bytes.
+
+Types:
+  mrd, mwr, dmwr            Memory requests, 64 bit above 4 GiB
+  cfgrd0, cfgwr0            Type 0 config requests
+  cfgrd1, cfgwr1            Type 1 config requests
+  cpl                       Completion, with data if --data is given
+  msg                       Message, with data if --data is given
+
+Fields:
+      --req <BB:DD.F>       Requester ID [default: 00:00.0]
+      --tag <N>             Tag [default: 0]
+      --addr <N>            Memory request address [default: 0]
+      --len <N>             Memory read length in dwords [default: 1]
+      --data <HEX>          Payload, as bytes or dwords in wire order
+      --fbe <N>, --lbe <N>  First and last dword byte enables
+      --target <BB:DD.F>    Config request or ID routed message target
+      --reg <N>             Config register offset [default: 0]
+      --cpl-id <BB:DD.F>    Completer ID [default: 00:00.0]
+      --status <STATUS>     Completion status: sc, ur, crs or ca [default: sc]
+      --bc <N>              Completion byte count [default: payload length]
+      --lower-addr <N>      Completion lower address [default: 0]
+      --code <N>            Message code [default: 0]
+      --route <ROUTE>       Message routing: rc, addr, id, bcast, local or
+                            gather [default: local]
+      --pasid <N>           Prepend a PASID prefix
+      --tc <N>              Traffic class [default: 0]
+      --ro, --ns, --ido     Set the relaxed, no-snoop or ID-based ordering bit
+      --ep                  Poison the TLP
+
+Output:
+      --format <FORMAT>     hex, readmemh or bin [default: hex]
+  -o, --output <PATH>       Write to PATH instead of stdout
+  -h, --help                Print this help
+
+Numbers are decimal or 0x prefixed hex. Exit status is 1 when the TLP cannot
+be built or written and 2 for usage errors.";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    MRd,
+    MWr,
+    DMWr,
+    CfgRd { type1: bool },
+    CfgWr { type1: bool },
+    Cpl,
+    Msg,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Format {
+    #[default]
+    Hex,
+    Readmemh,
+    Bin,
+}
+
+#[derive(Debug, Default)]
+struct Spec {
+    kind: Option<Kind>,
+    req: DeviceID,
+    tag: u8,
+    addr: u64,
+    len: Option<u16>,
+    data: Vec<u8>,
+    first_be: Option<u8>,
+    last_be: Option<u8>,
+    target: DeviceID,
+    reg: u16,
+    cpl_id: DeviceID,
+    status: CompletionStatus,
+    bc: Option<u16>,
+    lower_addr: u8,
+    code: u8,
+    route: Option<MsgRouting>,
+    pasid: Option<u32>,
+    tc: u8,
+    ro: bool,
+    ns: bool,
+    ido: bool,
+    ep: bool,
+    format: Format,
+    output: Option<String>,
+}
+
+/// Parses a decimal or 0x prefixed hex number that fits `T`
+fn parse_num<T>(s: &str) -> Result<T, String>
+where
+    T: TryFrom<u64>,
+{
+    let n = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+        None => s.replace('_', "").parse(),
+    }
+    .map_err(|e| format!("'{}': {}", s, e))?;
+    T::try_from(n).map_err(|_| format!("'{}' is out of range", s))
+}
+
+fn parse_id(s: &str) -> Result<DeviceID, String> {
+    s.parse().map_err(|e| format!("'{}': {}", s, e))
+}
+
+fn parse_kind(s: &str) -> Result<Kind, String> {
+    Ok(match s.to_ascii_lowercase().as_str() {
+        "mrd" => Kind::MRd,
+        "mwr" => Kind::MWr,
+        "dmwr" => Kind::DMWr,
+        "cfgrd0" => Kind::CfgRd { type1: false },
+        "cfgrd1" => Kind::CfgRd { type1: true },
+        "cfgwr0" => Kind::CfgWr { type1: false },
+        "cfgwr1" => Kind::CfgWr { type1: true },
+        "cpl" => Kind::Cpl,
+        "msg" => Kind::Msg,
+        _ => return Err(format!("unknown type '{}'", s)),
+    })
+}
+
+fn parse_status(s: &str) -> Result<CompletionStatus, String> {
+    Ok(match s.to_ascii_lowercase().as_str() {
+        "sc" => CompletionStatus::SuccessfulCompletion,
+        "ur" => CompletionStatus::UnsupportedRequest,
+        "crs" => CompletionStatus::ConfigurationRequestRetry,
+        "ca" => CompletionStatus::CompleterAbort,
+        _ => return Err(format!("unknown status '{}'", s)),
+    })
+}
+
+fn parse_route(s: &str) -> Result<MsgRouting, String> {
+    Ok(match s.to_ascii_lowercase().as_str() {
+        "rc" => MsgRouting::ToRootComplex,
+        "addr" => MsgRouting::ByAddress,
+        "id" => MsgRouting::ById,
+        "bcast" => MsgRouting::Broadcast,
+        "local" => MsgRouting::Local,
+        "gather" => MsgRouting::Gathered,
+        _ => return Err(format!("unknown routing '{}'", s)),
+    })
+}
+
+fn parse_format(s: &str) -> Result<Format, String> {
+    Ok(match s.to_ascii_lowercase().as_str() {
+        "hex" => Format::Hex,
+        "readmemh" => Format::Readmemh,
+        "bin" => Format::Bin,
+        _ => return Err(format!("unknown format '{}'", s)),
+    })
+}
+
+/// Parses the arguments, returning None if help was asked for
+fn parse_args<I>(args: I) -> Result<Option<Spec>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut spec = Spec::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let flag = arg.as_str();
+        match flag {
+            "-h" | "--help" => return Ok(None),
+            "--ro" => spec.ro = true,
+            "--ns" => spec.ns = true,
+            "--ido" => spec.ido = true,
+            "--ep" => spec.ep = true,
+            a if !a.starts_with('-') => return Err(format!("unexpected argument '{}'", a)),
+            _ => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", flag))?;
+                let v = value.as_str();
+                match flag {
+                    "--type" => spec.kind = Some(parse_kind(v)?),
+                    "--req" => spec.req = parse_id(v)?,
+                    "--tag" => spec.tag = parse_num(v)?,
+                    "--addr" => spec.addr = parse_num(v)?,
+                    "--len" => spec.len = Some(parse_num(v)?),
+                    "--data" => spec.data = parse_hex(v)?,
+                    "--fbe" => spec.first_be = Some(parse_num(v)?),
+                    "--lbe" => spec.last_be = Some(parse_num(v)?),
+                    "--target" => spec.target = parse_id(v)?,
+                    "--reg" => spec.reg = parse_num(v)?,
+                    "--cpl-id" => spec.cpl_id = parse_id(v)?,
+                    "--status" => spec.status = parse_status(v)?,
+                    "--bc" => spec.bc = Some(parse_num(v)?),
+                    "--lower-addr" => spec.lower_addr = parse_num(v)?,
+                    "--code" => spec.code = parse_num(v)?,
+                    "--route" => spec.route = Some(parse_route(v)?),
+                    "--pasid" => spec.pasid = Some(parse_num(v)?),
+                    "--tc" => spec.tc = parse_num(v)?,
+                    "--format" => spec.format = parse_format(v)?,
+                    "-o" | "--output" => spec.output = Some(value),
+                    _ => return Err(format!("unknown option {}", flag)),
+                }
+            }
+        }
+    }
+    match spec.kind {
+        None => return Err("--type is required".into()),
+        Some(Kind::MWr | Kind::DMWr) if spec.data.is_empty() => {
+            return Err("memory writes need --data".into())
+        }
+        _ => {}
+    }
+    Ok(Some(spec))
+}
+
+/// Applies the attributes shared by every TLP type to `hdr`
+fn with_attrs(hdr: TlpHeader, spec: &Spec) -> Result<TlpHeader, String> {
+    let tc = TrafficClass::from_u8(spec.tc).ok_or(format!("traffic class {} > 7", spec.tc))?;
+    Ok(hdr
+        .with_tc(tc)
+        .with_ro(spec.ro)
+        .with_ns(spec.ns)
+        .with_ibo(spec.ido)
+        .with_ep(spec.ep))
+}
+
+/// Builds a request header of `tlp_type` for `len` bytes
+fn request(tlp_type: TlpType, len: u16, spec: &Spec) -> Result<RequestHeader, String> {
+    let hdr = TlpHeader::new()
+        .with_type(tlp_type)
+        .with_length(len)
+        .map_err(|e| e.to_string())?;
+    let req = RequestHeader::new()
+        .with_hdr(with_attrs(hdr, spec)?)
+        .with_req_id(spec.req)
+        .with_tag(spec.tag)
+        .with_byte_enables();
+    with_byte_enables(req, spec)
+}
+
+/// Overrides the byte enables of `req` with those given on the command line
+fn with_byte_enables(mut req: RequestHeader, spec: &Spec) -> Result<RequestHeader, String> {
+    if let Some(be) = spec.first_be {
+        req = req.with_first_be(be).map_err(|e| e.to_string())?;
+    }
+    if let Some(be) = spec.last_be {
+        req = req.with_last_be(be).map_err(|e| e.to_string())?;
+    }
+    Ok(req)
+}
+
+/// Picks the 3 or 4 dword type of a memory request for `addr`
+fn mem_type(addr: Address, short: TlpType, long: TlpType) -> TlpType {
+    match addr {
+        Address::Addr32(_) => short,
+        Address::Addr64(_) => long,
+    }
+}
+
+/// Length of `data` for the length field of a header
+fn data_len(data: &[u8]) -> Result<u16, String> {
+    u16::try_from(data.len()).map_err(|_| format!("{} bytes of data is too long", data.len()))
+}
+
+/// Builds and encodes the TLP described by `spec`
+fn build(spec: &Spec) -> Result<Vec<u8>, String> {
+    let err = |e: rust_pcie_tlp::TlpError| e.to_string();
+    // SAFETY: parse_args rejects arguments without a type
+    let kind = spec.kind.unwrap();
+    let data = spec.data.as_slice();
+    let addr = Address::try_from(spec.addr).map_err(err)?;
+    let cfg_data: &[u8; DWORD_LEN] = match kind {
+        Kind::CfgWr { .. } => data
+            .try_into()
+            .map_err(|_| "config writes take exactly 4 bytes of data".to_string())?,
+        _ => &[0; DWORD_LEN],
+    };
+
+    let tlp = match kind {
+        Kind::MRd => {
+            let len = spec
+                .len
+                .unwrap_or(1)
+                .checked_mul(DWORD_LEN as u16)
+                .ok_or("--len is too long")?;
+            let hdr = request(mem_type(addr, TlpType::MRd3, TlpType::MRd4), len, spec)?;
+            Tlp::MRd(MRd { hdr, addr })
+        }
+        Kind::MWr => {
+            let len = data_len(data)?;
+            let hdr = request(mem_type(addr, TlpType::MWr3, TlpType::MWr4), len, spec)?;
+            Tlp::MWr(MWr { hdr, addr, data })
+        }
+        Kind::DMWr => {
+            let len = data_len(data)?;
+            let hdr = request(mem_type(addr, TlpType::DMWr3, TlpType::DMWr4), len, spec)?;
+            Tlp::DMWr(DMWr { hdr, addr, data })
+        }
+        Kind::CfgRd { type1 } => {
+            let mut cfg =
+                CfgRd::new(spec.req, spec.tag, spec.target, spec.reg, type1).map_err(err)?;
+            cfg.hdr = with_byte_enables(cfg.hdr.with_hdr(with_attrs(cfg.hdr.hdr, spec)?), spec)?;
+            Tlp::CfgRd(cfg)
+        }
+        Kind::CfgWr { type1 } => {
+            let mut cfg = CfgWr::new(spec.req, spec.tag, spec.target, spec.reg, type1, cfg_data)
+                .map_err(err)?;
+            cfg.hdr = with_byte_enables(cfg.hdr.with_hdr(with_attrs(cfg.hdr.hdr, spec)?), spec)?;
+            Tlp::CfgWr(cfg)
+        }
+        Kind::Cpl => {
+            let bc = spec.bc.unwrap_or(data_len(data)?.max(DWORD_LEN as u16));
+            let hdr = CplHeader::new()
+                .with_hdr(with_attrs(TlpHeader::new(), spec)?)
+                .with_cpl_id(spec.cpl_id)
+                .with_status(spec.status)
+                .with_req_id(spec.req)
+                .with_tag(spec.tag)
+                .with_bc(bc)
+                .map_err(err)?
+                .with_addr(spec.lower_addr)
+                .map_err(err)?;
+            Tlp::Cpl(Cpl::new(hdr, data).map_err(err)?)
+        }
+        Kind::Msg => {
+            let route = spec.route.unwrap_or(MsgRouting::Local);
+            let mut tlp_hdr = TlpHeader::new().with_type(TlpType::msg(route, !data.is_empty()));
+            if !data.is_empty() {
+                tlp_hdr = tlp_hdr.with_length(data_len(data)?).map_err(err)?;
+            }
+            let mut hdr = MsgHeader::new()
+                .with_hdr(with_attrs(tlp_hdr, spec)?)
+                .with_req_id(spec.req)
+                .with_tag(spec.tag)
+                .with_code(spec.code);
+            if route == MsgRouting::ById {
+                hdr = hdr.with_target_id(spec.target);
+            }
+            Tlp::Msg(Msg {
+                prefix: None,
+                hdr,
+                data,
+            })
+        }
+    };
+
+    let mut bytes = Vec::new();
+    if let Some(pasid) = spec.pasid {
+        let prefix = PasidPrefix::new().with_pasid(pasid).map_err(err)?;
+        bytes.extend_from_slice(&prefix.to_bytes());
+    }
+    let start = bytes.len();
+    bytes.resize(start + tlp.encoded_len(), 0);
+    tlp.to_bytes(&mut bytes[start..]).map_err(err)?;
+    Ok(bytes)
+}
+
+/// Formats `bytes` for output
+fn render(bytes: &[u8], format: Format) -> Vec<u8> {
+    let dwords = bytes
+        .chunks(DWORD_LEN)
+        .map(|dw| dw.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    match format {
+        Format::Hex => {
+            let mut s = dwords.collect::<Vec<_>>().join(" ");
+            s.push('\n');
+            s.into_bytes()
+        }
+        Format::Readmemh => dwords.map(|dw| dw + "\n").collect::<String>().into_bytes(),
+        Format::Bin => bytes.to_vec(),
+    }
+}
+
+fn run(spec: &Spec) -> Result<(), String> {
+    let out = render(&build(spec)?, spec.format);
+    match &spec.output {
+        Some(path) => fs::write(path, out).map_err(|e| format!("{}: {}", path, e)),
+        None => io::stdout().write_all(&out).map_err(|e| e.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let spec = match parse_args(env::args().skip(1)) {
+        Ok(Some(spec)) => spec,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&spec) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen(args: &[&str]) -> Result<Vec<u8>, String> {
+        let spec = parse_args(args.iter().map(|s| s.to_string()))?.unwrap();
+        build(&spec)
+    }
+
+    #[test]
+    fn mwr() {
+        let bytes = gen(&[
+            "--type", "mwr", "--addr", "0x1000", "--req", "01:00.0", "--tag", "5", "--data",
+            "12345678",
+        ])
+        .unwrap();
+        assert_eq!(
+            vec![0x40, 0, 0, 1, 1, 0, 5, 0xF, 0, 0, 0x10, 0, 0x12, 0x34, 0x56, 0x78],
+            bytes
+        );
+        assert!(matches!(Tlp::from_bytes(&bytes), Ok(Tlp::MWr(_))));
+    }
+
+    #[test]
+    fn mrd64_attrs() {
+        let bytes = gen(&[
+            "--type",
+            "MRd",
+            "--addr",
+            "0x1_0000_0000",
+            "--len",
+            "32",
+            "--tc",
+            "2",
+            "--ro",
+        ])
+        .unwrap();
+        let Ok(Tlp::MRd(mrd)) = Tlp::from_bytes(&bytes) else {
+            panic!("{:02x?} is not a read", bytes);
+        };
+        assert_eq!(TlpType::MRd4, mrd.hdr.hdr.tlp_type);
+        assert_eq!(32, mrd.hdr.hdr.length);
+        assert_eq!(TrafficClass::TC2, mrd.hdr.hdr.tc);
+        assert!(mrd.hdr.hdr.ro);
+        assert_eq!(0x1_0000_0000, u64::from(mrd.addr));
+    }
+
+    #[test]
+    fn cfg_cpl_and_msg() {
+        let bytes = gen(&[
+            "--type",
+            "cfgwr1",
+            "--target",
+            "02:00.0",
+            "--reg",
+            "0x10",
+            "--data",
+            "ff ff ff ff",
+            "--fbe",
+            "0x3",
+        ])
+        .unwrap();
+        let Ok(Tlp::CfgWr(cfg)) = Tlp::from_bytes(&bytes) else {
+            panic!("{:02x?} is not a config write", bytes);
+        };
+        assert!(cfg.is_type1());
+        assert_eq!(0x3, cfg.hdr.first_be);
+        assert!(gen(&["--type", "cfgwr0", "--data", "ff"]).is_err());
+
+        let bytes = gen(&["--type", "cpl", "--status", "ur", "--tag", "9"]).unwrap();
+        let Ok(Tlp::Cpl(cpl)) = Tlp::from_bytes(&bytes) else {
+            panic!("{:02x?} is not a completion", bytes);
+        };
+        assert_eq!(CompletionStatus::UnsupportedRequest, cpl.hdr.status);
+        assert_eq!(TlpType::CplE, cpl.hdr.hdr.tlp_type);
+
+        let bytes = gen(&[
+            "--type", "msg", "--route", "id", "--target", "03:00.0", "--code", "0x7f", "--pasid",
+            "5",
+        ])
+        .unwrap();
+        let Ok(Tlp::Msg(msg)) = Tlp::from_bytes(&bytes) else {
+            panic!("{:02x?} is not a message", bytes);
+        };
+        assert_eq!(5, msg.prefix.unwrap().pasid);
+        assert_eq!(Some(parse_id("03:00.0").unwrap()), msg.hdr.target_id());
+    }
+
+    #[test]
+    fn formats() {
+        let bytes = [0x40, 0, 0, 1, 1, 0, 5, 0xF];
+        assert_eq!(b"40000001 0100050f\n".to_vec(), render(&bytes, Format::Hex));
+        assert_eq!(
+            b"40000001\n0100050f\n".to_vec(),
+            render(&bytes, Format::Readmemh)
+        );
+        assert_eq!(bytes.to_vec(), render(&bytes, Format::Bin));
+    }
+
+    #[test]
+    fn bad_args() {
+        assert!(gen(&["--addr", "0x1000"]).is_err());
+        assert!(gen(&["--type", "mwr", "--tag", "256"]).is_err());
+        assert!(gen(&["--type", "mrd", "--addr", "0x1001"]).is_err());
+        assert!(gen(&["--type", "mrd", "--tc", "8"]).is_err());
+        assert!(gen(&["--type", "mrd", "--tag"]).is_err());
+    }
+
+    /// Tests that writes without data are usage errors rather than zero length
+    /// TLPs
+    #[test]
+    fn empty_writes() {
+        for ty in ["mwr", "dmwr"] {
+            let args = ["--type", ty, "--addr", "0x1000"];
+            assert!(parse_args(args.iter().map(|s| s.to_string())).is_err());
+            assert!(gen(&["--type", ty, "--addr", "0x1000", "--data", ""]).is_err());
+        }
+    }
+}
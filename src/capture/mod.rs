@@ -0,0 +1,207 @@
+//! Captures of TLPs in pcap and pcapng files
+//!
+//! TLPs are stored with the `LINKTYPE_USER0` link type, behind a 4 byte
+//! pseudo-header carrying the direction and link ID of the record:
+//!
+//! | Byte | Contents                                      |
+//! |------|-----------------------------------------------|
+//! | 0    | Bit 0 set for upstream TLPs, other bits are 0 |
+//! | 1    | Reserved, 0                                   |
+//! | 2-3  | Link ID, big endian                           |
+//!
+//! The encoded TLP follows, starting with any prefixes.
+
+use crate::{Tlp, TlpError};
+use byteorder::{BigEndian, ByteOrder};
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+mod pcap;
+mod pcapng;
+
+#[cfg(test)]
+mod tests;
+
+pub use pcap::{PcapReader, PcapWriter};
+pub use pcapng::{PcapngReader, PcapngWriter};
+
+/// Link type of raw TLP captures, the first of the user link types
+pub const LINKTYPE: u16 = 147;
+
+/// Length of the pseudo-header in front of each TLP
+pub const PSEUDO_HEADER_LEN: usize = 4;
+
+/// Largest record accepted when reading, which fits the largest TLP with
+/// prefixes and digest
+const MAX_RECORD_LEN: u32 = 0x10000;
+
+/// Direction a TLP travels on its link
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Direction {
+    /// Away from the root complex
+    #[default]
+    Downstream,
+    /// Towards the root complex
+    Upstream,
+}
+
+/// Captured TLP
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Record {
+    /// Nanoseconds since the Unix epoch
+    pub timestamp: u64,
+    pub direction: Direction,
+    /// Link the TLP was captured on
+    pub link: u16,
+    /// Encoded TLP
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    pub fn new(timestamp: u64, direction: Direction, link: u16, data: Vec<u8>) -> Self {
+        Self {
+            timestamp,
+            direction,
+            link,
+            data,
+        }
+    }
+
+    /// Builds a record by encoding `tlp`
+    pub fn from_tlp(
+        timestamp: u64,
+        direction: Direction,
+        link: u16,
+        tlp: &Tlp,
+    ) -> Result<Self, TlpError> {
+        Ok(Self::new(timestamp, direction, link, tlp.to_vec()?))
+    }
+
+    /// Decodes the captured TLP
+    pub fn tlp(&self) -> Result<Tlp<'_>, TlpError> {
+        Tlp::from_bytes(&self.data)
+    }
+
+    /// Encodes the pseudo-header and TLP as a packet of the capture
+    fn to_packet(&self) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(PSEUDO_HEADER_LEN + self.data.len());
+        packet.push((self.direction == Direction::Upstream) as u8);
+        packet.push(0);
+        packet.extend_from_slice(&self.link.to_be_bytes());
+        packet.extend_from_slice(&self.data);
+        packet
+    }
+
+    /// Decodes a packet of the capture
+    fn from_packet(timestamp: u64, packet: &[u8]) -> Result<Self, CaptureError> {
+        if packet.len() < PSEUDO_HEADER_LEN {
+            return Err(CaptureError::Malformed("packet shorter than pseudo-header"));
+        }
+        let direction = if packet[0] & 0x1 > 0 {
+            Direction::Upstream
+        } else {
+            Direction::Downstream
+        };
+        Ok(Self {
+            timestamp,
+            direction,
+            link: BigEndian::read_u16(&packet[2..4]),
+            data: packet[PSEUDO_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// Errors raised while reading or writing captures
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    /// The file does not start like a pcap or pcapng file
+    BadMagic(u32),
+    /// The capture holds packets of another link type
+    LinkType(u16),
+    /// A header, block or record is malformed
+    Malformed(&'static str),
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(e: io::Error) -> Self {
+        CaptureError::Io(e)
+    }
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(e) => write!(f, "I/O error: {}", e),
+            CaptureError::BadMagic(magic) => {
+                write!(f, "not a pcap or pcapng capture, magic {:#010X}", magic)
+            }
+            CaptureError::LinkType(lt) => {
+                write!(f, "link type {} is not {} for raw TLPs", lt, LINKTYPE)
+            }
+            CaptureError::Malformed(what) => write!(f, "malformed capture: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Reads every record of a pcap or pcapng capture, telling them apart by
+/// their magic
+pub fn read_records<R>(mut r: R) -> Result<Vec<Record>, CaptureError>
+where
+    R: Read,
+{
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    let r = io::Cursor::new(magic).chain(r);
+    match u32::from_le_bytes(magic) {
+        pcapng::SHB_TYPE => PcapngReader::new(r)?.collect(),
+        _ => PcapReader::new(r)?.collect(),
+    }
+}
+
+/// Reads exactly `buf.len()` bytes, returning false if the reader was
+/// already at its end
+fn read_or_eof<R>(r: &mut R, buf: &mut [u8]) -> Result<bool, CaptureError>
+where
+    R: Read,
+{
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(CaptureError::Malformed("truncated record")),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a little or big endian `u16` or `u32`
+fn read_u16(bytes: &[u8], big: bool) -> u16 {
+    if big {
+        BigEndian::read_u16(bytes)
+    } else {
+        byteorder::LittleEndian::read_u16(bytes)
+    }
+}
+
+fn read_u32(bytes: &[u8], big: bool) -> u32 {
+    if big {
+        BigEndian::read_u32(bytes)
+    } else {
+        byteorder::LittleEndian::read_u32(bytes)
+    }
+}
@@ -0,0 +1,116 @@
+use crate::capture::{read_or_eof, read_u32, CaptureError, Record, LINKTYPE, MAX_RECORD_LEN};
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::{Read, Write};
+
+/// Magic of captures with microsecond timestamps
+const MAGIC_US: u32 = 0xA1B2_C3D4;
+
+/// Magic of captures with nanosecond timestamps
+const MAGIC_NS: u32 = 0xA1B2_3C4D;
+
+const GLOBAL_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+
+/// Writer of classic pcap captures, with nanosecond timestamps
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the global header of the capture to `w`
+    pub fn new(mut w: W) -> Result<Self, CaptureError> {
+        let mut hdr = [0; GLOBAL_HEADER_LEN];
+        LittleEndian::write_u32(&mut hdr[0..4], MAGIC_NS);
+        LittleEndian::write_u16(&mut hdr[4..6], 2);
+        LittleEndian::write_u16(&mut hdr[6..8], 4);
+        // Time zone and accuracy are always 0
+        LittleEndian::write_u32(&mut hdr[16..20], MAX_RECORD_LEN);
+        LittleEndian::write_u32(&mut hdr[20..24], LINKTYPE.into());
+        w.write_all(&hdr)?;
+        Ok(Self { w })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), CaptureError> {
+        let packet = record.to_packet();
+        let mut hdr = [0; RECORD_HEADER_LEN];
+        let secs = u32::try_from(record.timestamp / 1_000_000_000)
+            .map_err(|_| CaptureError::Malformed("timestamp past 2106"))?;
+        LittleEndian::write_u32(&mut hdr[0..4], secs);
+        LittleEndian::write_u32(&mut hdr[4..8], (record.timestamp % 1_000_000_000) as u32);
+        LittleEndian::write_u32(&mut hdr[8..12], packet.len() as u32);
+        LittleEndian::write_u32(&mut hdr[12..16], packet.len() as u32);
+        self.w.write_all(&hdr)?;
+        self.w.write_all(&packet)?;
+        Ok(())
+    }
+
+    /// Flushes the writer and returns it
+    pub fn into_inner(mut self) -> Result<W, CaptureError> {
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// Reader of classic pcap captures in either byte order and timestamp
+/// resolution, yielding records in file order
+#[derive(Debug)]
+pub struct PcapReader<R: Read> {
+    r: R,
+    big: bool,
+    nanos: bool,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Reads and checks the global header of the capture
+    pub fn new(mut r: R) -> Result<Self, CaptureError> {
+        let mut hdr = [0; GLOBAL_HEADER_LEN];
+        if !read_or_eof(&mut r, &mut hdr)? {
+            return Err(CaptureError::Malformed("empty capture"));
+        }
+
+        let magic = LittleEndian::read_u32(&hdr[0..4]);
+        let (big, nanos) = match (magic, magic.swap_bytes()) {
+            (MAGIC_US, _) => (false, false),
+            (MAGIC_NS, _) => (false, true),
+            (_, MAGIC_US) => (true, false),
+            (_, MAGIC_NS) => (true, true),
+            _ => return Err(CaptureError::BadMagic(magic)),
+        };
+        // The upper bits of the link type hold FCS information
+        let linktype = read_u32(&hdr[20..24], big) as u16;
+        if linktype != LINKTYPE {
+            return Err(CaptureError::LinkType(linktype));
+        }
+        Ok(Self { r, big, nanos })
+    }
+
+    /// Reads the next record, or returns None at the end of the capture
+    pub fn read(&mut self) -> Result<Option<Record>, CaptureError> {
+        let mut hdr = [0; RECORD_HEADER_LEN];
+        if !read_or_eof(&mut self.r, &mut hdr)? {
+            return Ok(None);
+        }
+        let secs = u64::from(read_u32(&hdr[0..4], self.big));
+        let frac = u64::from(read_u32(&hdr[4..8], self.big));
+        let len = read_u32(&hdr[8..12], self.big);
+        if len > MAX_RECORD_LEN {
+            return Err(CaptureError::Malformed("record too long"));
+        }
+
+        let mut packet = vec![0; len as usize];
+        if !read_or_eof(&mut self.r, &mut packet)? {
+            return Err(CaptureError::Malformed("truncated record"));
+        }
+        let frac = if self.nanos { frac } else { frac * 1000 };
+        Record::from_packet(secs * 1_000_000_000 + frac, &packet).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<Record, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
@@ -0,0 +1,272 @@
+use crate::capture::{
+    read_or_eof, read_u16, read_u32, CaptureError, Direction, Record, LINKTYPE, MAX_RECORD_LEN,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::{Read, Write};
+
+/// Section header block, whose type reads the same in either byte order
+pub(crate) const SHB_TYPE: u32 = 0x0A0D_0D0A;
+const IDB_TYPE: u32 = 0x0000_0001;
+const EPB_TYPE: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+
+/// Inbound and outbound values of the direction bits of `epb_flags`
+const EPB_INBOUND: u32 = 0b01;
+const EPB_OUTBOUND: u32 = 0b10;
+
+/// Largest block accepted when reading
+const MAX_BLOCK_LEN: u32 = MAX_RECORD_LEN + 0x100;
+/// Smallest block: type, length and trailing length
+const MIN_BLOCK_LEN: u32 = 12;
+/// Smallest section header, adding the byte order magic, version and section
+/// length
+const MIN_SHB_LEN: u32 = 28;
+
+/// Writer of pcapng captures, with a single interface and nanosecond
+/// timestamps
+#[derive(Debug)]
+pub struct PcapngWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Writes the section header and interface description blocks to `w`
+    pub fn new(mut w: W) -> Result<Self, CaptureError> {
+        let mut shb = [0; 16];
+        LittleEndian::write_u32(&mut shb[0..4], BYTE_ORDER_MAGIC);
+        LittleEndian::write_u16(&mut shb[4..6], 1);
+        LittleEndian::write_u16(&mut shb[6..8], 0);
+        // Unknown section length
+        LittleEndian::write_i64(&mut shb[8..16], -1);
+        write_block(&mut w, SHB_TYPE, &shb)?;
+
+        let mut idb = [0; 20];
+        LittleEndian::write_u16(&mut idb[0..2], LINKTYPE);
+        LittleEndian::write_u32(&mut idb[4..8], MAX_RECORD_LEN);
+        LittleEndian::write_u16(&mut idb[8..10], OPT_IF_TSRESOL);
+        LittleEndian::write_u16(&mut idb[10..12], 1);
+        idb[12] = 9;
+        LittleEndian::write_u16(&mut idb[16..18], OPT_ENDOFOPT);
+        write_block(&mut w, IDB_TYPE, &idb)?;
+
+        Ok(Self { w })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), CaptureError> {
+        let packet = record.to_packet();
+        let padded = packet.len().next_multiple_of(4);
+
+        let mut epb = vec![0; 20 + padded + 12];
+        LittleEndian::write_u32(&mut epb[4..8], (record.timestamp >> 32) as u32);
+        LittleEndian::write_u32(&mut epb[8..12], record.timestamp as u32);
+        LittleEndian::write_u32(&mut epb[12..16], packet.len() as u32);
+        LittleEndian::write_u32(&mut epb[16..20], packet.len() as u32);
+        epb[20..20 + packet.len()].copy_from_slice(&packet);
+
+        let opts = &mut epb[20 + padded..];
+        let flags = match record.direction {
+            Direction::Upstream => EPB_INBOUND,
+            Direction::Downstream => EPB_OUTBOUND,
+        };
+        LittleEndian::write_u16(&mut opts[0..2], OPT_EPB_FLAGS);
+        LittleEndian::write_u16(&mut opts[2..4], 4);
+        LittleEndian::write_u32(&mut opts[4..8], flags);
+        LittleEndian::write_u16(&mut opts[8..10], OPT_ENDOFOPT);
+
+        write_block(&mut self.w, EPB_TYPE, &epb)
+    }
+
+    /// Flushes the writer and returns it
+    pub fn into_inner(mut self) -> Result<W, CaptureError> {
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+fn write_block<W>(w: &mut W, ty: u32, body: &[u8]) -> Result<(), CaptureError>
+where
+    W: Write,
+{
+    let len = (body.len() + 12) as u32;
+    w.write_all(&ty.to_le_bytes())?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(body)?;
+    w.write_all(&len.to_le_bytes())?;
+    Ok(())
+}
+
+/// Interface of a section, as described by its interface description block
+#[derive(Clone, Copy, Debug)]
+struct Interface {
+    linktype: u16,
+    /// Raw `if_tsresol` option value
+    tsresol: u8,
+}
+
+impl Interface {
+    /// Converts a timestamp in units of the interface to nanoseconds
+    fn nanos(&self, ts: u64) -> u64 {
+        let exp = u32::from(self.tsresol & 0x7F);
+        let ns = if self.tsresol & 0x80 > 0 {
+            (u128::from(ts) * 1_000_000_000) >> exp.min(127)
+        } else if exp <= 9 {
+            u128::from(ts) * 10u128.pow(9 - exp)
+        } else {
+            u128::from(ts) / 10u128.pow((exp - 9).min(38))
+        };
+        ns.min(u64::MAX.into()) as u64
+    }
+}
+
+/// Reader of pcapng captures in either byte order, yielding the records of
+/// enhanced packet blocks in file order
+///
+/// Blocks other than section headers, interface descriptions and enhanced
+/// packets are skipped. The direction of records comes from their
+/// pseudo-header, not from `epb_flags`.
+#[derive(Debug)]
+pub struct PcapngReader<R: Read> {
+    r: R,
+    big: bool,
+    /// Whether a section header block was read
+    in_section: bool,
+    interfaces: Vec<Interface>,
+}
+
+impl<R: Read> PcapngReader<R> {
+    /// Reads and checks the first section header block of the capture
+    pub fn new(r: R) -> Result<Self, CaptureError> {
+        let mut reader = Self {
+            r,
+            big: false,
+            in_section: false,
+            interfaces: Vec::new(),
+        };
+        match reader.read_block()? {
+            Some(_) => Ok(reader),
+            None => Err(CaptureError::Malformed("empty capture")),
+        }
+    }
+
+    /// Reads the next record, or returns None at the end of the capture
+    pub fn read(&mut self) -> Result<Option<Record>, CaptureError> {
+        loop {
+            let Some((ty, body)) = self.read_block()? else {
+                return Ok(None);
+            };
+            if ty != EPB_TYPE {
+                continue;
+            }
+            if body.len() < 20 {
+                return Err(CaptureError::Malformed("enhanced packet block too short"));
+            }
+
+            let iface = read_u32(&body[0..4], self.big) as usize;
+            let iface = *self.interfaces.get(iface).ok_or(CaptureError::Malformed(
+                "packet of an undescribed interface",
+            ))?;
+            if iface.linktype != LINKTYPE {
+                return Err(CaptureError::LinkType(iface.linktype));
+            }
+
+            let ts = u64::from(read_u32(&body[4..8], self.big)) << 32
+                | u64::from(read_u32(&body[8..12], self.big));
+            let len = read_u32(&body[12..16], self.big) as usize;
+            let packet = body
+                .get(20..20 + len)
+                .ok_or(CaptureError::Malformed("packet overruns its block"))?;
+            return Record::from_packet(iface.nanos(ts), packet).map(Some);
+        }
+    }
+
+    /// Reads the type and body of the next block, tracking the byte order and
+    /// interfaces of the current section
+    fn read_block(&mut self) -> Result<Option<(u32, Vec<u8>)>, CaptureError> {
+        let mut hdr = [0; 8];
+        if !read_or_eof(&mut self.r, &mut hdr)? {
+            return Ok(None);
+        }
+
+        let ty = read_u32(&hdr[0..4], self.big);
+        if ty == SHB_TYPE {
+            let mut magic = [0; 4];
+            if !read_or_eof(&mut self.r, &mut magic)? {
+                return Err(CaptureError::Malformed("truncated section header"));
+            }
+            self.big = match LittleEndian::read_u32(&magic) {
+                BYTE_ORDER_MAGIC => false,
+                m if m.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                m => return Err(CaptureError::BadMagic(m)),
+            };
+            self.interfaces.clear();
+            self.in_section = true;
+        } else if !self.in_section {
+            return Err(CaptureError::BadMagic(ty));
+        }
+
+        let len = read_u32(&hdr[4..8], self.big);
+        let (skip, min_len) = if ty == SHB_TYPE {
+            (4, MIN_SHB_LEN)
+        } else {
+            (0, MIN_BLOCK_LEN)
+        };
+        if !len.is_multiple_of(4) || !(min_len..=MAX_BLOCK_LEN).contains(&len) {
+            return Err(CaptureError::Malformed("bad block length"));
+        }
+        let mut body = vec![0; len as usize - 8 - skip];
+        if !read_or_eof(&mut self.r, &mut body)? {
+            return Err(CaptureError::Malformed("truncated block"));
+        }
+        let trailer = body.split_off(body.len() - 4);
+        if read_u32(&trailer, self.big) != len {
+            return Err(CaptureError::Malformed("mismatched block lengths"));
+        }
+
+        if ty == IDB_TYPE {
+            self.describe_interface(&body)?;
+        }
+        Ok(Some((ty, body)))
+    }
+
+    fn describe_interface(&mut self, body: &[u8]) -> Result<(), CaptureError> {
+        if body.len() < 8 {
+            return Err(CaptureError::Malformed(
+                "interface description block too short",
+            ));
+        }
+        let mut iface = Interface {
+            linktype: read_u16(&body[0..2], self.big),
+            tsresol: 6,
+        };
+
+        let mut opts = &body[8..];
+        while opts.len() >= 4 {
+            let code = read_u16(&opts[0..2], self.big);
+            let len = read_u16(&opts[2..4], self.big) as usize;
+            let value = opts
+                .get(4..4 + len)
+                .ok_or(CaptureError::Malformed("option overruns its block"))?;
+            match code {
+                OPT_ENDOFOPT => break,
+                OPT_IF_TSRESOL if len == 1 => iface.tsresol = value[0],
+                _ => {}
+            }
+            opts = opts.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+        }
+        self.interfaces.push(iface);
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for PcapngReader<R> {
+    type Item = Result<Record, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
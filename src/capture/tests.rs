@@ -0,0 +1,194 @@
+use super::*;
+use crate::{DeviceID, MRd, MWr};
+use proptest::prelude::*;
+use std::vec;
+
+fn direction() -> impl Strategy<Value = Direction> {
+    prop_oneof![Just(Direction::Downstream), Just(Direction::Upstream)]
+}
+
+prop_compose! {
+    fn record()(
+        timestamp in 0..u64::from(u32::MAX) * 1_000_000_000,
+        direction in direction(),
+        link: u16,
+        data in proptest::collection::vec(any::<u8>(), 0..64),
+    ) -> Record {
+        Record::new(timestamp, direction, link, data)
+    }
+}
+
+fn write_pcap(records: &[Record]) -> Vec<u8> {
+    let mut w = PcapWriter::new(Vec::new()).unwrap();
+    for r in records {
+        w.write(r).unwrap();
+    }
+    w.into_inner().unwrap()
+}
+
+fn write_pcapng(records: &[Record]) -> Vec<u8> {
+    let mut w = PcapngWriter::new(Vec::new()).unwrap();
+    for r in records {
+        w.write(r).unwrap();
+    }
+    w.into_inner().unwrap()
+}
+
+proptest! {
+    /// Roundtrip testing of pcap captures
+    #[test]
+    fn pcap_roundtrip(records in proptest::collection::vec(record(), 0..8)) {
+        let buf = write_pcap(&records);
+        let read: Result<Vec<_>, _> = PcapReader::new(buf.as_slice()).unwrap().collect();
+        assert_eq!(records, read.unwrap());
+        assert_eq!(records, read_records(buf.as_slice()).unwrap());
+    }
+
+    /// Roundtrip testing of pcapng captures
+    #[test]
+    fn pcapng_roundtrip(records in proptest::collection::vec(record(), 0..8)) {
+        let buf = write_pcapng(&records);
+        let read: Result<Vec<_>, _> = PcapngReader::new(buf.as_slice()).unwrap().collect();
+        assert_eq!(records, read.unwrap());
+        assert_eq!(records, read_records(buf.as_slice()).unwrap());
+    }
+
+    /// Tests that truncated captures are errors, never panics or short reads
+    #[test]
+    fn truncated_captures(records in proptest::collection::vec(record(), 1..4), cut: usize) {
+        for buf in [write_pcap(&records), write_pcapng(&records)] {
+            let cut = cut % buf.len();
+            let read = read_records(&buf[..cut]);
+            prop_assert!(read.is_err() || read.unwrap().len() < records.len());
+        }
+    }
+}
+
+#[test]
+fn record_tlp() {
+    let req_id = DeviceID::new(1, 0, 0).unwrap();
+    let data = [0xAA; 8];
+    let mwr = Tlp::MWr(MWr::new(req_id, 0, 0x1000, &data).unwrap());
+    let mrd = Tlp::MRd(MRd::new(req_id, 1, 0x1000, 8).unwrap());
+    let records = [
+        Record::from_tlp(1, Direction::Upstream, 3, &mwr).unwrap(),
+        Record::from_tlp(2, Direction::Downstream, 3, &mrd).unwrap(),
+    ];
+
+    for buf in [write_pcap(&records), write_pcapng(&records)] {
+        let read = read_records(buf.as_slice()).unwrap();
+        assert_eq!(Ok(mwr), read[0].tlp());
+        assert_eq!(Ok(mrd), read[1].tlp());
+        assert_eq!(Direction::Upstream, read[0].direction);
+        assert_eq!(3, read[1].link);
+    }
+}
+
+#[test]
+fn pcap_layout() {
+    let record = Record::new(1_500_000_001, Direction::Upstream, 0x0102, vec![0xDE, 0xAD]);
+    let buf = write_pcap(&[record]);
+    assert_eq!([0x4D, 0x3C, 0xB2, 0xA1], buf[0..4]);
+    assert_eq!([147, 0, 0, 0], buf[20..24]);
+    // Seconds, nanoseconds and lengths of the record header
+    assert_eq!([1, 0, 0, 0, 0x01, 0x65, 0xCD, 0x1D], buf[24..32]);
+    assert_eq!([6, 0, 0, 0, 6, 0, 0, 0], buf[32..40]);
+    assert_eq!([1, 0, 0x01, 0x02, 0xDE, 0xAD], buf[40..]);
+}
+
+#[test]
+fn pcap_big_endian_micros() {
+    let mut buf = vec![0xA1, 0xB2, 0xC3, 0xD4, 0, 2, 0, 4];
+    buf.extend_from_slice(&[0; 8]);
+    buf.extend_from_slice(&[0, 0, 0xFF, 0xFF, 0, 0, 0, 147]);
+    buf.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 7, 0, 0, 0, 4, 0, 0, 0, 4]);
+    buf.extend_from_slice(&[0, 0, 0, 9]);
+
+    let read = read_records(buf.as_slice()).unwrap();
+    assert_eq!(
+        vec![Record::new(2_000_007_000, Direction::Downstream, 9, vec![])],
+        read
+    );
+}
+
+#[test]
+fn pcapng_big_endian() {
+    let mut buf = vec![];
+    // Section header
+    buf.extend_from_slice(&[0x0A, 0x0D, 0x0D, 0x0A, 0, 0, 0, 28]);
+    buf.extend_from_slice(&[0x1A, 0x2B, 0x3C, 0x4D, 0, 1, 0, 0]);
+    buf.extend_from_slice(&[0xFF; 8]);
+    buf.extend_from_slice(&[0, 0, 0, 28]);
+    // Interface description with microsecond timestamps
+    buf.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 20, 0, 147, 0, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(&[0, 0, 0, 20]);
+    // Unknown block
+    buf.extend_from_slice(&[0, 0, 0, 0x42, 0, 0, 0, 16, 1, 2, 3, 4, 0, 0, 0, 16]);
+    // Enhanced packet
+    buf.extend_from_slice(&[0, 0, 0, 6, 0, 0, 0, 40, 0, 0, 0, 0]);
+    buf.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 6, 0, 0, 0, 6]);
+    buf.extend_from_slice(&[1, 0, 0, 1, 0xAB, 0xCD, 0, 0]);
+    buf.extend_from_slice(&[0, 0, 0, 40]);
+
+    let read = read_records(buf.as_slice()).unwrap();
+    assert_eq!(
+        vec![Record::new(5000, Direction::Upstream, 1, vec![0xAB, 0xCD])],
+        read
+    );
+}
+
+#[test]
+fn pcapng_tsresol() {
+    let iface = |tsresol| pcapng_nanos(tsresol, 3);
+    assert_eq!(3_000_000, iface(3));
+    assert_eq!(3, iface(9));
+    assert_eq!(0, iface(10));
+    assert_eq!(1_500_000_000, iface(0x81));
+}
+
+fn pcapng_nanos(tsresol: u8, ts: u64) -> u64 {
+    let mut buf = write_pcapng(&[Record::new(ts, Direction::Downstream, 0, vec![])]);
+    // Patch the if_tsresol option of the interface description
+    buf[28 + 8 + 12] = tsresol;
+    read_records(buf.as_slice()).unwrap()[0].timestamp
+}
+
+#[test]
+fn bad_captures() {
+    assert!(matches!(
+        read_records([0u8; 32].as_slice()),
+        Err(CaptureError::BadMagic(0))
+    ));
+
+    let mut buf = write_pcap(&[]);
+    buf[20] = 1;
+    assert!(matches!(
+        read_records(buf.as_slice()),
+        Err(CaptureError::LinkType(1))
+    ));
+
+    let mut buf = write_pcapng(&[Record::default()]);
+    buf[28 + 8] = 1;
+    assert!(matches!(
+        read_records(buf.as_slice()),
+        Err(CaptureError::LinkType(1))
+    ));
+
+    let mut buf = write_pcapng(&[]);
+    buf[4] = 27;
+    assert!(matches!(
+        read_records(buf.as_slice()),
+        Err(CaptureError::Malformed(_))
+    ));
+}
+
+#[test]
+fn pcapng_short_section_header() {
+    // Section header of 12 bytes, too short for the fields after the magic
+    let mut buf = vec![0x0A, 0x0D, 0x0D, 0x0A, 12, 0, 0, 0, 0x4D, 0x3C, 0x2B, 0x1A];
+    buf.extend_from_slice(&12u32.to_le_bytes());
+    assert!(matches!(
+        read_records(buf.as_slice()),
+        Err(CaptureError::Malformed("bad block length"))
+    ));
+}
@@ -0,0 +1,264 @@
+use crate::{config::ConfigAccess, Address, Field, MRd, MWr, TlpError};
+
+/// Address space decoded by a base address register
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BarKind {
+    Io,
+    #[default]
+    Memory32,
+    /// 64-bit memory, taking two consecutive registers
+    Memory64,
+}
+
+/// Decoded base address register
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Bar {
+    /// Index of the (lower) register
+    pub index: u8,
+    pub kind: BarKind,
+    pub prefetchable: bool,
+    /// Assigned base address
+    pub addr: u64,
+    /// Size in bytes, 0 until sized
+    pub size: u64,
+}
+
+impl Bar {
+    /// Offset of the first base address register
+    pub const OFFSET: usize = 0x10;
+
+    /// Decodes BAR `index` from the raw registers of a header
+    pub fn decode(regs: &[u32], index: usize) -> Result<Self, TlpError> {
+        let offset = Self::OFFSET + 4 * index;
+        let lo = *regs.get(index).ok_or(TlpError::OutOfRange {
+            field: Field::Bar,
+            value: index as u64,
+            min: 0,
+            max: regs.len().saturating_sub(1) as u64,
+            offset,
+        })?;
+
+        let (kind, addr) = if lo & 0x1 > 0 {
+            (BarKind::Io, u64::from(lo & !0x3))
+        } else {
+            match (lo >> 1) & 0x3 {
+                0b00 => (BarKind::Memory32, u64::from(lo & !0xF)),
+                0b10 => {
+                    let hi = *regs.get(index + 1).ok_or(TlpError::OutOfRange {
+                        field: Field::Bar,
+                        value: index as u64,
+                        min: 0,
+                        max: regs.len().saturating_sub(2) as u64,
+                        offset,
+                    })?;
+                    (
+                        BarKind::Memory64,
+                        u64::from(hi) << 32 | u64::from(lo & !0xF),
+                    )
+                }
+                _ => {
+                    return Err(TlpError::InvalidType {
+                        field: Field::Bar,
+                        value: lo,
+                        offset,
+                    })
+                }
+            }
+        };
+
+        Ok(Self {
+            index: index as u8,
+            kind,
+            prefetchable: kind != BarKind::Io && lo & 0x8 > 0,
+            addr,
+            size: 0,
+        })
+    }
+
+    /// Number of registers taken by the BAR
+    pub fn regs(&self) -> usize {
+        match self.kind {
+            BarKind::Memory64 => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn is_io(&self) -> bool {
+        self.kind == BarKind::Io
+    }
+
+    /// Returns the raw register values for the BAR at `addr`
+    pub fn encode(&self, addr: u64) -> [u32; 2] {
+        let flags = match self.kind {
+            BarKind::Io => 0x1,
+            BarKind::Memory32 => (self.prefetchable as u32) << 3,
+            BarKind::Memory64 => 0x4 | (self.prefetchable as u32) << 3,
+        };
+        // I/O BARs decode at least 4 bytes, memory BARs at least 16
+        let min = match self.kind {
+            BarKind::Io => 0x4,
+            _ => 0x10,
+        };
+        let addr = addr & !(self.size.max(min) - 1);
+        [addr as u32 | flags, (addr >> 32) as u32]
+    }
+
+    /// Returns the offset into the BAR of an access of `len` bytes at `addr`,
+    /// or None if the access does not lie wholly inside it
+    pub fn hit(&self, addr: Address, len: u64) -> Option<u64> {
+        let offset = u64::from(addr).checked_sub(self.addr)?;
+        (self.size > 0 && offset.checked_add(len)? <= self.size).then_some(offset)
+    }
+
+    /// Returns the offset into the BAR of a memory read, if it hits it
+    pub fn hit_mrd(&self, req: &MRd) -> Option<u64> {
+        (!self.is_io())
+            .then(|| self.hit(req.addr, req.hdr.hdr.data_len().into()))
+            .flatten()
+    }
+
+    /// Returns the offset into the BAR of a memory write, if it hits it
+    pub fn hit_mwr(&self, req: &MWr) -> Option<u64> {
+        (!self.is_io())
+            .then(|| self.hit(req.addr, req.data.len() as u64))
+            .flatten()
+    }
+
+    /// Sizes BAR `index` of a function with the write-all-ones handshake,
+    /// restoring its value afterwards
+    ///
+    /// Returns None if the BAR is not implemented, and an error if it is past
+    /// the BARs of the header layout.
+    pub fn probe<C>(cfg: &mut C, index: usize) -> Result<Option<Self>, TlpError>
+    where
+        C: ConfigAccess + ?Sized,
+    {
+        let count = bar_count(cfg)?;
+        let read_mask = |cfg: &mut C, i: usize| {
+            let offset = (Self::OFFSET + 4 * i) as u16;
+            let orig = cfg.read(offset);
+            cfg.write(offset, u32::MAX, 0xF);
+            let mask = cfg.read(offset);
+            cfg.write(offset, orig, 0xF);
+            (orig, mask)
+        };
+
+        if index >= count {
+            return Err(TlpError::OutOfRange {
+                field: Field::Bar,
+                value: index as u64,
+                min: 0,
+                max: (count - 1) as u64,
+                offset: Self::OFFSET + 4 * index,
+            });
+        }
+        let (orig, mask) = read_mask(cfg, index);
+        if mask == 0 {
+            return Ok(None);
+        }
+
+        // Only the flag bits matter to find out the kind of the BAR, which
+        // fails to decode if a 64-bit BAR has no upper register
+        let mut regs = [0; 6];
+        regs[index] = mask;
+        let mut bar = Self::decode(&regs[..count], index)?;
+        let mask = match bar.kind {
+            BarKind::Io => {
+                // The upper 16 bits are hardwired to 0 without 32-bit I/O
+                let mask = mask & !0x3;
+                let upper = if mask >> 16 == 0 { 0xFFFF_0000 } else { 0 };
+                0xFFFF_FFFF_0000_0000 | u64::from(mask | upper)
+            }
+            BarKind::Memory32 => 0xFFFF_FFFF_0000_0000 | u64::from(mask & !0xF),
+            BarKind::Memory64 => {
+                let (orig_hi, mask_hi) = read_mask(cfg, index + 1);
+                bar.addr = u64::from(orig_hi) << 32;
+                u64::from(mask_hi) << 32 | u64::from(mask & !0xF)
+            }
+        };
+        bar.addr |= u64::from(orig & if bar.is_io() { !0x3 } else { !0xF });
+        bar.size = (!mask).wrapping_add(1);
+        Ok(Some(bar))
+    }
+}
+
+/// Iterator over the BARs of a header, skipping the upper halves of 64-bit
+/// BARs and stopping after the first error
+///
+/// Unimplemented BARs read as 0 and decode as 32-bit memory at address 0,
+/// sizing is needed to tell them apart.
+#[derive(Clone, Debug)]
+pub struct Bars<'a> {
+    regs: &'a [u32],
+    index: usize,
+}
+
+impl<'a> Bars<'a> {
+    pub fn new(regs: &'a [u32]) -> Self {
+        Self { regs, index: 0 }
+    }
+}
+
+impl Iterator for Bars<'_> {
+    type Item = Result<Bar, TlpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.regs.len() {
+            return None;
+        }
+        let bar = Bar::decode(self.regs, self.index);
+        self.index = match bar {
+            Ok(bar) => self.index + bar.regs(),
+            Err(_) => self.regs.len(),
+        };
+        Some(bar)
+    }
+}
+
+/// Sizes every BAR of a function, with memory and I/O decoding disabled for
+/// the duration as software does
+pub fn size_bars<C>(cfg: &mut C) -> Result<[Option<Bar>; 6], TlpError>
+where
+    C: ConfigAccess + ?Sized,
+{
+    let count = bar_count(cfg)?;
+    let cmd = cfg.read(0x04);
+    cfg.write(0x04, cmd & !0x3, 0x3);
+
+    let mut bars = [None; 6];
+    let mut index = 0;
+    let res = loop {
+        if index >= count {
+            break Ok(());
+        }
+        match Bar::probe(cfg, index) {
+            Ok(Some(bar)) => {
+                index += bar.regs();
+                bars[usize::from(bar.index)] = Some(bar);
+            }
+            Ok(None) => index += 1,
+            Err(e) => break Err(e),
+        }
+    };
+
+    cfg.write(0x04, cmd, 0x3);
+    res.map(|_| bars)
+}
+
+/// Returns the number of BAR registers of the header layout of a function
+fn bar_count<C>(cfg: &mut C) -> Result<usize, TlpError>
+where
+    C: ConfigAccess + ?Sized,
+{
+    use crate::config::{HeaderLayout, HeaderType};
+
+    match HeaderType::from((cfg.read(0x0C) >> 16) as u8).layout {
+        HeaderLayout::Type0 => Ok(6),
+        HeaderLayout::Type1 => Ok(2),
+        layout => Err(TlpError::InvalidType {
+            field: Field::HeaderType,
+            value: u8::from(layout).into(),
+            offset: 0x0E,
+        }),
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{config::caps::check_fits, TlpError};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Access control services, as found in both the capability and control
+/// registers
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AcsFlags {
+    pub source_validation: bool,
+    pub translation_blocking: bool,
+    pub p2p_request_redirect: bool,
+    pub p2p_completion_redirect: bool,
+    pub upstream_forwarding: bool,
+    pub p2p_egress_control: bool,
+    pub direct_translated_p2p: bool,
+}
+
+impl From<u16> for AcsFlags {
+    fn from(x: u16) -> Self {
+        Self {
+            source_validation: x & (1 << 0) > 0,
+            translation_blocking: x & (1 << 1) > 0,
+            p2p_request_redirect: x & (1 << 2) > 0,
+            p2p_completion_redirect: x & (1 << 3) > 0,
+            upstream_forwarding: x & (1 << 4) > 0,
+            p2p_egress_control: x & (1 << 5) > 0,
+            direct_translated_p2p: x & (1 << 6) > 0,
+        }
+    }
+}
+
+/// Access control services capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AcsCap {
+    /// Supported services
+    pub capability: AcsFlags,
+    /// Number of bits in the egress control vector
+    pub egress_vector_size: u16,
+    /// Enabled services
+    pub control: AcsFlags,
+}
+
+impl AcsCap {
+    pub const ID: u16 = 0x000D;
+
+    pub const LENGTH: usize = 8;
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        let cap = LittleEndian::read_u16(&space[offset + 4..]);
+        let egress_vector_size = match cap >> 8 {
+            0 => 256,
+            n => n,
+        };
+
+        Ok(Self {
+            capability: cap.into(),
+            egress_vector_size,
+            control: LittleEndian::read_u16(&space[offset + 6..]).into(),
+        })
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{
+    config::caps::check_fits, CplHeader, Field, MsgHeader, PasidPrefix, RequestHeader, TlpError,
+    TlpHeader,
+};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use core::ops::{BitOr, BitOrAssign};
+
+/// Defines a set of AER error bits over a `u32` register
+macro_rules! aer_bits {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$vmeta:meta])*
+                const $bit:ident = $value:expr;
+            )+
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+        pub struct $name(pub u32);
+
+        impl $name {
+            $(
+                $(#[$vmeta])*
+                pub const $bit: Self = Self(1 << $value);
+            )+
+
+            /// Every defined error bit
+            pub const ALL: Self = Self($(1 << $value)|+);
+
+            pub fn bits(&self) -> u32 {
+                self.0
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.0 == 0
+            }
+
+            /// Returns true if every bit of `other` is set
+            pub fn contains(&self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+
+            /// Returns the bits set in both
+            pub fn intersection(&self, other: Self) -> Self {
+                Self(self.0 & other.0)
+            }
+
+            /// Returns the bits of `self` not set in `other`
+            pub fn difference(&self, other: Self) -> Self {
+                Self(self.0 & !other.0)
+            }
+
+            /// Returns the index of the lowest set bit
+            pub fn first(&self) -> Option<u8> {
+                (!self.is_empty()).then_some(self.0.trailing_zeros() as u8)
+            }
+        }
+
+        impl BitOr for $name {
+            type Output = Self;
+
+            fn bitor(self, rhs: Self) -> Self {
+                Self(self.0 | rhs.0)
+            }
+        }
+
+        impl BitOrAssign for $name {
+            fn bitor_assign(&mut self, rhs: Self) {
+                self.0 |= rhs.0;
+            }
+        }
+    };
+}
+
+aer_bits! {
+    /// Uncorrectable error status, mask and severity register bits
+    pub struct UncorrectableErrors {
+        const DATA_LINK_PROTOCOL = 4;
+        const SURPRISE_DOWN = 5;
+        const POISONED_TLP = 12;
+        const FLOW_CONTROL_PROTOCOL = 13;
+        const COMPLETION_TIMEOUT = 14;
+        const COMPLETER_ABORT = 15;
+        const UNEXPECTED_COMPLETION = 16;
+        const RECEIVER_OVERFLOW = 17;
+        const MALFORMED_TLP = 18;
+        const ECRC = 19;
+        const UNSUPPORTED_REQUEST = 20;
+        const ACS_VIOLATION = 21;
+        const INTERNAL = 22;
+        const MC_BLOCKED_TLP = 23;
+        const ATOMIC_OP_EGRESS_BLOCKED = 24;
+        const TLP_PREFIX_BLOCKED = 25;
+        const POISONED_TLP_EGRESS_BLOCKED = 26;
+    }
+}
+
+aer_bits! {
+    /// Correctable error status and mask register bits
+    pub struct CorrectableErrors {
+        const RECEIVER = 0;
+        const BAD_TLP = 6;
+        const BAD_DLLP = 7;
+        const REPLAY_NUM_ROLLOVER = 8;
+        const REPLAY_TIMER_TIMEOUT = 12;
+        const ADVISORY_NON_FATAL = 13;
+        const CORRECTED_INTERNAL = 14;
+        const HEADER_LOG_OVERFLOW = 15;
+    }
+}
+
+impl UncorrectableErrors {
+    /// Returns the error a receiver logs for a TLP rejected with `err`
+    ///
+    /// Receivers ignore reserved bits, and errors that do not come from
+    /// decoding a TLP have no AER equivalent.
+    pub fn from_error(err: &TlpError) -> Option<Self> {
+        match *err {
+            TlpError::InvalidType {
+                field: Field::MessageCode,
+                ..
+            } => Some(Self::UNSUPPORTED_REQUEST),
+            // A reserved completion status is handled as UR by the requester
+            TlpError::InvalidType {
+                field: Field::Status,
+                ..
+            } => None,
+            TlpError::ReservedBitsSet { .. } | TlpError::Loop { .. } => None,
+            TlpError::TooLong { .. } | TlpError::TooShort { .. } => Some(Self::MALFORMED_TLP),
+            TlpError::InvalidType { field, .. }
+            | TlpError::NotAligned { field, .. }
+            | TlpError::OutOfRange { field, .. } => match field {
+                Field::CapPointer
+                | Field::Bar
+                | Field::Vector
+                | Field::HeaderType
+                | Field::Bus
+                | Field::Device
+                | Field::Function
+                | Field::RequestType => None,
+                _ => Some(Self::MALFORMED_TLP),
+            },
+        }
+    }
+}
+
+/// Header or TLP prefix log, holding the first four dwords of a TLP with byte
+/// 0 in the most significant byte of the first dword
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HeaderLog(pub [u32; 4]);
+
+impl HeaderLog {
+    /// Fills the log from the start of an encoded TLP, padding with zeros
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut buf = [0; 16];
+        let len = bytes.len().min(buf.len());
+        buf[..len].clone_from_slice(&bytes[..len]);
+
+        let mut log = [0; 4];
+        BigEndian::read_u32_into(&buf, &mut log);
+        Self(log)
+    }
+
+    /// Returns the logged dwords as TLP bytes
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut buf = [0; 16];
+        BigEndian::write_u32_into(&self.0, &mut buf);
+        buf
+    }
+}
+
+impl From<TlpHeader> for HeaderLog {
+    fn from(hdr: TlpHeader) -> Self {
+        Self::from_bytes(&hdr.to_bytes())
+    }
+}
+
+impl From<RequestHeader> for HeaderLog {
+    fn from(hdr: RequestHeader) -> Self {
+        Self::from_bytes(&hdr.to_bytes())
+    }
+}
+
+impl From<CplHeader> for HeaderLog {
+    fn from(hdr: CplHeader) -> Self {
+        Self::from_bytes(&hdr.to_bytes())
+    }
+}
+
+impl From<MsgHeader> for HeaderLog {
+    fn from(hdr: MsgHeader) -> Self {
+        Self::from_bytes(&hdr.to_bytes())
+    }
+}
+
+impl From<PasidPrefix> for HeaderLog {
+    fn from(prefix: PasidPrefix) -> Self {
+        Self::from_bytes(&prefix.to_bytes())
+    }
+}
+
+/// Advanced error reporting capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AerCap {
+    pub uncorrectable_status: UncorrectableErrors,
+    pub uncorrectable_mask: UncorrectableErrors,
+    /// Errors set here are fatal, the others non-fatal
+    pub uncorrectable_severity: UncorrectableErrors,
+    pub correctable_status: CorrectableErrors,
+    pub correctable_mask: CorrectableErrors,
+    /// Bit of the first reported uncorrectable error
+    pub first_error_pointer: u8,
+    pub ecrc_generation_capable: bool,
+    pub ecrc_generation_enable: bool,
+    pub ecrc_check_capable: bool,
+    pub ecrc_check_enable: bool,
+    /// Header of the TLP that caused the first error
+    pub header_log: HeaderLog,
+    /// Prefixes of the TLP that caused the first error, if logged
+    pub tlp_prefix_log: Option<HeaderLog>,
+}
+
+impl AerCap {
+    pub const ID: u16 = 0x0001;
+
+    pub const LENGTH: usize = 0x2C;
+
+    /// Length when the TLP prefix log is present
+    pub const PREFIX_LOG_LENGTH: usize = 0x48;
+
+    /// Offset of the TLP prefix log
+    const PREFIX_LOG_OFFSET: usize = 0x38;
+
+    /// Capabilities and control bit advertising the TLP prefix log
+    const PREFIX_LOG_PRESENT: u32 = 1 << 11;
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        let bytes = &space[offset..];
+        let ctl = LittleEndian::read_u32(&bytes[0x18..]);
+        let mut header_log = [0; 4];
+        LittleEndian::read_u32_into(&bytes[0x1C..0x2C], &mut header_log);
+
+        let tlp_prefix_log = if ctl & Self::PREFIX_LOG_PRESENT > 0 {
+            check_fits(space, offset, Self::PREFIX_LOG_LENGTH)?;
+            let mut log = [0; 4];
+            let start = Self::PREFIX_LOG_OFFSET;
+            LittleEndian::read_u32_into(&bytes[start..start + 16], &mut log);
+            Some(HeaderLog(log))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            uncorrectable_status: UncorrectableErrors(LittleEndian::read_u32(&bytes[0x4..])),
+            uncorrectable_mask: UncorrectableErrors(LittleEndian::read_u32(&bytes[0x8..])),
+            uncorrectable_severity: UncorrectableErrors(LittleEndian::read_u32(&bytes[0xC..])),
+            correctable_status: CorrectableErrors(LittleEndian::read_u32(&bytes[0x10..])),
+            correctable_mask: CorrectableErrors(LittleEndian::read_u32(&bytes[0x14..])),
+            first_error_pointer: (ctl & 0x1F) as u8,
+            ecrc_generation_capable: ctl & (1 << 5) > 0,
+            ecrc_generation_enable: ctl & (1 << 6) > 0,
+            ecrc_check_capable: ctl & (1 << 7) > 0,
+            ecrc_check_enable: ctl & (1 << 8) > 0,
+            header_log: HeaderLog(header_log),
+            tlp_prefix_log,
+        })
+    }
+
+    /// Returns true if an uncorrectable error is currently logged
+    pub fn has_uncorrectable(&self) -> bool {
+        !self.uncorrectable_status.is_empty()
+    }
+
+    /// Returns true if any of the logged uncorrectable errors is fatal
+    pub fn is_fatal(&self) -> bool {
+        !self
+            .uncorrectable_status
+            .intersection(self.uncorrectable_severity)
+            .is_empty()
+    }
+
+    /// Records uncorrectable errors caused by a TLP the way a function does,
+    /// returning the errors that are signaled
+    ///
+    /// Masked errors still set their status bits. The first error pointer
+    /// and logs are only updated when no unmasked error is already pending.
+    pub fn record(
+        &mut self,
+        errors: UncorrectableErrors,
+        header: HeaderLog,
+        prefix: Option<HeaderLog>,
+    ) -> UncorrectableErrors {
+        let signaled = errors.difference(self.uncorrectable_mask);
+        let pending = self
+            .uncorrectable_status
+            .difference(self.uncorrectable_mask);
+        self.uncorrectable_status |= errors;
+
+        if let (true, Some(first)) = (pending.is_empty(), signaled.first()) {
+            self.first_error_pointer = first;
+            self.header_log = header;
+            if self.tlp_prefix_log.is_some() {
+                self.tlp_prefix_log = Some(prefix.unwrap_or_default());
+            }
+        }
+        signaled
+    }
+
+    /// Records the error a receiver logs for a TLP rejected with `err`, if
+    /// any, with the first bytes of the TLP as the header log
+    pub fn record_error(&mut self, err: &TlpError, tlp: &[u8]) -> UncorrectableErrors {
+        UncorrectableErrors::from_error(err)
+            .map(|e| self.record(e, HeaderLog::from_bytes(tlp), None))
+            .unwrap_or_default()
+    }
+}
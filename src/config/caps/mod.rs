@@ -0,0 +1,264 @@
+//! Walkers over the standard and extended capability lists, and typed
+//! decoders for the common capabilities
+
+use crate::{
+    config::{check_space_len, CONFIG_SPACE_LEN, EXT_CONFIG_SPACE_LEN},
+    Field, TlpError,
+};
+use byteorder::{ByteOrder, LittleEndian};
+
+mod acs;
+mod aer;
+mod msi;
+mod pasid;
+mod pcie;
+mod pm;
+mod sriov;
+
+#[cfg(test)]
+mod tests;
+
+pub use acs::{AcsCap, AcsFlags};
+pub use aer::{AerCap, CorrectableErrors, HeaderLog, UncorrectableErrors};
+pub use msi::{MsiCap, MsiMessage, MsixCap, MsixEntry};
+pub use pasid::PasidCap;
+pub use pcie::{PcieCap, PortType};
+pub use pm::PmCap;
+pub use sriov::SriovCap;
+
+/// Offset of the pointer to the first standard capability
+pub const CAP_PTR_OFFSET: usize = 0x34;
+
+/// Offset of the first extended capability
+pub const EXT_CAP_OFFSET: usize = 0x100;
+
+/// Status register bit advertising a standard capability list
+const STATUS_CAP_LIST: u8 = 0x10;
+
+/// Entry of a capability list
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Capability {
+    pub id: u16,
+    /// Capability version, 0 for standard capabilities without one
+    pub version: u8,
+    /// Offset of the capability in config space
+    pub offset: u16,
+    /// Capability sits in the extended list
+    pub extended: bool,
+}
+
+impl Capability {
+    /// Decodes the capability with the registered decoder for its ID
+    pub fn decode(&self, space: &[u8]) -> Result<DecodedCap, TlpError> {
+        let decoder = DECODERS
+            .iter()
+            .find(|d| d.0 == self.extended && d.1 == self.id)
+            .map_or(decode_unknown as Decoder, |d| d.2);
+        decoder(space, self.offset.into())
+    }
+}
+
+/// Capability decoded by type
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodedCap {
+    Pm(PmCap),
+    Msi(MsiCap),
+    Pcie(PcieCap),
+    MsiX(MsixCap),
+    Aer(AerCap),
+    Acs(AcsCap),
+    SrIov(SriovCap),
+    Pasid(PasidCap),
+    /// Capability without a registered decoder
+    Unknown,
+}
+
+type Decoder = fn(&[u8], usize) -> Result<DecodedCap, TlpError>;
+
+fn decode_unknown(_: &[u8], _: usize) -> Result<DecodedCap, TlpError> {
+    Ok(DecodedCap::Unknown)
+}
+
+/// Typed decoders, keyed by whether the capability is extended and its ID
+const DECODERS: &[(bool, u16, Decoder)] = &[
+    (false, PmCap::ID, |s, o| {
+        PmCap::from_bytes(s, o).map(DecodedCap::Pm)
+    }),
+    (false, MsiCap::ID, |s, o| {
+        MsiCap::from_bytes(s, o).map(DecodedCap::Msi)
+    }),
+    (false, PcieCap::ID, |s, o| {
+        PcieCap::from_bytes(s, o).map(DecodedCap::Pcie)
+    }),
+    (false, MsixCap::ID, |s, o| {
+        MsixCap::from_bytes(s, o).map(DecodedCap::MsiX)
+    }),
+    (true, AerCap::ID, |s, o| {
+        AerCap::from_bytes(s, o).map(DecodedCap::Aer)
+    }),
+    (true, AcsCap::ID, |s, o| {
+        AcsCap::from_bytes(s, o).map(DecodedCap::Acs)
+    }),
+    (true, SriovCap::ID, |s, o| {
+        SriovCap::from_bytes(s, o).map(DecodedCap::SrIov)
+    }),
+    (true, PasidCap::ID, |s, o| {
+        PasidCap::from_bytes(s, o).map(DecodedCap::Pasid)
+    }),
+];
+
+/// Checks that a capability of `len` bytes at `offset` fits in `space`
+pub(crate) fn check_fits(space: &[u8], offset: usize, len: usize) -> Result<(), TlpError> {
+    let end = if offset < EXT_CAP_OFFSET {
+        CONFIG_SPACE_LEN
+    } else {
+        space.len()
+    };
+    if offset + len > end {
+        Err(TlpError::OutOfRange {
+            field: Field::CapPointer,
+            value: offset as u64,
+            min: 0,
+            max: end.saturating_sub(len) as u64,
+            offset,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Iterator over a capability list, stopping after the first error
+#[derive(Clone, Debug)]
+pub struct Capabilities<'a> {
+    space: &'a [u8],
+    /// Offset of the next entry, 0 at the end of the list
+    next: usize,
+    /// Offset of the pointer to the next entry
+    ptr_offset: usize,
+    extended: bool,
+    /// One bit per dword of config space, set once visited
+    visited: [u64; EXT_CONFIG_SPACE_LEN / 4 / 64],
+}
+
+impl<'a> Capabilities<'a> {
+    /// Walks the standard capability list of a 256 byte or 4 KiB config space
+    pub fn standard(space: &'a [u8]) -> Result<Self, TlpError> {
+        check_space_len(space)?;
+        let next = if space[0x06] & STATUS_CAP_LIST > 0 {
+            space[CAP_PTR_OFFSET].into()
+        } else {
+            0
+        };
+
+        Ok(Self {
+            space,
+            next,
+            ptr_offset: CAP_PTR_OFFSET,
+            extended: false,
+            visited: Default::default(),
+        })
+    }
+
+    /// Walks the extended capability list of a 4 KiB config space, which is
+    /// empty for a 256 byte one
+    pub fn extended(space: &'a [u8]) -> Result<Self, TlpError> {
+        check_space_len(space)?;
+        let next = match space.len() {
+            EXT_CONFIG_SPACE_LEN if LittleEndian::read_u32(&space[EXT_CAP_OFFSET..]) != 0 => {
+                EXT_CAP_OFFSET
+            }
+            _ => 0,
+        };
+
+        Ok(Self {
+            space,
+            next,
+            ptr_offset: EXT_CAP_OFFSET,
+            extended: true,
+            visited: Default::default(),
+        })
+    }
+
+    /// Returns the first capability with `id`
+    pub fn find(mut self, id: u16) -> Result<Option<Capability>, TlpError> {
+        self.find_map(|c| match c {
+            Ok(c) if c.id == id => Some(Ok(c)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .transpose()
+    }
+
+    /// Range of offsets entries may start at
+    fn bounds(&self) -> (usize, usize) {
+        if self.extended {
+            (EXT_CAP_OFFSET, EXT_CONFIG_SPACE_LEN - 4)
+        } else {
+            (0x40, CONFIG_SPACE_LEN - 4)
+        }
+    }
+
+    fn step(&mut self) -> Result<Capability, TlpError> {
+        let offset = self.next;
+        let (min, max) = self.bounds();
+        if offset < min || offset > max {
+            return Err(TlpError::OutOfRange {
+                field: Field::CapPointer,
+                value: offset as u64,
+                min: min as u64,
+                max: max as u64,
+                offset: self.ptr_offset,
+            });
+        }
+
+        let (word, bit) = (offset / 4 / 64, offset / 4 % 64);
+        if self.visited[word] & (1 << bit) > 0 {
+            return Err(TlpError::Loop {
+                field: Field::CapPointer,
+                value: offset as u64,
+                offset: self.ptr_offset,
+            });
+        }
+        self.visited[word] |= 1 << bit;
+
+        let bytes = &self.space[offset..];
+        let (id, version) = if self.extended {
+            let x = LittleEndian::read_u32(bytes);
+            self.next = (x >> 20) as usize & !0x3;
+            self.ptr_offset = offset + 2;
+            ((x & 0xFFFF) as u16, ((x >> 16) & 0xF) as u8)
+        } else {
+            self.next = usize::from(bytes[1]) & !0x3;
+            self.ptr_offset = offset + 1;
+            let version = match u16::from(bytes[0]) {
+                PmCap::ID => bytes[2] & 0x7,
+                PcieCap::ID => bytes[2] & 0xF,
+                _ => 0,
+            };
+            (bytes[0].into(), version)
+        };
+
+        Ok(Capability {
+            id,
+            version,
+            offset: offset as u16,
+            extended: self.extended,
+        })
+    }
+}
+
+impl Iterator for Capabilities<'_> {
+    type Item = Result<Capability, TlpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == 0 {
+            return None;
+        }
+
+        let cap = self.step();
+        if cap.is_err() {
+            self.next = 0;
+        }
+        Some(cap)
+    }
+}
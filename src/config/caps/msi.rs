@@ -0,0 +1,318 @@
+use crate::{config::caps::check_fits, DeviceID, Field, MWr, TlpError};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Interrupt message a function sends as a memory write
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MsiMessage {
+    /// Dword aligned address written
+    pub addr: u64,
+    /// Payload in memory order, with the message data in little endian
+    pub payload: [u8; 4],
+}
+
+impl MsiMessage {
+    pub fn new(addr: u64, data: u32) -> Self {
+        Self {
+            addr: addr & !0x3,
+            payload: data.to_le_bytes(),
+        }
+    }
+
+    /// Returns the message data
+    pub fn data(&self) -> u32 {
+        u32::from_le_bytes(self.payload)
+    }
+
+    /// Builds the memory write sent by `req_id`, using a 3DW header when the
+    /// address fits in 32 bits and a 4DW one otherwise
+    pub fn mwr(&self, req_id: DeviceID) -> Result<MWr<'_>, TlpError> {
+        MWr::new(req_id, 0, self.addr, &self.payload)
+    }
+}
+
+/// MSI capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MsiCap {
+    pub enable: bool,
+    /// Log2 of the number of vectors requested
+    pub multi_msg_capable: u8,
+    /// Log2 of the number of vectors allocated
+    pub multi_msg_enable: u8,
+    /// Message address register is 64 bits wide
+    pub addr64: bool,
+    pub per_vector_masking: bool,
+    /// Message address
+    pub addr: u64,
+    /// Message data
+    pub data: u16,
+    /// Mask bits, if per vector masking is supported
+    pub mask: Option<u32>,
+    /// Pending bits, if per vector masking is supported
+    pub pending: Option<u32>,
+}
+
+impl MsiCap {
+    pub const ID: u16 = 0x05;
+
+    /// Length of the capability in bytes
+    pub fn len(addr64: bool, per_vector_masking: bool) -> usize {
+        10 + 4 * (addr64 as usize) + 10 * (per_vector_masking as usize)
+    }
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, 4)?;
+        let ctl = LittleEndian::read_u16(&space[offset + 2..]);
+        let addr64 = ctl & (1 << 7) > 0;
+        let per_vector_masking = ctl & (1 << 8) > 0;
+        check_fits(space, offset, Self::len(addr64, per_vector_masking))?;
+
+        let bytes = &space[offset..];
+        let (addr, data_offset) = if addr64 {
+            (LittleEndian::read_u64(&bytes[4..]), 0xC)
+        } else {
+            (LittleEndian::read_u32(&bytes[4..]).into(), 0x8)
+        };
+        let (mask, pending) = if per_vector_masking {
+            (
+                Some(LittleEndian::read_u32(&bytes[data_offset + 4..])),
+                Some(LittleEndian::read_u32(&bytes[data_offset + 8..])),
+            )
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            enable: ctl & 0x1 > 0,
+            multi_msg_capable: ((ctl >> 1) & 0x7) as u8,
+            multi_msg_enable: ((ctl >> 4) & 0x7) as u8,
+            addr64,
+            per_vector_masking,
+            addr: addr & !0x3,
+            data: LittleEndian::read_u16(&bytes[data_offset..]),
+            mask,
+            pending,
+        })
+    }
+
+    /// Encodes the capability registers at `offset`, leaving the ID and next
+    /// pointer untouched, and returns the length of the capability
+    pub fn to_bytes(&self, space: &mut [u8], offset: usize) -> Result<usize, TlpError> {
+        let len = Self::len(self.addr64, self.per_vector_masking);
+        check_fits(space, offset, len)?;
+
+        let bytes = &mut space[offset..offset + len];
+        let ctl = (self.enable as u16)
+            | u16::from(self.multi_msg_capable & 0x7) << 1
+            | u16::from(self.multi_msg_enable & 0x7) << 4
+            | (self.addr64 as u16) << 7
+            | (self.per_vector_masking as u16) << 8;
+        LittleEndian::write_u16(&mut bytes[2..], ctl);
+        let data_offset = if self.addr64 {
+            LittleEndian::write_u64(&mut bytes[4..], self.addr & !0x3);
+            0xC
+        } else {
+            LittleEndian::write_u32(&mut bytes[4..], self.addr as u32 & !0x3);
+            0x8
+        };
+        LittleEndian::write_u16(&mut bytes[data_offset..], self.data);
+        if self.per_vector_masking {
+            bytes[data_offset + 2..data_offset + 4].fill(0);
+            LittleEndian::write_u32(&mut bytes[data_offset + 4..], self.mask.unwrap_or(0));
+            LittleEndian::write_u32(&mut bytes[data_offset + 8..], self.pending.unwrap_or(0));
+        }
+        Ok(len)
+    }
+
+    /// Number of vectors allocated to the function
+    pub fn vectors(&self) -> u8 {
+        1 << self.multi_msg_enable.min(5)
+    }
+
+    /// Returns true if `vector` is masked
+    pub fn is_masked(&self, vector: u8) -> bool {
+        self.mask.is_some_and(|m| m & (1 << (vector & 0x1F)) > 0)
+    }
+
+    /// Returns the message the function sends for `vector`, or None if MSI is
+    /// disabled or the vector is masked, in which case it is left pending
+    ///
+    /// The vector number replaces the low bits of the message data, as many
+    /// as there are allocated vectors.
+    pub fn message(&self, vector: u8) -> Result<Option<MsiMessage>, TlpError> {
+        let vectors = self.vectors();
+        if vector >= vectors {
+            return Err(TlpError::OutOfRange {
+                field: Field::Vector,
+                value: vector.into(),
+                min: 0,
+                max: (vectors - 1).into(),
+                offset: 2,
+            });
+        }
+        if !self.enable || self.is_masked(vector) {
+            return Ok(None);
+        }
+
+        let data = (self.data & !u16::from(vectors - 1)) | u16::from(vector);
+        let addr = if self.addr64 {
+            self.addr
+        } else {
+            self.addr & 0xFFFF_FFFF
+        };
+        Ok(Some(MsiMessage::new(addr, data.into())))
+    }
+}
+
+/// Entry of an MSI-X table
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MsixEntry {
+    /// Message address
+    pub addr: u64,
+    /// Message data
+    pub data: u32,
+    /// Vector is masked
+    pub masked: bool,
+}
+
+impl MsixEntry {
+    pub const LENGTH: usize = 16;
+
+    pub fn new(addr: u64, data: u32) -> Self {
+        Self {
+            addr,
+            data,
+            masked: false,
+        }
+    }
+
+    pub fn with_masked(mut self, masked: bool) -> Self {
+        self.masked = masked;
+        self
+    }
+
+    /// Decodes an entry from its little endian bytes
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Self {
+        Self {
+            addr: LittleEndian::read_u64(&bytes[0..]) & !0x3,
+            data: LittleEndian::read_u32(&bytes[8..]),
+            masked: bytes[12] & 0x1 > 0,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0; Self::LENGTH];
+        LittleEndian::write_u64(&mut bytes[0..], self.addr & !0x3);
+        LittleEndian::write_u32(&mut bytes[8..], self.data);
+        bytes[12] = self.masked as u8;
+        bytes
+    }
+}
+
+/// MSI-X capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MsixCap {
+    pub enable: bool,
+    pub function_mask: bool,
+    /// Number of entries in the vector table
+    pub table_size: u16,
+    /// BAR holding the vector table
+    pub table_bir: u8,
+    /// Offset of the vector table in its BAR
+    pub table_offset: u32,
+    /// BAR holding the pending bit array
+    pub pba_bir: u8,
+    /// Offset of the pending bit array in its BAR
+    pub pba_offset: u32,
+}
+
+impl MsixCap {
+    pub const ID: u16 = 0x11;
+
+    pub const LENGTH: usize = 12;
+
+    /// Largest number of table entries
+    pub const MAX_TABLE_SIZE: u16 = 2048;
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        let bytes = &space[offset..];
+        let ctl = LittleEndian::read_u16(&bytes[2..]);
+        let table = LittleEndian::read_u32(&bytes[4..]);
+        let pba = LittleEndian::read_u32(&bytes[8..]);
+
+        Ok(Self {
+            enable: ctl & (1 << 15) > 0,
+            function_mask: ctl & (1 << 14) > 0,
+            table_size: (ctl & 0x7FF) + 1,
+            table_bir: (table & 0x7) as u8,
+            table_offset: table & !0x7,
+            pba_bir: (pba & 0x7) as u8,
+            pba_offset: pba & !0x7,
+        })
+    }
+
+    /// Encodes the capability registers at `offset`, leaving the ID and next
+    /// pointer untouched, and returns the length of the capability
+    pub fn to_bytes(&self, space: &mut [u8], offset: usize) -> Result<usize, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        if self.table_size == 0 || self.table_size > Self::MAX_TABLE_SIZE {
+            return Err(TlpError::OutOfRange {
+                field: Field::Vector,
+                value: self.table_size.into(),
+                min: 1,
+                max: Self::MAX_TABLE_SIZE.into(),
+                offset: offset + 2,
+            });
+        }
+
+        let bytes = &mut space[offset..offset + Self::LENGTH];
+        let ctl =
+            (self.table_size - 1) | (self.function_mask as u16) << 14 | (self.enable as u16) << 15;
+        LittleEndian::write_u16(&mut bytes[2..], ctl);
+        LittleEndian::write_u32(
+            &mut bytes[4..],
+            (self.table_offset & !0x7) | u32::from(self.table_bir & 0x7),
+        );
+        LittleEndian::write_u32(
+            &mut bytes[8..],
+            (self.pba_offset & !0x7) | u32::from(self.pba_bir & 0x7),
+        );
+        Ok(Self::LENGTH)
+    }
+
+    /// Reads the table entry for `vector` from the memory of the table BAR
+    pub fn entry(&self, bar: &[u8], vector: u16) -> Result<MsixEntry, TlpError> {
+        if vector >= self.table_size {
+            return Err(TlpError::OutOfRange {
+                field: Field::Vector,
+                value: vector.into(),
+                min: 0,
+                max: self.table_size.saturating_sub(1).into(),
+                offset: 2,
+            });
+        }
+
+        let start = self.table_offset as usize + usize::from(vector) * MsixEntry::LENGTH;
+        let end = start + MsixEntry::LENGTH;
+        match bar.get(start..end) {
+            // SAFETY: Slice is always the length of an entry
+            Some(bytes) => Ok(MsixEntry::from_bytes(bytes.try_into().unwrap())),
+            None => Err(TlpError::TooShort {
+                expected: end,
+                actual: bar.len(),
+            }),
+        }
+    }
+
+    /// Returns the message the function sends for `vector`, or None if MSI-X
+    /// is disabled or the vector or function is masked, in which case it is
+    /// left pending
+    pub fn message(&self, bar: &[u8], vector: u16) -> Result<Option<MsiMessage>, TlpError> {
+        let entry = self.entry(bar, vector)?;
+        if !self.enable || self.function_mask || entry.masked {
+            Ok(None)
+        } else {
+            Ok(Some(MsiMessage::new(entry.addr, entry.data)))
+        }
+    }
+}
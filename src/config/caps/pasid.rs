@@ -0,0 +1,35 @@
+use crate::{config::caps::check_fits, TlpError};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Process address space ID capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PasidCap {
+    pub exe_supported: bool,
+    pub privileged_supported: bool,
+    /// Number of PASID bits supported
+    pub max_pasid_width: u8,
+    pub enable: bool,
+    pub exe_enable: bool,
+    pub privileged_enable: bool,
+}
+
+impl PasidCap {
+    pub const ID: u16 = 0x001B;
+
+    pub const LENGTH: usize = 8;
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        let cap = LittleEndian::read_u16(&space[offset + 4..]);
+        let ctl = LittleEndian::read_u16(&space[offset + 6..]);
+
+        Ok(Self {
+            exe_supported: cap & (1 << 1) > 0,
+            privileged_supported: cap & (1 << 2) > 0,
+            max_pasid_width: ((cap >> 8) & 0x1F) as u8,
+            enable: ctl & (1 << 0) > 0,
+            exe_enable: ctl & (1 << 1) > 0,
+            privileged_enable: ctl & (1 << 2) > 0,
+        })
+    }
+}
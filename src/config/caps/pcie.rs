@@ -0,0 +1,85 @@
+use crate::{config::caps::check_fits, TlpError};
+use byteorder::{ByteOrder, LittleEndian};
+use num_traits::FromPrimitive;
+
+lossless_enum! {
+    /// Device/port type of a PCI Express function
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    pub enum PortType {
+        #[default]
+        Endpoint = 0x0,
+        LegacyEndpoint = 0x1,
+        RootPort = 0x4,
+        UpstreamSwitch = 0x5,
+        DownstreamSwitch = 0x6,
+        PcieToPciBridge = 0x7,
+        PciToPcieBridge = 0x8,
+        RcIntegratedEndpoint = 0x9,
+        RcEventCollector = 0xA,
+    }
+    /// Reserved port type
+    Reserved(ReservedPortType, 0xF)
+}
+
+/// PCI Express capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PcieCap {
+    pub version: u8,
+    pub port_type: PortType,
+    pub slot_implemented: bool,
+    /// MSI/MSI-X vector used for capability interrupts
+    pub int_msg_num: u8,
+    /// Largest supported payload in bytes
+    pub max_payload_supported: u16,
+    pub ext_tag_supported: bool,
+    pub relaxed_ordering: bool,
+    pub ext_tag: bool,
+    pub no_snoop: bool,
+    /// Programmed max payload size in bytes
+    pub max_payload_size: u16,
+    /// Programmed max read request size in bytes
+    pub max_read_request_size: u16,
+    /// Encoded maximum link speed
+    pub max_link_speed: u8,
+    pub max_link_width: u8,
+    /// Encoded current link speed
+    pub link_speed: u8,
+    pub link_width: u8,
+}
+
+impl PcieCap {
+    pub const ID: u16 = 0x10;
+
+    /// Length of the registers common to all port types
+    pub const LENGTH: usize = 0x14;
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        let bytes = &space[offset..];
+        let cap = LittleEndian::read_u16(&bytes[0x2..]);
+        let dev_cap = LittleEndian::read_u32(&bytes[0x4..]);
+        let dev_ctl = LittleEndian::read_u16(&bytes[0x8..]);
+        let link_cap = LittleEndian::read_u32(&bytes[0xC..]);
+        let link_sta = LittleEndian::read_u16(&bytes[0x12..]);
+        let size = |x: u32| 128u16 << (x & 0x7).min(5);
+
+        Ok(Self {
+            version: (cap & 0xF) as u8,
+            // SAFETY: Every 4 bit value is a port type
+            port_type: PortType::from_u8(((cap >> 4) & 0xF) as u8).unwrap(),
+            slot_implemented: cap & (1 << 8) > 0,
+            int_msg_num: ((cap >> 9) & 0x1F) as u8,
+            max_payload_supported: size(dev_cap),
+            ext_tag_supported: dev_cap & (1 << 5) > 0,
+            relaxed_ordering: dev_ctl & (1 << 4) > 0,
+            ext_tag: dev_ctl & (1 << 8) > 0,
+            no_snoop: dev_ctl & (1 << 11) > 0,
+            max_payload_size: size((dev_ctl >> 5).into()),
+            max_read_request_size: size((dev_ctl >> 12).into()),
+            max_link_speed: (link_cap & 0xF) as u8,
+            max_link_width: ((link_cap >> 4) & 0x3F) as u8,
+            link_speed: (link_sta & 0xF) as u8,
+            link_width: ((link_sta >> 4) & 0x3F) as u8,
+        })
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{config::caps::check_fits, TlpError};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Power management capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PmCap {
+    pub version: u8,
+    /// PME clock required
+    pub pme_clock: bool,
+    /// Device specific initialization required
+    pub dsi: bool,
+    /// Encoded auxiliary current requirement
+    pub aux_current: u8,
+    pub d1_support: bool,
+    pub d2_support: bool,
+    /// One bit per power state, D0 to D3cold, that can assert PME
+    pub pme_support: u8,
+    /// Current power state, 0 to 3 for D0 to D3hot
+    pub power_state: u8,
+    pub no_soft_reset: bool,
+    pub pme_enable: bool,
+    pub pme_status: bool,
+}
+
+impl PmCap {
+    pub const ID: u16 = 0x01;
+
+    pub const LENGTH: usize = 8;
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        let pmc = LittleEndian::read_u16(&space[offset + 2..]);
+        let pmcsr = LittleEndian::read_u16(&space[offset + 4..]);
+
+        Ok(Self {
+            version: (pmc & 0x7) as u8,
+            pme_clock: pmc & (1 << 3) > 0,
+            dsi: pmc & (1 << 5) > 0,
+            aux_current: ((pmc >> 6) & 0x7) as u8,
+            d1_support: pmc & (1 << 9) > 0,
+            d2_support: pmc & (1 << 10) > 0,
+            pme_support: (pmc >> 11) as u8,
+            power_state: (pmcsr & 0x3) as u8,
+            no_soft_reset: pmcsr & (1 << 3) > 0,
+            pme_enable: pmcsr & (1 << 8) > 0,
+            pme_status: pmcsr & (1 << 15) > 0,
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{config::caps::check_fits, DeviceID, TlpError};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Single root I/O virtualization capability
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SriovCap {
+    pub vf_migration_capable: bool,
+    pub ari_capable_hierarchy: bool,
+    pub vf_enable: bool,
+    /// VF memory space enable
+    pub vf_mse: bool,
+    pub initial_vfs: u16,
+    pub total_vfs: u16,
+    pub num_vfs: u16,
+    pub function_dependency_link: u8,
+    /// Routing ID offset of the first VF from the PF
+    pub first_vf_offset: u16,
+    /// Routing ID distance between consecutive VFs
+    pub vf_stride: u16,
+    pub vf_device_id: u16,
+    pub supported_page_sizes: u32,
+    pub system_page_size: u32,
+    /// Raw VF base address registers
+    pub vf_bars: [u32; 6],
+}
+
+impl SriovCap {
+    pub const ID: u16 = 0x0010;
+
+    pub const LENGTH: usize = 0x40;
+
+    pub fn from_bytes(space: &[u8], offset: usize) -> Result<Self, TlpError> {
+        check_fits(space, offset, Self::LENGTH)?;
+        let bytes = &space[offset..];
+        let cap = LittleEndian::read_u32(&bytes[0x4..]);
+        let ctl = LittleEndian::read_u16(&bytes[0x8..]);
+        let mut vf_bars = [0; 6];
+        LittleEndian::read_u32_into(&bytes[0x24..0x3C], &mut vf_bars);
+
+        Ok(Self {
+            vf_migration_capable: cap & 0x1 > 0,
+            ari_capable_hierarchy: ctl & (1 << 4) > 0,
+            vf_enable: ctl & 0x1 > 0,
+            vf_mse: ctl & (1 << 3) > 0,
+            initial_vfs: LittleEndian::read_u16(&bytes[0xC..]),
+            total_vfs: LittleEndian::read_u16(&bytes[0xE..]),
+            num_vfs: LittleEndian::read_u16(&bytes[0x10..]),
+            function_dependency_link: bytes[0x12],
+            first_vf_offset: LittleEndian::read_u16(&bytes[0x14..]),
+            vf_stride: LittleEndian::read_u16(&bytes[0x16..]),
+            vf_device_id: LittleEndian::read_u16(&bytes[0x1A..]),
+            supported_page_sizes: LittleEndian::read_u32(&bytes[0x1C..]),
+            system_page_size: LittleEndian::read_u32(&bytes[0x20..]),
+            vf_bars,
+        })
+    }
+
+    /// Returns the ID of VF `index`, counting from 0, under the PF `pf`
+    pub fn vf_id(&self, pf: DeviceID, index: u16) -> Option<DeviceID> {
+        if index >= self.num_vfs {
+            return None;
+        }
+        let rid = u32::from(u16::from(pf))
+            + u32::from(self.first_vf_offset)
+            + u32::from(index) * u32::from(self.vf_stride);
+        u16::try_from(rid).ok().map(DeviceID::from)
+    }
+}
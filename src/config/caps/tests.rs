@@ -0,0 +1,349 @@
+use super::*;
+use crate::config::Type0Header;
+use proptest::prelude::*;
+
+/// Builds a 4 KiB config space with a PM, MSI and PCIe capability, followed by
+/// AER and PASID extended capabilities
+fn space() -> Vec<u8> {
+    let mut space = vec![0; EXT_CONFIG_SPACE_LEN];
+    Type0Header::new(0x10EE, 0x9038)
+        .with_cap_ptr(0x40)
+        .to_bytes(&mut space)
+        .unwrap();
+
+    space[0x40..0x44].clone_from_slice(&[0x01, 0x50, 0x03, 0x00]);
+    // 64-bit MSI with per vector masking
+    space[0x50..0x54].clone_from_slice(&[0x05, 0x70, 0x80, 0x01]);
+    space[0x54..0x5C].clone_from_slice(&0xFEE0_1000u64.to_le_bytes());
+    space[0x5C..0x5E].clone_from_slice(&0x4021u16.to_le_bytes());
+    // Root port, version 2
+    space[0x70..0x74].clone_from_slice(&[0x10, 0x00, 0x42, 0x00]);
+
+    space[0x100..0x104].clone_from_slice(&(0x1400_0000 | 0x2_0000 | 0x0001u32).to_le_bytes());
+    space[0x118..0x11C].clone_from_slice(&0x141u32.to_le_bytes());
+    space[0x140..0x144].clone_from_slice(&(0x1_0000 | 0x001Bu32).to_le_bytes());
+    space[0x144..0x146].clone_from_slice(&0x1406u16.to_le_bytes());
+    space
+}
+
+#[test]
+fn caps_walk_standard() {
+    let space = space();
+    let caps: Result<Vec<_>, _> = Capabilities::standard(&space).unwrap().collect();
+    let caps: Vec<_> = caps
+        .unwrap()
+        .iter()
+        .map(|c| (c.id, c.version, c.offset))
+        .collect();
+    assert_eq!(
+        vec![(0x01, 3, 0x40), (0x05, 0, 0x50), (0x10, 2, 0x70)],
+        caps
+    );
+}
+
+#[test]
+fn caps_walk_extended() {
+    let space = space();
+    let caps: Result<Vec<_>, _> = Capabilities::extended(&space).unwrap().collect();
+    let caps: Vec<_> = caps
+        .unwrap()
+        .iter()
+        .map(|c| (c.id, c.version, c.offset))
+        .collect();
+    assert_eq!(vec![(0x0001, 2, 0x100), (0x001B, 1, 0x140)], caps);
+
+    // No extended capabilities in a conventional config space
+    let caps = Capabilities::extended(&space[..CONFIG_SPACE_LEN]).unwrap();
+    assert_eq!(0, caps.count());
+}
+
+#[test]
+fn caps_decode() {
+    let space = space();
+    let caps = Capabilities::standard(&space).unwrap();
+    let msi = caps.find(MsiCap::ID).unwrap().unwrap();
+    let DecodedCap::Msi(msi) = msi.decode(&space).unwrap() else {
+        panic!("MSI decoded as another capability");
+    };
+    assert!(msi.addr64 && msi.per_vector_masking);
+    assert_eq!((0xFEE0_1000, 0x4021), (msi.addr, msi.data));
+    assert_eq!(Some(0), msi.mask);
+
+    let pcie = Capabilities::standard(&space)
+        .unwrap()
+        .find(PcieCap::ID)
+        .unwrap()
+        .unwrap();
+    let DecodedCap::Pcie(pcie) = pcie.decode(&space).unwrap() else {
+        panic!("PCIe decoded as another capability");
+    };
+    assert_eq!(PortType::RootPort, pcie.port_type);
+
+    let ext: Vec<_> = Capabilities::extended(&space)
+        .unwrap()
+        .map(|c| c.unwrap().decode(&space).unwrap())
+        .collect();
+    let DecodedCap::Aer(aer) = ext[0] else {
+        panic!("AER decoded as another capability");
+    };
+    assert_eq!(1, aer.first_error_pointer);
+    assert!(aer.ecrc_generation_enable && aer.ecrc_check_enable);
+    let DecodedCap::Pasid(pasid) = ext[1] else {
+        panic!("PASID decoded as another capability");
+    };
+    assert!(pasid.exe_supported && pasid.privileged_supported);
+    assert_eq!(20, pasid.max_pasid_width);
+}
+
+#[test]
+fn caps_loop() {
+    let mut space = space();
+    // PCIe capability points back at the PM capability
+    space[0x71] = 0x40;
+    let caps: Vec<_> = Capabilities::standard(&space).unwrap().collect();
+    assert_eq!(4, caps.len());
+    assert_eq!(
+        Err(TlpError::Loop {
+            field: Field::CapPointer,
+            value: 0x40,
+            offset: 0x71
+        }),
+        caps[3]
+    );
+}
+
+#[test]
+fn caps_out_of_range() {
+    let mut space = space();
+    space[0x51] = 0x20;
+    let caps: Vec<_> = Capabilities::standard(&space).unwrap().collect();
+    assert_eq!(
+        Err(TlpError::OutOfRange {
+            field: Field::CapPointer,
+            value: 0x20,
+            min: 0x40,
+            max: 0xFC,
+            offset: 0x51
+        }),
+        caps[2]
+    );
+
+    // An MSI capability running past the conventional config space
+    space[0x41] = 0xF8;
+    space[0xF8..0xFC].clone_from_slice(&[0x05, 0x00, 0x80, 0x01]);
+    let cap = Capabilities::standard(&space)
+        .unwrap()
+        .find(MsiCap::ID)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Some(Field::CapPointer),
+        cap.decode(&space).unwrap_err().field()
+    );
+}
+
+proptest! {
+    /// Tests that walking arbitrary config space always terminates
+    #[test]
+    fn caps_walk_terminates(bytes in proptest::collection::vec(any::<u8>(), EXT_CONFIG_SPACE_LEN)) {
+        let std = Capabilities::standard(&bytes).unwrap();
+        assert!(std.count() <= 48);
+        let ext = Capabilities::extended(&bytes).unwrap();
+        assert!(ext.count() <= 960);
+    }
+}
+
+#[test]
+fn aer_decode_prefix_log() {
+    let mut space = space();
+    // The prefix log takes the place of the PASID capability
+    space[0x140..0x148].fill(0);
+    // Prefix log present, first error is a malformed TLP
+    space[0x118..0x11C].clone_from_slice(&(0x800u32 | 18).to_le_bytes());
+    space[0x104..0x108].clone_from_slice(&UncorrectableErrors::MALFORMED_TLP.0.to_le_bytes());
+    space[0x10C..0x110].clone_from_slice(&UncorrectableErrors::MALFORMED_TLP.0.to_le_bytes());
+    space[0x11C..0x120].clone_from_slice(&0x2000_0001u32.to_le_bytes());
+    space[0x138..0x13C].clone_from_slice(&0x9100_002Au32.to_le_bytes());
+
+    let aer = AerCap::from_bytes(&space, 0x100).unwrap();
+    assert!(aer.has_uncorrectable() && aer.is_fatal());
+    assert_eq!(18, aer.first_error_pointer);
+    assert_eq!([0x20, 0x00, 0x00, 0x01], aer.header_log.to_bytes()[..4]);
+    assert_eq!(Some(HeaderLog([0x9100_002A, 0, 0, 0])), aer.tlp_prefix_log);
+}
+
+#[test]
+fn aer_error_mapping() {
+    let e = TlpError::TooShort {
+        expected: 12,
+        actual: 8,
+    };
+    assert_eq!(Some(UncorrectableErrors::MALFORMED_TLP), e.aer_error());
+
+    let e = TlpError::InvalidType {
+        field: Field::MessageCode,
+        value: 0x7F,
+        offset: 7,
+    };
+    assert_eq!(
+        Some(UncorrectableErrors::UNSUPPORTED_REQUEST),
+        e.aer_error()
+    );
+
+    let e = TlpError::ReservedBitsSet {
+        offset: 1,
+        mask: 0x80,
+    };
+    assert_eq!(None, e.aer_error());
+}
+
+#[test]
+fn aer_record() {
+    use crate::{RequestHeader, TlpHeader, TlpType};
+
+    let hdr = RequestHeader::new()
+        .with_hdr(TlpHeader::new().with_type(TlpType::MRd3))
+        .with_tag(0x12);
+    let mut aer = AerCap {
+        uncorrectable_mask: UncorrectableErrors::UNSUPPORTED_REQUEST,
+        ..Default::default()
+    };
+
+    // Masked errors are recorded but not signaled or logged
+    let sig = aer.record(UncorrectableErrors::UNSUPPORTED_REQUEST, hdr.into(), None);
+    assert!(sig.is_empty());
+    assert_eq!(HeaderLog::default(), aer.header_log);
+
+    let sig = aer.record(UncorrectableErrors::POISONED_TLP, hdr.into(), None);
+    assert_eq!(UncorrectableErrors::POISONED_TLP, sig);
+    assert_eq!(12, aer.first_error_pointer);
+    assert_eq!(HeaderLog::from(hdr), aer.header_log);
+    assert_eq!(hdr.to_bytes(), aer.header_log.to_bytes()[..8]);
+
+    // The first error stays logged
+    let e = TlpError::TooLong {
+        expected: 12,
+        actual: 16,
+    };
+    let sig = aer.record_error(&e, &[0xFF; 16]);
+    assert_eq!(UncorrectableErrors::MALFORMED_TLP, sig);
+    assert_eq!(12, aer.first_error_pointer);
+    assert_eq!(HeaderLog::from(hdr), aer.header_log);
+}
+
+#[test]
+fn msi_message_tlp() {
+    let req_id = crate::DeviceID::new(1, 0, 0).unwrap();
+    let msi = MsiCap {
+        enable: true,
+        multi_msg_capable: 3,
+        multi_msg_enable: 2,
+        addr: 0xFEE0_1000,
+        data: 0x4020,
+        ..Default::default()
+    };
+    let msg = msi.message(3).unwrap().unwrap();
+    assert_eq!(0x4023, msg.data());
+
+    let mut buf = [0; 16];
+    let len = msg.mwr(req_id).unwrap().to_bytes(&mut buf).unwrap();
+    assert_eq!(
+        [0x40, 0, 0, 1, 0x01, 0x00, 0, 0x0F, 0xFE, 0xE0, 0x10, 0x00, 0x23, 0x40, 0, 0],
+        buf[..len]
+    );
+
+    let e = msi.message(4).unwrap_err();
+    assert_eq!(Some(Field::Vector), e.field());
+}
+
+#[test]
+fn msi_message_addr64() {
+    let req_id = crate::DeviceID::default();
+    let mut space = space();
+    let msi = MsiCap {
+        enable: true,
+        addr64: true,
+        per_vector_masking: true,
+        addr: 0x1_2345_6780,
+        data: 0x1,
+        mask: Some(0x0),
+        pending: Some(0x0),
+        ..Default::default()
+    };
+    msi.to_bytes(&mut space, 0x50).unwrap();
+    let msi = MsiCap::from_bytes(&space, 0x50).unwrap();
+
+    let msg = msi.message(0).unwrap().unwrap();
+    let mwr = msg.mwr(req_id).unwrap();
+    assert_eq!(crate::TlpType::MWr4, mwr.hdr.hdr.tlp_type);
+    assert_eq!(crate::Address::Addr64(0x1_2345_6780), mwr.addr);
+    assert_eq!([1, 0, 0, 0], mwr.data);
+
+    // A 64-bit capable function with an address below 4 GiB uses a 3DW header
+    let low = MsiCap {
+        addr: 0xFEE0_0000,
+        ..msi
+    };
+    let msg = low.message(0).unwrap().unwrap();
+    assert_eq!(
+        crate::TlpType::MWr3,
+        msg.mwr(req_id).unwrap().hdr.hdr.tlp_type
+    );
+
+    let masked = MsiCap {
+        mask: Some(0x1),
+        ..msi
+    };
+    assert_eq!(Ok(None), masked.message(0));
+}
+
+#[test]
+fn msix_message_tlp() {
+    let msix = MsixCap {
+        enable: true,
+        table_size: 4,
+        table_offset: 0x2000,
+        pba_offset: 0x3000,
+        ..Default::default()
+    };
+    let mut space = space();
+    msix.to_bytes(&mut space, 0x50).unwrap();
+    assert_eq!(Ok(msix), MsixCap::from_bytes(&space, 0x50));
+
+    let mut bar = vec![0; 0x4000];
+    let entry = MsixEntry::new(0x8_0000_1000, 0xDEAD_BEEF);
+    bar[0x2020..0x2030].clone_from_slice(&entry.to_bytes());
+    bar[0x2030..0x2040].clone_from_slice(&entry.with_masked(true).to_bytes());
+
+    let msg = msix.message(&bar, 2).unwrap().unwrap();
+    let mut buf = [0; 20];
+    let len = msg
+        .mwr(crate::DeviceID::default())
+        .unwrap()
+        .to_bytes(&mut buf)
+        .unwrap();
+    assert_eq!(
+        [0x60, 0, 0, 1, 0, 0, 0, 0x0F, 0, 0, 0, 0x08, 0, 0, 0x10, 0x00, 0xEF, 0xBE, 0xAD, 0xDE],
+        buf[..len]
+    );
+
+    assert_eq!(Ok(None), msix.message(&bar, 3));
+    let masked = MsixCap {
+        function_mask: true,
+        ..msix
+    };
+    assert_eq!(Ok(None), masked.message(&bar, 2));
+    assert_eq!(
+        Some(Field::Vector),
+        msix.message(&bar, 4).unwrap_err().field()
+    );
+}
+
+proptest! {
+    /// Roundtrip testing of MSI-X table entry en/decoding
+    #[test]
+    fn msix_entry_roundtrip(addr in any::<u64>().prop_map(|a| a & !0x3), data: u32, masked: bool) {
+        let entry = MsixEntry::new(addr, data).with_masked(masked);
+        assert_eq!(entry, MsixEntry::from_bytes(entry.to_bytes()));
+    }
+}
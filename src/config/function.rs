@@ -0,0 +1,159 @@
+use crate::{
+    config::{
+        bar::BarKind, ConfigHeader, HeaderLayout, HeaderType, EXT_CONFIG_SPACE_LEN,
+        HEADER_TYPE_OFFSET,
+    },
+    Field, TlpError,
+};
+
+/// Dword-granular access to the config space of a function
+pub trait ConfigAccess {
+    /// Reads the dword at `offset`, which is dword aligned
+    fn read(&mut self, offset: u16) -> u32;
+
+    /// Writes the bytes of `value` selected by the byte enables `be` to the
+    /// dword at `offset`, which is dword aligned
+    fn write(&mut self, offset: u16, value: u32, be: u8);
+}
+
+/// Config space of a simulated function, where each bit is either read-only
+/// or read-write
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SimFunction {
+    space: [u8; EXT_CONFIG_SPACE_LEN],
+    /// Writable bits of each byte
+    wmask: [u8; EXT_CONFIG_SPACE_LEN],
+}
+
+impl SimFunction {
+    pub fn new(hdr: ConfigHeader) -> Self {
+        let mut sim = Self {
+            space: [0; EXT_CONFIG_SPACE_LEN],
+            wmask: [0; EXT_CONFIG_SPACE_LEN],
+        };
+        // SAFETY: The buffer is always a whole config space
+        hdr.to_bytes(&mut sim.space).unwrap();
+
+        // Command, cache line size, latency timer and interrupt line
+        sim.set_writable(0x04, &[0x47, 0x05]);
+        sim.set_writable(0x0C, &[0xFF, 0xFF]);
+        sim.set_writable(0x3C, &[0xFF]);
+
+        if let ConfigHeader::Type1(hdr) = hdr {
+            sim.set_writable(0x18, &[0xFF; 4]);
+            sim.set_writable(0x1C, &[0xF0, 0xF0]);
+            sim.set_writable(0x20, &[0xF0, 0xFF, 0xF0, 0xFF, 0xF0, 0xFF, 0xF0, 0xFF]);
+            if hdr.is_prefetch64() {
+                sim.set_writable(0x28, &[0xFF; 8]);
+            }
+            if hdr.is_io32() {
+                sim.set_writable(0x30, &[0xFF; 4]);
+            }
+            sim.set_writable(0x3E, &[0x7F]);
+        }
+        sim
+    }
+
+    /// Implements BAR `index` with a power of two `size`, taking two registers
+    /// for 64-bit memory
+    pub fn with_bar(
+        mut self,
+        index: usize,
+        kind: BarKind,
+        prefetchable: bool,
+        size: u64,
+    ) -> Result<Self, TlpError> {
+        let offset = 0x10 + 4 * index;
+        let count = match self.layout() {
+            HeaderLayout::Type1 => 2,
+            _ => 6,
+        };
+        let regs = if kind == BarKind::Memory64 { 2 } else { 1 };
+        if index + regs > count {
+            return Err(TlpError::OutOfRange {
+                field: Field::Bar,
+                value: index as u64,
+                min: 0,
+                max: (count - regs) as u64,
+                offset,
+            });
+        }
+
+        let (min, max, flags) = match kind {
+            BarKind::Io => (0x4, 0x100, 0x1),
+            BarKind::Memory32 => (0x10, 0x8000_0000, (prefetchable as u8) << 3),
+            BarKind::Memory64 => (0x10, 1 << 63, 0x4 | (prefetchable as u8) << 3),
+        };
+        if size < min || size > max {
+            return Err(TlpError::OutOfRange {
+                field: Field::Bar,
+                value: size,
+                min,
+                max,
+                offset,
+            });
+        } else if !size.is_power_of_two() {
+            return Err(TlpError::NotAligned {
+                field: Field::Bar,
+                value: size,
+                align: size.next_power_of_two(),
+                offset,
+            });
+        }
+
+        let flag_bits: u64 = if kind == BarKind::Io { 0x3 } else { 0xF };
+        let mask = !(size - 1) & !flag_bits;
+        self.space[offset..offset + 4 * regs].fill(0);
+        self.space[offset] = flags;
+        self.set_writable(offset, &mask.to_le_bytes()[..4 * regs]);
+        Ok(self)
+    }
+
+    /// Marks the bits of `mask` as writable, starting at byte `offset`
+    pub fn set_writable(&mut self, offset: usize, mask: &[u8]) {
+        self.wmask[offset..offset + mask.len()].clone_from_slice(mask);
+    }
+
+    /// Returns the whole config space
+    pub fn space(&self) -> &[u8] {
+        &self.space
+    }
+
+    /// Returns the whole config space, bypassing the write masks
+    pub fn space_mut(&mut self) -> &mut [u8] {
+        &mut self.space
+    }
+
+    /// Decodes the current header
+    pub fn header(&self) -> Result<ConfigHeader, TlpError> {
+        ConfigHeader::from_bytes(&self.space)
+    }
+
+    fn layout(&self) -> HeaderLayout {
+        HeaderType::from(self.space[HEADER_TYPE_OFFSET]).layout
+    }
+}
+
+impl ConfigAccess for SimFunction {
+    fn read(&mut self, offset: u16) -> u32 {
+        let offset = usize::from(offset & !0x3);
+        match self.space.get(offset..offset + 4) {
+            // SAFETY: Slice is always 4 bytes long
+            Some(bytes) => u32::from_le_bytes(bytes.try_into().unwrap()),
+            None => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u32, be: u8) {
+        let offset = usize::from(offset & !0x3);
+        if offset + 4 > self.space.len() {
+            return;
+        }
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            if be & (1 << i) > 0 {
+                let mask = self.wmask[offset + i];
+                self.space[offset + i] = (self.space[offset + i] & !mask) | (byte & mask);
+            }
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! Configuration space of a PCIe function
+//!
+//! Registers are little endian, unlike the big endian TLP headers.
+
+use crate::{Field, TlpError};
+use core::cmp::Ordering;
+use num_traits::FromPrimitive;
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+mod bar;
+pub mod caps;
+mod function;
+mod type0;
+mod type1;
+
+#[cfg(test)]
+mod tests;
+
+pub use bar::{size_bars, Bar, BarKind, Bars};
+pub use function::{ConfigAccess, SimFunction};
+pub use type0::Type0Header;
+pub use type1::{BridgeControl, Type1Header, Window};
+
+/// Size of the conventional PCI configuration space in bytes
+pub const CONFIG_SPACE_LEN: usize = 256;
+
+/// Size of the PCIe extended configuration space in bytes
+pub const EXT_CONFIG_SPACE_LEN: usize = 4096;
+
+/// Size of the Type 0 and Type 1 headers in bytes
+pub const HEADER_LEN: usize = 64;
+
+/// Offset of the header type register
+pub(crate) const HEADER_TYPE_OFFSET: usize = 0x0E;
+
+/// Checks that a buffer holds a whole conventional or extended config space
+pub(crate) fn check_space_len(bytes: &[u8]) -> Result<(), TlpError> {
+    let actual = bytes.len();
+    match actual {
+        CONFIG_SPACE_LEN | EXT_CONFIG_SPACE_LEN => Ok(()),
+        _ => match actual.cmp(&CONFIG_SPACE_LEN) {
+            Ordering::Less => Err(TlpError::TooShort {
+                expected: CONFIG_SPACE_LEN,
+                actual,
+            }),
+            _ if actual < EXT_CONFIG_SPACE_LEN => Err(TlpError::TooLong {
+                expected: CONFIG_SPACE_LEN,
+                actual,
+            }),
+            _ => Err(TlpError::TooLong {
+                expected: EXT_CONFIG_SPACE_LEN,
+                actual,
+            }),
+        },
+    }
+}
+
+/// Checks the header type register against the expected layout
+pub(crate) fn check_layout(bytes: &[u8], layout: HeaderLayout) -> Result<(), TlpError> {
+    let found = HeaderType::from(bytes[HEADER_TYPE_OFFSET]).layout;
+    if found == layout {
+        Ok(())
+    } else {
+        Err(TlpError::InvalidType {
+            field: Field::HeaderType,
+            value: u8::from(found).into(),
+            offset: HEADER_TYPE_OFFSET,
+        })
+    }
+}
+
+/// Command register
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct Command {
+    /// Responds to I/O space accesses
+    pub io: bool,
+    /// Responds to memory space accesses
+    pub memory: bool,
+    /// Allowed to issue requests
+    pub bus_master: bool,
+    pub special_cycles: bool,
+    /// Memory write and invalidate enable
+    pub mwi: bool,
+    pub vga_snoop: bool,
+    pub parity_error_response: bool,
+    pub idsel_stepping: bool,
+    /// SERR# enable
+    pub serr: bool,
+    /// Fast back-to-back enable
+    pub fast_b2b: bool,
+    /// INTx emulation disable
+    pub int_disable: bool,
+    /// Reserved bits 15:11, kept in place so decoding is lossless
+    #[cfg_attr(
+        test,
+        proptest(strategy = "proptest::sample::select(vec![0, 0x0800, 0x8000, 0xF800])")
+    )]
+    pub reserved: u16,
+}
+
+impl Command {
+    const RSVD_MASK: u16 = 0xF800;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_io(mut self, io: bool) -> Self {
+        self.io = io;
+        self
+    }
+
+    pub fn with_memory(mut self, memory: bool) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    pub fn with_bus_master(mut self, bus_master: bool) -> Self {
+        self.bus_master = bus_master;
+        self
+    }
+
+    pub fn with_int_disable(mut self, int_disable: bool) -> Self {
+        self.int_disable = int_disable;
+        self
+    }
+}
+
+impl From<u16> for Command {
+    fn from(x: u16) -> Self {
+        Self {
+            io: x & (1 << 0) > 0,
+            memory: x & (1 << 1) > 0,
+            bus_master: x & (1 << 2) > 0,
+            special_cycles: x & (1 << 3) > 0,
+            mwi: x & (1 << 4) > 0,
+            vga_snoop: x & (1 << 5) > 0,
+            parity_error_response: x & (1 << 6) > 0,
+            idsel_stepping: x & (1 << 7) > 0,
+            serr: x & (1 << 8) > 0,
+            fast_b2b: x & (1 << 9) > 0,
+            int_disable: x & (1 << 10) > 0,
+            reserved: x & Self::RSVD_MASK,
+        }
+    }
+}
+
+impl From<Command> for u16 {
+    fn from(cmd: Command) -> Self {
+        (cmd.io as u16)
+            | (cmd.memory as u16) << 1
+            | (cmd.bus_master as u16) << 2
+            | (cmd.special_cycles as u16) << 3
+            | (cmd.mwi as u16) << 4
+            | (cmd.vga_snoop as u16) << 5
+            | (cmd.parity_error_response as u16) << 6
+            | (cmd.idsel_stepping as u16) << 7
+            | (cmd.serr as u16) << 8
+            | (cmd.fast_b2b as u16) << 9
+            | (cmd.int_disable as u16) << 10
+            | (cmd.reserved & Command::RSVD_MASK)
+    }
+}
+
+/// Status register, also used for the secondary status of a bridge
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct Status {
+    pub immediate_readiness: bool,
+    /// INTx is asserted
+    pub int_status: bool,
+    /// Capabilities list is present
+    pub cap_list: bool,
+    /// 66 MHz capable
+    pub mhz66: bool,
+    /// Fast back-to-back capable
+    pub fast_b2b: bool,
+    pub master_data_parity_error: bool,
+    /// DEVSEL timing
+    #[cfg_attr(test, proptest(strategy = "0u8..4"))]
+    pub devsel_timing: u8,
+    pub signaled_target_abort: bool,
+    pub received_target_abort: bool,
+    pub received_master_abort: bool,
+    pub signaled_system_error: bool,
+    pub detected_parity_error: bool,
+    /// Reserved bits 6 and 2:1, kept in place so decoding is lossless
+    #[cfg_attr(
+        test,
+        proptest(strategy = "proptest::sample::select(vec![0, 0x2, 0x4, 0x40, 0x46])")
+    )]
+    pub reserved: u16,
+}
+
+impl Status {
+    const RSVD_MASK: u16 = 0x0046;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cap_list(mut self, cap_list: bool) -> Self {
+        self.cap_list = cap_list;
+        self
+    }
+}
+
+impl From<u16> for Status {
+    fn from(x: u16) -> Self {
+        Self {
+            immediate_readiness: x & (1 << 0) > 0,
+            int_status: x & (1 << 3) > 0,
+            cap_list: x & (1 << 4) > 0,
+            mhz66: x & (1 << 5) > 0,
+            fast_b2b: x & (1 << 7) > 0,
+            master_data_parity_error: x & (1 << 8) > 0,
+            devsel_timing: ((x >> 9) & 0x3) as u8,
+            signaled_target_abort: x & (1 << 11) > 0,
+            received_target_abort: x & (1 << 12) > 0,
+            received_master_abort: x & (1 << 13) > 0,
+            signaled_system_error: x & (1 << 14) > 0,
+            detected_parity_error: x & (1 << 15) > 0,
+            reserved: x & Self::RSVD_MASK,
+        }
+    }
+}
+
+impl From<Status> for u16 {
+    fn from(st: Status) -> Self {
+        (st.immediate_readiness as u16)
+            | (st.int_status as u16) << 3
+            | (st.cap_list as u16) << 4
+            | (st.mhz66 as u16) << 5
+            | (st.fast_b2b as u16) << 7
+            | (st.master_data_parity_error as u16) << 8
+            | u16::from(st.devsel_timing & 0x3) << 9
+            | (st.signaled_target_abort as u16) << 11
+            | (st.received_target_abort as u16) << 12
+            | (st.received_master_abort as u16) << 13
+            | (st.signaled_system_error as u16) << 14
+            | (st.detected_parity_error as u16) << 15
+            | (st.reserved & Status::RSVD_MASK)
+    }
+}
+
+/// Class code register, identifying the generic function of a device
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct ClassCode {
+    pub base: u8,
+    pub sub: u8,
+    /// Programming interface
+    pub prog_if: u8,
+}
+
+impl ClassCode {
+    /// Base class of PCI-to-PCI bridges
+    pub const BRIDGE: u8 = 0x06;
+
+    pub fn new(base: u8, sub: u8, prog_if: u8) -> Self {
+        Self { base, sub, prog_if }
+    }
+}
+
+impl From<[u8; 3]> for ClassCode {
+    /// Decodes the register from its little endian bytes
+    fn from(bytes: [u8; 3]) -> Self {
+        Self {
+            prog_if: bytes[0],
+            sub: bytes[1],
+            base: bytes[2],
+        }
+    }
+}
+
+impl From<ClassCode> for [u8; 3] {
+    fn from(cc: ClassCode) -> Self {
+        [cc.prog_if, cc.sub, cc.base]
+    }
+}
+
+lossless_enum! {
+    /// Layout of the config space header
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    pub enum HeaderLayout {
+        /// Endpoint
+        #[default]
+        Type0 = 0x00,
+        /// PCI-to-PCI bridge
+        Type1 = 0x01,
+        /// CardBus bridge
+        CardBus = 0x02,
+    }
+    /// Reserved layout
+    Reserved(ReservedLayout, 0x7F)
+}
+
+/// Header type register
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HeaderType {
+    pub layout: HeaderLayout,
+    /// Device implements more than one function
+    pub multi_function: bool,
+}
+
+impl From<u8> for HeaderType {
+    fn from(x: u8) -> Self {
+        Self {
+            // SAFETY: Every 7 bit value is a layout
+            layout: HeaderLayout::from_u8(x & 0x7F).unwrap(),
+            multi_function: x & 0x80 > 0,
+        }
+    }
+}
+
+impl From<HeaderType> for u8 {
+    fn from(ht: HeaderType) -> Self {
+        (u8::from(ht.layout) & 0x7F) | (ht.multi_function as u8) << 7
+    }
+}
+
+/// Built-in self test register
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct Bist {
+    /// Completion code, 0 on success
+    #[cfg_attr(test, proptest(strategy = "0u8..16"))]
+    pub code: u8,
+    /// Write 1 to start the self test, cleared when done
+    pub start: bool,
+    /// Function supports a self test
+    pub capable: bool,
+    /// Reserved bits 5:4, kept in place so decoding is lossless
+    #[cfg_attr(
+        test,
+        proptest(strategy = "proptest::sample::select(vec![0, 0x10, 0x20, 0x30])")
+    )]
+    pub reserved: u8,
+}
+
+impl Bist {
+    const RSVD_MASK: u8 = 0x30;
+}
+
+impl From<u8> for Bist {
+    fn from(x: u8) -> Self {
+        Self {
+            code: x & 0xF,
+            start: x & 0x40 > 0,
+            capable: x & 0x80 > 0,
+            reserved: x & Self::RSVD_MASK,
+        }
+    }
+}
+
+impl From<Bist> for u8 {
+    fn from(bist: Bist) -> Self {
+        (bist.code & 0xF)
+            | (bist.reserved & Bist::RSVD_MASK)
+            | (bist.start as u8) << 6
+            | (bist.capable as u8) << 7
+    }
+}
+
+/// Registers in the first 16 bytes, shared by every header layout
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct CommonHeader {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub command: Command,
+    pub status: Status,
+    pub revision_id: u8,
+    pub class_code: ClassCode,
+    pub cache_line_size: u8,
+    pub latency_timer: u8,
+    /// Device implements more than one function
+    pub multi_function: bool,
+    pub bist: Bist,
+}
+
+impl CommonHeader {
+    /// Vendor ID read back from a function that is not present
+    pub const NO_DEVICE: u16 = 0xFFFF;
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        use byteorder::{ByteOrder, LittleEndian};
+
+        Self {
+            vendor_id: LittleEndian::read_u16(&bytes[0x00..]),
+            device_id: LittleEndian::read_u16(&bytes[0x02..]),
+            command: LittleEndian::read_u16(&bytes[0x04..]).into(),
+            status: LittleEndian::read_u16(&bytes[0x06..]).into(),
+            revision_id: bytes[0x08],
+            // SAFETY: Slice is always 3 bytes long
+            class_code: <[u8; 3]>::try_from(&bytes[0x09..0x0C]).unwrap().into(),
+            cache_line_size: bytes[0x0C],
+            latency_timer: bytes[0x0D],
+            multi_function: HeaderType::from(bytes[HEADER_TYPE_OFFSET]).multi_function,
+            bist: bytes[0x0F].into(),
+        }
+    }
+
+    pub(crate) fn to_bytes(self, layout: HeaderLayout, buf: &mut [u8]) {
+        buf[0x00..0x02].clone_from_slice(&self.vendor_id.to_le_bytes());
+        buf[0x02..0x04].clone_from_slice(&self.device_id.to_le_bytes());
+        buf[0x04..0x06].clone_from_slice(&u16::from(self.command).to_le_bytes());
+        buf[0x06..0x08].clone_from_slice(&u16::from(self.status).to_le_bytes());
+        buf[0x08] = self.revision_id;
+        buf[0x09..0x0C].clone_from_slice(&<[u8; 3]>::from(self.class_code));
+        buf[0x0C] = self.cache_line_size;
+        buf[0x0D] = self.latency_timer;
+        buf[HEADER_TYPE_OFFSET] = HeaderType {
+            layout,
+            multi_function: self.multi_function,
+        }
+        .into();
+        buf[0x0F] = self.bist.into();
+    }
+}
+
+/// Header of a function's config space, in either layout
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConfigHeader {
+    Type0(Type0Header),
+    Type1(Type1Header),
+}
+
+impl ConfigHeader {
+    /// Decodes the header from a 256 byte or 4 KiB config space, picking the
+    /// layout from the header type register
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        check_space_len(bytes)?;
+        match HeaderType::from(bytes[HEADER_TYPE_OFFSET]).layout {
+            HeaderLayout::Type0 => Type0Header::from_bytes(bytes).map(Self::Type0),
+            HeaderLayout::Type1 => Type1Header::from_bytes(bytes).map(Self::Type1),
+            layout => Err(TlpError::InvalidType {
+                field: Field::HeaderType,
+                value: u8::from(layout).into(),
+                offset: HEADER_TYPE_OFFSET,
+            }),
+        }
+    }
+
+    /// Encodes the header into the first bytes of a config space
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        match self {
+            ConfigHeader::Type0(hdr) => hdr.to_bytes(buf),
+            ConfigHeader::Type1(hdr) => hdr.to_bytes(buf),
+        }
+    }
+
+    /// Returns the registers shared by both layouts
+    pub fn common(&self) -> &CommonHeader {
+        match self {
+            ConfigHeader::Type0(hdr) => &hdr.common,
+            ConfigHeader::Type1(hdr) => &hdr.common,
+        }
+    }
+
+    /// Returns the offset of the first capability, if any
+    pub fn cap_ptr(&self) -> Option<u8> {
+        let ptr = match self {
+            ConfigHeader::Type0(hdr) => hdr.cap_ptr,
+            ConfigHeader::Type1(hdr) => hdr.cap_ptr,
+        };
+        (self.common().status.cap_list && ptr != 0).then_some(ptr & !0x3)
+    }
+}
@@ -0,0 +1,279 @@
+use super::*;
+use crate::Field;
+use proptest::prelude::*;
+
+proptest! {
+    /// Roundtrip testing of Type 0 header en/decoding
+    #[test]
+    fn type0_serde_roundtrip(hdr: Type0Header) {
+        let mut buf = [0; CONFIG_SPACE_LEN];
+        assert_eq!(Ok(HEADER_LEN), hdr.to_bytes(&mut buf));
+        assert_eq!(Ok(hdr), Type0Header::from_bytes(&buf));
+        assert_eq!(Ok(ConfigHeader::Type0(hdr)), ConfigHeader::from_bytes(&buf));
+    }
+
+    /// Roundtrip testing of Type 1 header en/decoding
+    #[test]
+    fn type1_serde_roundtrip(hdr: Type1Header) {
+        let mut buf = vec![0; EXT_CONFIG_SPACE_LEN];
+        assert_eq!(Ok(HEADER_LEN), hdr.to_bytes(&mut buf));
+        assert_eq!(Ok(hdr), Type1Header::from_bytes(&buf));
+        assert_eq!(Ok(ConfigHeader::Type1(hdr)), ConfigHeader::from_bytes(&buf));
+    }
+
+    /// Tests that every register value decodes and re-encodes unchanged
+    #[test]
+    fn config_registers_lossless(x: u16, y: u8) {
+        assert_eq!(x, u16::from(Command::from(x)));
+        assert_eq!(x, u16::from(Status::from(x)));
+        assert_eq!(x, u16::from(BridgeControl::from(x)));
+        assert_eq!(y, u8::from(Bist::from(y)));
+        assert_eq!(y, u8::from(HeaderType::from(y)));
+    }
+
+    /// Tests that memory windows survive programming and readback
+    #[test]
+    fn type1_memory_window(base in 0u64..0x1000, len in 1u64..0x1000) {
+        prop_assume!(base + len <= 0x1000);
+        let w = Window::new(base << 20, ((base + len) << 20) - 1);
+        let hdr = Type1Header::default().with_memory_window(Some(w)).unwrap();
+        assert_eq!(Some(w), hdr.memory_window());
+    }
+}
+
+#[test]
+fn config_space_len() {
+    let hdr = Type0Header::new(0x10EE, 0x9038);
+    let mut buf = [0; 300];
+    assert_eq!(
+        Err(TlpError::TooLong {
+            expected: CONFIG_SPACE_LEN,
+            actual: 300
+        }),
+        hdr.to_bytes(&mut buf)
+    );
+    assert_eq!(
+        Err(TlpError::TooShort {
+            expected: CONFIG_SPACE_LEN,
+            actual: 64
+        }),
+        Type0Header::from_bytes(&buf[..64])
+    );
+}
+
+#[test]
+fn config_wrong_layout() {
+    let mut buf = [0; CONFIG_SPACE_LEN];
+    Type1Header::new(0x8086, 0x1234).to_bytes(&mut buf).unwrap();
+    assert_eq!(
+        Err(TlpError::InvalidType {
+            field: Field::HeaderType,
+            value: 1,
+            offset: 0x0E
+        }),
+        Type0Header::from_bytes(&buf)
+    );
+
+    buf[HEADER_TYPE_OFFSET] = 0x82;
+    let e = ConfigHeader::from_bytes(&buf).unwrap_err();
+    assert_eq!(Some(Field::HeaderType), e.field());
+}
+
+#[test]
+fn config_little_endian() {
+    let mut buf = [0; CONFIG_SPACE_LEN];
+    let hdr = Type0Header::new(0x10EE, 0x9038)
+        .with_bar(0, 0xFE00_000C)
+        .with_cap_ptr(0x40);
+    hdr.to_bytes(&mut buf).unwrap();
+    assert_eq!([0xEE, 0x10, 0x38, 0x90], buf[..4]);
+    assert_eq!([0x0C, 0x00, 0x00, 0xFE], buf[0x10..0x14]);
+    assert_eq!(0x10, buf[0x06]);
+
+    let hdr = ConfigHeader::from_bytes(&buf).unwrap();
+    assert_eq!(Some(0x40), hdr.cap_ptr());
+}
+
+#[test]
+fn type1_windows() {
+    let hdr = Type1Header::new(0x8086, 0x1234).with_buses(0, 1, 4);
+    assert!(hdr.forwards_bus(1) && hdr.forwards_bus(4));
+    assert!(!hdr.forwards_bus(5));
+
+    // All windows of a reset bridge open at address 0
+    let hdr = hdr
+        .with_io_window(None)
+        .and_then(|h| h.with_memory_window(None))
+        .and_then(|h| h.with_prefetch_window(None))
+        .unwrap();
+    assert_eq!(None, hdr.io_window());
+    assert_eq!(None, hdr.memory_window());
+    assert_eq!(None, hdr.prefetch_window());
+
+    let io = Window::new(0x2000, 0x3FFF);
+    let hdr = hdr.with_io_window(Some(io)).unwrap();
+    assert_eq!(Some(io), hdr.io_window());
+
+    let hdr = Type1Header {
+        prefetch_base: 0x1,
+        ..hdr
+    };
+    let pf = Window::new(0x40_0000_0000, 0x40_001F_FFFF);
+    let hdr = hdr.with_prefetch_window(Some(pf)).unwrap();
+    assert_eq!(Some(pf), hdr.prefetch_window());
+
+    let e = hdr.with_memory_window(Some(Window::new(0x8_0000, 0xF_FFFF)));
+    assert_eq!(
+        Err(TlpError::NotAligned {
+            field: Field::Address,
+            value: 0x8_0000,
+            align: 0x10_0000,
+            offset: 0x20
+        }),
+        e
+    );
+}
+
+#[test]
+fn window_size() {
+    assert_eq!(0x2000, Window::new(0x2000, 0x3FFF).size());
+    assert_eq!(u64::MAX, Window::new(0, u64::MAX).size());
+    // A base above the limit disables the window
+    let w = Window::new(0x20_0000, 0xF_FFFF);
+    assert_eq!(0, w.size());
+    assert!(!w.contains(0x20_0000));
+}
+
+proptest! {
+    /// Tests that sizing finds the size a simulated BAR was built with
+    #[test]
+    fn bar_sizing(io_shift in 2u32..=8, mem_shift in 4u32..=31, mem64_shift in 4u32..=63,
+            prefetchable: bool) {
+        let mut sim = SimFunction::new(ConfigHeader::Type0(Type0Header::new(0x10EE, 0x9038)))
+            .with_bar(0, BarKind::Io, false, 1 << io_shift).unwrap()
+            .with_bar(1, BarKind::Memory32, prefetchable, 1 << mem_shift).unwrap()
+            .with_bar(3, BarKind::Memory64, prefetchable, 1 << mem64_shift).unwrap();
+        let before = sim.space().to_vec();
+
+        let bars = size_bars(&mut sim).unwrap();
+        assert_eq!(before, sim.space());
+        let sizes: Vec<_> = bars.iter().map(|b| b.map(|b| (b.kind, b.size))).collect();
+        assert_eq!(vec![
+            Some((BarKind::Io, 1 << io_shift)),
+            Some((BarKind::Memory32, 1 << mem_shift)),
+            None,
+            Some((BarKind::Memory64, 1 << mem64_shift)),
+            None,
+            None,
+        ], sizes);
+    }
+}
+
+#[test]
+fn bar_assign_and_hit() {
+    let mut sim = SimFunction::new(ConfigHeader::Type0(Type0Header::new(0x10EE, 0x9038)))
+        .with_bar(0, BarKind::Memory64, true, 0x10_0000)
+        .unwrap()
+        .with_bar(2, BarKind::Memory32, false, 0x1000)
+        .unwrap();
+    let bars = size_bars(&mut sim).unwrap();
+    let bar0 = bars[0].unwrap();
+    assert!(bar0.prefetchable);
+
+    let [lo, hi] = bar0.encode(0x40_0010_0000);
+    sim.write(0x10, lo, 0xF);
+    sim.write(0x14, hi, 0xF);
+    sim.write(0x18, bars[2].unwrap().encode(0xF000_0000)[0], 0xF);
+
+    let hdr = match sim.header().unwrap() {
+        ConfigHeader::Type0(hdr) => hdr,
+        hdr => panic!("Unexpected header {:?}", hdr),
+    };
+    let decoded: Result<Vec<_>, _> = hdr.decode_bars().collect();
+    let decoded = decoded.unwrap();
+    // Unimplemented BARs read as 0 and only sizing tells them apart
+    assert_eq!(5, decoded.len());
+    assert_eq!(0x40_0010_0000, decoded[0].addr);
+    assert_eq!(0xF000_0000, decoded[1].addr);
+
+    let bar0 = Bar {
+        addr: decoded[0].addr,
+        ..bar0
+    };
+    let req = crate::MRd::new(crate::DeviceID::default(), 0, 0x40_0010_0100, 16).unwrap();
+    assert_eq!(Some(0x100), bar0.hit_mrd(&req));
+    let data = [0; 8];
+    let req = crate::MWr::new(crate::DeviceID::default(), 0, 0x40_001F_FFFC, &data).unwrap();
+    assert_eq!(None, bar0.hit_mwr(&req));
+    let req = crate::MWr::new(crate::DeviceID::default(), 0, 0x40_001F_FFF8, &data).unwrap();
+    assert_eq!(Some(0xF_FFF8), bar0.hit_mwr(&req));
+}
+
+#[test]
+fn bar_sizing_past_header() {
+    // A 64-bit BAR in the last register of a bridge has no upper half
+    let mut sim = SimFunction::new(ConfigHeader::Type1(Type1Header::new(0x8086, 0x1234)));
+    sim.space_mut()[0x14] = 0x04;
+    sim.set_writable(0x14, &[0xF0, 0xFF, 0xFF, 0xFF]);
+    let before = sim.space().to_vec();
+
+    let e = TlpError::OutOfRange {
+        field: Field::Bar,
+        value: 1,
+        min: 0,
+        max: 0,
+        offset: 0x14,
+    };
+    assert_eq!(Err(e), size_bars(&mut sim));
+    assert_eq!(before, sim.space());
+    assert_eq!(Err(e), Bar::probe(&mut sim, 1));
+
+    let e = Bar::probe(&mut sim, 2).unwrap_err();
+    assert_eq!(Some(Field::Bar), e.field());
+    assert_eq!(before, sim.space());
+}
+
+#[test]
+fn bar_assign_io() {
+    let mut sim = SimFunction::new(ConfigHeader::Type0(Type0Header::new(0x10EE, 0x9038)))
+        .with_bar(0, BarKind::Io, false, 0x4)
+        .unwrap()
+        .with_bar(1, BarKind::Io, false, 0x8)
+        .unwrap();
+    let bars = size_bars(&mut sim).unwrap();
+    let (bar0, bar1) = (bars[0].unwrap(), bars[1].unwrap());
+    assert_eq!([0x1005, 0], bar0.encode(0x1004));
+    assert_eq!([0x2009, 0], bar1.encode(0x200C));
+
+    sim.write(0x10, bar0.encode(0x1004)[0], 0xF);
+    sim.write(0x14, bar1.encode(0x2008)[0], 0xF);
+    let hdr = match sim.header().unwrap() {
+        ConfigHeader::Type0(hdr) => hdr,
+        hdr => panic!("Unexpected header {:?}", hdr),
+    };
+    let decoded: Vec<_> = hdr.decode_bars().map(|b| b.unwrap()).collect();
+    assert_eq!((BarKind::Io, 0x1004), (decoded[0].kind, decoded[0].addr));
+    assert_eq!((BarKind::Io, 0x2008), (decoded[1].kind, decoded[1].addr));
+}
+
+#[test]
+fn bar_decode_errors() {
+    assert_eq!(
+        Err(TlpError::InvalidType {
+            field: Field::Bar,
+            value: 0x2,
+            offset: 0x14
+        }),
+        Bar::decode(&[0, 0x2], 1)
+    );
+
+    // 64-bit BAR in the last register
+    let e = Bar::decode(&[0, 0x4], 1).unwrap_err();
+    assert_eq!(Some(Field::Bar), e.field());
+
+    let sim = SimFunction::new(ConfigHeader::Type1(Type1Header::new(0x8086, 0x1234)));
+    let e = sim
+        .with_bar(1, BarKind::Memory64, false, 0x1000)
+        .unwrap_err();
+    assert_eq!(Some(0x14), e.offset());
+}
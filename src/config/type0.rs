@@ -0,0 +1,104 @@
+use crate::{
+    config::{check_layout, check_space_len, Bars, CommonHeader, HeaderLayout, HEADER_LEN},
+    TlpError,
+};
+use byteorder::{ByteOrder, LittleEndian};
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+/// Type 0 config space header, used by endpoints
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct Type0Header {
+    pub common: CommonHeader,
+    /// Raw base address registers
+    pub bars: [u32; 6],
+    pub cardbus_cis: u32,
+    pub subsystem_vendor_id: u16,
+    pub subsystem_id: u16,
+    /// Expansion ROM base address register
+    pub expansion_rom: u32,
+    /// Offset of the first capability
+    pub cap_ptr: u8,
+    pub int_line: u8,
+    pub int_pin: u8,
+    pub min_gnt: u8,
+    pub max_lat: u8,
+}
+
+impl Type0Header {
+    /// Number of base address registers
+    pub const BAR_COUNT: usize = 6;
+
+    pub fn new(vendor_id: u16, device_id: u16) -> Self {
+        Self {
+            common: CommonHeader {
+                vendor_id,
+                device_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn with_bar(mut self, index: usize, bar: u32) -> Self {
+        self.bars[index] = bar;
+        self
+    }
+
+    /// Decodes the base address registers
+    pub fn decode_bars(&self) -> Bars<'_> {
+        Bars::new(&self.bars)
+    }
+
+    pub fn with_cap_ptr(mut self, cap_ptr: u8) -> Self {
+        self.cap_ptr = cap_ptr;
+        self.common.status.cap_list = cap_ptr != 0;
+        self
+    }
+
+    /// Decodes the header from a 256 byte or 4 KiB config space
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        check_space_len(bytes)?;
+        check_layout(bytes, HeaderLayout::Type0)?;
+
+        let mut bars = [0; Self::BAR_COUNT];
+        LittleEndian::read_u32_into(&bytes[0x10..0x28], &mut bars);
+
+        Ok(Self {
+            common: CommonHeader::from_bytes(bytes),
+            bars,
+            cardbus_cis: LittleEndian::read_u32(&bytes[0x28..]),
+            subsystem_vendor_id: LittleEndian::read_u16(&bytes[0x2C..]),
+            subsystem_id: LittleEndian::read_u16(&bytes[0x2E..]),
+            expansion_rom: LittleEndian::read_u32(&bytes[0x30..]),
+            cap_ptr: bytes[0x34],
+            int_line: bytes[0x3C],
+            int_pin: bytes[0x3D],
+            min_gnt: bytes[0x3E],
+            max_lat: bytes[0x3F],
+        })
+    }
+
+    /// Encodes the header into the first 64 bytes of a 256 byte or 4 KiB
+    /// config space, leaving the rest untouched
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        check_space_len(buf)?;
+
+        self.common.to_bytes(HeaderLayout::Type0, buf);
+        LittleEndian::write_u32_into(&self.bars, &mut buf[0x10..0x28]);
+        LittleEndian::write_u32(&mut buf[0x28..], self.cardbus_cis);
+        LittleEndian::write_u16(&mut buf[0x2C..], self.subsystem_vendor_id);
+        LittleEndian::write_u16(&mut buf[0x2E..], self.subsystem_id);
+        LittleEndian::write_u32(&mut buf[0x30..], self.expansion_rom);
+        buf[0x34] = self.cap_ptr;
+        buf[0x35..0x3C].fill(0);
+        buf[0x3C] = self.int_line;
+        buf[0x3D] = self.int_pin;
+        buf[0x3E] = self.min_gnt;
+        buf[0x3F] = self.max_lat;
+
+        Ok(HEADER_LEN)
+    }
+}
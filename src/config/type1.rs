@@ -0,0 +1,377 @@
+use crate::{
+    config::{check_layout, check_space_len, Bars, CommonHeader, HeaderLayout, Status, HEADER_LEN},
+    Field, TlpError,
+};
+use byteorder::{ByteOrder, LittleEndian};
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+/// Bridge control register
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct BridgeControl {
+    pub parity_error_response: bool,
+    /// SERR# enable
+    pub serr: bool,
+    pub isa: bool,
+    pub vga: bool,
+    /// VGA 16-bit decode
+    pub vga16: bool,
+    pub master_abort_mode: bool,
+    pub secondary_bus_reset: bool,
+    /// Fast back-to-back enable
+    pub fast_b2b: bool,
+    pub primary_discard_timeout: bool,
+    pub secondary_discard_timeout: bool,
+    pub discard_timer_status: bool,
+    pub discard_timer_serr: bool,
+    /// Reserved bits 15:12, kept in place so decoding is lossless
+    #[cfg_attr(
+        test,
+        proptest(strategy = "proptest::sample::select(vec![0, 0x1000, 0x8000, 0xF000])")
+    )]
+    pub reserved: u16,
+}
+
+impl BridgeControl {
+    const RSVD_MASK: u16 = 0xF000;
+}
+
+impl From<u16> for BridgeControl {
+    fn from(x: u16) -> Self {
+        Self {
+            parity_error_response: x & (1 << 0) > 0,
+            serr: x & (1 << 1) > 0,
+            isa: x & (1 << 2) > 0,
+            vga: x & (1 << 3) > 0,
+            vga16: x & (1 << 4) > 0,
+            master_abort_mode: x & (1 << 5) > 0,
+            secondary_bus_reset: x & (1 << 6) > 0,
+            fast_b2b: x & (1 << 7) > 0,
+            primary_discard_timeout: x & (1 << 8) > 0,
+            secondary_discard_timeout: x & (1 << 9) > 0,
+            discard_timer_status: x & (1 << 10) > 0,
+            discard_timer_serr: x & (1 << 11) > 0,
+            reserved: x & Self::RSVD_MASK,
+        }
+    }
+}
+
+impl From<BridgeControl> for u16 {
+    fn from(bc: BridgeControl) -> Self {
+        (bc.parity_error_response as u16)
+            | (bc.serr as u16) << 1
+            | (bc.isa as u16) << 2
+            | (bc.vga as u16) << 3
+            | (bc.vga16 as u16) << 4
+            | (bc.master_abort_mode as u16) << 5
+            | (bc.secondary_bus_reset as u16) << 6
+            | (bc.fast_b2b as u16) << 7
+            | (bc.primary_discard_timeout as u16) << 8
+            | (bc.secondary_discard_timeout as u16) << 9
+            | (bc.discard_timer_status as u16) << 10
+            | (bc.discard_timer_serr as u16) << 11
+            | (bc.reserved & BridgeControl::RSVD_MASK)
+    }
+}
+
+/// Address range forwarded by a bridge, with an inclusive limit
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Window {
+    pub base: u64,
+    pub limit: u64,
+}
+
+impl Window {
+    pub fn new(base: u64, limit: u64) -> Self {
+        Self { base, limit }
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.base <= addr && addr <= self.limit
+    }
+
+    /// Size of the window in bytes, capped at `u64::MAX`, or 0 when the base
+    /// lies above the limit and the window is disabled
+    pub fn size(&self) -> u64 {
+        match self.limit.checked_sub(self.base) {
+            Some(len) => len.saturating_add(1),
+            None => 0,
+        }
+    }
+
+    /// Checks the window against the register granularity and address width
+    fn check(&self, align: u64, max: u64, offset: usize) -> Result<(), TlpError> {
+        if self.base & (align - 1) != 0 {
+            Err(TlpError::NotAligned {
+                field: Field::Address,
+                value: self.base,
+                align,
+                offset,
+            })
+        } else if self.limit & (align - 1) != align - 1 {
+            Err(TlpError::NotAligned {
+                field: Field::Address,
+                value: self.limit + 1,
+                align,
+                offset: offset + 1,
+            })
+        } else if self.base > self.limit || self.limit > max {
+            Err(TlpError::OutOfRange {
+                field: Field::Address,
+                value: self.limit,
+                min: self.base,
+                max,
+                offset: offset + 1,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Type 1 config space header, used by bridges and switch ports
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct Type1Header {
+    pub common: CommonHeader,
+    /// Raw base address registers
+    pub bars: [u32; 2],
+    pub primary_bus: u8,
+    pub secondary_bus: u8,
+    /// Highest bus number below the bridge
+    pub subordinate_bus: u8,
+    pub secondary_latency_timer: u8,
+    /// Raw I/O base, bits 3:0 advertise 32-bit addressing
+    pub io_base: u8,
+    pub io_limit: u8,
+    pub secondary_status: Status,
+    /// Raw memory base, bits 15:4 hold address bits 31:20
+    pub memory_base: u16,
+    pub memory_limit: u16,
+    /// Raw prefetchable memory base, bits 3:0 advertise 64-bit addressing
+    pub prefetch_base: u16,
+    pub prefetch_limit: u16,
+    pub prefetch_base_upper: u32,
+    pub prefetch_limit_upper: u32,
+    pub io_base_upper: u16,
+    pub io_limit_upper: u16,
+    /// Offset of the first capability
+    pub cap_ptr: u8,
+    /// Expansion ROM base address register
+    pub expansion_rom: u32,
+    pub int_line: u8,
+    pub int_pin: u8,
+    pub bridge_control: BridgeControl,
+}
+
+impl Type1Header {
+    /// Number of base address registers
+    pub const BAR_COUNT: usize = 2;
+
+    const IO_ALIGN: u64 = 0x1000;
+    const MEM_ALIGN: u64 = 0x10_0000;
+
+    pub fn new(vendor_id: u16, device_id: u16) -> Self {
+        Self {
+            common: CommonHeader {
+                vendor_id,
+                device_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn with_bar(mut self, index: usize, bar: u32) -> Self {
+        self.bars[index] = bar;
+        self
+    }
+
+    /// Decodes the base address registers
+    pub fn decode_bars(&self) -> Bars<'_> {
+        Bars::new(&self.bars)
+    }
+
+    pub fn with_cap_ptr(mut self, cap_ptr: u8) -> Self {
+        self.cap_ptr = cap_ptr;
+        self.common.status.cap_list = cap_ptr != 0;
+        self
+    }
+
+    pub fn with_buses(mut self, primary: u8, secondary: u8, subordinate: u8) -> Self {
+        self.primary_bus = primary;
+        self.secondary_bus = secondary;
+        self.subordinate_bus = subordinate;
+        self
+    }
+
+    /// Returns true if `bus` sits below the bridge
+    pub fn forwards_bus(&self, bus: u8) -> bool {
+        self.secondary_bus <= bus && bus <= self.subordinate_bus
+    }
+
+    /// Returns true if the I/O window supports 32-bit addresses
+    pub fn is_io32(&self) -> bool {
+        self.io_base & 0xF == 0x1
+    }
+
+    /// Returns true if the prefetchable window supports 64-bit addresses
+    pub fn is_prefetch64(&self) -> bool {
+        self.prefetch_base & 0xF == 0x1
+    }
+
+    /// Returns the I/O window, or None if it is disabled
+    pub fn io_window(&self) -> Option<Window> {
+        let upper = |x: u16| {
+            if self.is_io32() {
+                u64::from(x) << 16
+            } else {
+                0
+            }
+        };
+        let base = u64::from(self.io_base & 0xF0) << 8 | upper(self.io_base_upper);
+        let limit = u64::from(self.io_limit & 0xF0) << 8 | 0xFFF | upper(self.io_limit_upper);
+        (base <= limit).then_some(Window { base, limit })
+    }
+
+    /// Returns the memory window, or None if it is disabled
+    pub fn memory_window(&self) -> Option<Window> {
+        let base = u64::from(self.memory_base & 0xFFF0) << 16;
+        let limit = u64::from(self.memory_limit & 0xFFF0) << 16 | 0xF_FFFF;
+        (base <= limit).then_some(Window { base, limit })
+    }
+
+    /// Returns the prefetchable memory window, or None if it is disabled
+    pub fn prefetch_window(&self) -> Option<Window> {
+        let upper = |x: u32| {
+            if self.is_prefetch64() {
+                u64::from(x) << 32
+            } else {
+                0
+            }
+        };
+        let base = u64::from(self.prefetch_base & 0xFFF0) << 16 | upper(self.prefetch_base_upper);
+        let limit = u64::from(self.prefetch_limit & 0xFFF0) << 16
+            | 0xF_FFFF
+            | upper(self.prefetch_limit_upper);
+        (base <= limit).then_some(Window { base, limit })
+    }
+
+    /// Programs the I/O window, disabling it if `window` is None
+    pub fn with_io_window(mut self, window: Option<Window>) -> Result<Self, TlpError> {
+        let w = window.unwrap_or(Window::new(Self::IO_ALIGN, 0xFFF));
+        if window.is_some() {
+            let max = if self.is_io32() { 0xFFFF_FFFF } else { 0xFFFF };
+            w.check(Self::IO_ALIGN, max, 0x1C)?;
+        }
+        let cap = self.io_base & 0xF;
+        self.io_base = ((w.base >> 8) & 0xF0) as u8 | cap;
+        self.io_limit = ((w.limit >> 8) & 0xF0) as u8 | cap;
+        if self.is_io32() {
+            self.io_base_upper = (w.base >> 16) as u16;
+            self.io_limit_upper = (w.limit >> 16) as u16;
+        }
+        Ok(self)
+    }
+
+    /// Programs the memory window, disabling it if `window` is None
+    pub fn with_memory_window(mut self, window: Option<Window>) -> Result<Self, TlpError> {
+        let w = window.unwrap_or(Window::new(Self::MEM_ALIGN, 0xF_FFFF));
+        if window.is_some() {
+            w.check(Self::MEM_ALIGN, 0xFFFF_FFFF, 0x20)?;
+        }
+        self.memory_base = ((w.base >> 16) & 0xFFF0) as u16;
+        self.memory_limit = ((w.limit >> 16) & 0xFFF0) as u16;
+        Ok(self)
+    }
+
+    /// Programs the prefetchable memory window, disabling it if `window` is
+    /// None
+    pub fn with_prefetch_window(mut self, window: Option<Window>) -> Result<Self, TlpError> {
+        let w = window.unwrap_or(Window::new(Self::MEM_ALIGN, 0xF_FFFF));
+        if window.is_some() {
+            let max = if self.is_prefetch64() {
+                u64::MAX
+            } else {
+                0xFFFF_FFFF
+            };
+            w.check(Self::MEM_ALIGN, max, 0x24)?;
+        }
+        let cap = self.prefetch_base & 0xF;
+        self.prefetch_base = ((w.base >> 16) & 0xFFF0) as u16 | cap;
+        self.prefetch_limit = ((w.limit >> 16) & 0xFFF0) as u16 | cap;
+        if self.is_prefetch64() {
+            self.prefetch_base_upper = (w.base >> 32) as u32;
+            self.prefetch_limit_upper = (w.limit >> 32) as u32;
+        }
+        Ok(self)
+    }
+
+    /// Decodes the header from a 256 byte or 4 KiB config space
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        check_space_len(bytes)?;
+        check_layout(bytes, HeaderLayout::Type1)?;
+
+        let mut bars = [0; Self::BAR_COUNT];
+        LittleEndian::read_u32_into(&bytes[0x10..0x18], &mut bars);
+
+        Ok(Self {
+            common: CommonHeader::from_bytes(bytes),
+            bars,
+            primary_bus: bytes[0x18],
+            secondary_bus: bytes[0x19],
+            subordinate_bus: bytes[0x1A],
+            secondary_latency_timer: bytes[0x1B],
+            io_base: bytes[0x1C],
+            io_limit: bytes[0x1D],
+            secondary_status: LittleEndian::read_u16(&bytes[0x1E..]).into(),
+            memory_base: LittleEndian::read_u16(&bytes[0x20..]),
+            memory_limit: LittleEndian::read_u16(&bytes[0x22..]),
+            prefetch_base: LittleEndian::read_u16(&bytes[0x24..]),
+            prefetch_limit: LittleEndian::read_u16(&bytes[0x26..]),
+            prefetch_base_upper: LittleEndian::read_u32(&bytes[0x28..]),
+            prefetch_limit_upper: LittleEndian::read_u32(&bytes[0x2C..]),
+            io_base_upper: LittleEndian::read_u16(&bytes[0x30..]),
+            io_limit_upper: LittleEndian::read_u16(&bytes[0x32..]),
+            cap_ptr: bytes[0x34],
+            expansion_rom: LittleEndian::read_u32(&bytes[0x38..]),
+            int_line: bytes[0x3C],
+            int_pin: bytes[0x3D],
+            bridge_control: LittleEndian::read_u16(&bytes[0x3E..]).into(),
+        })
+    }
+
+    /// Encodes the header into the first 64 bytes of a 256 byte or 4 KiB
+    /// config space, leaving the rest untouched
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        check_space_len(buf)?;
+
+        self.common.to_bytes(HeaderLayout::Type1, buf);
+        LittleEndian::write_u32_into(&self.bars, &mut buf[0x10..0x18]);
+        buf[0x18] = self.primary_bus;
+        buf[0x19] = self.secondary_bus;
+        buf[0x1A] = self.subordinate_bus;
+        buf[0x1B] = self.secondary_latency_timer;
+        buf[0x1C] = self.io_base;
+        buf[0x1D] = self.io_limit;
+        LittleEndian::write_u16(&mut buf[0x1E..], self.secondary_status.into());
+        LittleEndian::write_u16(&mut buf[0x20..], self.memory_base);
+        LittleEndian::write_u16(&mut buf[0x22..], self.memory_limit);
+        LittleEndian::write_u16(&mut buf[0x24..], self.prefetch_base);
+        LittleEndian::write_u16(&mut buf[0x26..], self.prefetch_limit);
+        LittleEndian::write_u32(&mut buf[0x28..], self.prefetch_base_upper);
+        LittleEndian::write_u32(&mut buf[0x2C..], self.prefetch_limit_upper);
+        LittleEndian::write_u16(&mut buf[0x30..], self.io_base_upper);
+        LittleEndian::write_u16(&mut buf[0x32..], self.io_limit_upper);
+        buf[0x34] = self.cap_ptr;
+        buf[0x35..0x38].fill(0);
+        LittleEndian::write_u32(&mut buf[0x38..], self.expansion_rom);
+        buf[0x3C] = self.int_line;
+        buf[0x3D] = self.int_pin;
+        LittleEndian::write_u16(&mut buf[0x3E..], self.bridge_control.into());
+
+        Ok(HEADER_LEN)
+    }
+}
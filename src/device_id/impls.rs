@@ -18,14 +18,33 @@ impl From<DeviceID> for u16 {
 impl fmt::Display for DeviceIDError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            DeviceIDError::IncorrectStrLen => f.write_str("inccorect string length"),
-            DeviceIDError::InvalidFormat => f.write_str("invalid string format"),
-            DeviceIDError::OutOfRange => f.write_str("out of bounds"),
+            DeviceIDError::IncorrectStrLen { expected, actual } => f.write_fmt(format_args!(
+                "incorrect string length, expected {} characters but got {}",
+                expected, actual
+            )),
+            DeviceIDError::InvalidFormat { offset } => f.write_fmt(format_args!(
+                "invalid string format, missing separator at character {}",
+                offset
+            )),
+            DeviceIDError::OutOfRange { field, value, max } => f.write_fmt(format_args!(
+                "{} {:#X} is outside of 0x0..={:#X}",
+                field, value, max
+            )),
             DeviceIDError::IntError(ie) => f.write_fmt(format_args!("{}", ie)),
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for DeviceIDError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeviceIDError::IntError(ie) => Some(ie),
+            _ => None,
+        }
+    }
+}
+
 impl From<ParseIntError> for DeviceIDError {
     fn from(e: ParseIntError) -> Self {
         Self::IntError(e)
@@ -36,14 +55,21 @@ impl FromStr for DeviceID {
     type Err = DeviceIDError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.chars().count() != 7 {
-            return Err(DeviceIDError::IncorrectStrLen);
+        let actual = s.chars().count();
+        if actual != 7 {
+            return Err(DeviceIDError::IncorrectStrLen {
+                expected: 7,
+                actual,
+            });
         }
 
         let mut chs = s.chars();
 
-        if chs.nth(2) != Some(':') || chs.nth(2) != Some('.') {
-            return Err(DeviceIDError::InvalidFormat);
+        if chs.nth(2) != Some(':') {
+            return Err(DeviceIDError::InvalidFormat { offset: 2 });
+        }
+        if chs.nth(2) != Some('.') {
+            return Err(DeviceIDError::InvalidFormat { offset: 5 });
         }
 
         let bus = u8::from_str_radix(&s[0..=1], 16)?;
@@ -61,3 +87,61 @@ impl fmt::Display for DeviceID {
         ))
     }
 }
+
+/// Serialized as its `BB:DD.F` string in human-readable formats and as its
+/// `u16` value otherwise
+#[cfg(feature = "serde")]
+impl serde::Serialize for DeviceID {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_u16((*self).into());
+        }
+
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+        let buf = [
+            HEX[usize::from(self.bus >> 4)],
+            HEX[usize::from(self.bus & 0xF)],
+            b':',
+            HEX[usize::from(self.device >> 4 & 0xF)],
+            HEX[usize::from(self.device & 0xF)],
+            b'.',
+            HEX[usize::from(self.function & 0xF)],
+        ];
+        // SAFETY: Every byte is an ASCII character
+        serializer.serialize_str(core::str::from_utf8(&buf).unwrap())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DeviceID {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = DeviceID;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a device ID in BB:DD.F form")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(Visitor)
+        } else {
+            u16::deserialize(deserializer).map(Self::from)
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 mod impls;
 
-use crate::TlpError;
+use crate::{Field, TlpError};
 use byteorder::{BigEndian, ByteOrder};
 use core::num::ParseIntError; // Using core instead of std for no_std support
 
@@ -42,8 +42,18 @@ impl DeviceID {
     /// assert!(bad_did.is_err());
     /// ```
     pub fn new(bus: u8, device: u8, function: u8) -> Result<Self, DeviceIDError> {
-        if device > 31 || function > 7 {
-            Err(DeviceIDError::OutOfRange)
+        if device > 31 {
+            Err(DeviceIDError::OutOfRange {
+                field: Field::Device,
+                value: device,
+                max: 31,
+            })
+        } else if function > 7 {
+            Err(DeviceIDError::OutOfRange {
+                field: Field::Function,
+                value: function,
+                max: 7,
+            })
         } else {
             Ok(Self {
                 bus,
@@ -108,9 +118,10 @@ impl TryFrom<&[u8]> for DeviceID {
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         use core::cmp::Ordering;
 
-        match value.len().cmp(&2) {
-            Ordering::Less => Err(TlpError::TooShort),
-            Ordering::Greater => Err(TlpError::TooLong),
+        let (expected, actual) = (2, value.len());
+        match actual.cmp(&expected) {
+            Ordering::Less => Err(TlpError::TooShort { expected, actual }),
+            Ordering::Greater => Err(TlpError::TooLong { expected, actual }),
             Ordering::Equal => {
                 let did = BigEndian::read_u16(&value[0..2]);
                 Ok(did.into())
@@ -119,11 +130,16 @@ impl TryFrom<&[u8]> for DeviceID {
     }
 }
 
+/// Errors raised while building or parsing a DeviceID
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum DeviceIDError {
-    IncorrectStrLen,
-    InvalidFormat,
-    OutOfRange,
+    /// String is not the `BB:DD.F` length
+    IncorrectStrLen { expected: usize, actual: usize },
+    /// Separator missing at the given character offset
+    InvalidFormat { offset: usize },
+    /// A field lies outside of `0..=max`
+    OutOfRange { field: Field, value: u8, max: u8 },
+    /// A field is not valid hexadecimal
     IntError(ParseIntError),
 }
 
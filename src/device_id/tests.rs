@@ -55,7 +55,12 @@ proptest! {
         let text = format!("{:02X}:{:02X}.{:01X}", bus, device, function);
         let did = DeviceID::from_str(&text);
         assert!(did.is_err());
-        assert_eq!(did.unwrap_err(), DeviceIDError::OutOfRange);
+        let expect = if device > 31 {
+            DeviceIDError::OutOfRange { field: Field::Device, value: device, max: 31 }
+        } else {
+            DeviceIDError::OutOfRange { field: Field::Function, value: function, max: 7 }
+        };
+        assert_eq!(did.unwrap_err(), expect);
     }
 
     /// Tests that a string that is incorrectly sized is rejected
@@ -63,7 +68,8 @@ proptest! {
     fn str_serde_wrong_len_too_short(text in ".{0,6}") {
         let did = DeviceID::from_str(&text);
         assert!(did.is_err());
-        assert_eq!(did.unwrap_err(), DeviceIDError::IncorrectStrLen);
+        let expect = DeviceIDError::IncorrectStrLen { expected: 7, actual: text.chars().count() };
+        assert_eq!(did.unwrap_err(), expect);
     }
 
     /// Tests that a string that is incorrectly sized is rejected
@@ -71,7 +77,8 @@ proptest! {
     fn str_serde_wrong_len_too_long(text in ".{8}.*") {
         let did = DeviceID::from_str(&text);
         assert!(did.is_err());
-        assert_eq!(did.unwrap_err(), DeviceIDError::IncorrectStrLen);
+        let expect = DeviceIDError::IncorrectStrLen { expected: 7, actual: text.chars().count() };
+        assert_eq!(did.unwrap_err(), expect);
     }
 }
 
@@ -80,7 +87,10 @@ proptest! {
 fn str_serde_wrong_format() {
     let did = DeviceID::from_str("foobar!");
     assert!(did.is_err());
-    assert_eq!(did.unwrap_err(), DeviceIDError::InvalidFormat);
+    assert_eq!(did.unwrap_err(), DeviceIDError::InvalidFormat { offset: 2 });
+
+    let did = DeviceID::from_str("00:00:0");
+    assert_eq!(did.unwrap_err(), DeviceIDError::InvalidFormat { offset: 5 });
 }
 
 /// Tests that a string of the right length and almost the proper format is rejected
@@ -95,3 +105,25 @@ fn str_serde_bad_hex() {
         panic!("Somehow some other error occurred")
     }
 }
+
+#[cfg(feature = "serde")]
+proptest! {
+    /// Tests that a DeviceID serializes as its string form in human-readable
+    /// formats and as its u16 value otherwise
+    #[test]
+    fn serde_roundtrip(did: DeviceID) {
+        use serde_test::{assert_tokens, Configure, Token};
+
+        let json = serde_json::to_string(&did).unwrap();
+        assert_eq!(format!("\"{}\"", did), json);
+        assert_eq!(did, serde_json::from_str(&json).unwrap());
+        assert_tokens(&did.compact(), &[Token::U16(did.into())]);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_bad_str() {
+    assert!(serde_json::from_str::<DeviceID>("\"00:20.0\"").is_err());
+    assert!(serde_json::from_str::<DeviceID>("256").is_err());
+}
@@ -0,0 +1,320 @@
+//! Error types shared by the header and packet en/decoders
+
+// Using core instead of std for no_std support
+use core::fmt;
+
+/// Field of a TLP that an error refers to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    /// Format and type
+    FmtType,
+    /// Payload length
+    Length,
+    /// First data word byte enables
+    FirstBe,
+    /// Last data word byte enables
+    LastBe,
+    /// Completion byte count
+    ByteCount,
+    /// Completion status
+    Status,
+    /// Lower address of a completion
+    LowerAddress,
+    /// Request address
+    Address,
+    /// Address type
+    AddressType,
+    /// Process address space ID
+    Pasid,
+    /// Message code
+    MessageCode,
+    /// ATS invalidation tag
+    ITag,
+    /// ATS invalidation completion count
+    CompletionCount,
+    /// Page request group index
+    PrgIndex,
+    /// Page request group response code
+    ResponseCode,
+    /// Config space header type
+    HeaderType,
+    /// Pointer to the next entry of a capability list
+    CapPointer,
+    /// Base address register
+    Bar,
+    /// MSI or MSI-X vector
+    Vector,
+    /// Config space register of a config request
+    Register,
+    /// PCIe bus of a device ID
+    Bus,
+    /// PCIe device of a device ID
+    Device,
+    /// PCIe function of a device ID
+    Function,
+    /// Request type of a hard IP descriptor
+    RequestType,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Field::FmtType => "format and type",
+            Field::Length => "length",
+            Field::FirstBe => "first byte enable",
+            Field::LastBe => "last byte enable",
+            Field::ByteCount => "byte count",
+            Field::Status => "completion status",
+            Field::LowerAddress => "lower address",
+            Field::Address => "address",
+            Field::AddressType => "address type",
+            Field::Pasid => "PASID",
+            Field::MessageCode => "message code",
+            Field::ITag => "invalidation tag",
+            Field::CompletionCount => "completion count",
+            Field::PrgIndex => "page request group index",
+            Field::ResponseCode => "response code",
+            Field::HeaderType => "header type",
+            Field::CapPointer => "capability pointer",
+            Field::Bar => "base address register",
+            Field::Vector => "interrupt vector",
+            Field::Register => "register",
+            Field::Bus => "bus",
+            Field::Device => "device",
+            Field::Function => "function",
+            Field::RequestType => "request type",
+        })
+    }
+}
+
+/// Errors raised while building, encoding or decoding TLPs
+///
+/// Offsets are in bytes from the start of the buffer being decoded, or from
+/// the start of the encoded header for values rejected by a builder.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TlpError {
+    /// A field holds an encoding that is not supported
+    InvalidType {
+        field: Field,
+        value: u32,
+        offset: usize,
+    },
+    /// A value does not sit on the required boundary
+    NotAligned {
+        field: Field,
+        value: u64,
+        align: u64,
+        offset: usize,
+    },
+    /// A value lies outside of the range the field can hold
+    OutOfRange {
+        field: Field,
+        value: u64,
+        min: u64,
+        max: u64,
+        offset: usize,
+    },
+    /// A buffer is longer than expected
+    TooLong { expected: usize, actual: usize },
+    /// A buffer is shorter than expected
+    TooShort { expected: usize, actual: usize },
+    /// Reserved bits are set and strict decoding was requested
+    ReservedBitsSet { offset: usize, mask: u8 },
+    /// A pointer leads back to an entry that was already visited
+    Loop {
+        field: Field,
+        value: u64,
+        offset: usize,
+    },
+}
+
+impl TlpError {
+    /// Returns the field the error refers to, if any
+    pub fn field(&self) -> Option<Field> {
+        match *self {
+            TlpError::InvalidType { field, .. }
+            | TlpError::NotAligned { field, .. }
+            | TlpError::OutOfRange { field, .. }
+            | TlpError::Loop { field, .. } => Some(field),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte offset the error refers to, if any
+    pub fn offset(&self) -> Option<usize> {
+        match *self {
+            TlpError::InvalidType { offset, .. }
+            | TlpError::NotAligned { offset, .. }
+            | TlpError::OutOfRange { offset, .. }
+            | TlpError::ReservedBitsSet { offset, .. }
+            | TlpError::Loop { offset, .. } => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// Returns the AER uncorrectable error a receiver logs for this error, if
+    /// any
+    pub fn aer_error(&self) -> Option<crate::config::caps::UncorrectableErrors> {
+        crate::config::caps::UncorrectableErrors::from_error(self)
+    }
+
+    /// Shifts the offset of the error by `base` bytes
+    ///
+    /// Used when a header is decoded from the middle of a larger buffer.
+    pub(crate) fn at(self, base: usize) -> Self {
+        match self {
+            TlpError::InvalidType {
+                field,
+                value,
+                offset,
+            } => TlpError::InvalidType {
+                field,
+                value,
+                offset: offset + base,
+            },
+            TlpError::NotAligned {
+                field,
+                value,
+                align,
+                offset,
+            } => TlpError::NotAligned {
+                field,
+                value,
+                align,
+                offset: offset + base,
+            },
+            TlpError::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+                offset,
+            } => TlpError::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+                offset: offset + base,
+            },
+            TlpError::ReservedBitsSet { offset, mask } => TlpError::ReservedBitsSet {
+                offset: offset + base,
+                mask,
+            },
+            TlpError::Loop {
+                field,
+                value,
+                offset,
+            } => TlpError::Loop {
+                field,
+                value,
+                offset: offset + base,
+            },
+            TlpError::TooLong { expected, actual } => TlpError::TooLong {
+                expected: expected + base,
+                actual: actual + base,
+            },
+            TlpError::TooShort { expected, actual } => TlpError::TooShort {
+                expected: expected + base,
+                actual: actual + base,
+            },
+        }
+    }
+}
+
+impl fmt::Display for TlpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlpError::InvalidType {
+                field,
+                value,
+                offset,
+            } => write!(f, "invalid {} {:#X} at byte {}", field, value, offset),
+            TlpError::NotAligned {
+                field,
+                value,
+                align,
+                offset,
+            } => write!(
+                f,
+                "{} {:#X} at byte {} is not aligned to {} bytes",
+                field, value, offset, align
+            ),
+            TlpError::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+                offset,
+            } => write!(
+                f,
+                "{} {:#X} at byte {} is outside of {:#X}..={:#X}",
+                field, value, offset, min, max
+            ),
+            TlpError::TooLong { expected, actual } => write!(
+                f,
+                "buffer too long, expected {} bytes but got {}",
+                expected, actual
+            ),
+            TlpError::TooShort { expected, actual } => write!(
+                f,
+                "buffer too short, expected {} bytes but got {}",
+                expected, actual
+            ),
+            TlpError::ReservedBitsSet { offset, mask } => {
+                write!(f, "reserved bits {:#04X} set at byte {}", mask, offset)
+            }
+            TlpError::Loop {
+                field,
+                value,
+                offset,
+            } => write!(
+                f,
+                "{} {:#X} at byte {} loops back to a visited entry",
+                field, value, offset
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TlpError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tlp_error_display() {
+        let e = TlpError::OutOfRange {
+            field: Field::FirstBe,
+            value: 0x10,
+            min: 0,
+            max: 0xF,
+            offset: 7,
+        };
+        assert_eq!(
+            "first byte enable 0x10 at byte 7 is outside of 0x0..=0xF",
+            e.to_string()
+        );
+
+        let e = TlpError::TooShort {
+            expected: 8,
+            actual: 7,
+        };
+        assert_eq!(
+            "buffer too short, expected 8 bytes but got 7",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn tlp_error_accessors() {
+        let e = TlpError::ReservedBitsSet {
+            offset: 1,
+            mask: 0x80,
+        };
+        assert_eq!(Some(1), e.offset());
+        assert_eq!(None, e.field());
+        assert_eq!(Some(5), e.at(4).offset());
+    }
+}
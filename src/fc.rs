@@ -0,0 +1,512 @@
+//! Flow control credit accounting
+//!
+//! Every TLP uses one header credit of its class, plus one data credit per
+//! 4 data words of payload. Transmitters gate TLPs on the credits the
+//! receiver advertised, receivers check that TLPs fit in what they
+//! advertised. Counters wrap as the spec describes, with 8-bit header and
+//! 12-bit data fields; scaled flow control is not supported.
+
+use crate::{
+    config::caps::UncorrectableErrors, Field, TlpClass, TlpError, TlpFormat, TlpHeader, DWORD_LEN,
+};
+use core::fmt;
+
+/// Payload bytes covered by one data credit
+pub const DATA_CREDIT_LEN: usize = 4 * DWORD_LEN;
+
+/// Width of header credit counters in bits
+const HEADER_BITS: u32 = 8;
+
+/// Width of data credit counters in bits
+const DATA_BITS: u32 = 12;
+
+/// Flow control credits used by a TLP
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Credits {
+    pub class: TlpClass,
+    pub header: u16,
+    pub data: u16,
+}
+
+impl Credits {
+    /// Returns the credits used by a TLP with header `hdr`, or None for
+    /// prefixes and unknown types
+    pub fn of(hdr: &TlpHeader) -> Option<Self> {
+        let class = hdr.tlp_type.class()?;
+        let data = if hdr.tlp_type.has_data() {
+            hdr.data_len().div_ceil(DATA_CREDIT_LEN as u16)
+        } else {
+            0
+        };
+        Some(Self {
+            class,
+            header: 1,
+            data,
+        })
+    }
+
+    /// Returns the credits used by an encoded TLP, skipping any prefixes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let mut offset = 0;
+        loop {
+            let rest = &bytes[offset.min(bytes.len())..];
+            if rest.len() < TlpHeader::LENGTH {
+                return Err(TlpError::TooShort {
+                    expected: offset + TlpHeader::LENGTH,
+                    actual: bytes.len(),
+                });
+            }
+            // SAFETY: Slice is already confirmed to be long enough
+            let hdr = TlpHeader::from_bytes(rest[..TlpHeader::LENGTH].try_into().unwrap())
+                .map_err(|e| e.at(offset))?;
+            match hdr.tlp_type.format() {
+                Some(TlpFormat::TlpPrefix) => offset += TlpHeader::LENGTH,
+                _ => {
+                    return Self::of(&hdr).ok_or(TlpError::InvalidType {
+                        field: Field::FmtType,
+                        value: rest[0].into(),
+                        offset,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Credits advertised for each class during flow control initialisation,
+/// where 0 advertises infinite credits
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct InitFc {
+    /// Posted header credits, at most 127
+    pub ph: u16,
+    /// Posted data credits, at most 2047
+    pub pd: u16,
+    /// Non-posted header credits, at most 127
+    pub nph: u16,
+    /// Non-posted data credits, at most 2047
+    pub npd: u16,
+    /// Completion header credits, at most 127
+    pub cplh: u16,
+    /// Completion data credits, at most 2047
+    pub cpld: u16,
+}
+
+impl InitFc {
+    /// Advertises infinite credits for every class
+    pub fn infinite() -> Self {
+        Self::default()
+    }
+
+    /// Returns the header and data credits advertised for `class`
+    pub fn get(&self, class: TlpClass) -> (u16, u16) {
+        match class {
+            TlpClass::Posted => (self.ph, self.pd),
+            TlpClass::NonPosted => (self.nph, self.npd),
+            TlpClass::Completion => (self.cplh, self.cpld),
+        }
+    }
+
+    /// Checks that no value is larger than half of its counter, which would
+    /// be a flow control protocol error
+    fn check(&self) -> Result<(), FcError> {
+        for class in CLASSES {
+            let (header, data) = self.get(class);
+            if header >= 1 << (HEADER_BITS - 1) || data >= 1 << (DATA_BITS - 1) {
+                return Err(FcError::Protocol { class });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Errors raised by credit tracking
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FcError {
+    /// The transmitter does not have enough credits to send the TLP now
+    InsufficientCredits { class: TlpClass },
+    /// The TLP does not fit in the credits the receiver advertised
+    ReceiverOverflow { class: TlpClass },
+    /// Advertised credits are larger than the counters allow
+    Protocol { class: TlpClass },
+}
+
+impl FcError {
+    /// Returns the AER uncorrectable error the receiver logs, if any
+    pub fn aer_error(&self) -> Option<UncorrectableErrors> {
+        match self {
+            FcError::InsufficientCredits { .. } => None,
+            FcError::ReceiverOverflow { .. } => Some(UncorrectableErrors::RECEIVER_OVERFLOW),
+            FcError::Protocol { .. } => Some(UncorrectableErrors::FLOW_CONTROL_PROTOCOL),
+        }
+    }
+}
+
+impl fmt::Display for FcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FcError::InsufficientCredits { class } => {
+                write!(f, "not enough {:?} credits to transmit", class)
+            }
+            FcError::ReceiverOverflow { class } => {
+                write!(f, "{:?} TLP overflows the advertised credits", class)
+            }
+            FcError::Protocol { class } => {
+                write!(f, "{:?} credits out of range for flow control", class)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FcError {}
+
+const CLASSES: [TlpClass; 3] = [TlpClass::Posted, TlpClass::NonPosted, TlpClass::Completion];
+
+fn index(class: TlpClass) -> usize {
+    match class {
+        TlpClass::Posted => 0,
+        TlpClass::NonPosted => 1,
+        TlpClass::Completion => 2,
+    }
+}
+
+/// Header and data counters of one class, None for infinite credits
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Counters {
+    header: Option<u16>,
+    data: Option<u16>,
+}
+
+impl Counters {
+    fn new(init: (u16, u16), value: impl Fn(u16) -> u16) -> Self {
+        let counter = |x: u16| (x != 0).then(|| value(x));
+        Self {
+            header: counter(init.0),
+            data: counter(init.1),
+        }
+    }
+}
+
+fn mask(bits: u32) -> u16 {
+    (1 << bits) - 1
+}
+
+/// Gating function of a transmitter counter, always true for infinite credits
+fn fits(consumed: Option<u16>, limit: Option<u16>, req: u16, bits: u32) -> bool {
+    match (consumed, limit) {
+        (Some(consumed), Some(limit)) => {
+            let left = limit.wrapping_sub(consumed.wrapping_add(req)) & mask(bits);
+            left <= 1 << (bits - 1)
+        }
+        _ => true,
+    }
+}
+
+/// Transmitter side credit gating
+///
+/// Tracks the credits consumed by sent TLPs against the credit limit the
+/// receiver advertised with InitFC and UpdateFC.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FcTransmitter {
+    consumed: [Counters; 3],
+    limit: [Counters; 3],
+}
+
+impl FcTransmitter {
+    /// Sets up the credit limits from the receiver's InitFC values
+    pub fn new(init: InitFc) -> Result<Self, FcError> {
+        init.check()?;
+        Ok(Self {
+            consumed: CLASSES.map(|c| Counters::new(init.get(c), |_| 0)),
+            limit: CLASSES.map(|c| Counters::new(init.get(c), |x| x)),
+        })
+    }
+
+    /// Returns true if a TLP using `credits` may be sent now
+    pub fn can_send(&self, credits: &Credits) -> bool {
+        let i = index(credits.class);
+        fits(
+            self.consumed[i].header,
+            self.limit[i].header,
+            credits.header,
+            HEADER_BITS,
+        ) && fits(
+            self.consumed[i].data,
+            self.limit[i].data,
+            credits.data,
+            DATA_BITS,
+        )
+    }
+
+    /// Records a TLP using `credits` as sent, if there are enough credits
+    pub fn send(&mut self, credits: &Credits) -> Result<(), FcError> {
+        if !self.can_send(credits) {
+            return Err(FcError::InsufficientCredits {
+                class: credits.class,
+            });
+        }
+        let consumed = &mut self.consumed[index(credits.class)];
+        if let Some(h) = consumed.header.as_mut() {
+            *h = h.wrapping_add(credits.header) & mask(HEADER_BITS);
+        }
+        if let Some(d) = consumed.data.as_mut() {
+            *d = d.wrapping_add(credits.data) & mask(DATA_BITS);
+        }
+        Ok(())
+    }
+
+    /// Applies the credit limits of an UpdateFC DLLP, ignoring them for
+    /// infinite credits
+    pub fn update(&mut self, class: TlpClass, header: u16, data: u16) {
+        let limit = &mut self.limit[index(class)];
+        if let Some(h) = limit.header.as_mut() {
+            *h = header & mask(HEADER_BITS);
+        }
+        if let Some(d) = limit.data.as_mut() {
+            *d = data & mask(DATA_BITS);
+        }
+    }
+
+    /// Returns the header and data credits left for `class`, None for
+    /// infinite credits
+    pub fn available(&self, class: TlpClass) -> (Option<u16>, Option<u16>) {
+        let i = index(class);
+        let left = |consumed: Option<u16>, limit: Option<u16>, bits| {
+            Some(limit?.wrapping_sub(consumed?) & mask(bits))
+        };
+        (
+            left(self.consumed[i].header, self.limit[i].header, HEADER_BITS),
+            left(self.consumed[i].data, self.limit[i].data, DATA_BITS),
+        )
+    }
+}
+
+/// Receiver side credit accounting
+///
+/// Tracks the credits allocated to the transmitter against those used by
+/// received TLPs, and produces the values to advertise in UpdateFC DLLPs as
+/// buffer space is freed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FcReceiver {
+    init: InitFc,
+    allocated: [Counters; 3],
+    received: [Counters; 3],
+}
+
+impl FcReceiver {
+    /// Sets up the receiver with the credits it advertises in InitFC
+    pub fn new(init: InitFc) -> Result<Self, FcError> {
+        init.check()?;
+        Ok(Self {
+            init,
+            allocated: CLASSES.map(|c| Counters::new(init.get(c), |x| x)),
+            received: CLASSES.map(|c| Counters::new(init.get(c), |_| 0)),
+        })
+    }
+
+    /// Values to advertise in InitFC DLLPs
+    pub fn init_fc(&self) -> InitFc {
+        self.init
+    }
+
+    /// Records a received TLP using `credits`, rejecting it if it overflows
+    /// the advertised credits
+    pub fn receive(&mut self, credits: &Credits) -> Result<(), FcError> {
+        let i = index(credits.class);
+        let add =
+            |received: Option<u16>, n: u16, bits| received.map(|r| r.wrapping_add(n) & mask(bits));
+        let header = add(self.received[i].header, credits.header, HEADER_BITS);
+        let data = add(self.received[i].data, credits.data, DATA_BITS);
+
+        let overflows = |allocated: Option<u16>, received: Option<u16>, bits: u32| {
+            matches!((allocated, received), (Some(a), Some(r))
+                if a.wrapping_sub(r) & mask(bits) >= 1 << (bits - 1))
+        };
+        if overflows(self.allocated[i].header, header, HEADER_BITS)
+            || overflows(self.allocated[i].data, data, DATA_BITS)
+        {
+            return Err(FcError::ReceiverOverflow {
+                class: credits.class,
+            });
+        }
+        self.received[i] = Counters { header, data };
+        Ok(())
+    }
+
+    /// Frees the buffer space of a processed TLP, returning the credits
+    pub fn release(&mut self, credits: &Credits) {
+        let allocated = &mut self.allocated[index(credits.class)];
+        if let Some(h) = allocated.header.as_mut() {
+            *h = h.wrapping_add(credits.header) & mask(HEADER_BITS);
+        }
+        if let Some(d) = allocated.data.as_mut() {
+            *d = d.wrapping_add(credits.data) & mask(DATA_BITS);
+        }
+    }
+
+    /// Header and data values of the UpdateFC DLLP for `class`, 0 for
+    /// infinite credits
+    pub fn update_fc(&self, class: TlpClass) -> (u16, u16) {
+        let allocated = self.allocated[index(class)];
+        (allocated.header.unwrap_or(0), allocated.data.unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeviceID, MRd, MWr, Tlp, TlpType};
+    use proptest::prelude::*;
+
+    fn credits(class: TlpClass, header: u16, data: u16) -> Credits {
+        Credits {
+            class,
+            header,
+            data,
+        }
+    }
+
+    proptest! {
+        /// Tests that data credits cover the payload in 16 byte units
+        #[test]
+        fn fc_data_credits(len in 1u16..=1024) {
+            let data = vec![0; usize::from(len) * DWORD_LEN];
+            let req = MWr::new(DeviceID::default(), 0, 0x1000, &data).unwrap();
+            let buf = Tlp::MWr(req).to_vec().unwrap();
+
+            let c = Credits::from_bytes(&buf).unwrap();
+            assert_eq!(credits(TlpClass::Posted, 1, len.div_ceil(4)), c);
+        }
+
+        /// Tests that a transmitter never sends more than the receiver
+        /// allocates, across counter wraparound
+        #[test]
+        fn fc_tx_rx_agree(ph in 1u16..128, pd in 1u16..2048,
+                sizes in prop::collection::vec(0u16..=256, 1..200)) {
+            let init = InitFc { ph, pd, ..InitFc::infinite() };
+            let mut tx = FcTransmitter::new(init).unwrap();
+            let mut rx = FcReceiver::new(init).unwrap();
+            let mut pending = vec![];
+            for size in sizes {
+                let c = credits(TlpClass::Posted, 1, size.min(pd));
+                if tx.send(&c).is_err() {
+                    // Process everything received so far to free up credits
+                    for p in pending.drain(..) {
+                        rx.release(&p);
+                    }
+                    let (h, d) = rx.update_fc(TlpClass::Posted);
+                    tx.update(TlpClass::Posted, h, d);
+                    prop_assert_eq!(Ok(()), tx.send(&c));
+                }
+                prop_assert_eq!(Ok(()), rx.receive(&c));
+                pending.push(c);
+            }
+        }
+    }
+
+    #[test]
+    fn fc_credits_of() {
+        let req = MRd::new(DeviceID::default(), 0, 0x1000, 4096).unwrap();
+        assert_eq!(
+            Some(credits(TlpClass::NonPosted, 1, 0)),
+            Credits::of(&req.hdr.hdr)
+        );
+
+        let hdr = TlpHeader::new()
+            .with_type(TlpType::CplD)
+            .with_length(20)
+            .unwrap();
+        assert_eq!(Some(credits(TlpClass::Completion, 1, 2)), Credits::of(&hdr));
+
+        let prefix = TlpHeader::new().with_type(TlpType::PASID);
+        assert_eq!(None, Credits::of(&prefix));
+
+        let mut bytes = [0; 16];
+        bytes[0..4].clone_from_slice(&prefix.to_bytes());
+        bytes[4..8].clone_from_slice(&hdr.to_bytes());
+        assert_eq!(
+            Ok(credits(TlpClass::Completion, 1, 2)),
+            Credits::from_bytes(&bytes)
+        );
+        let e = Credits::from_bytes(&bytes[..6]);
+        assert_eq!(
+            Err(TlpError::TooShort {
+                expected: 8,
+                actual: 6
+            }),
+            e
+        );
+    }
+
+    #[test]
+    fn fc_transmitter_gating() {
+        let init = InitFc {
+            ph: 2,
+            pd: 8,
+            nph: 1,
+            ..InitFc::infinite()
+        };
+        let mut tx = FcTransmitter::new(init).unwrap();
+        let c = credits(TlpClass::Posted, 1, 4);
+        assert_eq!(Ok(()), tx.send(&c));
+        assert_eq!((Some(1), Some(4)), tx.available(TlpClass::Posted));
+        assert_eq!(Ok(()), tx.send(&c));
+        let e = Err(FcError::InsufficientCredits {
+            class: TlpClass::Posted,
+        });
+        assert_eq!(e, tx.send(&credits(TlpClass::Posted, 1, 0)));
+
+        // Completions are infinite and never blocked
+        for _ in 0..1000 {
+            assert_eq!(Ok(()), tx.send(&credits(TlpClass::Completion, 1, 256)));
+        }
+        assert_eq!((None, None), tx.available(TlpClass::Completion));
+        // Non-posted data credits are infinite but header credits are not
+        assert_eq!((Some(1), None), tx.available(TlpClass::NonPosted));
+
+        tx.update(TlpClass::Posted, 4, 16);
+        assert_eq!((Some(2), Some(8)), tx.available(TlpClass::Posted));
+    }
+
+    #[test]
+    fn fc_receiver_overflow() {
+        let init = InitFc {
+            cplh: 4,
+            cpld: 4,
+            ..InitFc::infinite()
+        };
+        let mut rx = FcReceiver::new(init).unwrap();
+        assert_eq!(init, rx.init_fc());
+        let c = credits(TlpClass::Completion, 1, 3);
+        assert_eq!(Ok(()), rx.receive(&c));
+        let e = rx.receive(&c).unwrap_err();
+        assert_eq!(
+            FcError::ReceiverOverflow {
+                class: TlpClass::Completion
+            },
+            e
+        );
+        assert_eq!(Some(UncorrectableErrors::RECEIVER_OVERFLOW), e.aer_error());
+
+        rx.release(&c);
+        assert_eq!((5, 7), rx.update_fc(TlpClass::Completion));
+        assert_eq!(Ok(()), rx.receive(&c));
+        assert_eq!((0, 0), rx.update_fc(TlpClass::Posted));
+    }
+
+    #[test]
+    fn fc_init_out_of_range() {
+        let init = InitFc {
+            pd: 2048,
+            ..InitFc::infinite()
+        };
+        let e = FcTransmitter::new(init).unwrap_err();
+        assert_eq!(
+            FcError::Protocol {
+                class: TlpClass::Posted
+            },
+            e
+        );
+        assert!(FcReceiver::new(init).is_err());
+    }
+}
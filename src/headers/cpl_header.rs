@@ -1,14 +1,17 @@
 use crate::{
-    headers::{CompletionStatus, TlpHeader},
-    DeviceID, TlpError,
+    headers::{CompletionStatus, DecodeMode, ReservedBits, TlpHeader},
+    DeviceID, Field, TlpError,
 };
 use byteorder::{BigEndian, ByteOrder};
-
 use num_traits::FromPrimitive;
+
 #[cfg(test)]
 use proptest_derive::Arbitrary;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 pub struct CplHeader {
     pub hdr: TlpHeader,
@@ -23,6 +26,9 @@ pub struct CplHeader {
 }
 
 impl CplHeader {
+    /// Reserved bits of byte 11
+    const RSVD_MASK_11: u8 = 0x80;
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -43,7 +49,13 @@ impl CplHeader {
 
     pub fn with_bc(mut self, bc: u16) -> Result<Self, TlpError> {
         if bc > 4095 {
-            Err(TlpError::TooLong)
+            Err(TlpError::OutOfRange {
+                field: Field::ByteCount,
+                value: bc.into(),
+                min: 0,
+                max: 4095,
+                offset: 6,
+            })
         } else {
             self.bc = bc;
             Ok(self)
@@ -71,7 +83,13 @@ impl CplHeader {
 
     pub fn with_addr(mut self, addr_low: u8) -> Result<Self, TlpError> {
         if addr_low > 127 {
-            Err(TlpError::TooLong)
+            Err(TlpError::OutOfRange {
+                field: Field::LowerAddress,
+                value: addr_low.into(),
+                min: 0,
+                max: 127,
+                offset: 11,
+            })
         } else {
             self.addr_low = addr_low;
             Ok(self)
@@ -83,7 +101,7 @@ impl CplHeader {
         ret[0..4].clone_from_slice(&self.hdr.to_bytes());
         ret[4..6].clone_from_slice(&self.cpl_id.to_bytes());
 
-        let bc_status: u16 = ((self.status as u16) << 13) | self.bc;
+        let bc_status: u16 = (u16::from(u8::from(self.status)) << 13) | self.bc;
         ret[6..8].clone_from_slice(&bc_status.to_be_bytes());
         ret[8..10].clone_from_slice(&self.req_id.to_bytes());
         ret[10] = self.tag;
@@ -92,25 +110,59 @@ impl CplHeader {
     }
 
     pub fn from_bytes(bytes: [u8; 12]) -> Result<Self, TlpError> {
-        let hdr = TlpHeader::try_from(&bytes[0..4])?;
+        Self::from_bytes_with_mode(bytes, DecodeMode::Lenient).map(|(hdr, _)| hdr)
+    }
+
+    /// Decodes a header, reporting or rejecting reserved bits according to `mode`
+    ///
+    /// In lenient mode the reserved bit of the lower address byte is kept in
+    /// `addr_low`, as it is by `from_bytes`.
+    pub fn from_bytes_with_mode(
+        bytes: [u8; 12],
+        mode: DecodeMode,
+    ) -> Result<(Self, ReservedBits), TlpError> {
+        // SAFETY: Slice is exactly the length of a TLP header
+        let (hdr, hdr_rsvd) =
+            TlpHeader::from_bytes_with_mode(bytes[0..4].try_into().unwrap(), DecodeMode::Lenient)?;
+        if mode == DecodeMode::Strict && hdr.tlp_type.is_unknown() {
+            return Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: bytes[0].into(),
+                offset: 0,
+            });
+        }
+        let mut rsvd = ReservedBits::new();
+        rsvd.merge(0, hdr_rsvd);
+        rsvd.check(11, bytes[11], Self::RSVD_MASK_11);
+        let rsvd = rsvd.enforce(mode)?;
         let cpl_id: DeviceID = BigEndian::read_u16(&bytes[4..6]).into();
         let bc_status = BigEndian::read_u16(&bytes[6..8]);
         let bc = bc_status & 0x1FFF;
-        let status: CompletionStatus =
-            FromPrimitive::from_u16((bc_status & 0xE000) >> 13).ok_or(TlpError::InvalidType)?;
+        // SAFETY: Every 3 bit value is a completion status
+        let status = CompletionStatus::from_u8((bc_status >> 13) as u8).unwrap();
+        if mode == DecodeMode::Strict && status.is_reserved() {
+            return Err(TlpError::InvalidType {
+                field: Field::Status,
+                value: u8::from(status).into(),
+                offset: 6,
+            });
+        }
         let req_id: DeviceID = BigEndian::read_u16(&bytes[8..10]).into();
         let tag = bytes[10];
         let addr_low = bytes[11];
 
-        Ok(Self {
-            hdr,
-            cpl_id,
-            bc,
-            status,
-            req_id,
-            tag,
-            addr_low,
-        })
+        Ok((
+            Self {
+                hdr,
+                cpl_id,
+                bc,
+                status,
+                req_id,
+                tag,
+                addr_low,
+            },
+            rsvd,
+        ))
     }
 }
 
@@ -128,9 +180,10 @@ impl TryFrom<&[u8]> for CplHeader {
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         use core::cmp::Ordering;
 
-        match value.len().cmp(&12) {
-            Ordering::Less => Err(TlpError::TooShort),
-            Ordering::Greater => Err(TlpError::TooLong),
+        let (expected, actual) = (12, value.len());
+        match actual.cmp(&expected) {
+            Ordering::Less => Err(TlpError::TooShort { expected, actual }),
+            Ordering::Greater => Err(TlpError::TooLong { expected, actual }),
             // SAFETY: Slice is already confirmed to be correct length
             Ordering::Equal => TryInto::<[u8; 12]>::try_into(value).unwrap().try_into(),
         }
@@ -151,5 +204,46 @@ mod tests {
                 assert!(new_hdr.is_ok());
                 assert_eq!(hdr, new_hdr.unwrap());
             }
+
+            /// Tests that reserved bits in both the TLP and completion headers are reported
+            #[test]
+            fn cpl_hdr_reserved_bits(hdr: CplHeader) {
+                let mut bytes = hdr.to_bytes();
+                bytes[1] |= 0x08;
+                bytes[11] |= 0x80;
+
+                let (new_hdr, report) = CplHeader::from_bytes_with_mode(bytes, DecodeMode::Lenient).unwrap();
+                assert_eq!(hdr.hdr, new_hdr.hdr);
+                assert_eq!(hdr.addr_low | 0x80, new_hdr.addr_low);
+                assert_eq!(vec![(1, 0x08), (11, 0x80)], report.iter().collect::<Vec<_>>());
+
+                let e = CplHeader::from_bytes_with_mode(bytes, DecodeMode::Strict);
+                assert_eq!(TlpError::ReservedBitsSet { offset: 1, mask: 0x08 }, e.unwrap_err());
+            }
+
+            /// Tests that any 12 bytes, including reserved statuses, decode and re-encode to
+            /// exactly the same bytes
+            #[test]
+            fn cpl_hdr_lossless(bytes: [u8; 12]) {
+                let (hdr, report) = CplHeader::from_bytes_with_mode(bytes, DecodeMode::Lenient).unwrap();
+                let mut new_bytes = hdr.to_bytes();
+                report.restore(&mut new_bytes);
+                assert_eq!(bytes, new_bytes);
+            }
+
+            /// Tests that reserved statuses are rejected in strict mode
+            #[test]
+            fn cpl_hdr_reserved_status(hdr: CplHeader, status in prop::sample::select(vec![3u8, 5, 6, 7])) {
+                let hdr = CplHeader {
+                    status: CompletionStatus::try_from(status).unwrap(),
+                    ..hdr
+                };
+                let bytes = hdr.to_bytes();
+                assert_eq!(hdr, CplHeader::from_bytes(bytes).unwrap());
+
+                let e = CplHeader::from_bytes_with_mode(bytes, DecodeMode::Strict);
+                let err = TlpError::InvalidType { field: Field::Status, value: status.into(), offset: 6 };
+                assert_eq!(err, e.unwrap_err());
+            }
     }
 }
@@ -1,26 +1,104 @@
 mod cpl_header;
+mod msg_header;
+mod pasid_prefix;
 mod req_header;
 mod tlp_header;
 
+use crate::{Field, TlpError, DWORD_LEN};
 use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 
 #[cfg(test)]
 use proptest_derive::Arbitrary;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 pub use cpl_header::CplHeader;
+pub use msg_header::MsgHeader;
+pub use pasid_prefix::PasidPrefix;
 pub use req_header::RequestHeader;
 pub use tlp_header::TlpHeader;
 
-#[derive(Debug, Eq, PartialEq)]
-pub enum TlpError {
-    InvalidType,
-    NotAligned,
-    TooLong,
-    TooShort,
+/// How strictly reserved bits are checked when decoding a header
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DecodeMode {
+    /// Reserved bits are ignored, but reported back to the caller
+    #[default]
+    Lenient,
+    /// Any nonzero reserved bit is rejected with `TlpError::ReservedBitsSet`,
+    /// and reserved or unknown encodings with `TlpError::InvalidType`
+    Strict,
+}
+
+/// Reserved bits that were found set while decoding a header
+///
+/// Each entry is a mask of the offending bits for the byte at that offset of
+/// the encoded header. The decoded fields leave reserved bits out, `restore`
+/// puts them back into the re-encoded header.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReservedBits([u8; ReservedBits::MAX_LEN]);
+
+impl ReservedBits {
+    /// Largest header (4 data words) that can be reported on
+    pub const MAX_LEN: usize = 4 * crate::DWORD_LEN;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if no reserved bits were set
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+
+    /// Returns the mask of reserved bits set in the byte at `offset`
+    pub fn get(&self, offset: usize) -> u8 {
+        self.0.get(offset).copied().unwrap_or(0)
+    }
+
+    /// Iterates over `(offset, mask)` pairs for every byte with reserved bits set
+    pub fn iter(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, &b)| b != 0)
+            .map(|(i, &b)| (i, b))
+    }
+
+    /// Sets the reported bits again in `bytes`, the header re-encoded from
+    /// the decoded fields, so that it matches the bytes that were decoded
+    pub fn restore(&self, bytes: &mut [u8]) {
+        for (b, rsvd) in bytes.iter_mut().zip(self.0) {
+            *b |= rsvd;
+        }
+    }
+
+    /// Records any bits of `byte` covered by the reserved `mask`
+    pub(crate) fn check(&mut self, offset: usize, byte: u8, mask: u8) {
+        self.0[offset] |= byte & mask;
+    }
+
+    /// Merges the report of a sub-header that starts at `offset`
+    pub(crate) fn merge(&mut self, offset: usize, other: ReservedBits) {
+        for (i, b) in other.iter() {
+            self.0[offset + i] |= b;
+        }
+    }
+
+    /// Turns the report into an error if strict decoding was requested
+    pub(crate) fn enforce(self, mode: DecodeMode) -> Result<Self, TlpError> {
+        match self.iter().next() {
+            Some((offset, mask)) if mode == DecodeMode::Strict => {
+                Err(TlpError::ReservedBitsSet { offset, mask })
+            }
+            _ => Ok(self),
+        }
+    }
 }
 
 /// TLP header types
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum TlpFormat {
     /// 3 data word header with no payload
@@ -34,26 +112,56 @@ pub enum TlpFormat {
     TlpPrefix = 0b100,
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, FromPrimitive)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 #[repr(u8)]
 pub enum AddressType {
+    #[default]
     DefaultUntranslated = 0b00,
     TranslationRequest = 0b01,
     Translated = 0b10,
     AddressTypeReserved = 0b11,
 }
 
-impl Default for AddressType {
-    fn default() -> Self {
-        Self::DefaultUntranslated
-    }
+/// Routing of a message request
+#[derive(Clone, Copy, Debug, Eq, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(test, derive(Arbitrary))]
+#[repr(u8)]
+pub enum MsgRouting {
+    /// Routed to the root complex
+    ToRootComplex = 0b000,
+    /// Routed by address
+    ByAddress = 0b001,
+    /// Routed by ID
+    ById = 0b010,
+    /// Broadcast from the root complex
+    Broadcast = 0b011,
+    /// Local, terminated at the receiver
+    Local = 0b100,
+    /// Gathered and routed to the root complex
+    Gathered = 0b101,
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, FromPrimitive)]
+/// Flow control and ordering class of a TLP
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TlpClass {
+    /// Posted request
+    Posted,
+    /// Non-posted request
+    NonPosted,
+    /// Completion
+    Completion,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 #[repr(u8)]
 pub enum TrafficClass {
+    #[default]
     TC0 = 0,
     TC1 = 1,
     TC2 = 2,
@@ -64,78 +172,301 @@ pub enum TrafficClass {
     TC7 = 7,
 }
 
-impl Default for TrafficClass {
-    fn default() -> Self {
-        Self::TC0
+lossless_enum! {
+    /// Completion status
+    ///
+    /// Converts to its 3 bit encoding with `From` and back with `TryFrom`, in
+    /// place of `as` casts.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(test, derive(Arbitrary))]
+    pub enum CompletionStatus {
+        #[default]
+        SuccessfulCompletion = 0b000,
+        UnsupportedRequest = 0b001,
+        ConfigurationRequestRetry = 0b010,
+        CompleterAbort = 0b100,
     }
+    /// Reserved status encoding
+    #[cfg_attr(test, proptest(skip))]
+    Reserved(ReservedStatus, 0b111)
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, FromPrimitive)]
-#[cfg_attr(test, derive(Arbitrary))]
-#[repr(u8)]
-pub enum CompletionStatus {
-    SuccessfulCompletion = 0b000,
-    UnsupportedRequest = 0b001,
-    ConfigurationRequestRetry = 0b010,
-    CompleterAbort = 0b100,
+lossless_enum! {
+    /// Format and type of a TLP
+    ///
+    /// Converts to and from the first header byte with `From`, in place of
+    /// `as` casts.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(test, derive(Arbitrary))]
+    pub enum TlpType {
+        /// Memory read request, 3 data words
+        #[default]
+        MRd3 = (TlpFormat::NoData3DW as u8) << 5,
+        /// Memory read request, 4 data words
+        MRd4 = (TlpFormat::NoData4DW as u8) << 5,
+        /// Memory read request-locked, 3 data words
+        MRdLk3 = (TlpFormat::NoData3DW as u8) << 5 | 1,
+        /// Memory read request-locked, 4 data words
+        MRdLk4 = (TlpFormat::NoData4DW as u8) << 5 | 1,
+        /// Memory write request, 3 data words
+        MWr3 = (TlpFormat::Data3DW as u8) << 5,
+        /// Memory write request, 4 data words
+        MWr4 = (TlpFormat::Data4DW as u8) << 5,
+        /// I/O read request
+        IORdT = (TlpFormat::NoData3DW as u8) << 5 | 0b10,
+        /// I/O write request
+        IOWrtT = (TlpFormat::Data3DW as u8) << 5 | 0b10,
+        /// Configuration read type 0
+        CfgRd0 = (TlpFormat::NoData3DW as u8) << 5 | 0b100,
+        /// Configuration write type 0
+        CfgWr0 = (TlpFormat::Data3DW as u8) << 5 | 0b100,
+        /// Configuration read type 1
+        CfgRd1 = (TlpFormat::NoData3DW as u8) << 5 | 0b101,
+        /// Configuration write type 1
+        CfgWr1 = (TlpFormat::Data3DW as u8) << 5 | 0b101,
+        /// Completion without data
+        CplE = (TlpFormat::NoData3DW as u8) << 5 | 0b1010,
+        /// Completion with data
+        CplD = (TlpFormat::Data3DW as u8) << 5 | 0b1010,
+        /// Completion without data for locked memory read
+        CplLk = (TlpFormat::NoData3DW as u8) << 5 | 0b1011,
+        /// Completion with data for locked memory read
+        CplLkD = (TlpFormat::Data3DW as u8) << 5 | 0b1011,
+        /// Multi-root I/O virtualization and sharing
+        MRIOV = (TlpFormat::TlpPrefix as u8) << 5,
+        /// Local TLP prefix with vendor subfield
+        LocalVendPrefix = (TlpFormat::TlpPrefix as u8) << 5 | 0b1110,
+        /// Extended TLP
+        ExtTPH = (TlpFormat::TlpPrefix as u8) << 5 | 0b10000,
+        /// Process address space id
+        PASID = (TlpFormat::TlpPrefix as u8) << 5 | 0b10001,
+        /// End-to-end TLP with vendor subfield
+        EndEndVendPrefix = (TlpFormat::TlpPrefix as u8) << 5 | 0b11110,
+        /// Deferrable memory write request, 3 data words
+        DMWr3 = (TlpFormat::Data3DW as u8) << 5 | 0b11011,
+        /// Deferrable memory write request, 4 data words
+        DMWr4 = (TlpFormat::Data4DW as u8) << 5 | 0b11011,
+        /// Message request, routed to root complex
+        MsgRC = (TlpFormat::NoData4DW as u8) << 5 | 0b10000,
+        /// Message request, routed by address
+        MsgAddr = (TlpFormat::NoData4DW as u8) << 5 | 0b10001,
+        /// Message request, routed by ID
+        MsgID = (TlpFormat::NoData4DW as u8) << 5 | 0b10010,
+        /// Message request, broadcast from root complex
+        MsgBcast = (TlpFormat::NoData4DW as u8) << 5 | 0b10011,
+        /// Message request, local, terminated at receiver
+        MsgLocal = (TlpFormat::NoData4DW as u8) << 5 | 0b10100,
+        /// Message request, gathered and routed to root complex
+        MsgGather = (TlpFormat::NoData4DW as u8) << 5 | 0b10101,
+        /// Message request with data, routed to root complex
+        MsgDRC = (TlpFormat::Data4DW as u8) << 5 | 0b10000,
+        /// Message request with data, routed by address
+        MsgDAddr = (TlpFormat::Data4DW as u8) << 5 | 0b10001,
+        /// Message request with data, routed by ID
+        MsgDID = (TlpFormat::Data4DW as u8) << 5 | 0b10010,
+        /// Message request with data, broadcast from root complex
+        MsgDBcast = (TlpFormat::Data4DW as u8) << 5 | 0b10011,
+        /// Message request with data, local, terminated at receiver
+        MsgDLocal = (TlpFormat::Data4DW as u8) << 5 | 0b10100,
+        /// Message request with data, gathered and routed to root complex
+        MsgDGather = (TlpFormat::Data4DW as u8) << 5 | 0b10101,
+    }
+    /// Format and type encoding not known to this crate
+    #[cfg_attr(test, proptest(skip))]
+    Unknown(UnknownType, 0xFF)
 }
 
-impl Default for CompletionStatus {
-    fn default() -> Self {
-        Self::SuccessfulCompletion
+impl TryFrom<u8> for CompletionStatus {
+    type Error = TlpError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::from_u8(value).ok_or(TlpError::OutOfRange {
+            field: Field::Status,
+            value: value.into(),
+            min: 0,
+            max: Self::MAX.into(),
+            offset: 6,
+        })
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, FromPrimitive)]
-#[cfg_attr(test, derive(Arbitrary))]
-#[repr(u8)]
-pub enum TlpType {
-    /// Memory read request, 3 data words
-    MRd3 = (TlpFormat::NoData3DW as u8) << 5,
-    /// Memory read request, 4 data words
-    MRd4 = (TlpFormat::NoData4DW as u8) << 5,
-    /// Memory read request-locked, 3 data words
-    MRdLk3 = (TlpFormat::NoData3DW as u8) << 5 | 1,
-    /// Memory read request-locked, 4 data words
-    MRdLk4 = (TlpFormat::NoData4DW as u8) << 5 | 1,
-    /// Memory write request, 3 data words
-    MWr3 = (TlpFormat::Data3DW as u8) << 5,
-    /// Memory write request, 4 data words
-    MWr4 = (TlpFormat::Data4DW as u8) << 5,
-    /// I/O read request
-    IORdT = (TlpFormat::NoData3DW as u8) << 5 | 0b10,
-    /// I/O write request
-    IOWrtT = (TlpFormat::Data3DW as u8) << 5 | 0b10,
-    /// Configuration read type 0
-    CfgRd0 = (TlpFormat::NoData3DW as u8) << 5 | 0b100,
-    /// Configuration write type 0
-    CfgWr0 = (TlpFormat::Data3DW as u8) << 5 | 0b100,
-    /// Configuration read type 1
-    CfgRd1 = (TlpFormat::NoData3DW as u8) << 5 | 0b101,
-    /// Configuration write type 1
-    CfgWr1 = (TlpFormat::Data3DW as u8) << 5 | 0b101,
-    /// Completion without data
-    CplE = (TlpFormat::NoData3DW as u8) << 5 | 0b1010,
-    /// Completion with data
-    CplD = (TlpFormat::Data3DW as u8) << 5 | 0b1010,
-    /// Completion without data for locked memory read
-    CplLk = (TlpFormat::NoData3DW as u8) << 5 | 0b1011,
-    /// Completion with data for locked memory read
-    CplLkD = (TlpFormat::Data3DW as u8) << 5 | 0b1011,
-    /// Multi-root I/O virtualization and sharing
-    MRIOV = (TlpFormat::TlpPrefix as u8) << 5,
-    /// Local TLP prefix with vendor subfield
-    LocalVendPrefix = (TlpFormat::TlpPrefix as u8) << 5 | 0b1110,
-    /// Extended TLP
-    ExtTPH = (TlpFormat::TlpPrefix as u8) << 5 | 0b10000,
-    /// Process address space id
-    PASID = (TlpFormat::TlpPrefix as u8) << 5 | 0b10001,
-    /// End-to-end TLP with vendor subfield
-    EndEndVendPrefix = (TlpFormat::TlpPrefix as u8) << 5 | 0b11110,
+impl From<u8> for TlpType {
+    fn from(value: u8) -> Self {
+        // SAFETY: Every byte is a format and type encoding
+        Self::from_u8(value).unwrap()
+    }
+}
+
+impl CompletionStatus {
+    /// Returns true if the status is a reserved encoding
+    pub fn is_reserved(&self) -> bool {
+        matches!(self, CompletionStatus::Reserved(_))
+    }
+}
+
+impl TlpType {
+    /// Returns true if the encoding is not known to this crate
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, TlpType::Unknown(_))
+    }
+
+    /// Returns the header format, or `None` for reserved formats
+    pub fn format(&self) -> Option<TlpFormat> {
+        TlpFormat::from_u8(u8::from(*self) >> 5)
+    }
+
+    /// Returns true if the TLP carries a data payload
+    pub fn has_data(&self) -> bool {
+        matches!(
+            self.format(),
+            Some(TlpFormat::Data3DW) | Some(TlpFormat::Data4DW)
+        )
+    }
+
+    /// Returns the length of the header in bytes, or `None` for prefixes and
+    /// reserved formats
+    pub fn header_len(&self) -> Option<usize> {
+        match self.format()? {
+            TlpFormat::NoData3DW | TlpFormat::Data3DW => Some(3 * DWORD_LEN),
+            TlpFormat::NoData4DW | TlpFormat::Data4DW => Some(4 * DWORD_LEN),
+            TlpFormat::TlpPrefix => None,
+        }
+    }
+
+    /// Returns the class of the TLP, or `None` for prefixes and unknown types
+    pub fn class(&self) -> Option<TlpClass> {
+        use TlpType::*;
+
+        match self {
+            MWr3 | MWr4 | MsgRC | MsgAddr | MsgID | MsgBcast | MsgLocal | MsgGather | MsgDRC
+            | MsgDAddr | MsgDID | MsgDBcast | MsgDLocal | MsgDGather => Some(TlpClass::Posted),
+            MRd3 | MRd4 | MRdLk3 | MRdLk4 | IORdT | IOWrtT | CfgRd0 | CfgWr0 | CfgRd1 | CfgWr1
+            | DMWr3 | DMWr4 => Some(TlpClass::NonPosted),
+            CplE | CplD | CplLk | CplLkD => Some(TlpClass::Completion),
+            MRIOV | LocalVendPrefix | ExtTPH | PASID | EndEndVendPrefix | Unknown(_) => None,
+        }
+    }
+
+    /// Returns the routing of a message request, or `None` for other types
+    pub fn msg_routing(&self) -> Option<MsgRouting> {
+        let t = u8::from(*self);
+        match self.format()? {
+            TlpFormat::NoData4DW | TlpFormat::Data4DW if t & 0b11000 == 0b10000 => {
+                MsgRouting::from_u8(t & 0b111)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the message request type for the given routing
+    pub fn msg(routing: MsgRouting, with_data: bool) -> Self {
+        let fmt = if with_data {
+            TlpFormat::Data4DW
+        } else {
+            TlpFormat::NoData4DW
+        };
+        TlpType::from((fmt as u8) << 5 | 0b10000 | routing as u8)
+    }
+
+    /// Returns true for posted requests
+    pub fn is_posted(&self) -> bool {
+        self.class() == Some(TlpClass::Posted)
+    }
+
+    /// Returns true for non-posted requests, which are answered by a completion
+    pub fn is_non_posted(&self) -> bool {
+        self.class() == Some(TlpClass::NonPosted)
+    }
+
+    /// Returns true for completions
+    pub fn is_completion(&self) -> bool {
+        self.class() == Some(TlpClass::Completion)
+    }
 }
 
-impl Default for TlpType {
-    fn default() -> Self {
-        Self::MRd3
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Tests that every type byte round trips
+        #[test]
+        fn tlp_type_lossless(t: u8) {
+            assert_eq!(t, u8::from(TlpType::from(t)));
+        }
+
+        /// Tests that only known requests and completions have a class
+        #[test]
+        fn tlp_type_class(t: TlpType) {
+            let is_prefix = t.format() == Some(TlpFormat::TlpPrefix);
+            assert_eq!(is_prefix, t.class().is_none());
+            assert_eq!(is_prefix, t.header_len().is_none());
+        }
+    }
+
+    #[test]
+    fn reserved_status_checked() {
+        assert_eq!(None, ReservedStatus::new(0));
+        assert_eq!(None, ReservedStatus::new(9));
+        let raw = ReservedStatus::new(3).unwrap();
+        assert_eq!(Ok(CompletionStatus::Reserved(raw)), 3.try_into());
+        assert_eq!(3, u8::from(CompletionStatus::Reserved(raw)));
+
+        // Values wider than the field are rejected rather than truncated
+        assert_eq!(
+            Err(TlpError::OutOfRange {
+                field: Field::Status,
+                value: 9,
+                min: 0,
+                max: 7,
+                offset: 6
+            }),
+            CompletionStatus::try_from(9)
+        );
+        assert_eq!(None, CompletionStatus::from_u8(9));
+        assert_eq!(
+            CompletionStatus::try_from(5).ok(),
+            CompletionStatus::from_u8(5)
+        );
+        assert_eq!(Some(TlpType::PASID), TlpType::from_u64(0x91));
+        assert_eq!(None, TlpType::from_u64(0x100));
+        assert_eq!(None, UnknownType::new(TlpType::MRd3.into()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn reserved_status_serde() {
+        let status = CompletionStatus::try_from(6).unwrap();
+        let json = serde_json::to_string(&status).unwrap();
+        assert_eq!("{\"Reserved\":6}", json);
+        assert_eq!(status, serde_json::from_str(&json).unwrap());
+        assert!(serde_json::from_str::<CompletionStatus>("{\"Reserved\":0}").is_err());
+        assert!(serde_json::from_str::<CompletionStatus>("{\"Reserved\":9}").is_err());
+    }
+
+    #[test]
+    fn tlp_type_dmwr_non_posted() {
+        assert!(TlpType::DMWr3.is_non_posted());
+        assert!(TlpType::DMWr4.is_non_posted());
+        assert!(TlpType::DMWr4.has_data());
+        assert_eq!(Some(16), TlpType::DMWr4.header_len());
+        assert!(TlpType::MWr3.is_posted());
+        assert!(TlpType::CplD.is_completion());
+        assert_eq!(None, TlpType::from(0xFF).class());
+    }
+
+    proptest! {
+        /// Tests that message types round trip through their routing
+        #[test]
+        fn tlp_type_msg_routing(routing: MsgRouting, with_data: bool) {
+            let t = TlpType::msg(routing, with_data);
+            assert!(!t.is_unknown());
+            assert!(t.is_posted());
+            assert_eq!(with_data, t.has_data());
+            assert_eq!(Some(routing), t.msg_routing());
+        }
     }
 }
@@ -0,0 +1,195 @@
+use crate::{
+    headers::{DecodeMode, MsgRouting, ReservedBits, TlpHeader},
+    DeviceID, Field, TlpError,
+};
+use byteorder::{BigEndian, ByteOrder};
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Header of a message request
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct MsgHeader {
+    pub hdr: TlpHeader,
+    pub req_id: DeviceID,
+    pub tag: u8,
+    /// Message code
+    pub code: u8,
+    /// Bytes 8 to 15, whose meaning depends on the routing and message code
+    pub body: [u8; 8],
+}
+
+impl MsgHeader {
+    pub const LENGTH: usize = 16;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_hdr(mut self, hdr: TlpHeader) -> Self {
+        self.hdr = hdr;
+        self
+    }
+
+    pub fn with_req_id<T>(mut self, req_id: T) -> Self
+    where
+        T: Into<DeviceID>,
+    {
+        self.req_id = req_id.into();
+        self
+    }
+
+    pub fn with_tag(mut self, tag: u8) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    pub fn with_code(mut self, code: u8) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn with_body(mut self, body: [u8; 8]) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Sets the destination of a message routed by ID
+    pub fn with_target_id<T>(mut self, target: T) -> Self
+    where
+        T: Into<DeviceID>,
+    {
+        self.body[0..2].clone_from_slice(&target.into().to_bytes());
+        self
+    }
+
+    /// Returns the routing of the message, or `None` if the type is not a message
+    pub fn routing(&self) -> Option<MsgRouting> {
+        self.hdr.tlp_type.msg_routing()
+    }
+
+    /// Returns the destination of a message routed by ID
+    pub fn target_id(&self) -> Option<DeviceID> {
+        match self.routing()? {
+            MsgRouting::ById => Some(BigEndian::read_u16(&self.body[0..2]).into()),
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut ret = [0; Self::LENGTH];
+        ret[0..4].clone_from_slice(&self.hdr.to_bytes());
+        ret[4..6].clone_from_slice(&self.req_id.to_bytes());
+        ret[6] = self.tag;
+        ret[7] = self.code;
+        ret[8..16].clone_from_slice(&self.body);
+        ret
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Result<Self, TlpError> {
+        Self::from_bytes_with_mode(bytes, DecodeMode::Lenient).map(|(hdr, _)| hdr)
+    }
+
+    /// Decodes a header, reporting or rejecting reserved bits according to `mode`
+    ///
+    /// In strict mode the type must also be a message request.
+    pub fn from_bytes_with_mode(
+        bytes: [u8; Self::LENGTH],
+        mode: DecodeMode,
+    ) -> Result<(Self, ReservedBits), TlpError> {
+        // SAFETY: Slice is exactly the length of a TLP header
+        let (hdr, rsvd) = TlpHeader::from_bytes_with_mode(bytes[0..4].try_into().unwrap(), mode)?;
+        if mode == DecodeMode::Strict && hdr.tlp_type.msg_routing().is_none() {
+            return Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: bytes[0].into(),
+                offset: 0,
+            });
+        }
+
+        let mut body = [0; 8];
+        body.clone_from_slice(&bytes[8..16]);
+
+        Ok((
+            Self {
+                hdr,
+                req_id: BigEndian::read_u16(&bytes[4..6]).into(),
+                tag: bytes[6],
+                code: bytes[7],
+                body,
+            },
+            rsvd,
+        ))
+    }
+}
+
+impl TryFrom<[u8; MsgHeader::LENGTH]> for MsgHeader {
+    type Error = TlpError;
+
+    fn try_from(value: [u8; MsgHeader::LENGTH]) -> Result<Self, Self::Error> {
+        Self::from_bytes(value)
+    }
+}
+
+impl TryFrom<&[u8]> for MsgHeader {
+    type Error = TlpError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        use core::cmp::Ordering;
+
+        let (expected, actual) = (Self::LENGTH, value.len());
+        match actual.cmp(&expected) {
+            Ordering::Less => Err(TlpError::TooShort { expected, actual }),
+            Ordering::Greater => Err(TlpError::TooLong { expected, actual }),
+            // SAFETY: Slice is already confirmed to be correct length
+            Ordering::Equal => Self::from_bytes(value.try_into().unwrap()),
+        }
+    }
+}
+
+impl From<MsgHeader> for [u8; MsgHeader::LENGTH] {
+    fn from(hdr: MsgHeader) -> Self {
+        hdr.to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TlpType;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of header en/decoding
+        #[test]
+        fn msg_hdr_serde_roundtrip(hdr: MsgHeader) {
+            let bytes = hdr.to_bytes();
+            assert_eq!(Ok(hdr), MsgHeader::from_bytes(bytes));
+        }
+
+        /// Tests that the destination of ID routed messages is found in bytes 8 and 9
+        #[test]
+        fn msg_hdr_target_id(target: DeviceID, with_data: bool) {
+            let hdr = MsgHeader::new()
+                .with_hdr(TlpHeader::new().with_type(TlpType::msg(MsgRouting::ById, with_data)))
+                .with_target_id(target);
+            assert_eq!(Some(target), hdr.target_id());
+        }
+    }
+
+    #[test]
+    fn msg_hdr_strict_not_a_message() {
+        let e = MsgHeader::from_bytes_with_mode([0; 16], DecodeMode::Strict);
+        assert!(matches!(
+            e,
+            Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{headers::TlpType, Field, TlpError};
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// PASID end-to-end TLP prefix
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct PasidPrefix {
+    /// Process address space ID
+    #[cfg_attr(test, proptest(strategy = "0u32..=PasidPrefix::MAX_PASID"))]
+    pub pasid: u32,
+    /// Execute requested
+    pub exe: bool,
+    /// Privileged mode requested
+    pub privileged: bool,
+}
+
+impl PasidPrefix {
+    pub const LENGTH: usize = 4;
+
+    /// Largest PASID that fits in the 20 bit field
+    pub const MAX_PASID: u32 = 0xF_FFFF;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_pasid(mut self, pasid: u32) -> Result<Self, TlpError> {
+        if pasid > Self::MAX_PASID {
+            Err(TlpError::OutOfRange {
+                field: Field::Pasid,
+                value: pasid.into(),
+                min: 0,
+                max: Self::MAX_PASID.into(),
+                offset: 1,
+            })
+        } else {
+            self.pasid = pasid;
+            Ok(self)
+        }
+    }
+
+    pub fn with_exe(mut self, exe: bool) -> Self {
+        self.exe = exe;
+        self
+    }
+
+    pub fn with_privileged(mut self, privileged: bool) -> Self {
+        self.privileged = privileged;
+        self
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let x = (self.privileged as u32) << 23 | (self.exe as u32) << 22 | self.pasid;
+        let mut ret = x.to_be_bytes();
+        ret[0] = TlpType::PASID.into();
+        ret
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Result<Self, TlpError> {
+        if TlpType::from(bytes[0]) != TlpType::PASID {
+            return Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: bytes[0].into(),
+                offset: 0,
+            });
+        }
+
+        let x = u32::from_be_bytes(bytes);
+        Ok(Self {
+            pasid: x & Self::MAX_PASID,
+            exe: x & (1 << 22) > 0,
+            privileged: x & (1 << 23) > 0,
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for PasidPrefix {
+    type Error = TlpError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        use core::cmp::Ordering;
+
+        let (expected, actual) = (Self::LENGTH, value.len());
+        match actual.cmp(&expected) {
+            Ordering::Less => Err(TlpError::TooShort { expected, actual }),
+            Ordering::Greater => Err(TlpError::TooLong { expected, actual }),
+            // SAFETY: Slice is already confirmed to be correct length
+            Ordering::Equal => Self::from_bytes(value.try_into().unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of prefix en/decoding
+        #[test]
+        fn pasid_prefix_serde_roundtrip(prefix: PasidPrefix) {
+            let bytes = prefix.to_bytes();
+            assert_eq!(Ok(prefix), PasidPrefix::from_bytes(bytes));
+        }
+
+        /// Tests that a PASID wider than 20 bits is rejected
+        #[test]
+        fn pasid_prefix_too_large(pasid in (PasidPrefix::MAX_PASID + 1)..) {
+            let e = PasidPrefix::new().with_pasid(pasid);
+            assert_eq!(Some(Field::Pasid), e.unwrap_err().field());
+        }
+    }
+
+    #[test]
+    fn pasid_prefix_known_bytes() {
+        let prefix = PasidPrefix::from_bytes([0x91, 0xC0, 0x00, 0x05]).unwrap();
+        assert_eq!(
+            PasidPrefix {
+                pasid: 5,
+                exe: true,
+                privileged: true
+            },
+            prefix
+        );
+        assert_eq!(
+            [0x91, 0x40, 0x00, 0x05],
+            prefix.with_privileged(false).to_bytes()
+        );
+
+        // Bits 21:20 are reserved
+        let prefix = PasidPrefix::from_bytes([0x91, 0x30, 0x00, 0x05]).unwrap();
+        assert!(!prefix.exe && !prefix.privileged);
+    }
+
+    #[test]
+    fn pasid_prefix_wrong_type() {
+        let e = PasidPrefix::from_bytes([0x90, 0, 0, 1]);
+        assert!(matches!(e, Err(TlpError::InvalidType { value: 0x90, .. })));
+    }
+}
@@ -1,13 +1,16 @@
 use crate::{
-    headers::{TlpError, TlpHeader},
-    DeviceID,
+    headers::{DecodeMode, ReservedBits, TlpHeader},
+    DeviceID, Field, TlpError,
 };
 use byteorder::{BigEndian, ByteOrder};
 
 #[cfg(test)]
 use proptest_derive::Arbitrary;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 pub struct RequestHeader {
     pub hdr: TlpHeader,
@@ -45,7 +48,13 @@ impl RequestHeader {
 
     pub fn with_first_be(mut self, first_be: u8) -> Result<Self, TlpError> {
         if first_be > 0xF {
-            Err(TlpError::TooLong)
+            Err(TlpError::OutOfRange {
+                field: Field::FirstBe,
+                value: first_be.into(),
+                min: 0,
+                max: 0xF,
+                offset: 7,
+            })
         } else {
             self.first_be = first_be;
             Ok(self)
@@ -54,7 +63,13 @@ impl RequestHeader {
 
     pub fn with_last_be(mut self, last_be: u8) -> Result<Self, TlpError> {
         if last_be > 0xF {
-            Err(TlpError::TooLong)
+            Err(TlpError::OutOfRange {
+                field: Field::LastBe,
+                value: last_be.into(),
+                min: 0,
+                max: 0xF,
+                offset: 7,
+            })
         } else {
             self.last_be = last_be;
             Ok(self)
@@ -76,19 +91,31 @@ impl RequestHeader {
     }
 
     pub fn from_bytes(bytes: [u8; 8]) -> Result<Self, TlpError> {
-        let hdr = TlpHeader::try_from(&bytes[0..4])?;
+        Self::from_bytes_with_mode(bytes, DecodeMode::Lenient).map(|(hdr, _)| hdr)
+    }
+
+    /// Decodes a header, reporting or rejecting reserved bits according to `mode`
+    pub fn from_bytes_with_mode(
+        bytes: [u8; 8],
+        mode: DecodeMode,
+    ) -> Result<(Self, ReservedBits), TlpError> {
+        // SAFETY: Slice is exactly the length of a TLP header
+        let (hdr, rsvd) = TlpHeader::from_bytes_with_mode(bytes[0..4].try_into().unwrap(), mode)?;
         let req_id: DeviceID = BigEndian::read_u16(&bytes[4..6]).into();
         let tag = bytes[6];
         let first_be = bytes[7] & 0xF;
         let last_be = (bytes[7] & 0xF0) >> 4;
 
-        Ok(Self {
-            hdr,
-            req_id,
-            tag,
-            first_be,
-            last_be,
-        })
+        Ok((
+            Self {
+                hdr,
+                req_id,
+                tag,
+                first_be,
+                last_be,
+            },
+            rsvd,
+        ))
     }
 
     pub fn set_byte_enables(&mut self) {
@@ -114,9 +141,10 @@ impl TryFrom<&[u8]> for RequestHeader {
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         use core::cmp::Ordering;
 
-        match value.len().cmp(&8) {
-            Ordering::Less => Err(TlpError::TooShort),
-            Ordering::Greater => Err(TlpError::TooLong),
+        let (expected, actual) = (8, value.len());
+        match actual.cmp(&expected) {
+            Ordering::Less => Err(TlpError::TooShort { expected, actual }),
+            Ordering::Greater => Err(TlpError::TooLong { expected, actual }),
             // SAFETY: slice already confirmed to be correct length
             Ordering::Equal => TryInto::<[u8; 8]>::try_into(value).unwrap().try_into(),
         }
@@ -148,14 +176,46 @@ mod tests {
         fn req_hdr_first_be_too_large(first_be in 0x10u8..) {
             let req = RequestHeader::new().with_first_be(first_be);
             assert!(req.is_err());
-            assert_eq!(TlpError::TooLong, req.unwrap_err());
+            let e = req.unwrap_err();
+            assert_eq!(Some(Field::FirstBe), e.field());
+            assert_eq!(Some(7), e.offset());
         }
 
         #[test]
         fn req_hdr_last_be_too_large(last_be in 0x10u8..) {
             let req = RequestHeader::new().with_last_be(last_be);
             assert!(req.is_err());
-            assert_eq!(TlpError::TooLong, req.unwrap_err());
+            let e = TlpError::OutOfRange {
+                field: Field::LastBe,
+                value: last_be.into(),
+                min: 0,
+                max: 0xF,
+                offset: 7,
+            };
+            assert_eq!(e, req.unwrap_err());
+        }
+
+        /// Tests that reserved bits of the TLP header are reported at the right offset
+        #[test]
+        fn req_hdr_reserved_bits(hdr: RequestHeader) {
+            let mut bytes = hdr.to_bytes();
+            bytes[1] |= 0x80;
+
+            let (new_hdr, report) = RequestHeader::from_bytes_with_mode(bytes, DecodeMode::Lenient).unwrap();
+            assert_eq!(hdr, new_hdr);
+            assert_eq!(0x80, report.get(1));
+
+            let e = RequestHeader::from_bytes_with_mode(bytes, DecodeMode::Strict);
+            assert_eq!(TlpError::ReservedBitsSet { offset: 1, mask: 0x80 }, e.unwrap_err());
+        }
+
+        /// Tests that any 8 bytes decode and re-encode to exactly the same bytes
+        #[test]
+        fn req_hdr_lossless(bytes: [u8; 8]) {
+            let (hdr, report) = RequestHeader::from_bytes_with_mode(bytes, DecodeMode::Lenient).unwrap();
+            let mut new_bytes = hdr.to_bytes();
+            report.restore(&mut new_bytes);
+            assert_eq!(bytes, new_bytes);
         }
     }
 
@@ -164,7 +224,13 @@ mod tests {
         let v = vec![1, 2, 3, 4, 5, 6, 7];
         let e = RequestHeader::try_from(v.as_slice());
         assert!(e.is_err());
-        assert_eq!(TlpError::TooShort, e.unwrap_err());
+        assert_eq!(
+            TlpError::TooShort {
+                expected: 8,
+                actual: 7
+            },
+            e.unwrap_err()
+        );
     }
 
     #[test]
@@ -172,6 +238,12 @@ mod tests {
         let v = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
         let e = RequestHeader::try_from(v.as_slice());
         assert!(e.is_err());
-        assert_eq!(TlpError::TooLong, e.unwrap_err());
+        assert_eq!(
+            TlpError::TooLong {
+                expected: 8,
+                actual: 9
+            },
+            e.unwrap_err()
+        );
     }
 }
@@ -1,13 +1,16 @@
 use crate::{
-    headers::{AddressType, TlpError, TlpType, TrafficClass},
-    DWORD_LEN, MAX_DATA_LEN,
+    headers::{AddressType, DecodeMode, ReservedBits, TlpType, TrafficClass},
+    Field, TlpError, DWORD_LEN, MAX_DATA_LEN,
 };
 use num_traits::FromPrimitive;
 
 #[cfg(test)]
 use proptest_derive::Arbitrary;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 pub struct TlpHeader {
     /// Format and type
@@ -38,6 +41,9 @@ pub struct TlpHeader {
 impl TlpHeader {
     pub const LENGTH: usize = 4;
 
+    /// Reserved bits of byte 1
+    const RSVD_MASK_1: u8 = 0x88;
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -94,9 +100,20 @@ impl TlpHeader {
 
     pub fn with_length(mut self, len: u16) -> Result<Self, TlpError> {
         if usize::from(len) > MAX_DATA_LEN {
-            Err(TlpError::TooLong)
+            Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: len.into(),
+                min: 0,
+                max: MAX_DATA_LEN as u64,
+                offset: 2,
+            })
         } else if len & 0x3 > 0 {
-            Err(TlpError::NotAligned)
+            Err(TlpError::NotAligned {
+                field: Field::Length,
+                value: len.into(),
+                align: DWORD_LEN as u64,
+                offset: 2,
+            })
         } else {
             self.length = match len >> 2 {
                 1024 => 0,
@@ -107,7 +124,7 @@ impl TlpHeader {
     }
 
     pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
-        let tlp_type = self.tlp_type as u8;
+        let tlp_type = self.tlp_type.into();
         let attrs1 =
             (self.tc as u8) << 4 | (self.ibo as u8) << 2 | (self.ln as u8) << 1 | (self.th as u8);
         let attrs2 = (self.td as u8) << 7
@@ -121,7 +138,26 @@ impl TlpHeader {
     }
 
     pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Result<Self, TlpError> {
-        let tlp_type = TlpType::from_u8(bytes[0]).ok_or(TlpError::InvalidType)?;
+        Self::from_bytes_with_mode(bytes, DecodeMode::Lenient).map(|(hdr, _)| hdr)
+    }
+
+    /// Decodes a header, reporting or rejecting reserved bits according to `mode`
+    pub fn from_bytes_with_mode(
+        bytes: [u8; Self::LENGTH],
+        mode: DecodeMode,
+    ) -> Result<(Self, ReservedBits), TlpError> {
+        let mut rsvd = ReservedBits::new();
+        rsvd.check(1, bytes[1], Self::RSVD_MASK_1);
+        let rsvd = rsvd.enforce(mode)?;
+
+        let tlp_type = TlpType::from(bytes[0]);
+        if mode == DecodeMode::Strict && tlp_type.is_unknown() {
+            return Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: bytes[0].into(),
+                offset: 0,
+            });
+        }
         // SAFETY: All combinations of bits in the TC field are valid
         let tc = TrafficClass::from_u8((bytes[1] & 0x70) >> 4).unwrap();
         let ibo = (bytes[1] & 0x4) > 0;
@@ -135,19 +171,22 @@ impl TlpHeader {
         let at = AddressType::from_u8((bytes[2] & 0xC) >> 2).unwrap();
         let length = u16::from_be_bytes([bytes[2] & 0x3, bytes[3]]);
 
-        Ok(TlpHeader {
-            tlp_type,
-            tc,
-            ibo,
-            ln,
-            th,
-            td,
-            ep,
-            ro,
-            ns,
-            at,
-            length,
-        })
+        Ok((
+            TlpHeader {
+                tlp_type,
+                tc,
+                ibo,
+                ln,
+                th,
+                td,
+                ep,
+                ro,
+                ns,
+                at,
+                length,
+            },
+            rsvd,
+        ))
     }
 
     pub fn data_len(&self) -> u16 {
@@ -174,11 +213,14 @@ impl TryFrom<&[u8]> for TlpHeader {
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         use core::cmp::Ordering;
 
-        match value.len().cmp(&Self::LENGTH) {
-            Ordering::Less => Err(TlpError::TooShort),
-            Ordering::Greater => Err(TlpError::TooLong),
+        let (expected, actual) = (Self::LENGTH, value.len());
+        match actual.cmp(&expected) {
+            Ordering::Less => Err(TlpError::TooShort { expected, actual }),
+            Ordering::Greater => Err(TlpError::TooLong { expected, actual }),
             // SAFETY: Slice is already confirmed to be correct length
-            Ordering::Equal => TryInto::<[u8; Self::LENGTH]>::try_into(value).unwrap().try_into(),
+            Ordering::Equal => TryInto::<[u8; Self::LENGTH]>::try_into(value)
+                .unwrap()
+                .try_into(),
         }
     }
 }
@@ -209,7 +251,14 @@ mod tests {
         fn tlp_hdr_len_too_long(i in 4097u16..) {
             let hdr = TlpHeader::default().with_length(i);
             assert!(hdr.is_err());
-            assert_eq!(TlpError::TooLong, hdr.unwrap_err());
+            let e = TlpError::OutOfRange {
+                field: Field::Length,
+                value: i.into(),
+                min: 0,
+                max: 4096,
+                offset: 2,
+            };
+            assert_eq!(e, hdr.unwrap_err());
         }
 
         /// Tests that a length that is not dword-aligned is rejected as invalid
@@ -218,7 +267,63 @@ mod tests {
                 |x| x % 4 != 0)) {
             let hdr = TlpHeader::default().with_length(i);
             assert!(hdr.is_err());
-            assert_eq!(TlpError::NotAligned, hdr.unwrap_err());
+            let e = TlpError::NotAligned {
+                field: Field::Length,
+                value: i.into(),
+                align: 4,
+                offset: 2,
+            };
+            assert_eq!(e, hdr.unwrap_err());
+        }
+
+        /// Tests that reserved bits are reported in lenient mode and rejected in strict mode
+        #[test]
+        fn tlp_hdr_reserved_bits(hdr: TlpHeader, rsvd in 1u8..4) {
+            let mut bytes = hdr.to_bytes();
+            let mask = (rsvd & 0x1) << 3 | (rsvd & 0x2) << 6;
+            bytes[1] |= mask;
+            let err = TlpError::ReservedBitsSet { offset: 1, mask };
+
+            let (new_hdr, report) = TlpHeader::from_bytes_with_mode(bytes, DecodeMode::Lenient).unwrap();
+            assert_eq!(hdr, new_hdr);
+            assert_eq!(mask, report.get(1));
+            assert_eq!(1, report.iter().count());
+
+            let e = TlpHeader::from_bytes_with_mode(bytes, DecodeMode::Strict);
+            assert_eq!(err, e.unwrap_err());
+        }
+
+        /// Tests that any 4 bytes decode and re-encode to exactly the same bytes
+        #[test]
+        fn tlp_hdr_lossless(bytes: [u8; 4]) {
+            let (hdr, report) = TlpHeader::from_bytes_with_mode(bytes, DecodeMode::Lenient).unwrap();
+            let mut new_bytes = hdr.to_bytes();
+            report.restore(&mut new_bytes);
+            assert_eq!(bytes, new_bytes);
+        }
+
+        /// Tests that unknown types decode leniently but are rejected in strict mode
+        #[test]
+        fn tlp_hdr_unknown_type(hdr: TlpHeader, t in 0u8..) {
+            prop_assume!(TlpType::from(t).is_unknown());
+            let mut bytes = hdr.to_bytes();
+            bytes[0] = t;
+
+            let new_hdr = TlpHeader::from_bytes(bytes).unwrap();
+            assert_eq!(TlpType::from(t), new_hdr.tlp_type);
+            assert_eq!(t, u8::from(new_hdr.tlp_type));
+
+            let e = TlpHeader::from_bytes_with_mode(bytes, DecodeMode::Strict);
+            let err = TlpError::InvalidType { field: Field::FmtType, value: t.into(), offset: 0 };
+            assert_eq!(err, e.unwrap_err());
+        }
+
+        /// Tests that strict mode accepts headers with clear reserved bits
+        #[test]
+        fn tlp_hdr_strict_clean(hdr: TlpHeader) {
+            let (new_hdr, report) = TlpHeader::from_bytes_with_mode(hdr.to_bytes(), DecodeMode::Strict).unwrap();
+            assert_eq!(hdr, new_hdr);
+            assert!(report.is_empty());
         }
     }
 
@@ -227,7 +332,13 @@ mod tests {
         let v = vec![1, 2, 3];
         let e = TlpHeader::try_from(v.as_slice());
         assert!(e.is_err());
-        assert_eq!(TlpError::TooShort, e.unwrap_err());
+        assert_eq!(
+            TlpError::TooShort {
+                expected: 4,
+                actual: 3
+            },
+            e.unwrap_err()
+        );
     }
 
     #[test]
@@ -235,6 +346,12 @@ mod tests {
         let v = vec![1, 2, 3, 4, 5];
         let e = TlpHeader::try_from(v.as_slice());
         assert!(e.is_err());
-        assert_eq!(TlpError::TooLong, e.unwrap_err());
+        assert_eq!(
+            TlpError::TooLong {
+                expected: 4,
+                actual: 5
+            },
+            e.unwrap_err()
+        );
     }
 }
@@ -0,0 +1,325 @@
+//! Byte order conversion for the Avalon-ST interfaces of Intel PCIe hard IP
+//!
+//! Avalon-ST buses are little-endian, bus byte 0 being bits 7:0. Header and
+//! prefix dwords keep the bit numbering of the spec, so byte 0 of a dword
+//! lands in bits 31:24 and dword 0 in bits 31:0, while payload bytes are
+//! placed in order.
+//!
+//! The P-Tile interface carries the header, prefix and payload of a TLP on
+//! separate buses, as [`PTileHeader`] and a payload slice. Earlier hard IP
+//! carries the prefix, header and payload on a single data bus, produced by
+//! [`to_avst`] as a stream of bus bytes to be cut into beats.
+
+use crate::{
+    packets::{check_len, check_min_len},
+    Field, TlpError, TlpFormat, TlpHeader, TlpType, DWORD_LEN,
+};
+
+/// Placement of the payload after the header on a single Avalon-ST data bus
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DataAlignment {
+    /// Payload follows the header directly
+    #[default]
+    Packed,
+    /// Payload dwords of qword aligned addresses start at even dword
+    /// positions, with a gap dword after 3DW headers of qword aligned
+    /// addresses and after 4DW headers of unaligned ones
+    Qword,
+}
+
+/// Prefix, header and payload lengths of an encoded TLP in bytes, and whether
+/// its first payload dword has a qword unaligned address
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    prefix: usize,
+    hdr: usize,
+    data: usize,
+    unaligned: bool,
+}
+
+impl Layout {
+    /// Reads the layout from the prefix and header of `bytes`, which may be
+    /// followed by any payload
+    fn new(bytes: &[u8]) -> Result<Self, TlpError> {
+        check_min_len(bytes.len(), DWORD_LEN)?;
+        let prefix = match TlpType::from(bytes[0]).format() {
+            Some(TlpFormat::TlpPrefix) => DWORD_LEN,
+            _ => 0,
+        };
+        check_min_len(bytes.len(), prefix + TlpHeader::LENGTH)?;
+        // SAFETY: Slice is already confirmed to be long enough
+        let hdr = TlpHeader::from_bytes(bytes[prefix..prefix + DWORD_LEN].try_into().unwrap())
+            .map_err(|e| e.at(prefix))?;
+        let tlp_type = hdr.tlp_type;
+        let hdr_len = tlp_type.header_len().ok_or(TlpError::InvalidType {
+            field: Field::FmtType,
+            value: u8::from(tlp_type).into(),
+            offset: prefix,
+        })?;
+        check_min_len(bytes.len(), prefix + hdr_len)?;
+
+        // Bit 2 of the address, lower address or register sits in the last
+        // header byte of every TLP but messages
+        let unaligned = tlp_type.msg_routing().is_none() && bytes[prefix + hdr_len - 1] & 0x4 > 0;
+        Ok(Self {
+            prefix,
+            hdr: hdr_len,
+            data: match tlp_type.has_data() {
+                true => hdr.data_len().into(),
+                false => 0,
+            },
+            unaligned,
+        })
+    }
+
+    /// Length of the prefix and header in bytes
+    fn header_len(&self) -> usize {
+        self.prefix + self.hdr
+    }
+
+    /// Length of the gap between the header and payload on a single bus
+    fn gap(&self, align: DataAlignment) -> usize {
+        let odd = (self.header_len() / DWORD_LEN) % 2 == 1;
+        match align {
+            DataAlignment::Qword if self.data > 0 && odd != self.unaligned => DWORD_LEN,
+            _ => 0,
+        }
+    }
+}
+
+/// Copies dwords from `src` to `dst`, swapping the bytes of each
+fn swap_dwords(src: &[u8], dst: &mut [u8]) {
+    for (s, d) in src.chunks(DWORD_LEN).zip(dst.chunks_mut(DWORD_LEN)) {
+        for (i, b) in s.iter().rev().enumerate() {
+            d[i] = *b;
+        }
+    }
+}
+
+/// Lays an encoded TLP out on a single Avalon-ST data bus, returning the
+/// number of bus bytes written to `buf`
+///
+/// `buf` needs room for a gap dword on top of the TLP when aligning to qwords.
+pub fn to_avst(tlp: &[u8], align: DataAlignment, buf: &mut [u8]) -> Result<usize, TlpError> {
+    let layout = Layout::new(tlp)?;
+    let hdr_len = layout.header_len();
+    check_len(tlp.len(), hdr_len + layout.data)?;
+    let data_start = hdr_len + layout.gap(align);
+    let len = data_start + layout.data;
+    check_min_len(buf.len(), len)?;
+
+    swap_dwords(&tlp[..hdr_len], &mut buf[..hdr_len]);
+    buf[hdr_len..data_start].fill(0);
+    buf[data_start..len].clone_from_slice(&tlp[hdr_len..]);
+    Ok(len)
+}
+
+/// Reads a TLP from the bytes of a single Avalon-ST data bus, returning the
+/// number of bytes of the encoded TLP written to `buf`
+///
+/// Bus bytes after the TLP, such as the padding of its last beat, are
+/// ignored.
+pub fn from_avst(bus: &[u8], align: DataAlignment, buf: &mut [u8]) -> Result<usize, TlpError> {
+    // Swap back enough dwords to hold the longest prefix and header
+    let mut hdr = [0; DWORD_LEN + 4 * DWORD_LEN];
+    let n = bus.len().min(hdr.len()) / DWORD_LEN * DWORD_LEN;
+    swap_dwords(&bus[..n], &mut hdr[..n]);
+    let layout = Layout::new(&hdr[..n])?;
+    let hdr_len = layout.header_len();
+
+    let data_start = hdr_len + layout.gap(align);
+    check_min_len(bus.len(), data_start + layout.data)?;
+    let len = hdr_len + layout.data;
+    check_min_len(buf.len(), len)?;
+    buf[..hdr_len].clone_from_slice(&hdr[..hdr_len]);
+    buf[hdr_len..len].clone_from_slice(&bus[data_start..data_start + layout.data]);
+    Ok(len)
+}
+
+/// Header and prefix bus values of a TLP on the P-Tile Avalon-ST interface
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PTileHeader {
+    /// Header bus, with header dword 0 in bits 31:0 and dword 3 of 3DW
+    /// headers left zero
+    pub hdr: u128,
+    /// Prefix bus, zero when the TLP has no prefix
+    pub prefix: u32,
+}
+
+impl PTileHeader {
+    /// Splits an encoded TLP with at most one prefix into the header and
+    /// prefix bus values and its payload, which the data bus carries in order
+    pub fn from_tlp(tlp: &[u8]) -> Result<(Self, &[u8]), TlpError> {
+        let layout = Layout::new(tlp)?;
+        let hdr_len = layout.header_len();
+        check_len(tlp.len(), hdr_len + layout.data)?;
+
+        let dword = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+        let hdr = tlp[layout.prefix..hdr_len]
+            .chunks(DWORD_LEN)
+            .enumerate()
+            .fold(0, |hdr, (i, dw)| hdr | u128::from(dword(dw)) << (32 * i));
+        let prefix = match layout.prefix {
+            0 => 0,
+            _ => dword(tlp),
+        };
+        Ok((Self { hdr, prefix }, &tlp[hdr_len..]))
+    }
+
+    /// Encodes the TLP with payload `data` into `buf`, returning the number of
+    /// bytes written
+    pub fn to_tlp(&self, data: &[u8], buf: &mut [u8]) -> Result<usize, TlpError> {
+        let mut bytes = [0; DWORD_LEN + 4 * DWORD_LEN];
+        let base = match self.prefix {
+            0 => 0,
+            p => {
+                bytes[..DWORD_LEN].clone_from_slice(&p.to_be_bytes());
+                if TlpType::from(bytes[0]).format() != Some(TlpFormat::TlpPrefix) {
+                    return Err(TlpError::InvalidType {
+                        field: Field::FmtType,
+                        value: bytes[0].into(),
+                        offset: 0,
+                    });
+                }
+                DWORD_LEN
+            }
+        };
+        for i in 0..4 {
+            let dw = (self.hdr >> (32 * i)) as u32;
+            bytes[base + i * DWORD_LEN..][..DWORD_LEN].clone_from_slice(&dw.to_be_bytes());
+        }
+
+        let layout = Layout::new(&bytes)?;
+        let hdr_len = layout.header_len();
+        check_len(data.len(), layout.data)?;
+        let len = hdr_len + data.len();
+        check_min_len(buf.len(), len)?;
+        buf[..hdr_len].clone_from_slice(&bytes[..hdr_len]);
+        buf[hdr_len..len].clone_from_slice(data);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        strategy::{self, TlpParams},
+        Cpl, CplHeader, DeviceID, MRd, MWr, PasidPrefix, Tlp, MAX_TLP_BUFFER,
+    };
+    use proptest::prelude::*;
+
+    fn avst(tlp: &[u8], align: DataAlignment) -> Vec<u8> {
+        let mut buf = vec![0; MAX_TLP_BUFFER];
+        let len = to_avst(tlp, align, &mut buf).unwrap();
+        buf.truncate(len);
+        buf
+    }
+
+    fn mwr(addr: u64) -> Vec<u8> {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        Tlp::MWr(MWr::new(DeviceID::default(), 0, addr, &data).unwrap())
+            .to_vec()
+            .unwrap()
+    }
+
+    proptest! {
+        /// Tests that TLPs survive a single Avalon-ST bus and back, whatever
+        /// padding follows them
+        #[test]
+        fn avst_roundtrip(
+            bytes in strategy::tlp(&TlpParams::new()),
+            qword: bool,
+            padding in 0usize..32,
+        ) {
+            let align = if qword { DataAlignment::Qword } else { DataAlignment::Packed };
+            let mut bus = avst(&bytes, align);
+            bus.resize(bus.len() + padding, 0xFF);
+
+            let mut buf = vec![0; MAX_TLP_BUFFER];
+            let len = from_avst(&bus, align, &mut buf).unwrap();
+            assert_eq!(bytes, buf[..len]);
+        }
+
+        /// Tests that TLPs survive the P-Tile buses and back
+        #[test]
+        fn ptile_roundtrip(bytes in strategy::tlp(&TlpParams::new())) {
+            let (hdr, data) = PTileHeader::from_tlp(&bytes).unwrap();
+            let mut buf = vec![0; MAX_TLP_BUFFER];
+            let len = hdr.to_tlp(data, &mut buf).unwrap();
+            assert_eq!(bytes, buf[..len]);
+        }
+    }
+
+    #[test]
+    fn avst_header_order() {
+        let mrd = Tlp::MRd(MRd::new(DeviceID::new(1, 0, 0).unwrap(), 2, 0x1000, 4).unwrap())
+            .to_vec()
+            .unwrap();
+        assert_eq!(
+            [0x01, 0x00, 0x00, 0x00, 0x0F, 0x02, 0x00, 0x01, 0x00, 0x10, 0x00, 0x00],
+            avst(&mrd, DataAlignment::Qword)[..]
+        );
+
+        let (hdr, data) = PTileHeader::from_tlp(&mrd).unwrap();
+        assert_eq!(0x0000_1000_0100_020F_0000_0001, hdr.hdr);
+        assert_eq!(0, hdr.prefix);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn avst_qword_alignment() {
+        // Bus dword that the payload starts at
+        let start = |tlp: &[u8]| {
+            let bus = avst(tlp, DataAlignment::Qword);
+            (bus.len() - (tlp.len() - Layout::new(tlp).unwrap().header_len())) / DWORD_LEN
+        };
+        assert_eq!(4, start(&mwr(0x1000)));
+        assert_eq!(3, start(&mwr(0x1004)));
+        assert_eq!(4, start(&mwr(0x1_0000_0000)));
+        assert_eq!(5, start(&mwr(0x1_0000_0004)));
+        assert_eq!(20, avst(&mwr(0x1000), DataAlignment::Packed).len());
+
+        let cpl = |addr_low| {
+            let hdr = CplHeader::new().with_addr(addr_low).unwrap();
+            Tlp::Cpl(Cpl::new(hdr, &[0; 4]).unwrap()).to_vec().unwrap()
+        };
+        assert_eq!(4, start(&cpl(0x40)));
+        assert_eq!(3, start(&cpl(0x44)));
+    }
+
+    #[test]
+    fn prefixes() {
+        let prefix = PasidPrefix::new().with_pasid(5).unwrap();
+        let mut tlp = prefix.to_bytes().to_vec();
+        tlp.extend_from_slice(&mwr(0x1000));
+
+        let (hdr, _) = PTileHeader::from_tlp(&tlp).unwrap();
+        assert_eq!(u32::from_be_bytes(prefix.to_bytes()), hdr.prefix);
+        // The prefix shifts the header, so the payload needs no gap
+        assert_eq!(tlp.len(), avst(&tlp, DataAlignment::Qword).len());
+
+        let mut twice = prefix.to_bytes().to_vec();
+        twice.extend_from_slice(&tlp);
+        assert!(matches!(
+            PTileHeader::from_tlp(&twice),
+            Err(TlpError::InvalidType { offset: 4, .. })
+        ));
+
+        let bad = PTileHeader {
+            hdr: 0,
+            prefix: 0x1234_5678,
+        };
+        assert!(bad.to_tlp(&[], &mut [0; 32]).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let bus = avst(&mwr(0x1000), DataAlignment::Qword);
+        let mut buf = [0; 64];
+        for len in 0..bus.len() {
+            assert!(from_avst(&bus[..len], DataAlignment::Qword, &mut buf).is_err());
+        }
+        assert!(to_avst(&mwr(0x1000)[..16], DataAlignment::Packed, &mut buf).is_err());
+    }
+}
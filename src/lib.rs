@@ -1,12 +1,34 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+#[cfg(any(test, feature = "alloc"))]
+extern crate alloc;
+
+#[macro_use]
+mod macros;
 
 mod address;
+#[cfg(any(test, feature = "std"))]
+pub mod capture;
+pub mod config;
 mod device_id;
+mod error;
+pub mod fc;
 mod headers;
+pub mod intel;
+pub mod ordering;
 mod packets;
+mod pretty;
+#[cfg(any(test, feature = "alloc"))]
+pub mod sim;
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategy;
+#[cfg(any(test, feature = "alloc"))]
+pub mod stream;
+pub mod xilinx;
 
 pub use address::Address;
-pub use device_id::DeviceID;
+pub use device_id::{DeviceID, DeviceIDError};
+pub use error::{Field, TlpError};
 pub use headers::*;
 pub use packets::*;
 
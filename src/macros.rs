@@ -0,0 +1,102 @@
+//! Macros shared across the crate
+
+/// Defines an enum over `u8` encodings plus a catch-all variant, given after
+/// the listed encodings, so that every encoding decodes and re-encodes
+/// losslessly
+///
+/// The catch-all variant wraps a newtype whose value can only be an encoding
+/// that fits in the field, `$max` having every bit of the field set, and has
+/// no variant of its own. Encodings convert back with `FromPrimitive`, which
+/// rejects values that do not fit in the field.
+macro_rules! lossless_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $(
+                $(#[$vmeta:meta])*
+                $variant:ident = $value:expr,
+            )+
+        }
+        $(#[$cmeta:meta])*
+        $catch_all:ident($raw:ident, $max:expr)
+    ) => {
+        $(#[$meta])*
+        pub enum $name {
+            $(
+                $(#[$vmeta])*
+                $variant,
+            )+
+            $(#[$cmeta])*
+            $catch_all($raw),
+        }
+
+        #[doc = concat!("Encoding of a [`", stringify!($name), "`] without a variant of its own")]
+        #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+        pub struct $raw(u8);
+
+        impl $raw {
+            /// Returns `None` if `value` has a variant of its own or does not
+            /// fit in the field
+            pub fn new(value: u8) -> Option<Self> {
+                match <$name as num_traits::FromPrimitive>::from_u8(value)? {
+                    $name::$catch_all(raw) => Some(raw),
+                    _ => None,
+                }
+            }
+
+            pub fn value(self) -> u8 {
+                self.0
+            }
+        }
+
+        impl $name {
+            /// Largest encoding, with every bit of the field set
+            pub const MAX: u8 = $max;
+        }
+
+        impl From<$name> for u8 {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)+
+                    $name::$catch_all(raw) => raw.0,
+                }
+            }
+        }
+
+        impl num_traits::FromPrimitive for $name {
+            fn from_i64(n: i64) -> Option<Self> {
+                u64::try_from(n).ok().and_then(Self::from_u64)
+            }
+
+            fn from_u64(n: u64) -> Option<Self> {
+                let value = u8::try_from(n).ok().filter(|&v| v <= Self::MAX)?;
+                $(
+                    if value == $value {
+                        return Some(Self::$variant);
+                    }
+                )+
+                Some(Self::$catch_all($raw(value)))
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $raw {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_u8(self.0)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $raw {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = <u8 as serde::Deserialize>::deserialize(deserializer)?;
+                Self::new(value).ok_or_else(|| {
+                    serde::de::Error::invalid_value(
+                        serde::de::Unexpected::Unsigned(value.into()),
+                        &concat!("an encoding of ", stringify!($name), " without a variant"),
+                    )
+                })
+            }
+        }
+    };
+}
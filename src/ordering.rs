@@ -0,0 +1,352 @@
+//! Transaction ordering rules
+//!
+//! Decides whether a TLP may pass an earlier one in the same stream,
+//! following the ordering rules summary table of the spec. Relaxed ordering
+//! lets posted requests and completions pass posted requests; ID-based
+//! ordering does the same for traffic from different requesters. No-snoop
+//! has no effect on ordering.
+
+use crate::{packets::check_min_len, DeviceID, Field, Tlp, TlpClass, TlpError, TlpHeader};
+use byteorder::{BigEndian, ByteOrder};
+
+/// Whether a TLP may pass an earlier one
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Passing {
+    /// Must be able to pass, to avoid deadlocks
+    Required,
+    /// Permitted to pass, but not required to
+    Permitted,
+    /// Must not pass
+    Forbidden,
+}
+
+impl Passing {
+    /// Returns true unless passing is forbidden
+    pub fn is_allowed(&self) -> bool {
+        *self != Passing::Forbidden
+    }
+}
+
+/// Attributes of a TLP that its ordering depends on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OrderingInfo {
+    pub class: TlpClass,
+    /// Relaxed ordering
+    pub ro: bool,
+    /// ID-based ordering
+    pub ido: bool,
+    /// Requester ID of a request, or completer ID of a completion
+    pub id: DeviceID,
+    /// Requester ID and tag of the request a completion answers
+    pub transaction: Option<(DeviceID, u8)>,
+    /// PASID of the prefix, if any
+    pub pasid: Option<u32>,
+}
+
+impl OrderingInfo {
+    /// Extracts the ordering attributes of an encoded TLP
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (prefix, rest) = Tlp::split_prefix(bytes)?;
+        let base = bytes.len() - rest.len();
+        // SAFETY: Slice is already confirmed to be long enough
+        let hdr = TlpHeader::from_bytes(rest[..TlpHeader::LENGTH].try_into().unwrap())
+            .map_err(|e| e.at(base))?;
+        let class = hdr.tlp_type.class().ok_or(TlpError::InvalidType {
+            field: Field::FmtType,
+            value: rest[0].into(),
+            offset: base,
+        })?;
+
+        check_min_len(rest.len(), 8).map_err(|e| e.at(base))?;
+        let id = BigEndian::read_u16(&rest[4..6]).into();
+        let transaction = if class == TlpClass::Completion {
+            check_min_len(rest.len(), 12).map_err(|e| e.at(base))?;
+            Some((BigEndian::read_u16(&rest[8..10]).into(), rest[10]))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            class,
+            ro: hdr.ro,
+            ido: hdr.ibo,
+            id,
+            transaction,
+            pasid: prefix.map(|p| p.pasid),
+        })
+    }
+
+    /// Returns true if ID-based ordering tells the two TLPs apart
+    fn ids_differ(&self, earlier: &Self) -> bool {
+        match (self.pasid, earlier.pasid) {
+            (Some(a), Some(b)) if a != b => true,
+            _ => self.id != earlier.id,
+        }
+    }
+}
+
+/// Returns whether `later` may pass `earlier`, a TLP sent before it in the
+/// same direction
+pub fn can_pass(later: &OrderingInfo, earlier: &OrderingInfo) -> Passing {
+    use TlpClass::*;
+
+    // Relaxed ordering only lets posted requests and completions pass
+    // posted requests; ID-based ordering also applies to non-posted ones
+    let relaxed = |ro_applies: bool| {
+        if (ro_applies && later.ro) || (later.ido && later.ids_differ(earlier)) {
+            Passing::Permitted
+        } else {
+            Passing::Forbidden
+        }
+    };
+
+    match (later.class, earlier.class) {
+        (Posted, Posted) => relaxed(true),
+        (Posted, NonPosted) | (Completion, NonPosted) => Passing::Required,
+        (Posted, Completion) => Passing::Permitted,
+        (NonPosted, Posted) => relaxed(false),
+        (NonPosted, NonPosted) | (NonPosted, Completion) => Passing::Permitted,
+        (Completion, Posted) => relaxed(true),
+        // Completions of the same request stay in address order
+        (Completion, Completion) if later.transaction == earlier.transaction => Passing::Forbidden,
+        (Completion, Completion) => Passing::Permitted,
+    }
+}
+
+/// Shuffles streams of TLPs using only the reorderings the ordering rules
+/// permit
+///
+/// Randomness comes from a small seeded generator, so runs are repeatable.
+#[derive(Clone, Debug)]
+pub struct Reorderer {
+    state: u64,
+}
+
+impl Reorderer {
+    pub fn new(seed: u64) -> Self {
+        // The generator gets stuck at 0
+        Self {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    /// xorshift64* step
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Tries `attempts` swaps of random neighbouring TLPs of `stream`, each
+    /// applied only if the later TLP may pass the earlier one, and returns
+    /// the number of swaps applied
+    ///
+    /// Every pair of TLPs that ends up out of order was swapped directly, so
+    /// the result only holds legal reorderings of the input.
+    pub fn shuffle<T>(&mut self, stream: &mut [T], attempts: usize) -> Result<usize, TlpError>
+    where
+        T: AsRef<[u8]>,
+    {
+        if stream.len() < 2 {
+            return Ok(0);
+        }
+
+        let mut swaps = 0;
+        for _ in 0..attempts {
+            let i = (self.next() % (stream.len() as u64 - 1)) as usize;
+            let earlier = OrderingInfo::from_bytes(stream[i].as_ref())?;
+            let later = OrderingInfo::from_bytes(stream[i + 1].as_ref())?;
+            if can_pass(&later, &earlier).is_allowed() {
+                stream.swap(i, i + 1);
+                swaps += 1;
+            }
+        }
+        Ok(swaps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cpl, CplHeader, MRd, MWr, TlpType};
+    use proptest::prelude::*;
+
+    fn info(class: TlpClass, id: u16) -> OrderingInfo {
+        OrderingInfo {
+            class,
+            ro: false,
+            ido: false,
+            id: id.into(),
+            transaction: (class == TlpClass::Completion).then(|| (DeviceID::default(), 0)),
+            pasid: None,
+        }
+    }
+
+    #[test]
+    fn ordering_table() {
+        use Passing::*;
+        use TlpClass::*;
+
+        let classes = [Posted, NonPosted, Completion];
+        let expected = [
+            [Forbidden, Required, Permitted],
+            [Forbidden, Permitted, Permitted],
+            [Forbidden, Required, Forbidden],
+        ];
+        for (row, later) in classes.iter().enumerate() {
+            for (col, earlier) in classes.iter().enumerate() {
+                let pass = can_pass(&info(*later, 1), &info(*earlier, 1));
+                assert_eq!(
+                    expected[row][col], pass,
+                    "{:?} passing {:?}",
+                    later, earlier
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ordering_relaxed_and_id_based() {
+        let posted = info(TlpClass::Posted, 1);
+        let ro = |class| OrderingInfo {
+            ro: true,
+            ..info(class, 1)
+        };
+        let ido = |class, id| OrderingInfo {
+            ido: true,
+            ..info(class, id)
+        };
+
+        assert_eq!(Passing::Permitted, can_pass(&ro(TlpClass::Posted), &posted));
+        assert_eq!(
+            Passing::Permitted,
+            can_pass(&ro(TlpClass::Completion), &posted)
+        );
+        assert_eq!(
+            Passing::Forbidden,
+            can_pass(&ro(TlpClass::NonPosted), &posted)
+        );
+
+        for class in [TlpClass::Posted, TlpClass::NonPosted, TlpClass::Completion] {
+            assert_eq!(Passing::Permitted, can_pass(&ido(class, 2), &posted));
+            assert_eq!(Passing::Forbidden, can_pass(&ido(class, 1), &posted));
+        }
+
+        // Same requester but different PASIDs
+        let later = OrderingInfo {
+            pasid: Some(1),
+            ..ido(TlpClass::Posted, 1)
+        };
+        let earlier = OrderingInfo {
+            pasid: Some(2),
+            ..posted
+        };
+        assert_eq!(Passing::Permitted, can_pass(&later, &earlier));
+
+        // Completions of different requests
+        let other = OrderingInfo {
+            transaction: Some((1.into(), 0)),
+            ..info(TlpClass::Completion, 1)
+        };
+        let cpl = info(TlpClass::Completion, 1);
+        assert_eq!(Passing::Permitted, can_pass(&other, &cpl));
+    }
+
+    #[test]
+    fn ordering_info_from_bytes() {
+        let data = [0; 4];
+        let mut req = MWr::new(DeviceID::new(1, 2, 3).unwrap(), 0, 0x1000, &data).unwrap();
+        req.hdr.hdr.ro = true;
+        let mut buf = [0; 16];
+        let len = req.to_bytes(&mut buf).unwrap();
+        let i = OrderingInfo::from_bytes(&buf[..len]).unwrap();
+        assert_eq!((TlpClass::Posted, true, false), (i.class, i.ro, i.ido));
+        assert_eq!(DeviceID::new(1, 2, 3).unwrap(), i.id);
+
+        let hdr = CplHeader::new()
+            .with_cpl_id(DeviceID::new(4, 0, 0).unwrap())
+            .with_req_id(DeviceID::new(1, 2, 3).unwrap())
+            .with_tag(9);
+        let cpl = Cpl::new(hdr, &data).unwrap();
+        let len = cpl.to_bytes(&mut buf).unwrap();
+        let i = OrderingInfo::from_bytes(&buf[..len]).unwrap();
+        assert_eq!(DeviceID::new(4, 0, 0).unwrap(), i.id);
+        assert_eq!(Some((DeviceID::new(1, 2, 3).unwrap(), 9)), i.transaction);
+
+        let prefix = TlpHeader::new().with_type(TlpType::PASID).to_bytes();
+        assert!(OrderingInfo::from_bytes(&prefix).is_err());
+    }
+
+    fn stream_tlp(i: usize, kind: u8, attrs: u8) -> (usize, Vec<u8>) {
+        let id = DeviceID::new(attrs & 0x3, 0, 0).unwrap();
+        let data = [0; 4];
+        let mut buf = vec![0; 32];
+        let len = match kind {
+            0 => {
+                let mut req = MWr::new(id, 0, 0x1000, &data).unwrap();
+                req.hdr.hdr.ro = attrs & 0x4 > 0;
+                req.hdr.hdr.ibo = attrs & 0x8 > 0;
+                req.to_bytes(&mut buf).unwrap()
+            }
+            1 => {
+                let mut req = MRd::new(id, i as u8, 0x1000, 4).unwrap();
+                req.hdr.hdr.ibo = attrs & 0x8 > 0;
+                req.to_bytes(&mut buf).unwrap()
+            }
+            _ => {
+                let mut hdr = CplHeader::new().with_cpl_id(id).with_tag(attrs & 0x10);
+                hdr.hdr.ro = attrs & 0x4 > 0;
+                hdr.hdr.ibo = attrs & 0x8 > 0;
+                Cpl::new(hdr, &data).unwrap().to_bytes(&mut buf).unwrap()
+            }
+        };
+        buf.truncate(len);
+        (i, buf)
+    }
+
+    struct Entry((usize, Vec<u8>));
+
+    impl AsRef<[u8]> for Entry {
+        fn as_ref(&self) -> &[u8] {
+            &self.0 .1
+        }
+    }
+
+    proptest! {
+        /// Tests that every pair a shuffle leaves out of order may be reordered
+        #[test]
+        fn ordering_shuffle_is_legal(seed: u64,
+                tlps in prop::collection::vec((0u8..3, any::<u8>()), 2..32)) {
+            let orig: Vec<_> = tlps.iter().enumerate()
+                .map(|(i, (kind, attrs))| stream_tlp(i, *kind, *attrs))
+                .collect();
+            let mut stream: Vec<_> = orig.iter().cloned().map(Entry).collect();
+            Reorderer::new(seed).shuffle(&mut stream, 256).unwrap();
+
+            for (pos, a) in stream.iter().enumerate() {
+                for b in &stream[pos + 1..] {
+                    let (earlier, later) = (&b.0, &a.0);
+                    if later.0 > earlier.0 {
+                        let later = OrderingInfo::from_bytes(&later.1).unwrap();
+                        let earlier = OrderingInfo::from_bytes(&earlier.1).unwrap();
+                        prop_assert!(can_pass(&later, &earlier).is_allowed());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ordering_shuffle_moves_posted_past_reads() {
+        let mut stream: Vec<_> = [1, 0].iter().map(|k| Entry(stream_tlp(0, *k, 0))).collect();
+        let swaps = Reorderer::new(1).shuffle(&mut stream, 1).unwrap();
+        assert_eq!(1, swaps);
+        let first = OrderingInfo::from_bytes(stream[0].as_ref()).unwrap();
+        assert_eq!(TlpClass::Posted, first.class);
+    }
+}
@@ -0,0 +1,749 @@
+use crate::{
+    packets::{check_min_len, decode_msg, decode_prefix, encode_msg, encode_prefix, Cpl},
+    Address, AddressType, CplHeader, DeviceID, Field, MRd, MsgHeader, MsgRouting, PasidPrefix,
+    TlpError, TlpHeader, TlpType,
+};
+use byteorder::{BigEndian, ByteOrder};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Smallest translation and the alignment of untranslated addresses
+const PAGE_SIZE: u64 = 4096;
+
+/// Mask of the page address bits 63:12
+const PAGE_MASK: u64 = !(PAGE_SIZE - 1);
+
+/// Encodes a range of `size` bytes at `base` as a page address whose low bits
+/// are set to give the size, as used by translations and invalidations,
+/// returning the address and the S bit
+fn encode_size(base: u64, size: u64, offset: usize) -> Result<(u64, bool), TlpError> {
+    if size < PAGE_SIZE || !size.is_power_of_two() {
+        return Err(TlpError::OutOfRange {
+            field: Field::Length,
+            value: size,
+            min: PAGE_SIZE,
+            max: 1 << 63,
+            offset,
+        });
+    }
+    if base & (size - 1) != 0 {
+        return Err(TlpError::NotAligned {
+            field: Field::Address,
+            value: base,
+            align: size,
+            offset,
+        });
+    }
+
+    // Bits below the most significant size bit are set to encode the size
+    Ok((base | ((size / 2 - 1) & PAGE_MASK), size > PAGE_SIZE))
+}
+
+/// Decodes the size in bytes of a page address and S bit, capped at 2^63
+fn decode_size(addr: u64, s: bool) -> u64 {
+    if !s {
+        return PAGE_SIZE;
+    }
+
+    let ones = (addr >> 12).trailing_ones();
+    1u64 << (13 + ones).min(63)
+}
+
+/// ATS translation request, a memory read with the translation request
+/// address type
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TranslationRequest {
+    pub prefix: Option<PasidPrefix>,
+    pub req: MRd,
+    /// No write, the function only needs read access
+    pub no_write: bool,
+}
+
+impl TranslationRequest {
+    /// Largest number of translations that fit in a single completion
+    pub const MAX_COUNT: u16 = (crate::MAX_DATA_LEN / TranslationEntry::LENGTH) as u16;
+
+    /// Builds a request for `count` translations starting at the untranslated
+    /// address `addr`, which must be 4 KiB aligned
+    pub fn new(req_id: DeviceID, tag: u8, addr: u64, count: u16) -> Result<Self, TlpError> {
+        if addr & (PAGE_SIZE - 1) != 0 {
+            return Err(TlpError::NotAligned {
+                field: Field::Address,
+                value: addr,
+                align: PAGE_SIZE,
+                offset: 8,
+            });
+        }
+        if count == 0 || count > Self::MAX_COUNT {
+            return Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: count.into(),
+                min: 1,
+                max: Self::MAX_COUNT.into(),
+                offset: 2,
+            });
+        }
+
+        let length = count * TranslationEntry::LENGTH as u16;
+        let mut req = MRd::new(req_id, tag, addr, length)?;
+        req.hdr.hdr.at = AddressType::TranslationRequest;
+
+        Ok(Self {
+            prefix: None,
+            req,
+            no_write: false,
+        })
+    }
+
+    pub fn with_pasid(mut self, prefix: PasidPrefix) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    pub fn with_no_write(mut self, no_write: bool) -> Self {
+        self.no_write = no_write;
+        self
+    }
+
+    /// Returns the untranslated address
+    pub fn untranslated(&self) -> u64 {
+        match self.req.addr {
+            Address::Addr32(a) => a.into(),
+            Address::Addr64(a) => a,
+        }
+    }
+
+    /// Returns the number of translations requested
+    pub fn count(&self) -> u16 {
+        self.req.hdr.hdr.data_len() / TranslationEntry::LENGTH as u16
+    }
+
+    /// Length of the encoded request in bytes, including any prefix
+    pub fn encoded_len(&self) -> usize {
+        self.prefix_len() + self.req.encoded_len()
+    }
+
+    fn prefix_len(&self) -> usize {
+        self.prefix.map_or(0, |_| PasidPrefix::LENGTH)
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let len = self.encoded_len();
+        check_min_len(buf.len(), len)?;
+
+        let base = encode_prefix(self.prefix, buf)?;
+        self.req.to_bytes(&mut buf[base..len])?;
+        // The no write flag sits in the otherwise reserved low bit of the address
+        buf[len - 1] |= self.no_write as u8;
+        Ok(len)
+    }
+
+    /// Decodes a request, with or without a PASID prefix
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (prefix, base) = decode_prefix(bytes)?;
+
+        let req = MRd::from_bytes(&bytes[base..]).map_err(|e| e.at(base))?;
+        if req.hdr.hdr.at != AddressType::TranslationRequest {
+            return Err(TlpError::InvalidType {
+                field: Field::AddressType,
+                value: (req.hdr.hdr.at as u8).into(),
+                offset: base + 2,
+            });
+        }
+        // Every translation takes two dwords of the completion
+        let len = req.hdr.hdr.data_len();
+        if !len.is_multiple_of(TranslationEntry::LENGTH as u16) {
+            return Err(TlpError::NotAligned {
+                field: Field::Length,
+                value: len.into(),
+                align: TranslationEntry::LENGTH as u64,
+                offset: base + 2,
+            });
+        }
+
+        Ok(Self {
+            prefix,
+            req,
+            no_write: bytes[bytes.len() - 1] & 0x1 > 0,
+        })
+    }
+
+    /// Builds the translation completion returned by `cpl_id`, whose payload
+    /// is a series of encoded `TranslationEntry`s
+    pub fn completion<'a, T>(&self, cpl_id: T, data: &'a [u8]) -> Result<Cpl<'a>, TlpError>
+    where
+        T: Into<DeviceID>,
+    {
+        let hdr = CplHeader::new()
+            .with_cpl_id(cpl_id)
+            .with_req_id(self.req.hdr.req_id)
+            .with_tag(self.req.hdr.tag)
+            // A byte count of 0 encodes 4096 bytes
+            .with_bc(data.len() as u16 & 0xFFF)?;
+        Cpl::new(hdr, data)
+    }
+}
+
+/// Translation returned in the payload of a translation completion
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TranslationEntry {
+    /// Translated address bits 63:12, holding the size encoding when `s` is set
+    pub addr: u64,
+    /// Size, the translation is larger than 4 KiB
+    pub s: bool,
+    /// Non-snooped accesses
+    pub n: bool,
+    /// Global mapping
+    pub global: bool,
+    /// Privileged mode access
+    pub privileged: bool,
+    /// Execute permitted
+    pub exe: bool,
+    /// Untranslated access only
+    pub u: bool,
+    /// Write permitted
+    pub w: bool,
+    /// Read permitted
+    pub r: bool,
+}
+
+impl TranslationEntry {
+    pub const LENGTH: usize = 8;
+
+    /// Builds a translation of `size` bytes at the translated address `base`
+    ///
+    /// `size` must be a power of two of at least 4 KiB and `base` aligned to it.
+    pub fn new(base: u64, size: u64) -> Result<Self, TlpError> {
+        let (addr, s) = encode_size(base, size, 0)?;
+        Ok(Self {
+            addr,
+            s,
+            ..Default::default()
+        })
+    }
+
+    /// Returns the size of the translation in bytes, capped at 2^63
+    pub fn size(&self) -> u64 {
+        decode_size(self.addr, self.s)
+    }
+
+    /// Returns the translated base address with the size encoding removed
+    pub fn base(&self) -> u64 {
+        self.addr & !(self.size() - 1)
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let x = (self.addr & PAGE_MASK)
+            | (self.s as u64) << 11
+            | (self.n as u64) << 10
+            | (self.global as u64) << 5
+            | (self.privileged as u64) << 4
+            | (self.exe as u64) << 3
+            | (self.u as u64) << 2
+            | (self.w as u64) << 1
+            | (self.r as u64);
+        x.to_be_bytes()
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Self {
+        let x = u64::from_be_bytes(bytes);
+        Self {
+            addr: x & PAGE_MASK,
+            s: x & (1 << 11) > 0,
+            n: x & (1 << 10) > 0,
+            global: x & (1 << 5) > 0,
+            privileged: x & (1 << 4) > 0,
+            exe: x & (1 << 3) > 0,
+            u: x & (1 << 2) > 0,
+            w: x & (1 << 1) > 0,
+            r: x & 1 > 0,
+        }
+    }
+}
+
+/// ATS translation completion
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TranslationCompletion<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub cpl: Cpl<'a>,
+}
+
+impl<'a> TranslationCompletion<'a> {
+    /// Decodes a translation completion, borrowing its payload from `bytes`
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TlpError> {
+        Cpl::from_bytes(bytes)?.try_into()
+    }
+
+    /// Iterates over the translations in the payload
+    pub fn entries(&self) -> impl Iterator<Item = TranslationEntry> + 'a {
+        self.cpl
+            .data
+            .chunks_exact(TranslationEntry::LENGTH)
+            .map(|c| {
+                // SAFETY: Chunks are exactly the length of an entry
+                TranslationEntry::from_bytes(c.try_into().unwrap())
+            })
+    }
+
+    /// Iterates over the untranslated ranges covered by each translation
+    ///
+    /// `untranslated` is the address of the request and `stu` the smallest
+    /// translation unit programmed in the ATS capability, each range covers at
+    /// least `4096 << stu` bytes.
+    pub fn ranges(&self, untranslated: u64, stu: u8) -> impl Iterator<Item = TranslatedRange> + 'a {
+        let stu_size = PAGE_SIZE << stu.min(51);
+        let mut next = untranslated;
+        self.entries().map(move |entry| {
+            let len = entry.size().max(stu_size);
+            let start = next & !(len - 1);
+            next = start.wrapping_add(len);
+            TranslatedRange {
+                untranslated: start,
+                len,
+                entry,
+            }
+        })
+    }
+}
+
+impl<'a> TryFrom<Cpl<'a>> for TranslationCompletion<'a> {
+    type Error = TlpError;
+
+    fn try_from(cpl: Cpl<'a>) -> Result<Self, Self::Error> {
+        if cpl.data.len() & (TranslationEntry::LENGTH - 1) != 0 {
+            return Err(TlpError::NotAligned {
+                field: Field::Length,
+                value: cpl.data.len() as u64,
+                align: TranslationEntry::LENGTH as u64,
+                offset: 2,
+            });
+        }
+        Ok(Self { cpl })
+    }
+}
+
+/// Untranslated range covered by a single translation
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TranslatedRange {
+    /// Start of the untranslated range
+    pub untranslated: u64,
+    /// Length of the range in bytes
+    pub len: u64,
+    pub entry: TranslationEntry,
+}
+
+impl TranslatedRange {
+    /// Returns true if the untranslated address falls within the range
+    pub fn contains(&self, addr: u64) -> bool {
+        addr.wrapping_sub(self.untranslated) < self.len
+    }
+
+    /// Translates an untranslated address, if it falls within the range
+    pub fn translate(&self, addr: u64) -> Option<u64> {
+        if self.contains(addr) {
+            Some(self.entry.base() + (addr - self.untranslated))
+        } else {
+            None
+        }
+    }
+}
+
+/// ATS invalidate request, sent by a translation agent to a function
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InvalidateRequest {
+    pub prefix: Option<PasidPrefix>,
+    /// Translation agent sending the request
+    pub req_id: DeviceID,
+    /// Function whose translations are invalidated
+    pub target: DeviceID,
+    /// Invalidation tag, returned in the completion
+    pub itag: u8,
+    /// Untranslated address bits 63:12, holding the size encoding when `s` is set
+    pub addr: u64,
+    /// Size, the range is larger than 4 KiB
+    pub s: bool,
+    /// Invalidate global mappings of all PASIDs
+    pub global: bool,
+}
+
+impl InvalidateRequest {
+    pub const CODE: u8 = 0b0000_0001;
+
+    /// Largest invalidation tag
+    pub const MAX_ITAG: u8 = 31;
+
+    const DATA_LEN: usize = 8;
+
+    /// Builds a request invalidating `size` bytes of untranslated addresses at
+    /// `base` in `target`
+    pub fn new<T, U>(req_id: T, target: U, itag: u8, base: u64, size: u64) -> Result<Self, TlpError>
+    where
+        T: Into<DeviceID>,
+        U: Into<DeviceID>,
+    {
+        if itag > Self::MAX_ITAG {
+            return Err(TlpError::OutOfRange {
+                field: Field::ITag,
+                value: itag.into(),
+                min: 0,
+                max: Self::MAX_ITAG.into(),
+                offset: 15,
+            });
+        }
+        let (addr, s) = encode_size(base, size, MsgHeader::LENGTH)?;
+
+        Ok(Self {
+            prefix: None,
+            req_id: req_id.into(),
+            target: target.into(),
+            itag,
+            addr,
+            s,
+            global: false,
+        })
+    }
+
+    pub fn with_pasid(mut self, prefix: PasidPrefix) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    pub fn with_global(mut self, global: bool) -> Self {
+        self.global = global;
+        self
+    }
+
+    /// Returns the size of the invalidated range in bytes, capped at 2^63
+    pub fn size(&self) -> u64 {
+        decode_size(self.addr, self.s)
+    }
+
+    /// Returns the untranslated base address with the size encoding removed
+    pub fn base(&self) -> u64 {
+        self.addr & !(self.size() - 1)
+    }
+
+    /// Length of the encoded request in bytes, including any prefix
+    pub fn encoded_len(&self) -> usize {
+        self.prefix.map_or(0, |_| PasidPrefix::LENGTH) + MsgHeader::LENGTH + Self::DATA_LEN
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let tlp_hdr = TlpHeader::new()
+            .with_type(TlpType::msg(MsgRouting::ById, true))
+            .with_length(Self::DATA_LEN as u16)?;
+        let mut hdr = MsgHeader::new()
+            .with_hdr(tlp_hdr)
+            .with_req_id(self.req_id)
+            .with_code(Self::CODE)
+            .with_target_id(self.target);
+        hdr.body[7] = self.itag & Self::MAX_ITAG;
+
+        let x = (self.addr & PAGE_MASK) | (self.s as u64) << 11 | (self.global as u64);
+        encode_msg(self.prefix, &hdr, &x.to_be_bytes(), buf)
+    }
+
+    /// Decodes a request, with or without a PASID prefix
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (prefix, hdr, data) =
+            decode_msg(bytes, TlpType::msg(MsgRouting::ById, true), Self::CODE)?;
+        if data.len() != Self::DATA_LEN {
+            return Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: data.len() as u64,
+                min: Self::DATA_LEN as u64,
+                max: Self::DATA_LEN as u64,
+                offset: 2,
+            });
+        }
+        let x = BigEndian::read_u64(data);
+
+        Ok(Self {
+            prefix,
+            req_id: hdr.req_id,
+            // SAFETY: The header is always routed by ID
+            target: hdr.target_id().unwrap(),
+            itag: hdr.body[7] & Self::MAX_ITAG,
+            addr: x & PAGE_MASK,
+            s: x & (1 << 11) > 0,
+            global: x & 1 > 0,
+        })
+    }
+
+    /// Builds the completion returned by the target, one of `cc` completions
+    /// sent for this request
+    pub fn completion(&self, cc: u8) -> Result<InvalidateCompletion, TlpError> {
+        InvalidateCompletion::new(self.target, self.req_id, cc, 1 << self.itag)
+    }
+}
+
+/// ATS invalidate completion, sent by a function back to the translation agent
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InvalidateCompletion {
+    /// Function that completed the invalidation
+    pub req_id: DeviceID,
+    /// Translation agent that sent the request
+    pub target: DeviceID,
+    /// Number of completions sent for the request, 1 to 8
+    pub cc: u8,
+    /// One bit set for each invalidation tag that is completed
+    pub itag_vector: u32,
+}
+
+impl InvalidateCompletion {
+    pub const CODE: u8 = 0b0000_0010;
+
+    /// Largest completion count
+    pub const MAX_CC: u8 = 8;
+
+    pub fn new<T, U>(req_id: T, target: U, cc: u8, itag_vector: u32) -> Result<Self, TlpError>
+    where
+        T: Into<DeviceID>,
+        U: Into<DeviceID>,
+    {
+        if cc == 0 || cc > Self::MAX_CC {
+            return Err(TlpError::OutOfRange {
+                field: Field::CompletionCount,
+                value: cc.into(),
+                min: 1,
+                max: Self::MAX_CC.into(),
+                offset: 11,
+            });
+        }
+
+        Ok(Self {
+            req_id: req_id.into(),
+            target: target.into(),
+            cc,
+            itag_vector,
+        })
+    }
+
+    /// Returns true if the completion covers the invalidation tag
+    pub fn completes(&self, itag: u8) -> bool {
+        itag <= InvalidateRequest::MAX_ITAG && self.itag_vector & (1 << itag) > 0
+    }
+
+    /// Length of the encoded completion in bytes
+    pub fn encoded_len(&self) -> usize {
+        MsgHeader::LENGTH
+    }
+
+    /// Encodes the completion into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let mut hdr = MsgHeader::new()
+            .with_hdr(TlpHeader::new().with_type(TlpType::msg(MsgRouting::ById, false)))
+            .with_req_id(self.req_id)
+            .with_code(Self::CODE)
+            .with_target_id(self.target);
+        // A count of 8 is encoded as 0
+        hdr.body[3] = self.cc & 0x7;
+        hdr.body[4..8].clone_from_slice(&self.itag_vector.to_be_bytes());
+        encode_msg(None, &hdr, &[], buf)
+    }
+
+    /// Decodes a completion
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (_, hdr, _) = decode_msg(bytes, TlpType::msg(MsgRouting::ById, false), Self::CODE)?;
+
+        Ok(Self {
+            req_id: hdr.req_id,
+            // SAFETY: The header is always routed by ID
+            target: hdr.target_id().unwrap(),
+            cc: match hdr.body[3] & 0x7 {
+                0 => Self::MAX_CC,
+                cc => cc,
+            },
+            itag_vector: BigEndian::read_u32(&hdr.body[4..8]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of request en/decoding, with and without a PASID prefix
+        #[test]
+        fn ats_req_serde_roundtrip(req_id: DeviceID, tag: u8, addr in any::<u64>().prop_map(|a| a & !0xFFF),
+                count in 1u16..=TranslationRequest::MAX_COUNT, no_write: bool, prefix: Option<PasidPrefix>) {
+            let mut req = TranslationRequest::new(req_id, tag, addr, count).unwrap().with_no_write(no_write);
+            req.prefix = prefix;
+            assert_eq!(count, req.count());
+            assert_eq!(addr, req.untranslated());
+
+            let mut buf = [0; 20];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+            assert_eq!(Ok(req), TranslationRequest::from_bytes(&buf[..len]));
+        }
+
+        /// Tests that translation sizes survive the S bit encoding
+        #[test]
+        fn ats_entry_size(shift in 12u32..63, base: u64, r: bool, w: bool) {
+            let size = 1u64 << shift;
+            let base = base & !(size - 1);
+            let entry = TranslationEntry { r, w, ..TranslationEntry::new(base, size).unwrap() };
+            let new_entry = TranslationEntry::from_bytes(entry.to_bytes());
+            assert_eq!(entry, new_entry);
+            assert_eq!(size, new_entry.size());
+            assert_eq!(base, new_entry.base());
+        }
+    }
+
+    #[test]
+    fn ats_req_odd_length() {
+        let req = TranslationRequest::new(DeviceID::default(), 0, 0x1000, 1).unwrap();
+        let mut buf = [0; 16];
+        let len = req.to_bytes(&mut buf).unwrap();
+        buf[3] = 3;
+        assert_eq!(
+            Err(TlpError::NotAligned {
+                field: Field::Length,
+                value: 12,
+                align: 8,
+                offset: 2
+            }),
+            TranslationRequest::from_bytes(&buf[..len])
+        );
+    }
+
+    proptest! {
+        /// Roundtrip testing of invalidate request en/decoding
+        #[test]
+        fn ats_inv_req_serde_roundtrip(req_id: DeviceID, target: DeviceID, itag in 0u8..=31,
+                shift in 12u32..63, base: u64, global: bool, prefix: Option<PasidPrefix>) {
+            let size = 1u64 << shift;
+            let base = base & !(size - 1);
+            let mut req = InvalidateRequest::new(req_id, target, itag, base, size).unwrap()
+                .with_global(global);
+            req.prefix = prefix;
+            assert_eq!((base, size), (req.base(), req.size()));
+
+            let mut buf = [0; 28];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+            assert_eq!(Ok(req), InvalidateRequest::from_bytes(&buf[..len]));
+        }
+
+        /// Roundtrip testing of invalidate completion en/decoding
+        #[test]
+        fn ats_inv_cpl_serde_roundtrip(req_id: DeviceID, target: DeviceID, cc in 1u8..=8, itags: u32) {
+            let cpl = InvalidateCompletion::new(req_id, target, cc, itags).unwrap();
+            let mut buf = [0; 16];
+            let len = cpl.to_bytes(&mut buf).unwrap();
+            assert_eq!(Ok(cpl), InvalidateCompletion::from_bytes(&buf[..len]));
+        }
+    }
+
+    #[test]
+    fn ats_inv_completion_matches_request() {
+        let ta = DeviceID::new(0, 0, 0).unwrap();
+        let dev = DeviceID::new(3, 0, 1).unwrap();
+        let req = InvalidateRequest::new(ta, dev, 9, 0x10_0000, 0x1000).unwrap();
+        let cpl = req.completion(1).unwrap();
+        assert_eq!((dev, ta), (cpl.req_id, cpl.target));
+        assert!(cpl.completes(9));
+        assert!(!cpl.completes(8));
+
+        let e = InvalidateRequest::new(ta, dev, 32, 0x10_0000, 0x1000);
+        assert_eq!(Some(Field::ITag), e.unwrap_err().field());
+    }
+
+    #[test]
+    fn ats_inv_wrong_code() {
+        let cpl =
+            InvalidateCompletion::new(DeviceID::default(), DeviceID::default(), 1, 1).unwrap();
+        let mut buf = [0; 16];
+        cpl.to_bytes(&mut buf).unwrap();
+        buf[7] = InvalidateRequest::CODE;
+        let e = InvalidateCompletion::from_bytes(&buf);
+        assert_eq!(
+            Err(TlpError::InvalidType {
+                field: Field::MessageCode,
+                value: 1,
+                offset: 7
+            }),
+            e
+        );
+    }
+
+    #[test]
+    fn ats_req_unaligned() {
+        let e = TranslationRequest::new(DeviceID::default(), 0, 0x1004, 1);
+        assert_eq!(Some(Field::Address), e.unwrap_err().field());
+    }
+
+    #[test]
+    fn ats_req_plain_read() {
+        let req = MRd::new(DeviceID::default(), 0, 0x1000, 8).unwrap();
+        let mut buf = [0; 12];
+        req.to_bytes(&mut buf).unwrap();
+        let e = TranslationRequest::from_bytes(&buf);
+        assert!(matches!(
+            e,
+            Err(TlpError::InvalidType {
+                field: Field::AddressType,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn ats_cpl_ranges() {
+        let req = TranslationRequest::new(DeviceID::default(), 7, 0x4_3000, 2).unwrap();
+        let small = TranslationEntry {
+            r: true,
+            ..TranslationEntry::new(0x8000_0000, 0x1000).unwrap()
+        };
+        let large = TranslationEntry {
+            r: true,
+            w: true,
+            ..TranslationEntry::new(0x9000_0000, 0x4000).unwrap()
+        };
+        let mut data = [0; 16];
+        data[..8].clone_from_slice(&small.to_bytes());
+        data[8..].clone_from_slice(&large.to_bytes());
+
+        let cpl = req
+            .completion(DeviceID::new(0, 0, 0).unwrap(), &data)
+            .unwrap();
+        let mut buf = [0; 28];
+        let len = cpl.to_bytes(&mut buf).unwrap();
+        let cpl = TranslationCompletion::from_bytes(&buf[..len]).unwrap();
+        assert_eq!(7, cpl.cpl.hdr.tag);
+        assert_eq!(vec![small, large], cpl.entries().collect::<Vec<_>>());
+
+        // With the smallest STU each entry covers exactly its own size
+        let ranges: Vec<_> = cpl.ranges(req.untranslated(), 0).collect();
+        assert_eq!(0x4_3000, ranges[0].untranslated);
+        assert_eq!(0x4_4000, ranges[1].untranslated);
+        assert_eq!(Some(0x8000_0010), ranges[0].translate(0x4_3010));
+        assert_eq!(Some(0x9000_2000), ranges[1].translate(0x4_6000));
+        assert_eq!(None, ranges[1].translate(0x4_8000));
+
+        // An 8 KiB STU widens the 4 KiB translation
+        let ranges: Vec<_> = cpl.ranges(req.untranslated(), 1).collect();
+        assert_eq!((0x4_2000, 0x2000), (ranges[0].untranslated, ranges[0].len));
+        assert_eq!(0x4_4000, ranges[1].untranslated);
+    }
+
+    #[test]
+    fn ats_cpl_bad_payload() {
+        let hdr = CplHeader::new();
+        let cpl = Cpl::new(hdr, &[0; 12]).unwrap();
+        let e = TranslationCompletion::try_from(cpl);
+        assert!(matches!(e, Err(TlpError::NotAligned { align: 8, .. })));
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{
+    packets::{check_len, check_min_len, check_type, REQ_HDR_LEN},
+    CompletionStatus, Cpl, CplHeader, DeviceID, Field, RequestHeader, TlpError, TlpHeader, TlpType,
+    DWORD_LEN,
+};
+use byteorder::{BigEndian, ByteOrder};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Largest dword aligned register offset in the extended config space
+const MAX_REG: u16 = 0xFFC;
+
+/// Checks that a register offset is dword aligned and inside config space
+fn check_reg(reg: u16) -> Result<(), TlpError> {
+    if reg > MAX_REG {
+        Err(TlpError::OutOfRange {
+            field: Field::Register,
+            value: reg.into(),
+            min: 0,
+            max: MAX_REG.into(),
+            offset: 10,
+        })
+    } else if reg & 0x3 != 0 {
+        Err(TlpError::NotAligned {
+            field: Field::Register,
+            value: reg.into(),
+            align: DWORD_LEN as u64,
+            offset: 11,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Builds the header shared by config reads and writes
+fn cfg_hdr(tlp_type: TlpType, req_id: DeviceID, tag: u8) -> Result<RequestHeader, TlpError> {
+    let hdr = TlpHeader::new()
+        .with_type(tlp_type)
+        .with_length(DWORD_LEN as u16)?;
+    Ok(RequestHeader::new()
+        .with_hdr(hdr)
+        .with_req_id(req_id)
+        .with_tag(tag)
+        .with_byte_enables())
+}
+
+/// Encodes a config request header, returning the number of bytes written
+fn encode_cfg(
+    hdr: &RequestHeader,
+    target: DeviceID,
+    reg: u16,
+    buf: &mut [u8],
+) -> Result<usize, TlpError> {
+    check_min_len(buf.len(), CfgRd::LENGTH)?;
+    buf[0..REQ_HDR_LEN].clone_from_slice(&hdr.to_bytes());
+    buf[8..10].clone_from_slice(&target.to_bytes());
+    buf[10] = ((reg >> 8) & 0xF) as u8;
+    buf[11] = (reg & 0xFC) as u8;
+    Ok(CfgRd::LENGTH)
+}
+
+/// Decodes a config request header, returning it with the target and register
+fn decode_cfg(bytes: &[u8]) -> Result<(RequestHeader, DeviceID, u16), TlpError> {
+    check_min_len(bytes.len(), CfgRd::LENGTH)?;
+    // SAFETY: Slice is already confirmed to be long enough
+    let hdr = RequestHeader::from_bytes(bytes[0..REQ_HDR_LEN].try_into().unwrap())?;
+    let target = BigEndian::read_u16(&bytes[8..10]).into();
+    let reg = u16::from(bytes[10] & 0xF) << 8 | u16::from(bytes[11] & 0xFC);
+    Ok((hdr, target, reg))
+}
+
+/// Builds the completion returned by `cpl_id` for a config request
+fn cfg_completion<'a, T>(
+    hdr: &RequestHeader,
+    cpl_id: T,
+    status: CompletionStatus,
+    data: &'a [u8],
+) -> Result<Cpl<'a>, TlpError>
+where
+    T: Into<DeviceID>,
+{
+    let cpl = CplHeader {
+        hdr: TlpHeader::new().with_tc(hdr.hdr.tc),
+        // Byte count is always 4 for completions of requests other than memory reads
+        bc: 4,
+        ..CplHeader::new()
+    }
+    .with_cpl_id(cpl_id)
+    .with_status(status)
+    .with_req_id(hdr.req_id)
+    .with_tag(hdr.tag);
+    Cpl::new(cpl, data)
+}
+
+/// Configuration read request
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CfgRd {
+    pub hdr: RequestHeader,
+    /// Function being read
+    pub target: DeviceID,
+    /// Dword aligned offset of the register
+    pub reg: u16,
+}
+
+impl CfgRd {
+    pub const LENGTH: usize = 12;
+
+    /// Builds a type 0 read, or a type 1 read to be forwarded by bridges
+    pub fn new<T, U>(req_id: T, tag: u8, target: U, reg: u16, type1: bool) -> Result<Self, TlpError>
+    where
+        T: Into<DeviceID>,
+        U: Into<DeviceID>,
+    {
+        check_reg(reg)?;
+        let tlp_type = if type1 {
+            TlpType::CfgRd1
+        } else {
+            TlpType::CfgRd0
+        };
+
+        Ok(Self {
+            hdr: cfg_hdr(tlp_type, req_id.into(), tag)?,
+            target: target.into(),
+            reg,
+        })
+    }
+
+    pub fn is_type1(&self) -> bool {
+        self.hdr.hdr.tlp_type == TlpType::CfgRd1
+    }
+
+    /// Length of the encoded request in bytes
+    pub fn encoded_len(&self) -> usize {
+        Self::LENGTH
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        encode_cfg(&self.hdr, self.target, self.reg, buf)
+    }
+
+    /// Decodes a request
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (hdr, target, reg) = decode_cfg(bytes)?;
+        check_type(hdr.hdr.tlp_type, &[TlpType::CfgRd0, TlpType::CfgRd1])?;
+        check_len(bytes.len(), Self::LENGTH)?;
+
+        Ok(Self { hdr, target, reg })
+    }
+
+    /// Builds the completion returned by `cpl_id`, with the register value as
+    /// `data` on success
+    pub fn completion<'a, T>(
+        &self,
+        cpl_id: T,
+        status: CompletionStatus,
+        data: &'a [u8; 4],
+    ) -> Result<Cpl<'a>, TlpError>
+    where
+        T: Into<DeviceID>,
+    {
+        let data: &[u8] = match status {
+            CompletionStatus::SuccessfulCompletion => data,
+            _ => &[],
+        };
+        cfg_completion(&self.hdr, cpl_id, status, data)
+    }
+}
+
+/// Configuration write request
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CfgWr<'a> {
+    pub hdr: RequestHeader,
+    /// Function being written
+    pub target: DeviceID,
+    /// Dword aligned offset of the register
+    pub reg: u16,
+    /// Register value in config space byte order, with bytes selected by the
+    /// first byte enables
+    pub data: &'a [u8],
+}
+
+impl<'a> CfgWr<'a> {
+    /// Builds a type 0 write, or a type 1 write to be forwarded by bridges
+    pub fn new<T, U>(
+        req_id: T,
+        tag: u8,
+        target: U,
+        reg: u16,
+        type1: bool,
+        data: &'a [u8; 4],
+    ) -> Result<Self, TlpError>
+    where
+        T: Into<DeviceID>,
+        U: Into<DeviceID>,
+    {
+        check_reg(reg)?;
+        let tlp_type = if type1 {
+            TlpType::CfgWr1
+        } else {
+            TlpType::CfgWr0
+        };
+
+        Ok(Self {
+            hdr: cfg_hdr(tlp_type, req_id.into(), tag)?,
+            target: target.into(),
+            reg,
+            data,
+        })
+    }
+
+    /// Selects the bytes of the register that are written
+    pub fn with_first_be(mut self, first_be: u8) -> Result<Self, TlpError> {
+        self.hdr = self.hdr.with_first_be(first_be)?;
+        Ok(self)
+    }
+
+    pub fn is_type1(&self) -> bool {
+        self.hdr.hdr.tlp_type == TlpType::CfgWr1
+    }
+
+    /// Returns the register value, taking the payload as little endian
+    pub fn value(&self) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.clone_from_slice(&self.data[..DWORD_LEN]);
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Length of the encoded request in bytes
+    pub fn encoded_len(&self) -> usize {
+        CfgRd::LENGTH + DWORD_LEN
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let len = self.encoded_len();
+        check_min_len(buf.len(), len)?;
+        encode_cfg(&self.hdr, self.target, self.reg, buf)?;
+        buf[CfgRd::LENGTH..len].clone_from_slice(&self.data[..DWORD_LEN]);
+        Ok(len)
+    }
+
+    /// Decodes a request, borrowing its payload from `bytes`
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TlpError> {
+        let (hdr, target, reg) = decode_cfg(bytes)?;
+        check_type(hdr.hdr.tlp_type, &[TlpType::CfgWr0, TlpType::CfgWr1])?;
+        let len = CfgRd::LENGTH + usize::from(hdr.hdr.data_len());
+        check_len(bytes.len(), len)?;
+        if len != CfgRd::LENGTH + DWORD_LEN {
+            return Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: hdr.hdr.length.into(),
+                min: 1,
+                max: 1,
+                offset: 2,
+            });
+        }
+
+        Ok(Self {
+            hdr,
+            target,
+            reg,
+            data: &bytes[CfgRd::LENGTH..len],
+        })
+    }
+
+    /// Builds the completion returned by `cpl_id`
+    pub fn completion<T>(
+        &self,
+        cpl_id: T,
+        status: CompletionStatus,
+    ) -> Result<Cpl<'static>, TlpError>
+    where
+        T: Into<DeviceID>,
+    {
+        cfg_completion(&self.hdr, cpl_id, status, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of config read en/decoding
+        #[test]
+        fn cfg_rd_serde_roundtrip(req_id: DeviceID, tag: u8, target: DeviceID,
+                reg in (0u16..0x400).prop_map(|r| r * 4), type1: bool) {
+            let req = CfgRd::new(req_id, tag, target, reg, type1).unwrap();
+            let mut buf = [0; 12];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+            assert_eq!(Ok(req), CfgRd::from_bytes(&buf[..len]));
+            assert_eq!(type1, req.is_type1());
+        }
+
+        /// Roundtrip testing of config write en/decoding
+        #[test]
+        fn cfg_wr_serde_roundtrip(req_id: DeviceID, tag: u8, target: DeviceID,
+                reg in (0u16..0x400).prop_map(|r| r * 4), type1: bool, data: [u8; 4], be in 0u8..16) {
+            let req = CfgWr::new(req_id, tag, target, reg, type1, &data).unwrap()
+                .with_first_be(be).unwrap();
+            let mut buf = [0; 16];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+            assert_eq!(Ok(req), CfgWr::from_bytes(&buf[..len]));
+        }
+    }
+
+    #[test]
+    fn cfg_rd_layout() {
+        let req_id = DeviceID::new(0, 0, 0).unwrap();
+        let target = DeviceID::new(2, 3, 1).unwrap();
+        let req = CfgRd::new(req_id, 5, target, 0x104, false).unwrap();
+        let mut buf = [0; 12];
+        req.to_bytes(&mut buf).unwrap();
+        assert_eq!([0x04, 0, 0, 1, 0, 0, 5, 0x0F, 0x02, 0x19, 0x01, 0x04], buf);
+
+        let data = 0x1234_5678u32.to_le_bytes();
+        let cpl = req
+            .completion(target, CompletionStatus::SuccessfulCompletion, &data)
+            .unwrap();
+        assert_eq!(TlpType::CplD, cpl.hdr.hdr.tlp_type);
+        assert_eq!((4, 5), (cpl.hdr.bc, cpl.hdr.tag));
+    }
+
+    #[test]
+    fn cfg_bad_reg() {
+        let e = CfgRd::new(DeviceID::default(), 0, DeviceID::default(), 0x1000, false);
+        assert_eq!(Some(Field::Register), e.unwrap_err().field());
+        let e = CfgRd::new(DeviceID::default(), 0, DeviceID::default(), 0x102, false);
+        assert_eq!(Some(Field::Register), e.unwrap_err().field());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{
+    packets::{check_len, check_min_len, payload_len},
+    CplHeader, Field, TlpError, TlpHeader, TlpType,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Completion, with or without data
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cpl<'a> {
+    pub hdr: CplHeader,
+    pub data: &'a [u8],
+}
+
+impl<'a> Cpl<'a> {
+    pub const HEADER_LEN: usize = 12;
+
+    /// Builds a completion around `hdr`, setting its type and length from `data`
+    ///
+    /// Locked completions stay locked; everything else becomes `CplD` or `CplE`.
+    pub fn new(hdr: CplHeader, data: &'a [u8]) -> Result<Self, TlpError> {
+        let locked = matches!(hdr.hdr.tlp_type, TlpType::CplLk | TlpType::CplLkD);
+        let tlp_hdr = if data.is_empty() {
+            TlpHeader {
+                length: 0,
+                ..hdr.hdr
+            }
+            .with_type(if locked {
+                TlpType::CplLk
+            } else {
+                TlpType::CplE
+            })
+        } else {
+            hdr.hdr
+                .with_type(if locked {
+                    TlpType::CplLkD
+                } else {
+                    TlpType::CplD
+                })
+                .with_length(payload_len(data)?)?
+        };
+
+        Ok(Self {
+            hdr: hdr.with_hdr(tlp_hdr),
+            data,
+        })
+    }
+
+    /// Length of the encoded completion in bytes
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_LEN + self.data.len()
+    }
+
+    /// Encodes the completion into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let len = self.encoded_len();
+        check_min_len(buf.len(), len)?;
+        buf[0..Self::HEADER_LEN].clone_from_slice(&self.hdr.to_bytes());
+        buf[Self::HEADER_LEN..len].clone_from_slice(self.data);
+        Ok(len)
+    }
+
+    /// Decodes a completion, borrowing its payload from `bytes`
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TlpError> {
+        check_min_len(bytes.len(), Self::HEADER_LEN)?;
+        // SAFETY: Slice is already confirmed to be long enough
+        let hdr = CplHeader::from_bytes(bytes[0..Self::HEADER_LEN].try_into().unwrap())?;
+        if !hdr.hdr.tlp_type.is_completion() {
+            return Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: bytes[0].into(),
+                offset: 0,
+            });
+        }
+
+        let len = if hdr.hdr.tlp_type.has_data() {
+            Self::HEADER_LEN + usize::from(hdr.hdr.data_len())
+        } else {
+            Self::HEADER_LEN
+        };
+        check_len(bytes.len(), len)?;
+
+        Ok(Self {
+            hdr,
+            data: &bytes[Self::HEADER_LEN..len],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of completion en/decoding
+        #[test]
+        fn cpl_serde_roundtrip(hdr: CplHeader, data in prop::collection::vec(any::<u8>(), 0..=16)
+                .prop_map(|mut v| { v.truncate(v.len() & !0x3); v })) {
+            let cpl = Cpl::new(hdr, &data).unwrap();
+            assert_eq!(data.is_empty(), !cpl.hdr.hdr.tlp_type.has_data());
+
+            let mut buf = [0; 32];
+            let len = cpl.to_bytes(&mut buf).unwrap();
+            assert_eq!(cpl.encoded_len(), len);
+            assert_eq!(Ok(cpl), Cpl::from_bytes(&buf[..len]));
+        }
+    }
+
+    #[test]
+    fn cpl_not_a_completion() {
+        let e = Cpl::from_bytes(&[0; 12]);
+        assert!(matches!(e, Err(TlpError::InvalidType { value: 0, .. })));
+    }
+}
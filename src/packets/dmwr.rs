@@ -0,0 +1,215 @@
+use crate::{
+    packets::{
+        check_len, check_min_len, check_type, decode_mem_req, encode_mem_req, mem_req_len,
+        payload_len,
+    },
+    Address, CompletionStatus, CplHeader, DeviceID, Field, RequestHeader, TlpError, TlpHeader,
+    TlpType,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Deferrable memory write request
+///
+/// Unlike a memory write this is non-posted: the completer answers with a
+/// completion that says whether the write was accepted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DMWr<'a> {
+    pub hdr: RequestHeader,
+    pub addr: Address,
+    pub data: &'a [u8],
+}
+
+impl<'a> DMWr<'a> {
+    pub fn new(req_id: DeviceID, tag: u8, addr: u64, data: &'a [u8]) -> Result<Self, TlpError> {
+        let addr = Address::try_from(addr)?;
+        let hdr = TlpHeader::new()
+            .with_type(if let Address::Addr32(_) = addr {
+                TlpType::DMWr3
+            } else {
+                TlpType::DMWr4
+            })
+            .with_length(payload_len(data)?)?;
+
+        Ok(Self {
+            hdr: RequestHeader::new()
+                .with_hdr(hdr)
+                .with_tag(tag)
+                .with_byte_enables()
+                .with_req_id(req_id),
+            addr,
+            data,
+        })
+    }
+
+    /// Length of the encoded request in bytes
+    pub fn encoded_len(&self) -> usize {
+        mem_req_len(&self.addr) + self.data.len()
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let hdr_len = encode_mem_req(&self.hdr, &self.addr, buf)?;
+        let len = hdr_len + self.data.len();
+        check_min_len(buf.len(), len)?;
+        buf[hdr_len..len].clone_from_slice(self.data);
+        Ok(len)
+    }
+
+    /// Decodes a request, borrowing its payload from `bytes`
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TlpError> {
+        let (hdr, addr, hdr_len) = decode_mem_req(bytes)?;
+        check_type(hdr.hdr.tlp_type, &[TlpType::DMWr3, TlpType::DMWr4])?;
+        let len = hdr_len + usize::from(hdr.hdr.data_len());
+        check_len(bytes.len(), len)?;
+
+        Ok(Self {
+            hdr,
+            addr,
+            data: &bytes[hdr_len..len],
+        })
+    }
+
+    /// Builds the completion returned by `cpl_id` for this request
+    pub fn completion<T>(&self, cpl_id: T, status: DMWrStatus) -> CplHeader
+    where
+        T: Into<DeviceID>,
+    {
+        CplHeader {
+            hdr: TlpHeader::new().with_type(TlpType::CplE),
+            // Byte count is always 4 for completions of requests other than reads
+            bc: 4,
+            ..CplHeader::new()
+        }
+        .with_cpl_id(cpl_id)
+        .with_status(status.into())
+        .with_req_id(self.hdr.req_id)
+        .with_tag(self.hdr.tag)
+    }
+
+    /// Returns true if `cpl` is the completion for this request
+    pub fn is_completed_by(&self, cpl: &CplHeader) -> bool {
+        cpl.hdr.tlp_type == TlpType::CplE
+            && cpl.req_id == self.hdr.req_id
+            && cpl.tag == self.hdr.tag
+    }
+}
+
+/// Outcome of a deferrable memory write as reported by its completion
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DMWrStatus {
+    /// The write was accepted by the completer
+    Accepted,
+    /// The completer could not take the write now and it may be retried
+    Retry,
+    /// The completer does not support deferrable memory writes
+    UnsupportedRequest,
+    /// The completer aborted the write
+    CompleterAbort,
+}
+
+impl From<DMWrStatus> for CompletionStatus {
+    fn from(status: DMWrStatus) -> Self {
+        match status {
+            DMWrStatus::Accepted => CompletionStatus::SuccessfulCompletion,
+            // Request retry status shares its encoding with configuration retry
+            DMWrStatus::Retry => CompletionStatus::ConfigurationRequestRetry,
+            DMWrStatus::UnsupportedRequest => CompletionStatus::UnsupportedRequest,
+            DMWrStatus::CompleterAbort => CompletionStatus::CompleterAbort,
+        }
+    }
+}
+
+impl TryFrom<CompletionStatus> for DMWrStatus {
+    type Error = TlpError;
+
+    fn try_from(status: CompletionStatus) -> Result<Self, Self::Error> {
+        match status {
+            CompletionStatus::SuccessfulCompletion => Ok(DMWrStatus::Accepted),
+            CompletionStatus::ConfigurationRequestRetry => Ok(DMWrStatus::Retry),
+            CompletionStatus::UnsupportedRequest => Ok(DMWrStatus::UnsupportedRequest),
+            CompletionStatus::CompleterAbort => Ok(DMWrStatus::CompleterAbort),
+            CompletionStatus::Reserved(s) => Err(TlpError::InvalidType {
+                field: Field::Status,
+                value: s.value().into(),
+                offset: 6,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of request en/decoding
+        #[test]
+        fn dmwr_serde_roundtrip(req_id: DeviceID, tag: u8, addr in any::<u64>().prop_map(|a| a & !0x3),
+                data in prop::collection::vec(any::<u8>(), 1..=32).prop_map(|mut v| {
+                    v.truncate(v.len() & !0x3);
+                    v
+                }).prop_filter("Payload must not be empty", |v| !v.is_empty())) {
+            let req = DMWr::new(req_id, tag, addr, &data).unwrap();
+            let mut buf = [0; crate::MAX_TLP_BUFFER];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+
+            let new_req = DMWr::from_bytes(&buf[..len]);
+            assert_eq!(Ok(req), new_req);
+        }
+
+        /// Tests that completions built for a request match it and carry the status
+        #[test]
+        fn dmwr_completion(req_id: DeviceID, cpl_id: DeviceID, tag: u8, status in prop::sample::select(vec![
+                DMWrStatus::Accepted, DMWrStatus::Retry,
+                DMWrStatus::UnsupportedRequest, DMWrStatus::CompleterAbort])) {
+            let data = [0; 4];
+            let req = DMWr::new(req_id, tag, 0x1000, &data).unwrap();
+            let cpl = CplHeader::from_bytes(req.completion(cpl_id, status).to_bytes()).unwrap();
+            assert!(req.is_completed_by(&cpl));
+            assert_eq!(cpl_id, cpl.cpl_id);
+            assert_eq!(Ok(status), DMWrStatus::try_from(cpl.status));
+        }
+    }
+
+    #[test]
+    fn dmwr_empty_payload() {
+        let e = DMWr::new(DeviceID::default(), 0, 0x1000, &[]);
+        assert_eq!(Some(Field::Length), e.unwrap_err().field());
+    }
+
+    #[test]
+    fn dmwr_wrong_type() {
+        let bytes = [0x40, 0, 0, 1, 0, 0, 0, 0xF, 0, 0, 0x10, 0, 1, 2, 3, 4];
+        let e = DMWr::from_bytes(&bytes);
+        assert_eq!(
+            Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: 0x40,
+                offset: 0
+            }),
+            e
+        );
+    }
+
+    #[test]
+    fn dmwr_truncated_payload() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        let req = DMWr::new(DeviceID::default(), 0, 0x1000, &data).unwrap();
+        let mut buf = [0; 20];
+        let len = req.to_bytes(&mut buf).unwrap();
+        let e = DMWr::from_bytes(&buf[..len - 1]);
+        assert_eq!(
+            Err(TlpError::TooShort {
+                expected: 20,
+                actual: 19
+            }),
+            e
+        );
+    }
+}
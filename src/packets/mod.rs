@@ -1,3 +1,220 @@
+mod ats;
+mod cfg;
+mod cpl;
+mod dmwr;
 mod mrd;
+mod msg;
+mod mwr;
+mod pri;
+mod tlp;
+#[cfg(all(feature = "serde", any(test, feature = "alloc")))]
+mod tlp_buf;
 
+pub use ats::{
+    InvalidateCompletion, InvalidateRequest, TranslatedRange, TranslationCompletion,
+    TranslationEntry, TranslationRequest,
+};
+pub use cfg::{CfgRd, CfgWr};
+pub use cpl::Cpl;
+pub use dmwr::{DMWr, DMWrStatus};
 pub use mrd::MRd;
+pub use msg::Msg;
+pub use mwr::MWr;
+pub use pri::{PageRequest, PrgResponse, PrgResponseCode};
+pub use tlp::Tlp;
+#[cfg(all(feature = "serde", any(test, feature = "alloc")))]
+pub use tlp_buf::TlpBuf;
+
+use crate::{Address, Field, MsgHeader, PasidPrefix, RequestHeader, TlpError, TlpType, DWORD_LEN};
+use byteorder::{BigEndian, ByteOrder};
+
+/// Length of a request header without its address
+const REQ_HDR_LEN: usize = 8;
+
+/// Checks that a buffer holds at least `expected` bytes
+pub(crate) fn check_min_len(actual: usize, expected: usize) -> Result<(), TlpError> {
+    if actual < expected {
+        Err(TlpError::TooShort { expected, actual })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that a buffer holds exactly `expected` bytes
+pub(crate) fn check_len(actual: usize, expected: usize) -> Result<(), TlpError> {
+    check_min_len(actual, expected)?;
+    if actual > expected {
+        Err(TlpError::TooLong { expected, actual })
+    } else {
+        Ok(())
+    }
+}
+
+/// Converts a payload length in bytes to the `u16` taken by `TlpHeader::with_length`
+///
+/// Empty payloads are rejected since a length of 0 encodes 1024 data words.
+fn payload_len(data: &[u8]) -> Result<u16, TlpError> {
+    match u16::try_from(data.len()) {
+        Ok(len) if len > 0 => Ok(len),
+        _ => Err(TlpError::OutOfRange {
+            field: Field::Length,
+            value: data.len() as u64,
+            min: DWORD_LEN as u64,
+            max: crate::MAX_DATA_LEN as u64,
+            offset: 2,
+        }),
+    }
+}
+
+/// Length of a memory request header with the given address
+fn mem_req_len(addr: &Address) -> usize {
+    match addr {
+        Address::Addr32(_) => REQ_HDR_LEN + DWORD_LEN,
+        Address::Addr64(_) => REQ_HDR_LEN + 2 * DWORD_LEN,
+    }
+}
+
+/// Encodes a memory request header followed by its address, returning the
+/// number of bytes written
+fn encode_mem_req(hdr: &RequestHeader, addr: &Address, buf: &mut [u8]) -> Result<usize, TlpError> {
+    let len = mem_req_len(addr);
+    check_min_len(buf.len(), len)?;
+
+    buf[0..REQ_HDR_LEN].clone_from_slice(&hdr.to_bytes());
+    match *addr {
+        Address::Addr32(a) => BigEndian::write_u32(&mut buf[REQ_HDR_LEN..len], a),
+        Address::Addr64(a) => BigEndian::write_u64(&mut buf[REQ_HDR_LEN..len], a),
+    }
+    Ok(len)
+}
+
+/// Decodes a memory request header and its address, returning them along with
+/// the length of the header in bytes
+///
+/// The two reserved low bits of the address are ignored.
+fn decode_mem_req(bytes: &[u8]) -> Result<(RequestHeader, Address, usize), TlpError> {
+    check_min_len(bytes.len(), REQ_HDR_LEN)?;
+    // SAFETY: Slice is already confirmed to be long enough
+    let hdr = RequestHeader::from_bytes(bytes[0..REQ_HDR_LEN].try_into().unwrap())?;
+    let len = hdr.hdr.tlp_type.header_len().ok_or(TlpError::InvalidType {
+        field: Field::FmtType,
+        value: u8::from(hdr.hdr.tlp_type).into(),
+        offset: 0,
+    })?;
+    check_min_len(bytes.len(), len)?;
+
+    let addr = if len == REQ_HDR_LEN + DWORD_LEN {
+        Address::Addr32(BigEndian::read_u32(&bytes[REQ_HDR_LEN..len]) & !0x3)
+    } else {
+        Address::Addr64(BigEndian::read_u64(&bytes[REQ_HDR_LEN..len]) & !0x3)
+    };
+    Ok((hdr, addr, len))
+}
+
+/// Checks that a decoded header holds one of the `expected` types
+fn check_type(tlp_type: TlpType, expected: &[TlpType]) -> Result<(), TlpError> {
+    if expected.contains(&tlp_type) {
+        Ok(())
+    } else {
+        Err(TlpError::InvalidType {
+            field: Field::FmtType,
+            value: u8::from(tlp_type).into(),
+            offset: 0,
+        })
+    }
+}
+
+/// Splits off a PASID prefix if the buffer starts with one, returning it along
+/// with the offset of the header that follows
+fn decode_prefix(bytes: &[u8]) -> Result<(Option<PasidPrefix>, usize), TlpError> {
+    check_min_len(bytes.len(), PasidPrefix::LENGTH)?;
+    if TlpType::from(bytes[0]) == TlpType::PASID {
+        let prefix = PasidPrefix::try_from(&bytes[0..PasidPrefix::LENGTH])?;
+        Ok((Some(prefix), PasidPrefix::LENGTH))
+    } else {
+        Ok((None, 0))
+    }
+}
+
+/// Encodes an optional PASID prefix, returning the number of bytes written
+fn encode_prefix(prefix: Option<PasidPrefix>, buf: &mut [u8]) -> Result<usize, TlpError> {
+    match prefix {
+        Some(prefix) => {
+            check_min_len(buf.len(), PasidPrefix::LENGTH)?;
+            buf[0..PasidPrefix::LENGTH].clone_from_slice(&prefix.to_bytes());
+            Ok(PasidPrefix::LENGTH)
+        }
+        None => Ok(0),
+    }
+}
+
+/// Encodes a message with an optional PASID prefix and payload, returning the
+/// number of bytes written
+fn encode_msg(
+    prefix: Option<PasidPrefix>,
+    hdr: &MsgHeader,
+    data: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, TlpError> {
+    let base = encode_prefix(prefix, buf)?;
+    let len = base + MsgHeader::LENGTH + data.len();
+    check_min_len(buf.len(), len)?;
+    buf[base..base + MsgHeader::LENGTH].clone_from_slice(&hdr.to_bytes());
+    buf[base + MsgHeader::LENGTH..len].clone_from_slice(data);
+    Ok(len)
+}
+
+/// Decodes a message of any type and code, returning its prefix, header and
+/// payload
+fn decode_any_msg(bytes: &[u8]) -> Result<(Option<PasidPrefix>, MsgHeader, &[u8]), TlpError> {
+    decode_msg_as(bytes, None)
+}
+
+/// Decodes a message of the given type and code, returning its prefix, header
+/// and payload
+fn decode_msg(
+    bytes: &[u8],
+    tlp_type: TlpType,
+    code: u8,
+) -> Result<(Option<PasidPrefix>, MsgHeader, &[u8]), TlpError> {
+    decode_msg_as(bytes, Some((tlp_type, code)))
+}
+
+/// Decodes a message, checking its type and code against `expected` if given
+fn decode_msg_as(
+    bytes: &[u8],
+    expected: Option<(TlpType, u8)>,
+) -> Result<(Option<PasidPrefix>, MsgHeader, &[u8]), TlpError> {
+    let (prefix, base) = decode_prefix(bytes)?;
+    let hdr_end = base + MsgHeader::LENGTH;
+    check_min_len(bytes.len(), hdr_end)?;
+    let hdr = MsgHeader::try_from(&bytes[base..hdr_end]).map_err(|e| e.at(base))?;
+    match expected {
+        Some((tlp_type, code)) => {
+            check_type(hdr.hdr.tlp_type, &[tlp_type]).map_err(|e| e.at(base))?;
+            if hdr.code != code {
+                return Err(TlpError::InvalidType {
+                    field: Field::MessageCode,
+                    value: hdr.code.into(),
+                    offset: base + 7,
+                });
+            }
+        }
+        None if hdr.routing().is_none() => {
+            return Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: u8::from(hdr.hdr.tlp_type).into(),
+                offset: base,
+            });
+        }
+        None => (),
+    }
+
+    let len = if hdr.hdr.tlp_type.has_data() {
+        hdr_end + usize::from(hdr.hdr.data_len())
+    } else {
+        hdr_end
+    };
+    check_len(bytes.len(), len)?;
+    Ok((prefix, hdr, &bytes[hdr_end..len]))
+}
@@ -1,6 +1,13 @@
-use crate::{Address, DeviceID, RequestHeader, TlpError, TlpHeader, TlpType};
+use crate::{
+    packets::{check_len, check_type, decode_mem_req, encode_mem_req, mem_req_len},
+    Address, DeviceID, RequestHeader, TlpError, TlpHeader, TlpType,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MRd {
     pub hdr: RequestHeader,
     pub addr: Address,
@@ -44,4 +51,42 @@ impl MRd {
             addr,
         })
     }
+
+    /// Length of the encoded request in bytes
+    pub fn encoded_len(&self) -> usize {
+        mem_req_len(&self.addr)
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        encode_mem_req(&self.hdr, &self.addr, buf)
+    }
+
+    /// Decodes a request
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (hdr, addr, len) = decode_mem_req(bytes)?;
+        check_type(hdr.hdr.tlp_type, &[TlpType::MRd3, TlpType::MRd4])?;
+        check_len(bytes.len(), len)?;
+
+        Ok(Self { hdr, addr })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of request en/decoding
+        #[test]
+        fn mrd_serde_roundtrip(req_id: DeviceID, tag: u8, addr in any::<u64>().prop_map(|a| a & !0x3),
+                length in (1u16..=1024).prop_map(|l| l * 4)) {
+            let req = MRd::new(req_id, tag, addr, length).unwrap();
+            let mut buf = [0; 16];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+            assert_eq!(Ok(req), MRd::from_bytes(&buf[..len]));
+        }
+    }
 }
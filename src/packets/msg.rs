@@ -0,0 +1,36 @@
+use crate::{
+    packets::{decode_any_msg, encode_msg},
+    MsgHeader, PasidPrefix, TlpError,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Message of any routing and code, with an optional PASID prefix
+///
+/// The ATS and PRI messages have their own typed decoders.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Msg<'a> {
+    pub prefix: Option<PasidPrefix>,
+    pub hdr: MsgHeader,
+    pub data: &'a [u8],
+}
+
+impl<'a> Msg<'a> {
+    /// Length of the encoded message in bytes, including any prefix
+    pub fn encoded_len(&self) -> usize {
+        self.prefix.map_or(0, |_| PasidPrefix::LENGTH) + MsgHeader::LENGTH + self.data.len()
+    }
+
+    /// Encodes the message into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        encode_msg(self.prefix, &self.hdr, self.data, buf)
+    }
+
+    /// Decodes a message, borrowing its payload from `bytes`
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TlpError> {
+        let (prefix, hdr, data) = decode_any_msg(bytes)?;
+        Ok(Self { prefix, hdr, data })
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    packets::{
+        check_len, check_min_len, check_type, decode_mem_req, encode_mem_req, mem_req_len,
+        payload_len,
+    },
+    Address, DeviceID, RequestHeader, TlpError, TlpHeader, TlpType,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Memory write request
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MWr<'a> {
+    pub hdr: RequestHeader,
+    pub addr: Address,
+    pub data: &'a [u8],
+}
+
+impl<'a> MWr<'a> {
+    pub fn new(req_id: DeviceID, tag: u8, addr: u64, data: &'a [u8]) -> Result<Self, TlpError> {
+        let addr = Address::try_from(addr)?;
+        let hdr = TlpHeader::new()
+            .with_type(if let Address::Addr32(_) = addr {
+                TlpType::MWr3
+            } else {
+                TlpType::MWr4
+            })
+            .with_length(payload_len(data)?)?;
+
+        Ok(Self {
+            hdr: RequestHeader::new()
+                .with_hdr(hdr)
+                .with_tag(tag)
+                .with_byte_enables()
+                .with_req_id(req_id),
+            addr,
+            data,
+        })
+    }
+
+    /// Length of the encoded request in bytes
+    pub fn encoded_len(&self) -> usize {
+        mem_req_len(&self.addr) + self.data.len()
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let hdr_len = encode_mem_req(&self.hdr, &self.addr, buf)?;
+        let len = hdr_len + self.data.len();
+        check_min_len(buf.len(), len)?;
+        buf[hdr_len..len].clone_from_slice(self.data);
+        Ok(len)
+    }
+
+    /// Decodes a request, borrowing its payload from `bytes`
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TlpError> {
+        let (hdr, addr, hdr_len) = decode_mem_req(bytes)?;
+        check_type(hdr.hdr.tlp_type, &[TlpType::MWr3, TlpType::MWr4])?;
+        let len = hdr_len + usize::from(hdr.hdr.data_len());
+        check_len(bytes.len(), len)?;
+
+        Ok(Self {
+            hdr,
+            addr,
+            data: &bytes[hdr_len..len],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Field;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of request en/decoding
+        #[test]
+        fn mwr_serde_roundtrip(req_id: DeviceID, tag: u8, addr in any::<u64>().prop_map(|a| a & !0x3),
+                data in (1usize..=16).prop_flat_map(|l| proptest::collection::vec(any::<u8>(), l * 4))) {
+            let req = MWr::new(req_id, tag, addr, &data).unwrap();
+            let mut buf = [0; 80];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+            assert_eq!(Ok(req), MWr::from_bytes(&buf[..len]));
+        }
+    }
+
+    #[test]
+    fn mwr_wrong_type() {
+        // DMWr with the same header layout
+        let bytes = [0x5B, 0, 0, 1, 0, 0, 0, 0xF, 0, 0, 0x10, 0, 1, 2, 3, 4];
+        let e = MWr::from_bytes(&bytes);
+        assert_eq!(
+            Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: 0x5B,
+                offset: 0
+            }),
+            e
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    packets::{decode_msg, encode_msg},
+    DeviceID, Field, MsgHeader, MsgRouting, PasidPrefix, TlpError, TlpHeader, TlpType,
+};
+use byteorder::{BigEndian, ByteOrder};
+use num_traits::FromPrimitive;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Largest page request group index
+const MAX_PRG_INDEX: u16 = 0x1FF;
+
+/// Checks that a page request group index fits in its 9 bit field
+fn check_prg_index(prg_index: u16, offset: usize) -> Result<(), TlpError> {
+    if prg_index > MAX_PRG_INDEX {
+        Err(TlpError::OutOfRange {
+            field: Field::PrgIndex,
+            value: prg_index.into(),
+            min: 0,
+            max: MAX_PRG_INDEX.into(),
+            offset,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// PRI page request, sent by a function to ask for a page to be made resident
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PageRequest {
+    pub prefix: Option<PasidPrefix>,
+    /// Function making the request
+    pub req_id: DeviceID,
+    /// Untranslated page address, 4 KiB aligned
+    pub addr: u64,
+    /// Page request group index
+    pub prg_index: u16,
+    /// Last request of the page request group
+    pub last: bool,
+    /// Write access requested
+    pub w: bool,
+    /// Read access requested
+    pub r: bool,
+}
+
+impl PageRequest {
+    pub const CODE: u8 = 0b0000_0100;
+
+    pub fn new<T>(req_id: T, addr: u64, prg_index: u16) -> Result<Self, TlpError>
+    where
+        T: Into<DeviceID>,
+    {
+        if addr & 0xFFF != 0 {
+            return Err(TlpError::NotAligned {
+                field: Field::Address,
+                value: addr,
+                align: 0x1000,
+                offset: 8,
+            });
+        }
+        check_prg_index(prg_index, 14)?;
+
+        Ok(Self {
+            req_id: req_id.into(),
+            addr,
+            prg_index,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_pasid(mut self, prefix: PasidPrefix) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    pub fn with_last(mut self, last: bool) -> Self {
+        self.last = last;
+        self
+    }
+
+    pub fn with_access(mut self, r: bool, w: bool) -> Self {
+        self.r = r;
+        self.w = w;
+        self
+    }
+
+    /// Length of the encoded request in bytes, including any prefix
+    pub fn encoded_len(&self) -> usize {
+        self.prefix.map_or(0, |_| PasidPrefix::LENGTH) + MsgHeader::LENGTH
+    }
+
+    /// Encodes the request into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let x = (self.addr & !0xFFF)
+            | u64::from(self.prg_index & MAX_PRG_INDEX) << 3
+            | (self.last as u64) << 2
+            | (self.w as u64) << 1
+            | (self.r as u64);
+        let hdr = MsgHeader::new()
+            .with_hdr(TlpHeader::new().with_type(TlpType::msg(MsgRouting::ToRootComplex, false)))
+            .with_req_id(self.req_id)
+            .with_code(Self::CODE)
+            .with_body(x.to_be_bytes());
+        encode_msg(self.prefix, &hdr, &[], buf)
+    }
+
+    /// Decodes a request, with or without a PASID prefix
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (prefix, hdr, _) = decode_msg(
+            bytes,
+            TlpType::msg(MsgRouting::ToRootComplex, false),
+            Self::CODE,
+        )?;
+        let x = u64::from_be_bytes(hdr.body);
+
+        Ok(Self {
+            prefix,
+            req_id: hdr.req_id,
+            addr: x & !0xFFF,
+            prg_index: ((x >> 3) as u16) & MAX_PRG_INDEX,
+            last: x & (1 << 2) > 0,
+            w: x & (1 << 1) > 0,
+            r: x & 1 > 0,
+        })
+    }
+
+    /// Builds the response sent by `rc_id` to the page request group this
+    /// request belongs to
+    pub fn response<T>(&self, rc_id: T, code: PrgResponseCode) -> PrgResponse
+    where
+        T: Into<DeviceID>,
+    {
+        PrgResponse {
+            // Only the PASID is returned in the response
+            prefix: self
+                .prefix
+                .map(|p| PasidPrefix::new().with_pasid(p.pasid).unwrap_or_default()),
+            req_id: rc_id.into(),
+            target: self.req_id,
+            code,
+            prg_index: self.prg_index,
+        }
+    }
+}
+
+lossless_enum! {
+    /// Outcome of a page request group
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum PrgResponseCode {
+        /// All pages of the group were made resident
+        #[default]
+        Success = 0b0000,
+        /// At least one page could not be made resident
+        InvalidRequest = 0b0001,
+        /// The request failed and page requests should be disabled
+        ResponseFailure = 0b1111,
+    }
+    /// Reserved response code
+    Reserved(ReservedResponseCode, 0xF)
+}
+
+/// PRI page request group response, sent to the function that made a group
+/// of page requests
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PrgResponse {
+    pub prefix: Option<PasidPrefix>,
+    /// Root complex sending the response
+    pub req_id: DeviceID,
+    /// Function that made the page requests
+    pub target: DeviceID,
+    pub code: PrgResponseCode,
+    /// Page request group index
+    pub prg_index: u16,
+}
+
+impl PrgResponse {
+    pub const CODE: u8 = 0b0000_0101;
+
+    pub fn new<T, U>(
+        req_id: T,
+        target: U,
+        code: PrgResponseCode,
+        prg_index: u16,
+    ) -> Result<Self, TlpError>
+    where
+        T: Into<DeviceID>,
+        U: Into<DeviceID>,
+    {
+        check_prg_index(prg_index, 10)?;
+
+        Ok(Self {
+            prefix: None,
+            req_id: req_id.into(),
+            target: target.into(),
+            code,
+            prg_index,
+        })
+    }
+
+    pub fn with_pasid(mut self, prefix: PasidPrefix) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    /// Length of the encoded response in bytes, including any prefix
+    pub fn encoded_len(&self) -> usize {
+        self.prefix.map_or(0, |_| PasidPrefix::LENGTH) + MsgHeader::LENGTH
+    }
+
+    /// Encodes the response into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        let mut hdr = MsgHeader::new()
+            .with_hdr(TlpHeader::new().with_type(TlpType::msg(MsgRouting::ById, false)))
+            .with_req_id(self.req_id)
+            .with_code(Self::CODE)
+            .with_target_id(self.target);
+        let x = u16::from(u8::from(self.code) & 0xF) << 12 | (self.prg_index & MAX_PRG_INDEX);
+        hdr.body[2..4].clone_from_slice(&x.to_be_bytes());
+        encode_msg(self.prefix, &hdr, &[], buf)
+    }
+
+    /// Decodes a response, with or without a PASID prefix
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TlpError> {
+        let (prefix, hdr, _) =
+            decode_msg(bytes, TlpType::msg(MsgRouting::ById, false), Self::CODE)?;
+        let x = BigEndian::read_u16(&hdr.body[2..4]);
+
+        Ok(Self {
+            prefix,
+            req_id: hdr.req_id,
+            // SAFETY: The header is always routed by ID
+            target: hdr.target_id().unwrap(),
+            // SAFETY: Every 4 bit value is a response code
+            code: PrgResponseCode::from_u8((x >> 12) as u8).unwrap(),
+            prg_index: x & MAX_PRG_INDEX,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Roundtrip testing of page request en/decoding
+        #[test]
+        fn page_req_serde_roundtrip(req_id: DeviceID, addr in any::<u64>().prop_map(|a| a & !0xFFF),
+                prg_index in 0u16..=MAX_PRG_INDEX, last: bool, r: bool, w: bool,
+                prefix: Option<PasidPrefix>) {
+            let mut req = PageRequest::new(req_id, addr, prg_index).unwrap()
+                .with_last(last)
+                .with_access(r, w);
+            req.prefix = prefix;
+
+            let mut buf = [0; 20];
+            let len = req.to_bytes(&mut buf).unwrap();
+            assert_eq!(req.encoded_len(), len);
+            assert_eq!(Ok(req), PageRequest::from_bytes(&buf[..len]));
+        }
+
+        /// Roundtrip testing of page request group response en/decoding
+        #[test]
+        fn prg_rsp_serde_roundtrip(req_id: DeviceID, target: DeviceID, code in 0u8..16,
+                prg_index in 0u16..=MAX_PRG_INDEX, prefix: Option<PasidPrefix>) {
+            let mut rsp = PrgResponse::new(req_id, target, PrgResponseCode::from_u8(code).unwrap(), prg_index).unwrap();
+            rsp.prefix = prefix;
+
+            let mut buf = [0; 20];
+            let len = rsp.to_bytes(&mut buf).unwrap();
+            assert_eq!(rsp.encoded_len(), len);
+            assert_eq!(Ok(rsp), PrgResponse::from_bytes(&buf[..len]));
+        }
+    }
+
+    #[test]
+    fn prg_rsp_for_request() {
+        let dev = DeviceID::new(2, 0, 0).unwrap();
+        let rc = DeviceID::default();
+        let prefix = PasidPrefix::new().with_pasid(42).unwrap().with_exe(true);
+        let req = PageRequest::new(dev, 0x7000, 3)
+            .unwrap()
+            .with_last(true)
+            .with_pasid(prefix);
+        let rsp = req.response(rc, PrgResponseCode::Success);
+        assert_eq!((rc, dev, 3), (rsp.req_id, rsp.target, rsp.prg_index));
+        assert_eq!(Some(PasidPrefix::new().with_pasid(42).unwrap()), rsp.prefix);
+    }
+
+    #[test]
+    fn page_req_bad_index() {
+        let e = PageRequest::new(DeviceID::default(), 0x1000, 0x200);
+        assert_eq!(Some(Field::PrgIndex), e.unwrap_err().field());
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    packets::{check_len, check_min_len, decode_prefix},
+    CfgRd, CfgWr, Cpl, DMWr, MRd, MWr, Msg, PasidPrefix, TlpError, TlpHeader, TlpType,
+};
+#[cfg(any(test, feature = "alloc"))]
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Any TLP, decoded by type
+///
+/// `Display` prints a protocol analyzer style summary, and the alternate form
+/// (`{:#}`) every header field with its bit range, followed by the payload.
+/// Payloads are borrowed when deserializing; use `TlpBuf` for formats such as
+/// JSON that cannot lend them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Tlp<'a> {
+    MRd(MRd),
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    MWr(MWr<'a>),
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    DMWr(DMWr<'a>),
+    CfgRd(CfgRd),
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    CfgWr(CfgWr<'a>),
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    Cpl(Cpl<'a>),
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    Msg(Msg<'a>),
+    /// TLP without a typed decoder, such as I/O and locked requests, kept as
+    /// its encoded bytes
+    Other(TlpHeader, &'a [u8]),
+}
+
+impl<'a> Tlp<'a> {
+    /// Decodes a TLP, picking the decoder from its type
+    ///
+    /// Messages keep their PASID prefix; other prefixed TLPs decode as
+    /// `Other`, use `split_prefix` first to decode what follows the prefix.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TlpError> {
+        check_min_len(bytes.len(), TlpHeader::LENGTH)?;
+        // SAFETY: Slice is already confirmed to be long enough
+        let hdr = TlpHeader::from_bytes(bytes[0..TlpHeader::LENGTH].try_into().unwrap())?;
+
+        use TlpType::*;
+        Ok(match hdr.tlp_type {
+            MRd3 | MRd4 => Tlp::MRd(MRd::from_bytes(bytes)?),
+            MWr3 | MWr4 => Tlp::MWr(MWr::from_bytes(bytes)?),
+            DMWr3 | DMWr4 => Tlp::DMWr(DMWr::from_bytes(bytes)?),
+            CfgRd0 | CfgRd1 => Tlp::CfgRd(CfgRd::from_bytes(bytes)?),
+            CfgWr0 | CfgWr1 => Tlp::CfgWr(CfgWr::from_bytes(bytes)?),
+            CplE | CplD | CplLk | CplLkD => Tlp::Cpl(Cpl::from_bytes(bytes)?),
+            PASID => match Self::split_prefix(bytes)? {
+                (_, rest) if TlpType::from(rest[0]).msg_routing().is_some() => {
+                    Tlp::Msg(Msg::from_bytes(bytes)?)
+                }
+                _ => Tlp::Other(hdr, bytes),
+            },
+            t if t.msg_routing().is_some() => Tlp::Msg(Msg::from_bytes(bytes)?),
+            t => {
+                let len = match t.header_len() {
+                    Some(len) if t.has_data() => len + usize::from(hdr.data_len()),
+                    Some(len) => len,
+                    None => bytes.len(),
+                };
+                check_len(bytes.len(), len)?;
+                Tlp::Other(hdr, bytes)
+            }
+        })
+    }
+
+    /// Splits a leading PASID prefix off a TLP, returning it with the rest of
+    /// the bytes
+    pub fn split_prefix(bytes: &[u8]) -> Result<(Option<PasidPrefix>, &[u8]), TlpError> {
+        let (prefix, base) = decode_prefix(bytes)?;
+        check_min_len(bytes.len(), base + TlpHeader::LENGTH)?;
+        Ok((prefix, &bytes[base..]))
+    }
+
+    /// Returns the first header dword
+    pub fn header(&self) -> TlpHeader {
+        match self {
+            Tlp::MRd(t) => t.hdr.hdr,
+            Tlp::MWr(t) => t.hdr.hdr,
+            Tlp::DMWr(t) => t.hdr.hdr,
+            Tlp::CfgRd(t) => t.hdr.hdr,
+            Tlp::CfgWr(t) => t.hdr.hdr,
+            Tlp::Cpl(t) => t.hdr.hdr,
+            Tlp::Msg(t) => t.hdr.hdr,
+            Tlp::Other(hdr, _) => *hdr,
+        }
+    }
+
+    /// Returns the payload, if any
+    pub fn data(&self) -> &'a [u8] {
+        match self {
+            Tlp::MWr(t) => t.data,
+            Tlp::DMWr(t) => t.data,
+            Tlp::CfgWr(t) => t.data,
+            Tlp::Cpl(t) => t.data,
+            Tlp::Msg(t) => t.data,
+            Tlp::MRd(_) | Tlp::CfgRd(_) => &[],
+            Tlp::Other(hdr, bytes) => match hdr.tlp_type.header_len() {
+                Some(len) if hdr.tlp_type.has_data() => &bytes[len..],
+                _ => &[],
+            },
+        }
+    }
+
+    /// Length of the encoded TLP in bytes
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Tlp::MRd(t) => t.encoded_len(),
+            Tlp::MWr(t) => t.encoded_len(),
+            Tlp::DMWr(t) => t.encoded_len(),
+            Tlp::CfgRd(t) => t.encoded_len(),
+            Tlp::CfgWr(t) => t.encoded_len(),
+            Tlp::Cpl(t) => t.encoded_len(),
+            Tlp::Msg(t) => t.encoded_len(),
+            Tlp::Other(_, bytes) => bytes.len(),
+        }
+    }
+
+    /// Encodes the TLP into `buf`, returning the number of bytes written
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, TlpError> {
+        match self {
+            Tlp::MRd(t) => t.to_bytes(buf),
+            Tlp::MWr(t) => t.to_bytes(buf),
+            Tlp::DMWr(t) => t.to_bytes(buf),
+            Tlp::CfgRd(t) => t.to_bytes(buf),
+            Tlp::CfgWr(t) => t.to_bytes(buf),
+            Tlp::Cpl(t) => t.to_bytes(buf),
+            Tlp::Msg(t) => t.to_bytes(buf),
+            Tlp::Other(_, bytes) => {
+                check_min_len(buf.len(), bytes.len())?;
+                buf[..bytes.len()].clone_from_slice(bytes);
+                Ok(bytes.len())
+            }
+        }
+    }
+
+    /// Encodes the TLP into a newly allocated buffer
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn to_vec(&self) -> Result<Vec<u8>, TlpError> {
+        let mut buf = vec![0; self.encoded_len()];
+        self.to_bytes(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Tlp<'a> {
+    type Error = TlpError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeviceID, InvalidateCompletion};
+
+    #[test]
+    fn tlp_decode_by_type() {
+        let mut buf = [0; 32];
+        let id = DeviceID::default();
+
+        let len = MRd::new(id, 1, 0x1000, 8)
+            .unwrap()
+            .to_bytes(&mut buf)
+            .unwrap();
+        let tlp = Tlp::from_bytes(&buf[..len]).unwrap();
+        assert!(matches!(tlp, Tlp::MRd(_)));
+        assert_eq!(len, tlp.encoded_len());
+
+        let data = [1, 2, 3, 4];
+        let len = CfgWr::new(id, 2, id, 0x10, false, &data)
+            .unwrap()
+            .to_bytes(&mut buf)
+            .unwrap();
+        let tlp = Tlp::from_bytes(&buf[..len]).unwrap();
+        assert!(matches!(tlp, Tlp::CfgWr(_)));
+        assert_eq!(data, tlp.data());
+
+        let len = InvalidateCompletion::new(id, id, 1, 1)
+            .unwrap()
+            .to_bytes(&mut buf)
+            .unwrap();
+        let tlp = Tlp::from_bytes(&buf[..len]).unwrap();
+        let Tlp::Msg(msg) = tlp else {
+            panic!("Message decoded as {:?}", tlp);
+        };
+        assert_eq!(InvalidateCompletion::CODE, msg.hdr.code);
+
+        let mut out = [0; 32];
+        assert_eq!(Ok(len), tlp.to_bytes(&mut out));
+        assert_eq!(buf[..len], out[..len]);
+        assert_eq!(Ok(buf[..len].to_vec()), tlp.to_vec());
+    }
+
+    #[test]
+    fn tlp_decode_other() {
+        // I/O read of one data word
+        let bytes = [0x02, 0, 0, 1, 0, 0, 0, 0x0F, 0, 0, 0x10, 0];
+        let tlp = Tlp::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            Tlp::Other(TlpHeader::from_bytes([0x02, 0, 0, 1]).unwrap(), &bytes),
+            tlp
+        );
+        assert_eq!(TlpType::IORdT, tlp.header().tlp_type);
+
+        let e = Tlp::from_bytes(&bytes[..8]);
+        assert_eq!(
+            Err(TlpError::TooShort {
+                expected: 12,
+                actual: 8
+            }),
+            e
+        );
+    }
+
+    #[test]
+    fn tlp_decode_prefixed() {
+        let prefix = PasidPrefix::new().with_pasid(7).unwrap();
+        let mut buf = [0; 20];
+        buf[..4].clone_from_slice(&prefix.to_bytes());
+        let len = MRd::new(DeviceID::default(), 1, 0x1000, 4)
+            .unwrap()
+            .to_bytes(&mut buf[4..])
+            .unwrap();
+
+        let bytes = &buf[..4 + len];
+        assert!(matches!(Tlp::from_bytes(bytes), Ok(Tlp::Other(..))));
+        let (p, rest) = Tlp::split_prefix(bytes).unwrap();
+        assert_eq!(Some(prefix), p);
+        assert!(matches!(Tlp::from_bytes(rest), Ok(Tlp::MRd(_))));
+    }
+
+    #[test]
+    fn tlp_decode_truncated() {
+        let e = Tlp::from_bytes(&[0x40, 0]);
+        assert_eq!(
+            Err(TlpError::TooShort {
+                expected: 4,
+                actual: 2
+            }),
+            e
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn tlp_serde() {
+        let mrd = Tlp::MRd(MRd::new(DeviceID::new(1, 2, 3).unwrap(), 1, 0x1000, 8).unwrap());
+        let json = serde_json::to_string(&mrd).unwrap();
+        assert!(json.contains("\"req_id\":\"01:02.3\""));
+        assert_eq!(mrd, serde_json::from_str(&json).unwrap());
+
+        let data = [1, 2, 3, 4];
+        let mwr = Tlp::MWr(MWr::new(DeviceID::default(), 2, 0x2000, &data).unwrap());
+        let json = serde_json::to_value(mwr).unwrap();
+        assert_eq!(serde_json::json!([1, 2, 3, 4]), json["MWr"]["data"]);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    Address, CfgRd, CfgWr, Cpl, CplHeader, DMWr, DeviceID, MRd, MWr, Msg, MsgHeader, PasidPrefix,
+    RequestHeader, Tlp, TlpError, TlpHeader,
+};
+use alloc::vec::Vec;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Encoded TLP that owns its bytes
+///
+/// Serializes like [`Tlp`] and deserializes from the same representation, in
+/// formats such as JSON that cannot lend the byte slices `Tlp` borrows.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TlpBuf(Vec<u8>);
+
+impl TlpBuf {
+    pub fn from_tlp(tlp: &Tlp) -> Result<Self, TlpError> {
+        Self::from_bytes(tlp.to_vec()?)
+    }
+
+    /// Takes ownership of an encoded TLP, checking that it decodes
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, TlpError> {
+        Tlp::from_bytes(&bytes)?;
+        Ok(Self(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the decoded TLP, borrowing its payload from the buffer
+    pub fn tlp(&self) -> Tlp<'_> {
+        // SAFETY: Bytes are checked to decode when the buffer is built
+        Tlp::from_bytes(&self.0).unwrap()
+    }
+}
+
+impl Serialize for TlpBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.tlp().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TlpBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let owned = OwnedTlp::deserialize(deserializer)?;
+        let tlp = owned.as_tlp();
+        let buf = Self::from_tlp(&tlp).map_err(de::Error::custom)?;
+        // Fields such as the header of `Other` can disagree with the bytes
+        if buf.tlp() != tlp {
+            return Err(de::Error::custom("TLP does not match its encoding"));
+        }
+        Ok(buf)
+    }
+}
+
+/// Mirror of [`Tlp`] owning the payloads, deserialized in its place
+#[derive(Deserialize)]
+#[serde(rename = "Tlp")]
+enum OwnedTlp {
+    MRd(MRd),
+    MWr(OwnedMem),
+    DMWr(OwnedMem),
+    CfgRd(CfgRd),
+    CfgWr(OwnedCfgWr),
+    Cpl(OwnedCpl),
+    Msg(OwnedMsg),
+    Other(TlpHeader, Vec<u8>),
+}
+
+impl OwnedTlp {
+    fn as_tlp(&self) -> Tlp<'_> {
+        match self {
+            OwnedTlp::MRd(t) => Tlp::MRd(*t),
+            OwnedTlp::MWr(t) => Tlp::MWr(MWr {
+                hdr: t.hdr,
+                addr: t.addr,
+                data: &t.data,
+            }),
+            OwnedTlp::DMWr(t) => Tlp::DMWr(DMWr {
+                hdr: t.hdr,
+                addr: t.addr,
+                data: &t.data,
+            }),
+            OwnedTlp::CfgRd(t) => Tlp::CfgRd(*t),
+            OwnedTlp::CfgWr(t) => Tlp::CfgWr(CfgWr {
+                hdr: t.hdr,
+                target: t.target,
+                reg: t.reg,
+                data: &t.data,
+            }),
+            OwnedTlp::Cpl(t) => Tlp::Cpl(Cpl {
+                hdr: t.hdr,
+                data: &t.data,
+            }),
+            OwnedTlp::Msg(t) => Tlp::Msg(Msg {
+                prefix: t.prefix,
+                hdr: t.hdr,
+                data: &t.data,
+            }),
+            OwnedTlp::Other(hdr, bytes) => Tlp::Other(*hdr, bytes),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OwnedMem {
+    hdr: RequestHeader,
+    addr: Address,
+    data: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct OwnedCfgWr {
+    hdr: RequestHeader,
+    target: DeviceID,
+    reg: u16,
+    data: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct OwnedCpl {
+    hdr: CplHeader,
+    data: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct OwnedMsg {
+    prefix: Option<PasidPrefix>,
+    hdr: MsgHeader,
+    data: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        strategy::{self, TlpParams},
+        TlpType,
+    };
+    use proptest::prelude::*;
+
+    proptest! {
+        /// Tests that TLPs of every kind survive JSON through a `TlpBuf`
+        #[test]
+        fn tlp_buf_json_roundtrip(bytes in strategy::tlp(&TlpParams::new())) {
+            let tlp = Tlp::from_bytes(&bytes).unwrap();
+            let json = serde_json::to_string(&tlp).unwrap();
+            let buf: TlpBuf = serde_json::from_str(&json).unwrap();
+            assert_eq!(tlp, buf.tlp());
+            assert_eq!(bytes, buf.as_bytes());
+            assert_eq!(json, serde_json::to_string(&buf).unwrap());
+        }
+    }
+
+    fn roundtrip(tlp: Tlp) {
+        let json = serde_json::to_string(&tlp).unwrap();
+        let buf: TlpBuf = serde_json::from_str(&json).unwrap();
+        assert_eq!(tlp, buf.tlp());
+    }
+
+    #[test]
+    fn tlp_buf_payloads() {
+        let id = DeviceID::new(1, 2, 3).unwrap();
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        roundtrip(Tlp::MWr(MWr::new(id, 1, 0x1_0000_1000, &data).unwrap()));
+        roundtrip(Tlp::CfgWr(
+            CfgWr::new(id, 2, id, 0x10, true, &[1, 2, 3, 4]).unwrap(),
+        ));
+        let hdr = CplHeader::new()
+            .with_cpl_id(id)
+            .with_bc(8)
+            .unwrap()
+            .with_addr(0x40)
+            .unwrap();
+        roundtrip(Tlp::Cpl(Cpl::new(hdr, &data).unwrap()));
+        roundtrip(Tlp::Msg(Msg {
+            prefix: Some(PasidPrefix::new().with_pasid(5).unwrap()),
+            hdr: MsgHeader::new()
+                .with_hdr(
+                    TlpHeader::new()
+                        .with_type(TlpType::MsgDID)
+                        .with_length(data.len() as u16)
+                        .unwrap(),
+                )
+                .with_target_id(id)
+                .with_code(0x7F),
+            data: &data,
+        }));
+    }
+
+    #[test]
+    fn tlp_buf_rejects_mismatched_payload() {
+        let data = [0; 8];
+        let mwr = Tlp::MWr(MWr::new(DeviceID::default(), 0, 0x1000, &data).unwrap());
+        let json = serde_json::to_string(&mwr)
+            .unwrap()
+            .replace("[0,0,0,0,0,0,0,0]", "[0,0,0,0]");
+        assert!(serde_json::from_str::<TlpBuf>(&json).is_err());
+    }
+}
@@ -0,0 +1,642 @@
+//! Protocol analyzer style formatting of TLPs
+//!
+//! `Display` of a [`Tlp`] prints a one-line summary such as
+//! `MRd64 ReqID=01:00.0 Tag=0x12 Addr=0x1_0000_0000 Len=32DW FBE=F LBE=F TC0 RO`.
+//! The alternate form (`{:#}`) follows the summary with every header field,
+//! its bit range and the raw header dwords, then a hex dump of the payload.
+
+// Using core instead of std for no_std support
+use core::fmt;
+
+use crate::{
+    headers::{AddressType, MsgRouting, TlpFormat},
+    Address, CfgWr, CompletionStatus, Cpl, CplHeader, DMWr, DeviceID, MWr, Msg, MsgHeader,
+    PasidPrefix, RequestHeader, Tlp, TlpClass, TlpHeader, TlpType, DWORD_LEN,
+};
+use byteorder::{BigEndian, ByteOrder};
+
+/// Longest header shown in the expanded form, a 4 dword header behind one
+/// prefix
+const MAX_HEADER_LEN: usize = PasidPrefix::LENGTH + 4 * DWORD_LEN;
+
+/// Payload bytes per line of the hex dump
+const DUMP_WIDTH: usize = 16;
+
+impl fmt::Display for TlpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TlpType::*;
+
+        let name = match self {
+            MRd3 => "MRd32",
+            MRd4 => "MRd64",
+            MRdLk3 => "MRdLk32",
+            MRdLk4 => "MRdLk64",
+            MWr3 => "MWr32",
+            MWr4 => "MWr64",
+            IORdT => "IORd",
+            IOWrtT => "IOWr",
+            CfgRd0 => "CfgRd0",
+            CfgWr0 => "CfgWr0",
+            CfgRd1 => "CfgRd1",
+            CfgWr1 => "CfgWr1",
+            CplE => "Cpl",
+            CplD => "CplD",
+            CplLk => "CplLk",
+            CplLkD => "CplLkD",
+            MRIOV => "MR-IOV",
+            LocalVendPrefix => "LocalVendPrefix",
+            ExtTPH => "TPH",
+            PASID => "PASID",
+            EndEndVendPrefix => "E2EVendPrefix",
+            DMWr3 => "DMWr32",
+            DMWr4 => "DMWr64",
+            MsgRC | MsgAddr | MsgID | MsgBcast | MsgLocal | MsgGather => "Msg",
+            MsgDRC | MsgDAddr | MsgDID | MsgDBcast | MsgDLocal | MsgDGather => "MsgD",
+            Unknown(t) => return write!(f, "Unknown({:#04X})", t.value()),
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for CompletionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompletionStatus::SuccessfulCompletion => f.write_str("SC"),
+            CompletionStatus::UnsupportedRequest => f.write_str("UR"),
+            CompletionStatus::ConfigurationRequestRetry => f.write_str("CRS"),
+            CompletionStatus::CompleterAbort => f.write_str("CA"),
+            CompletionStatus::Reserved(s) => write!(f, "Rsvd({:#X})", s.value()),
+        }
+    }
+}
+
+impl fmt::Display for Tlp<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        summary(f, self)?;
+        if f.alternate() {
+            expanded(f, self)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hex number with its digits grouped by four, as in `0x1_0000_0000`
+struct GroupedHex(u64);
+
+impl fmt::Display for GroupedHex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = (u64::BITS - self.0.leading_zeros()).div_ceil(4).max(1);
+        f.write_str("0x")?;
+        for i in (0..digits).rev() {
+            write!(f, "{:X}", (self.0 >> (4 * i)) & 0xF)?;
+            if i > 0 && i.is_multiple_of(4) {
+                f.write_str("_")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn summary(f: &mut fmt::Formatter<'_>, tlp: &Tlp) -> fmt::Result {
+    match tlp {
+        Tlp::MRd(t) => mem_req(f, &t.hdr, t.addr)?,
+        Tlp::MWr(t) => mem_req(f, &t.hdr, t.addr)?,
+        Tlp::DMWr(t) => mem_req(f, &t.hdr, t.addr)?,
+        Tlp::CfgRd(t) => cfg_req(f, &t.hdr, t.target, t.reg)?,
+        Tlp::CfgWr(t) => {
+            cfg_req(f, &t.hdr, t.target, t.reg)?;
+            write!(f, " Data={:#010X}", t.value())?;
+        }
+        Tlp::Cpl(t) => cpl(f, &t.hdr)?,
+        Tlp::Msg(t) => {
+            if let Some(prefix) = t.prefix {
+                pasid(f, &prefix)?;
+            }
+            msg(f, &t.hdr)?;
+        }
+        Tlp::Other(hdr, bytes) => return other(f, hdr, bytes),
+    }
+    attrs(f, &tlp.header())
+}
+
+fn mem_req(f: &mut fmt::Formatter<'_>, hdr: &RequestHeader, addr: Address) -> fmt::Result {
+    write!(
+        f,
+        "{} ReqID={} Tag={:#04X} Addr={} Len={}DW FBE={:X} LBE={:X}",
+        hdr.hdr.tlp_type,
+        hdr.req_id,
+        hdr.tag,
+        GroupedHex(addr.into()),
+        dwords(&hdr.hdr),
+        hdr.first_be,
+        hdr.last_be
+    )
+}
+
+fn cfg_req(
+    f: &mut fmt::Formatter<'_>,
+    hdr: &RequestHeader,
+    target: DeviceID,
+    reg: u16,
+) -> fmt::Result {
+    write!(
+        f,
+        "{} ReqID={} Tag={:#04X} Target={} Reg={:#05X} FBE={:X}",
+        hdr.hdr.tlp_type, hdr.req_id, hdr.tag, target, reg, hdr.first_be
+    )
+}
+
+fn cpl(f: &mut fmt::Formatter<'_>, hdr: &CplHeader) -> fmt::Result {
+    write!(
+        f,
+        "{} CplID={} ReqID={} Tag={:#04X} Status={} BC={} LowerAddr={:#04X}",
+        hdr.hdr.tlp_type, hdr.cpl_id, hdr.req_id, hdr.tag, hdr.status, hdr.bc, hdr.addr_low
+    )?;
+    if hdr.hdr.tlp_type.has_data() {
+        write!(f, " Len={}DW", dwords(&hdr.hdr))?;
+    }
+    Ok(())
+}
+
+fn msg(f: &mut fmt::Formatter<'_>, hdr: &MsgHeader) -> fmt::Result {
+    let route = match hdr.routing() {
+        Some(MsgRouting::ToRootComplex) => "RC",
+        Some(MsgRouting::ByAddress) => "Addr",
+        Some(MsgRouting::ById) => "ID",
+        Some(MsgRouting::Broadcast) => "Bcast",
+        Some(MsgRouting::Local) => "Local",
+        Some(MsgRouting::Gathered) => "Gather",
+        None => "Rsvd",
+    };
+    write!(
+        f,
+        "{} Route={} ReqID={} Tag={:#04X} Code={:#04X}",
+        hdr.hdr.tlp_type, route, hdr.req_id, hdr.tag, hdr.code
+    )?;
+    if let Some(target) = hdr.target_id() {
+        write!(f, " Target={}", target)?;
+    }
+    if hdr.hdr.tlp_type.has_data() {
+        write!(f, " Len={}DW", dwords(&hdr.hdr))?;
+    }
+    Ok(())
+}
+
+fn pasid(f: &mut fmt::Formatter<'_>, prefix: &PasidPrefix) -> fmt::Result {
+    write!(f, "PASID={:#X}", prefix.pasid)?;
+    if prefix.exe {
+        f.write_str(" Exe")?;
+    }
+    if prefix.privileged {
+        f.write_str(" Priv")?;
+    }
+    f.write_str(" ")
+}
+
+/// Summarizes TLPs without a typed decoder, looking through a PASID prefix
+/// and decoding I/O and locked requests like memory requests
+fn other(f: &mut fmt::Formatter<'_>, hdr: &TlpHeader, bytes: &[u8]) -> fmt::Result {
+    let t = hdr.tlp_type;
+    if t == TlpType::PASID {
+        if let Ok((Some(prefix), rest)) = Tlp::split_prefix(bytes) {
+            if let Ok(inner) = Tlp::from_bytes(rest) {
+                pasid(f, &prefix)?;
+                return summary(f, &inner);
+            }
+        }
+    }
+
+    match t.header_len() {
+        Some(len) if t.class() == Some(TlpClass::NonPosted) && bytes.len() >= len => {
+            // SAFETY: Slice is already confirmed to be long enough
+            let req = RequestHeader::from_bytes(bytes[0..8].try_into().unwrap());
+            let addr = match len {
+                12 => BigEndian::read_u32(&bytes[8..12]).into(),
+                _ => BigEndian::read_u64(&bytes[8..16]),
+            };
+            match req {
+                Ok(req) => mem_req(f, &req, Address::new(addr))?,
+                Err(_) => write!(f, "{}", t)?,
+            }
+        }
+        _ if t.has_data() => write!(f, "{} Len={}DW", t, dwords(hdr))?,
+        _ => write!(f, "{}", t)?,
+    }
+    attrs(f, hdr)
+}
+
+/// Payload length in dwords, with 0 meaning 1024
+fn dwords(hdr: &TlpHeader) -> usize {
+    usize::from(hdr.data_len()) / DWORD_LEN
+}
+
+fn attrs(f: &mut fmt::Formatter<'_>, hdr: &TlpHeader) -> fmt::Result {
+    write!(f, " {:?}", hdr.tc)?;
+    let flags = [
+        (hdr.ro, " RO"),
+        (hdr.ns, " NS"),
+        (hdr.ibo, " IDO"),
+        (hdr.ep, " EP"),
+        (hdr.td, " TD"),
+        (hdr.th, " TH"),
+        (hdr.ln, " LN"),
+    ];
+    for (_, name) in flags.iter().filter(|(set, _)| *set) {
+        f.write_str(name)?;
+    }
+    match hdr.at {
+        AddressType::DefaultUntranslated => Ok(()),
+        AddressType::TranslationRequest => f.write_str(" AT=TransReq"),
+        AddressType::Translated => f.write_str(" AT=Trans"),
+        AddressType::AddressTypeReserved => f.write_str(" AT=Rsvd"),
+    }
+}
+
+/// How the value of a header field is shown
+#[derive(Clone, Copy)]
+enum Show {
+    /// Shifted down to bit 0, in hex
+    Hex,
+    /// As a device ID
+    Id,
+    /// Masked but not shifted, as addresses are
+    InPlace,
+}
+
+/// Field of a header dword
+struct Bits {
+    name: &'static str,
+    hi: u32,
+    lo: u32,
+    show: Show,
+}
+
+const fn hex(name: &'static str, hi: u32, lo: u32) -> Bits {
+    Bits {
+        name,
+        hi,
+        lo,
+        show: Show::Hex,
+    }
+}
+
+const fn id(name: &'static str, hi: u32) -> Bits {
+    Bits {
+        name,
+        hi,
+        lo: hi - 15,
+        show: Show::Id,
+    }
+}
+
+const fn in_place(name: &'static str, hi: u32, lo: u32) -> Bits {
+    Bits {
+        name,
+        hi,
+        lo,
+        show: Show::InPlace,
+    }
+}
+
+const COMMON_DW0: &[Bits] = &[
+    hex("Fmt", 31, 29),
+    hex("Type", 28, 24),
+    hex("T9", 23, 23),
+    hex("TC", 22, 20),
+    hex("T8", 19, 19),
+    hex("IDO", 18, 18),
+    hex("LN", 17, 17),
+    hex("TH", 16, 16),
+    hex("TD", 15, 15),
+    hex("EP", 14, 14),
+    hex("RO", 13, 13),
+    hex("NS", 12, 12),
+    hex("AT", 11, 10),
+    hex("Length", 9, 0),
+];
+
+const REQ_DW1: &[Bits] = &[
+    id("Requester ID", 31),
+    hex("Tag", 15, 8),
+    hex("Last DW BE", 7, 4),
+    hex("First DW BE", 3, 0),
+];
+
+const ADDR_HI: &[Bits] = &[in_place("Address[63:32]", 31, 0)];
+const ADDR_LO: &[Bits] = &[in_place("Address[31:2]", 31, 2)];
+
+const CFG_DW2: &[Bits] = &[
+    id("Target ID", 31),
+    hex("Ext Register", 11, 8),
+    hex("Register", 7, 2),
+];
+
+const CPL_DW1: &[Bits] = &[
+    id("Completer ID", 31),
+    hex("Status", 15, 13),
+    hex("BCM", 12, 12),
+    hex("Byte Count", 11, 0),
+];
+
+const CPL_DW2: &[Bits] = &[
+    id("Requester ID", 31),
+    hex("Tag", 15, 8),
+    hex("Lower Address", 6, 0),
+];
+
+const MSG_DW1: &[Bits] = &[
+    id("Requester ID", 31),
+    hex("Tag", 15, 8),
+    hex("Message Code", 7, 0),
+];
+
+const MSG_ID_DW2: &[Bits] = &[id("Target ID", 31), hex("Bytes 10-11", 15, 0)];
+const MSG_DW2: &[Bits] = &[hex("Bytes 8-11", 31, 0)];
+const MSG_DW3: &[Bits] = &[hex("Bytes 12-15", 31, 0)];
+
+const PASID_PREFIX: &[Bits] = &[
+    hex("Fmt", 31, 29),
+    hex("Type", 28, 24),
+    hex("PMR", 23, 23),
+    hex("Exe", 22, 22),
+    hex("Reserved", 21, 20),
+    hex("PASID", 19, 0),
+];
+
+const OTHER_PREFIX: &[Bits] = &[
+    hex("Fmt", 31, 29),
+    hex("Type", 28, 24),
+    hex("Prefix Body", 23, 0),
+];
+
+/// Fields of each dword of the header of type `t`
+fn layout(t: TlpType) -> [&'static [Bits]; 4] {
+    use TlpType::*;
+
+    match t {
+        CfgRd0 | CfgWr0 | CfgRd1 | CfgWr1 => [COMMON_DW0, REQ_DW1, CFG_DW2, &[]],
+        CplE | CplD | CplLk | CplLkD => [COMMON_DW0, CPL_DW1, CPL_DW2, &[]],
+        t if t.msg_routing() == Some(MsgRouting::ById) => {
+            [COMMON_DW0, MSG_DW1, MSG_ID_DW2, MSG_DW3]
+        }
+        t if t.msg_routing().is_some() => [COMMON_DW0, MSG_DW1, MSG_DW2, MSG_DW3],
+        t if t.header_len() == Some(4 * DWORD_LEN) => [COMMON_DW0, REQ_DW1, ADDR_HI, ADDR_LO],
+        _ => [COMMON_DW0, REQ_DW1, ADDR_LO, &[]],
+    }
+}
+
+/// Encodes the prefixes and header of `tlp` into `buf`, returning their length
+fn header_bytes(tlp: &Tlp, buf: &mut [u8; MAX_HEADER_LEN]) -> Result<usize, fmt::Error> {
+    let encode = |t: Tlp, buf: &mut [u8]| {
+        let len = t.to_bytes(buf).map_err(|_| fmt::Error)?;
+        Ok(len - t.data().len())
+    };
+
+    // Encode without the payload so that only the header needs room
+    let stub = [0; DWORD_LEN];
+    match *tlp {
+        Tlp::MWr(t) => encode(Tlp::MWr(MWr { data: &[], ..t }), buf),
+        Tlp::DMWr(t) => encode(Tlp::DMWr(DMWr { data: &[], ..t }), buf),
+        Tlp::CfgWr(t) => {
+            let mut wide = [0; MAX_HEADER_LEN + DWORD_LEN];
+            let len = encode(Tlp::CfgWr(CfgWr { data: &stub, ..t }), &mut wide)?;
+            buf[..len].clone_from_slice(&wide[..len]);
+            Ok(len)
+        }
+        Tlp::Cpl(t) => encode(Tlp::Cpl(Cpl { data: &[], ..t }), buf),
+        Tlp::Msg(t) => encode(Tlp::Msg(Msg { data: &[], ..t }), buf),
+        Tlp::Other(_, bytes) => {
+            let len = split_other(bytes).0.len().min(MAX_HEADER_LEN);
+            buf[..len].clone_from_slice(&bytes[..len]);
+            Ok(len)
+        }
+        t => encode(t, buf),
+    }
+}
+
+/// Splits the bytes of a TLP without a typed decoder into its prefixes and
+/// header, and its payload
+///
+/// The payload follows the header given by the Fmt and Type after the
+/// prefixes, and TLPs whose header is unknown are all header.
+fn split_other(bytes: &[u8]) -> (&[u8], &[u8]) {
+    let prefixes = bytes
+        .chunks_exact(DWORD_LEN)
+        .take_while(|dw| TlpType::from(dw[0]).format() == Some(TlpFormat::TlpPrefix))
+        .count()
+        * DWORD_LEN;
+    let len = match bytes.get(prefixes).map(|b| TlpType::from(*b)) {
+        Some(t) if t.has_data() => t.header_len().map(|len| prefixes + len),
+        _ => None,
+    };
+    bytes.split_at(len.unwrap_or(bytes.len()).min(bytes.len()))
+}
+
+fn expanded(f: &mut fmt::Formatter<'_>, tlp: &Tlp) -> fmt::Result {
+    let mut buf = [0; MAX_HEADER_LEN];
+    let len = header_bytes(tlp, &mut buf)?;
+    let dwords = buf[..len].chunks_exact(DWORD_LEN).map(BigEndian::read_u32);
+
+    let mut fields = [&[][..]; 4].into_iter();
+    let mut in_prefix = true;
+    for (i, dw) in dwords.enumerate() {
+        let t = TlpType::from((dw >> 24) as u8);
+        let bits = match t.format() {
+            Some(TlpFormat::TlpPrefix) if in_prefix && t == TlpType::PASID => PASID_PREFIX,
+            Some(TlpFormat::TlpPrefix) if in_prefix => OTHER_PREFIX,
+            _ => {
+                if in_prefix {
+                    in_prefix = false;
+                    fields = layout(t).into_iter();
+                }
+                fields.next().unwrap_or(&[])
+            }
+        };
+        write!(f, "\n  DW{} {:08X}", i, dw)?;
+        for b in bits {
+            field(f, b, dw)?;
+        }
+    }
+
+    let data = match tlp {
+        Tlp::Other(_, bytes) => split_other(bytes).1,
+        t => t.data(),
+    };
+    if !data.is_empty() {
+        write!(f, "\n  Data, {} bytes", data.len())?;
+        for (i, line) in data.chunks(DUMP_WIDTH).enumerate() {
+            write!(f, "\n    {:04X} ", i * DUMP_WIDTH)?;
+            for b in line {
+                write!(f, " {:02X}", b)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a field as `[hi:lo] name value`, with the ranges aligned
+fn field(f: &mut fmt::Formatter<'_>, b: &Bits, dw: u32) -> fmt::Result {
+    let mask = (u32::MAX >> (31 - b.hi)) & (u32::MAX << b.lo);
+    let value = dw & mask;
+
+    f.write_str("\n    ")?;
+    let range_len = if b.hi == b.lo {
+        write!(f, "[{}]", b.hi)?;
+        digits(b.hi) + 2
+    } else {
+        write!(f, "[{}:{}]", b.hi, b.lo)?;
+        digits(b.hi) + digits(b.lo) + 3
+    };
+    write!(f, "{:pad$} {:<14} ", "", b.name, pad = 7 - range_len)?;
+
+    match b.show {
+        Show::Hex => write!(f, "{:#X}", value >> b.lo),
+        Show::Id => write!(f, "{}", DeviceID::from((value >> b.lo) as u16)),
+        Show::InPlace => write!(f, "{:#010X}", value),
+    }
+}
+
+fn digits(x: u32) -> usize {
+    if x < 10 {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MRd;
+    use std::format;
+
+    #[test]
+    fn mrd_summary() {
+        let req_id = DeviceID::new(1, 0, 0).unwrap();
+        let mut mrd = MRd::new(req_id, 0x12, 0x1_0000_0000, 128).unwrap();
+        mrd.hdr.hdr.ro = true;
+        assert_eq!(
+            "MRd64 ReqID=01:00.0 Tag=0x12 Addr=0x1_0000_0000 Len=32DW FBE=F LBE=F TC0 RO",
+            format!("{}", Tlp::MRd(mrd))
+        );
+    }
+
+    #[test]
+    fn grouped_hex() {
+        assert_eq!("0x0", format!("{}", GroupedHex(0)));
+        assert_eq!("0x1000", format!("{}", GroupedHex(0x1000)));
+        assert_eq!("0xFEE0_0000", format!("{}", GroupedHex(0xFEE0_0000)));
+        assert_eq!(
+            "0xFFFF_FFFF_FFFF_FFFC",
+            format!("{}", GroupedHex(0xFFFF_FFFF_FFFF_FFFC))
+        );
+    }
+
+    #[test]
+    fn cfg_and_cpl_summary() {
+        let id = DeviceID::new(0, 0, 0).unwrap();
+        let target = DeviceID::new(2, 3, 1).unwrap();
+        let wr = CfgWr::new(id, 1, target, 0x104, true, &[0x78, 0x56, 0x34, 0x12]).unwrap();
+        assert_eq!(
+            "CfgWr1 ReqID=00:00.0 Tag=0x01 Target=02:03.1 Reg=0x104 FBE=F Data=0x12345678 TC0",
+            format!("{}", Tlp::CfgWr(wr))
+        );
+
+        let data = [0; 8];
+        let cpl = wr.completion(target, CompletionStatus::UnsupportedRequest);
+        assert_eq!(
+            "Cpl CplID=02:03.1 ReqID=00:00.0 Tag=0x01 Status=UR BC=4 LowerAddr=0x00 TC0",
+            format!("{}", Tlp::Cpl(cpl.unwrap()))
+        );
+        let cpl = Cpl::new(CplHeader::new().with_bc(8).unwrap(), &data).unwrap();
+        assert_eq!(
+            "CplD CplID=00:00.0 ReqID=00:00.0 Tag=0x00 Status=SC BC=8 LowerAddr=0x00 Len=2DW TC0",
+            format!("{}", Tlp::Cpl(cpl))
+        );
+    }
+
+    #[test]
+    fn other_summary() {
+        // I/O read of one data word
+        let bytes = [0x02, 0, 0, 1, 0, 0x10, 0x20, 0x0F, 0, 0, 0x10, 0];
+        let tlp = Tlp::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            "IORd ReqID=00:02.0 Tag=0x20 Addr=0x1000 Len=1DW FBE=F LBE=0 TC0",
+            format!("{}", tlp)
+        );
+
+        // PASID prefixed memory read
+        let bytes = [0x91, 0x40, 0, 7, 0, 0, 0, 1, 0, 0, 0, 0xFF, 0, 0, 0x20, 0];
+        let tlp = Tlp::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            "PASID=0x7 Exe MRd32 ReqID=00:00.0 Tag=0x00 Addr=0x2000 Len=1DW FBE=F LBE=F TC0",
+            format!("{}", tlp)
+        );
+    }
+
+    #[test]
+    fn mwr_expanded() {
+        let req_id = DeviceID::new(1, 0, 0).unwrap();
+        let data = [0xAB; 20];
+        let mwr = MWr::new(req_id, 5, 0x1000, &data).unwrap();
+        let expect = "\
+MWr32 ReqID=01:00.0 Tag=0x05 Addr=0x1000 Len=5DW FBE=F LBE=F TC0
+  DW0 40000005
+    [31:29] Fmt            0x2
+    [28:24] Type           0x0
+    [23]    T9             0x0
+    [22:20] TC             0x0
+    [19]    T8             0x0
+    [18]    IDO            0x0
+    [17]    LN             0x0
+    [16]    TH             0x0
+    [15]    TD             0x0
+    [14]    EP             0x0
+    [13]    RO             0x0
+    [12]    NS             0x0
+    [11:10] AT             0x0
+    [9:0]   Length         0x5
+  DW1 010005FF
+    [31:16] Requester ID   01:00.0
+    [15:8]  Tag            0x5
+    [7:4]   Last DW BE     0xF
+    [3:0]   First DW BE    0xF
+  DW2 00001000
+    [31:2]  Address[31:2]  0x00001000
+  Data, 20 bytes
+    0000  AB AB AB AB AB AB AB AB AB AB AB AB AB AB AB AB
+    0010  AB AB AB AB";
+        assert_eq!(expect, format!("{:#}", Tlp::MWr(mwr)));
+    }
+
+    #[test]
+    fn prefixed_expanded() {
+        let bytes = [0x91, 0x80, 0, 7, 0, 0, 0, 1, 0, 0, 0, 0xFF, 0, 0, 0x20, 0];
+        let tlp = Tlp::from_bytes(&bytes).unwrap();
+        let out = format!("{:#}", tlp);
+        let lines: std::vec::Vec<_> = out.lines().collect();
+        assert_eq!("  DW0 91800007", lines[1]);
+        assert_eq!("    [23]    PMR            0x1", lines[4]);
+        assert_eq!("    [22]    Exe            0x0", lines[5]);
+        assert_eq!("    [21:20] Reserved       0x0", lines[6]);
+        assert_eq!("    [19:0]  PASID          0x7", lines[7]);
+        assert_eq!("  DW1 00000001", lines[8]);
+        assert_eq!("  DW3 00002000", lines[28]);
+    }
+
+    #[test]
+    fn prefixed_payload_expanded() {
+        // PASID prefixed memory write of one data word
+        let bytes = [
+            0x91, 0, 0, 7, 0x40, 0, 0, 1, 0, 0, 0, 0x0F, 0, 0, 0x20, 0, 0xDE, 0xAD, 0xBE, 0xEF,
+        ];
+        let tlp = Tlp::from_bytes(&bytes).unwrap();
+        assert!(matches!(tlp, Tlp::Other(..)));
+        let out = format!("{:#}", tlp);
+        let lines: std::vec::Vec<_> = out.lines().collect();
+        assert_eq!("  DW3 00002000", lines[28]);
+        assert_eq!("  Data, 4 bytes", lines[30]);
+        assert_eq!("    0000  DE AD BE EF", lines[31]);
+        assert!(!out.contains("DW4"));
+    }
+}
@@ -0,0 +1,493 @@
+use crate::{
+    config::{Bar, BarKind, ConfigAccess, ConfigHeader, SimFunction, Type0Header},
+    sim::SparseMemory,
+    Address, CfgRd, CfgWr, CompletionStatus, Cpl, CplHeader, DMWr, DMWrStatus, DeviceID, Field,
+    MRd, RequestHeader, Tlp, TlpError, TlpHeader, TlpType, DWORD_LEN, MAX_DATA_LEN,
+};
+use alloc::{vec, vec::Vec};
+
+/// Command register bit enabling memory decoding
+const CMD_MEMORY: u32 = 0x2;
+
+/// Software endpoint that completes the requests it receives
+///
+/// Memory requests are served from a sparse memory per BAR, addressed by the
+/// offset into the BAR so that contents survive BAR reassignment. Config
+/// requests are served from a simulated config space.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    id: DeviceID,
+    config: SimFunction,
+    /// Kind and size of each implemented BAR
+    bars: [Option<Bar>; 6],
+    memory: [SparseMemory; 6],
+    max_payload: u16,
+    rcb: u16,
+}
+
+impl Endpoint {
+    /// Default max payload size in bytes
+    pub const DEFAULT_MAX_PAYLOAD: u16 = 128;
+
+    /// Default read completion boundary in bytes
+    pub const DEFAULT_RCB: u16 = 64;
+
+    pub fn new<T>(id: T, hdr: Type0Header) -> Self
+    where
+        T: Into<DeviceID>,
+    {
+        Self {
+            id: id.into(),
+            config: SimFunction::new(ConfigHeader::Type0(hdr)),
+            bars: [None; 6],
+            memory: Default::default(),
+            max_payload: Self::DEFAULT_MAX_PAYLOAD,
+            rcb: Self::DEFAULT_RCB,
+        }
+    }
+
+    /// Implements a memory or I/O BAR of `size` bytes
+    pub fn with_bar(
+        mut self,
+        index: usize,
+        kind: BarKind,
+        prefetchable: bool,
+        size: u64,
+    ) -> Result<Self, TlpError> {
+        self.config = self.config.with_bar(index, kind, prefetchable, size)?;
+        self.bars[index] = Some(Bar {
+            index: index as u8,
+            kind,
+            prefetchable: prefetchable && kind != BarKind::Io,
+            addr: 0,
+            size,
+        });
+        Ok(self)
+    }
+
+    /// Sets the largest completion payload in bytes, a power of two from 128
+    /// to 4096
+    pub fn with_max_payload(mut self, max_payload: u16) -> Result<Self, TlpError> {
+        if !max_payload.is_power_of_two() || !(128..=MAX_DATA_LEN as u16).contains(&max_payload) {
+            return Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: max_payload.into(),
+                min: 128,
+                max: MAX_DATA_LEN as u64,
+                offset: 2,
+            });
+        }
+        self.max_payload = max_payload;
+        Ok(self)
+    }
+
+    /// Sets the read completion boundary in bytes, either 64 or 128
+    pub fn with_rcb(mut self, rcb: u16) -> Result<Self, TlpError> {
+        if rcb != 64 && rcb != 128 {
+            return Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: rcb.into(),
+                min: 64,
+                max: 128,
+                offset: 2,
+            });
+        }
+        self.rcb = rcb;
+        Ok(self)
+    }
+
+    /// ID used as completer ID, updated by type 0 config writes
+    pub fn id(&self) -> DeviceID {
+        self.id
+    }
+
+    pub fn config(&self) -> &SimFunction {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut SimFunction {
+        &mut self.config
+    }
+
+    /// Memory behind BAR `index`, addressed by offset into the BAR
+    pub fn memory(&self, index: usize) -> &SparseMemory {
+        &self.memory[index]
+    }
+
+    pub fn memory_mut(&mut self, index: usize) -> &mut SparseMemory {
+        &mut self.memory[index]
+    }
+
+    /// Returns the BARs with their currently assigned addresses
+    pub fn bars(&self) -> impl Iterator<Item = Bar> + '_ {
+        let regs: Vec<u32> = (0..6)
+            .map(|i| read_dword(&self.config, 0x10 + 4 * i))
+            .collect();
+        self.bars.iter().flatten().map(move |bar| Bar {
+            addr: Bar::decode(&regs, bar.index.into()).map_or(0, |b| b.addr),
+            ..*bar
+        })
+    }
+
+    /// Handles an encoded request, returning the encoded completions sent
+    /// back in order
+    ///
+    /// Posted requests and unexpected completions are consumed silently.
+    /// Requests that cannot be decoded are rejected with the decode error.
+    pub fn handle(&mut self, tlp: &[u8]) -> Result<Vec<Vec<u8>>, TlpError> {
+        match Tlp::from_bytes(tlp)? {
+            Tlp::MRd(req) => self.mem_read(&req),
+            Tlp::MWr(req) => {
+                self.mem_write(&req.hdr, req.addr, req.data);
+                Ok(vec![])
+            }
+            Tlp::DMWr(req) => self.deferrable_write(&req),
+            Tlp::CfgRd(req) => self.cfg_read(&req),
+            Tlp::CfgWr(req) => self.cfg_write(&req),
+            Tlp::Cpl(_) | Tlp::Msg(_) => Ok(vec![]),
+            Tlp::Other(hdr, bytes) if hdr.tlp_type.is_non_posted() => {
+                // SAFETY: Non-posted requests always have a header of at least 3 data words
+                let req = RequestHeader::from_bytes(bytes[0..8].try_into().unwrap())?;
+                self.completion(&req, CompletionStatus::UnsupportedRequest, 4, 0, &[])
+                    .map(|c| vec![c])
+            }
+            Tlp::Other(..) => Ok(vec![]),
+        }
+    }
+
+    /// Finds the memory BAR and offset an access of `len` bytes at `addr` hits
+    fn claim(&self, addr: Address, len: u64) -> Option<(usize, u64)> {
+        if read_dword(&self.config, 0x04) & CMD_MEMORY == 0 {
+            return None;
+        }
+        self.bars()
+            .filter(|bar| !bar.is_io())
+            .find_map(|bar| Some((usize::from(bar.index), bar.hit(addr, len)?)))
+    }
+
+    fn mem_read(&mut self, req: &MRd) -> Result<Vec<Vec<u8>>, TlpError> {
+        let len = u64::from(req.hdr.hdr.data_len());
+        let (first, bc) = byte_span(&req.hdr);
+        let addr = u64::from(req.addr);
+
+        let Some((bar, offset)) = self.claim(req.addr, len) else {
+            let lower = ((addr + first) & 0x7F) as u8;
+            let cpl = self.completion(
+                &req.hdr,
+                CompletionStatus::UnsupportedRequest,
+                bc,
+                lower,
+                &[],
+            )?;
+            return Ok(vec![cpl]);
+        };
+
+        let mut data = vec![0; len as usize];
+        self.memory[bar].read(offset, &mut data);
+
+        // Split at read completion boundaries, without exceeding the max payload
+        let end = addr + len;
+        let mut cpls = vec![];
+        let (mut cur, mut remaining) = (addr, bc);
+        while cur < end {
+            let chunk_end =
+                end.min((cur + u64::from(self.max_payload)) & !(u64::from(self.rcb) - 1));
+            let (lower, sent) = if cur == addr {
+                (addr + first, chunk_end - addr - first)
+            } else {
+                (cur, chunk_end - cur)
+            };
+            let chunk = &data[(cur - addr) as usize..(chunk_end - addr) as usize];
+            cpls.push(self.completion(
+                &req.hdr,
+                CompletionStatus::SuccessfulCompletion,
+                remaining,
+                (lower & 0x7F) as u8,
+                chunk,
+            )?);
+            remaining = remaining.saturating_sub(sent);
+            cur = chunk_end;
+        }
+        Ok(cpls)
+    }
+
+    /// Writes the enabled bytes of `data`, returning false if no BAR claims
+    /// the address
+    fn mem_write(&mut self, hdr: &RequestHeader, addr: Address, data: &[u8]) -> bool {
+        let Some((bar, offset)) = self.claim(addr, data.len() as u64) else {
+            return false;
+        };
+
+        let last_dw = data.len() / DWORD_LEN - 1;
+        self.memory[bar].write_masked(offset, data, |i| {
+            let (dw, bit) = (i / DWORD_LEN, 1 << (i % DWORD_LEN));
+            match dw {
+                0 => hdr.first_be & bit > 0,
+                dw if dw == last_dw => hdr.last_be & bit > 0,
+                _ => true,
+            }
+        });
+        true
+    }
+
+    fn deferrable_write(&mut self, req: &DMWr) -> Result<Vec<Vec<u8>>, TlpError> {
+        let status = if self.mem_write(&req.hdr, req.addr, req.data) {
+            DMWrStatus::Accepted
+        } else {
+            DMWrStatus::UnsupportedRequest
+        };
+        let cpl = Cpl::new(req.completion(self.id, status), &[])?;
+        Ok(vec![Tlp::Cpl(cpl).to_vec()?])
+    }
+
+    /// Returns true if a config request targets this function
+    fn cfg_claims(&self, tlp_type: TlpType, target: DeviceID) -> bool {
+        matches!(tlp_type, TlpType::CfgRd0 | TlpType::CfgWr0) && target.function == self.id.function
+    }
+
+    fn cfg_read(&mut self, req: &CfgRd) -> Result<Vec<Vec<u8>>, TlpError> {
+        let (status, data) = if self.cfg_claims(req.hdr.hdr.tlp_type, req.target) {
+            let data = self.config.read(req.reg).to_le_bytes();
+            (CompletionStatus::SuccessfulCompletion, data)
+        } else {
+            (CompletionStatus::UnsupportedRequest, [0; 4])
+        };
+        Ok(vec![
+            Tlp::Cpl(req.completion(self.id, status, &data)?).to_vec()?
+        ])
+    }
+
+    fn cfg_write(&mut self, req: &CfgWr) -> Result<Vec<Vec<u8>>, TlpError> {
+        let status = if self.cfg_claims(req.hdr.hdr.tlp_type, req.target) {
+            // Functions capture their bus and device numbers from type 0 writes
+            self.id.bus = req.target.bus;
+            self.id.device = req.target.device;
+            self.config.write(req.reg, req.value(), req.hdr.first_be);
+            CompletionStatus::SuccessfulCompletion
+        } else {
+            CompletionStatus::UnsupportedRequest
+        };
+        Ok(vec![Tlp::Cpl(req.completion(self.id, status)?).to_vec()?])
+    }
+
+    /// Builds an encoded completion for `req`, copying its traffic class and
+    /// ordering attributes
+    fn completion(
+        &self,
+        req: &RequestHeader,
+        status: CompletionStatus,
+        bc: u64,
+        addr_low: u8,
+        data: &[u8],
+    ) -> Result<Vec<u8>, TlpError> {
+        let hdr = CplHeader {
+            hdr: TlpHeader::new()
+                .with_tc(req.hdr.tc)
+                .with_ro(req.hdr.ro)
+                .with_ns(req.hdr.ns)
+                .with_ibo(req.hdr.ibo),
+            // A byte count of 4096 is encoded as 0
+            bc: (bc & 0xFFF) as u16,
+            ..CplHeader::new()
+        }
+        .with_cpl_id(self.id)
+        .with_status(status)
+        .with_req_id(req.req_id)
+        .with_tag(req.tag)
+        .with_addr(addr_low)?;
+        Tlp::Cpl(Cpl::new(hdr, data)?).to_vec()
+    }
+}
+
+/// Reads a dword of config space without going through `ConfigAccess`
+fn read_dword(cfg: &SimFunction, offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.clone_from_slice(&cfg.space()[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Returns the offset of the first enabled byte of a read and the number of
+/// bytes from there to the last enabled byte
+fn byte_span(hdr: &RequestHeader) -> (u64, u64) {
+    let len = u64::from(hdr.hdr.data_len());
+    // Index of the highest enabled byte in a data word, or none
+    let high = |be: u8| (be & 0xF).checked_ilog2().map(u64::from);
+
+    if len == DWORD_LEN as u64 {
+        match high(hdr.first_be) {
+            Some(h) => {
+                let first = u64::from(hdr.first_be.trailing_zeros());
+                (first, h - first + 1)
+            }
+            // A zero length read still returns one byte
+            None => (0, 1),
+        }
+    } else {
+        let first = u64::from((hdr.first_be | 0x10).trailing_zeros().min(3));
+        let last_unused = 3 - high(hdr.last_be).unwrap_or(3);
+        (first, len - first - last_unused)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CfgWr, MWr};
+
+    fn endpoint() -> Endpoint {
+        let mut ep = Endpoint::new(
+            DeviceID::new(1, 0, 0).unwrap(),
+            Type0Header::new(0x10EE, 0x9038),
+        )
+        .with_bar(0, BarKind::Memory64, false, 0x10000)
+        .unwrap();
+        let cfg = ep.config_mut();
+        cfg.write(0x10, 0x8000_0004, 0xF);
+        cfg.write(0x14, 0x1, 0xF);
+        cfg.write(0x04, CMD_MEMORY, 0x3);
+        ep
+    }
+
+    #[test]
+    fn ep_write_then_read() {
+        let mut ep = endpoint();
+        let req_id = DeviceID::default();
+        let data: Vec<u8> = (0..16).collect();
+        let mut wr = MWr::new(req_id, 0, 0x1_8000_0100, &data).unwrap();
+        // Skip the first and last byte
+        wr.hdr.first_be = 0xE;
+        wr.hdr.last_be = 0x7;
+        assert_eq!(Ok(vec![]), ep.handle(&Tlp::MWr(wr).to_vec().unwrap()));
+
+        let mut buf = [0; 16];
+        ep.memory(0).read(0x100, &mut buf);
+        assert_eq!([0, 1, 2, 3], buf[..4]);
+        assert_eq!([12, 13, 14, 0], buf[12..]);
+
+        let rd = MRd::new(req_id, 7, 0x1_8000_0100, 16).unwrap();
+        let cpls = ep.handle(&Tlp::MRd(rd).to_vec().unwrap()).unwrap();
+        assert_eq!(1, cpls.len());
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert_eq!((16, 0, 7), (cpl.hdr.bc, cpl.hdr.addr_low, cpl.hdr.tag));
+        assert_eq!(buf, cpl.data);
+        assert_eq!(DeviceID::new(1, 0, 0).unwrap(), cpl.hdr.cpl_id);
+    }
+
+    #[test]
+    fn ep_split_completions() {
+        let mut ep = endpoint();
+        // 512 bytes starting 16 bytes before a read completion boundary, so the
+        // first completion stops at the next boundary within the max payload
+        let mut rd = MRd::new(DeviceID::default(), 1, 0x1_8000_0030, 512).unwrap();
+        rd.hdr.first_be = 0xC;
+        let cpls = ep.handle(&Tlp::MRd(rd).to_vec().unwrap()).unwrap();
+
+        let cpls: Vec<_> = cpls
+            .iter()
+            .map(|c| {
+                let c = Cpl::from_bytes(c).unwrap();
+                (c.hdr.bc, c.hdr.addr_low, c.data.len())
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (510, 0x32, 80),
+                (432, 0x00, 128),
+                (304, 0x00, 128),
+                (176, 0x00, 128),
+                (48, 0x00, 48)
+            ],
+            cpls
+        );
+    }
+
+    #[test]
+    fn ep_unsupported_request() {
+        let mut ep = endpoint();
+        let rd = MRd::new(DeviceID::default(), 3, 0x1000, 4).unwrap();
+        let cpls = ep.handle(&Tlp::MRd(rd).to_vec().unwrap()).unwrap();
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert_eq!(CompletionStatus::UnsupportedRequest, cpl.hdr.status);
+        assert_eq!(TlpType::CplE, cpl.hdr.hdr.tlp_type);
+
+        // Unclaimed posted writes are dropped
+        let data = [1; 4];
+        let wr = MWr::new(DeviceID::default(), 0, 0x1000, &data).unwrap();
+        assert_eq!(Ok(vec![]), ep.handle(&Tlp::MWr(wr).to_vec().unwrap()));
+
+        // Memory decoding disabled
+        ep.config_mut().write(0x04, 0, 0x3);
+        let rd = MRd::new(DeviceID::default(), 3, 0x1_8000_0000, 4).unwrap();
+        let cpls = ep.handle(&Tlp::MRd(rd).to_vec().unwrap()).unwrap();
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert_eq!(CompletionStatus::UnsupportedRequest, cpl.hdr.status);
+
+        // I/O read
+        let bytes = [0x02, 0, 0, 1, 0, 0, 9, 0x0F, 0, 0, 0x10, 0];
+        let cpls = ep.handle(&bytes).unwrap();
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert_eq!(
+            (CompletionStatus::UnsupportedRequest, 9),
+            (cpl.hdr.status, cpl.hdr.tag)
+        );
+    }
+
+    #[test]
+    fn ep_config_requests() {
+        let mut ep = endpoint();
+        let rc = DeviceID::default();
+        let target = DeviceID::new(4, 0, 0).unwrap();
+
+        let rd = CfgRd::new(rc, 1, target, 0x00, false).unwrap();
+        let cpls = ep.handle(&Tlp::CfgRd(rd).to_vec().unwrap()).unwrap();
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert_eq!([0xEE, 0x10, 0x38, 0x90], cpl.data);
+
+        let data = 0xFFFF_FFFFu32.to_le_bytes();
+        let wr = CfgWr::new(rc, 2, target, 0x10, false, &data).unwrap();
+        let cpls = ep.handle(&Tlp::CfgWr(wr).to_vec().unwrap()).unwrap();
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert_eq!(CompletionStatus::SuccessfulCompletion, cpl.hdr.status);
+        assert_eq!(target, ep.id());
+        assert_eq!(0xFFFF_0004, ep.config_mut().read(0x10));
+
+        // Another function of the device does not exist
+        let other = DeviceID::new(4, 0, 1).unwrap();
+        let rd = CfgRd::new(rc, 3, other, 0x00, false).unwrap();
+        let cpls = ep.handle(&Tlp::CfgRd(rd).to_vec().unwrap()).unwrap();
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert_eq!(CompletionStatus::UnsupportedRequest, cpl.hdr.status);
+        assert!(cpl.data.is_empty());
+    }
+
+    #[test]
+    fn ep_deferrable_write() {
+        let mut ep = endpoint();
+        let data = [0xAB; 8];
+        let req = DMWr::new(DeviceID::default(), 4, 0x1_8000_0008, &data).unwrap();
+        let mut buf = [0; 32];
+        let len = req.to_bytes(&mut buf).unwrap();
+        let cpls = ep.handle(&buf[..len]).unwrap();
+        let cpl = Cpl::from_bytes(&cpls[0]).unwrap();
+        assert!(req.is_completed_by(&cpl.hdr));
+        assert_eq!(CompletionStatus::SuccessfulCompletion, cpl.hdr.status);
+
+        let mut out = [0; 8];
+        ep.memory(0).read(0x8, &mut out);
+        assert_eq!(data, out);
+    }
+
+    #[test]
+    fn ep_byte_span() {
+        let hdr = |len: u16, first_be, last_be| RequestHeader {
+            hdr: TlpHeader::new().with_length(len).unwrap(),
+            first_be,
+            last_be,
+            ..Default::default()
+        };
+        assert_eq!((0, 4), byte_span(&hdr(4, 0xF, 0)));
+        assert_eq!((1, 2), byte_span(&hdr(4, 0x6, 0)));
+        assert_eq!((0, 1), byte_span(&hdr(4, 0x0, 0)));
+        assert_eq!((3, 4), byte_span(&hdr(8, 0x8, 0x7)));
+    }
+}
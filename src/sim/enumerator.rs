@@ -0,0 +1,514 @@
+use crate::{
+    config::{
+        size_bars, Bar, BarKind, ClassCode, ConfigAccess, HeaderLayout, HeaderType, Type1Header,
+        Window, CONFIG_SPACE_LEN,
+    },
+    sim::Hierarchy,
+    CfgRd, CfgWr, CompletionStatus, Cpl, DeviceID, Field, Tlp, TlpError,
+};
+use alloc::{vec, vec::Vec};
+
+/// Command register bits enabling I/O and memory decoding and bus mastering
+const CMD_IO: u32 = 0x1;
+const CMD_MEMORY: u32 = 0x2;
+const CMD_BUS_MASTER: u32 = 0x4;
+
+/// Granularity of bridge windows
+const IO_WINDOW_ALIGN: u64 = 0x1000;
+const MEM_WINDOW_ALIGN: u64 = 0x10_0000;
+
+/// Function found while enumerating
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeviceNode {
+    pub id: DeviceID,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class_code: ClassCode,
+    /// Implemented BARs with their assigned addresses
+    pub bars: Vec<Bar>,
+    /// Secondary and subordinate bus numbers of a bridge
+    pub buses: Option<(u8, u8)>,
+    /// Functions on the secondary bus of a bridge
+    pub children: Vec<DeviceNode>,
+}
+
+/// Result of enumerating a hierarchy
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Enumeration {
+    /// Functions on the root bus
+    pub devices: Vec<DeviceNode>,
+    /// Encoded config requests, in the order they were sent
+    pub sent: Vec<Vec<u8>>,
+}
+
+/// Address range handed out in increasing order
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    next: u64,
+    limit: u64,
+}
+
+impl Region {
+    /// Allocates `size` bytes aligned to `size`
+    fn alloc(&mut self, size: u64, offset: usize) -> Result<u64, TlpError> {
+        let addr = self.align(size);
+        match addr.checked_add(size - 1) {
+            Some(last) if addr >= self.next && last <= self.limit => {
+                self.next = last + 1;
+                Ok(addr)
+            }
+            _ => Err(TlpError::OutOfRange {
+                field: Field::Bar,
+                value: size,
+                min: self.next,
+                max: self.limit,
+                offset,
+            }),
+        }
+    }
+
+    /// Returns the next address aligned to `align`
+    fn align(&self, align: u64) -> u64 {
+        self.next
+            .checked_next_multiple_of(align)
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Moves to the next boundary of `align` bytes, returning the new position
+    fn skip_to(&mut self, align: u64) -> u64 {
+        self.next = self.align(align);
+        self.next
+    }
+
+    /// Returns the window from `start` to the current position, if not empty
+    fn window(&self, start: u64) -> Option<Window> {
+        (self.next > start).then(|| Window::new(start, self.next - 1))
+    }
+}
+
+/// Enumerates a [`Hierarchy`] the way firmware does, through config
+/// requests built by the crate
+///
+/// Buses are scanned depth first. Bridges get consecutive bus numbers and
+/// windows covering what was assigned below them, BARs get naturally
+/// aligned addresses from the I/O, memory and prefetchable memory regions.
+#[derive(Clone, Debug)]
+pub struct Enumerator {
+    req_id: DeviceID,
+    tag: u8,
+    last_bus: u8,
+    io: Region,
+    memory: Region,
+    prefetch: Region,
+    sent: Vec<Vec<u8>>,
+}
+
+impl Enumerator {
+    pub fn new<T>(req_id: T) -> Self
+    where
+        T: Into<DeviceID>,
+    {
+        Self {
+            req_id: req_id.into(),
+            tag: 0,
+            last_bus: 0,
+            io: Region {
+                next: 0x1000,
+                limit: 0xFFFF,
+            },
+            memory: Region {
+                next: 0x8000_0000,
+                limit: 0xBFFF_FFFF,
+            },
+            prefetch: Region {
+                next: 0xC000_0000,
+                limit: 0xDFFF_FFFF,
+            },
+            sent: Vec::new(),
+        }
+    }
+
+    /// Sets the range I/O BARs are assigned from
+    pub fn with_io(mut self, window: Window) -> Self {
+        self.io = Region {
+            next: window.base,
+            limit: window.limit,
+        };
+        self
+    }
+
+    /// Sets the range non-prefetchable memory BARs are assigned from
+    pub fn with_memory(mut self, window: Window) -> Self {
+        self.memory = Region {
+            next: window.base,
+            limit: window.limit,
+        };
+        self
+    }
+
+    /// Sets the range prefetchable 64-bit memory BARs are assigned from
+    pub fn with_prefetch(mut self, window: Window) -> Self {
+        self.prefetch = Region {
+            next: window.base,
+            limit: window.limit,
+        };
+        self
+    }
+
+    /// Scans the hierarchy from the root bus, programming bus numbers, BARs
+    /// and bridge windows
+    pub fn enumerate(mut self, hierarchy: &mut Hierarchy) -> Result<Enumeration, TlpError> {
+        let devices = self.scan_bus(hierarchy, 0)?;
+        Ok(Enumeration {
+            devices,
+            sent: self.sent,
+        })
+    }
+
+    fn scan_bus(&mut self, h: &mut Hierarchy, bus: u8) -> Result<Vec<DeviceNode>, TlpError> {
+        let mut devices = vec![];
+        for device in 0..32 {
+            for function in 0..8 {
+                let id = DeviceID {
+                    bus,
+                    device,
+                    function,
+                };
+                let ids = self.access(h, id).read(0x00);
+                if ids & 0xFFFF == 0xFFFF {
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                }
+
+                let hdr_type = HeaderType::from((self.access(h, id).read(0x0C) >> 16) as u8);
+                devices.push(self.configure(h, id, ids, hdr_type)?);
+                if function == 0 && !hdr_type.multi_function {
+                    break;
+                }
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Assigns the BARs of a function found at `id`, and the bus numbers and
+    /// windows of a bridge after scanning below it
+    fn configure(
+        &mut self,
+        h: &mut Hierarchy,
+        id: DeviceID,
+        ids: u32,
+        hdr_type: HeaderType,
+    ) -> Result<DeviceNode, TlpError> {
+        let class = self.access(h, id).read(0x08);
+        let mut node = DeviceNode {
+            id,
+            vendor_id: ids as u16,
+            device_id: (ids >> 16) as u16,
+            class_code: ClassCode {
+                base: (class >> 24) as u8,
+                sub: (class >> 16) as u8,
+                prog_if: (class >> 8) as u8,
+            },
+            bars: vec![],
+            buses: None,
+            children: vec![],
+        };
+
+        let mut cmd = CMD_BUS_MASTER;
+        for bar in size_bars(&mut self.access(h, id))?.into_iter().flatten() {
+            let offset = Bar::OFFSET + 4 * usize::from(bar.index);
+            let addr = match (bar.kind, bar.prefetchable) {
+                (BarKind::Io, _) => self.io.alloc(bar.size, offset)?,
+                (BarKind::Memory64, true) => self.prefetch.alloc(bar.size, offset)?,
+                _ => self.memory.alloc(bar.size, offset)?,
+            };
+            let regs = bar.encode(addr);
+            for (i, reg) in regs.iter().take(bar.regs()).enumerate() {
+                self.access(h, id).write((offset + 4 * i) as u16, *reg, 0xF);
+            }
+            cmd |= if bar.is_io() { CMD_IO } else { CMD_MEMORY };
+            node.bars.push(Bar { addr, ..bar });
+        }
+
+        if hdr_type.layout == HeaderLayout::Type1 {
+            cmd |= self.configure_bridge(h, &mut node)?;
+        }
+
+        let mut cfg = self.access(h, id);
+        let orig = cfg.read(0x04);
+        cfg.write(0x04, orig | cmd, 0x3);
+        Ok(node)
+    }
+
+    /// Numbers the buses below a bridge and opens its windows over what was
+    /// assigned below it, returning the command bits to enable
+    fn configure_bridge(
+        &mut self,
+        h: &mut Hierarchy,
+        node: &mut DeviceNode,
+    ) -> Result<u32, TlpError> {
+        let id = node.id;
+        let secondary = self.last_bus.checked_add(1).ok_or(TlpError::OutOfRange {
+            field: Field::Bus,
+            value: 256,
+            min: 0,
+            max: 255,
+            offset: 0x19,
+        })?;
+        self.last_bus = secondary;
+
+        // Forward everything above the secondary bus until the subordinate
+        // bus is known
+        let buses = u32::from(id.bus) | u32::from(secondary) << 8 | 0xFF << 16;
+        self.access(h, id).write(0x18, buses, 0x7);
+
+        let io = self.io.skip_to(IO_WINDOW_ALIGN);
+        let memory = self.memory.skip_to(MEM_WINDOW_ALIGN);
+        let prefetch = self.prefetch.skip_to(MEM_WINDOW_ALIGN);
+
+        node.children = self.scan_bus(h, secondary)?;
+        let subordinate = self.last_bus;
+        self.access(h, id)
+            .write(0x18, u32::from(subordinate) << 16, 0x4);
+        node.buses = Some((secondary, subordinate));
+
+        self.io.skip_to(IO_WINDOW_ALIGN);
+        self.memory.skip_to(MEM_WINDOW_ALIGN);
+        self.prefetch.skip_to(MEM_WINDOW_ALIGN);
+        let windows = (
+            self.io.window(io),
+            self.memory.window(memory),
+            self.prefetch.window(prefetch),
+        );
+
+        // Build the window registers from a header that advertises the same
+        // addressing capabilities as the bridge
+        let mut hdr = Type1Header {
+            io_base: self.access(h, id).read(0x1C) as u8 & 0xF,
+            prefetch_base: self.access(h, id).read(0x24) as u16 & 0xF,
+            ..Default::default()
+        };
+        hdr = hdr
+            .with_io_window(windows.0)?
+            .with_memory_window(windows.1)?
+            .with_prefetch_window(windows.2)?;
+        let mut space = [0; CONFIG_SPACE_LEN];
+        hdr.to_bytes(&mut space)?;
+
+        let mut cfg = self.access(h, id);
+        // Leave the secondary status alone, its bits are cleared by writing 1
+        cfg.write(
+            0x1C,
+            u32::from_le_bytes([space[0x1C], space[0x1D], 0, 0]),
+            0x3,
+        );
+        for offset in (0x20..=0x30).step_by(4) {
+            let mut dword = [0; 4];
+            dword.clone_from_slice(&space[offset..offset + 4]);
+            cfg.write(offset as u16, u32::from_le_bytes(dword), 0xF);
+        }
+
+        let io_cmd = if windows.0.is_some() { CMD_IO } else { 0 };
+        let mem_cmd = if windows.1.is_some() || windows.2.is_some() {
+            CMD_MEMORY
+        } else {
+            0
+        };
+        Ok(io_cmd | mem_cmd)
+    }
+
+    fn access<'a>(&'a mut self, hierarchy: &'a mut Hierarchy, target: DeviceID) -> TlpAccess<'a> {
+        TlpAccess {
+            enumerator: self,
+            hierarchy,
+            target,
+        }
+    }
+
+    fn next_tag(&mut self) -> u8 {
+        let tag = self.tag;
+        self.tag = self.tag.wrapping_add(1);
+        tag
+    }
+
+    /// Sends a config request, returning the completion status and data
+    fn send(&mut self, h: &mut Hierarchy, req: Tlp) -> Option<[u8; 4]> {
+        // SAFETY: Requests are built from valid register offsets
+        let bytes = req.to_vec().unwrap();
+        let cpl = h.handle(&bytes);
+        self.sent.push(bytes);
+
+        let cpl = cpl.ok()?;
+        let cpl = Cpl::from_bytes(&cpl).ok()?;
+        match cpl.hdr.status {
+            CompletionStatus::SuccessfulCompletion => cpl.data.try_into().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Config access to one function through config requests
+///
+/// Reads that are not completed successfully return all ones, as root
+/// complexes do.
+struct TlpAccess<'a> {
+    enumerator: &'a mut Enumerator,
+    hierarchy: &'a mut Hierarchy,
+    target: DeviceID,
+}
+
+impl ConfigAccess for TlpAccess<'_> {
+    fn read(&mut self, offset: u16) -> u32 {
+        let (e, type1) = (&mut *self.enumerator, self.target.bus != 0);
+        // SAFETY: Offsets are dword aligned registers inside the config space
+        let req = CfgRd::new(e.req_id, e.next_tag(), self.target, offset, type1).unwrap();
+        e.send(self.hierarchy, Tlp::CfgRd(req))
+            .map_or(u32::MAX, u32::from_le_bytes)
+    }
+
+    fn write(&mut self, offset: u16, value: u32, be: u8) {
+        let (e, type1) = (&mut *self.enumerator, self.target.bus != 0);
+        let data = value.to_le_bytes();
+        // SAFETY: Offsets are dword aligned registers inside the config space
+        // and byte enables are at most 0xF
+        let req = CfgWr::new(e.req_id, e.next_tag(), self.target, offset, type1, &data)
+            .and_then(|req| req.with_first_be(be))
+            .unwrap();
+        e.send(self.hierarchy, Tlp::CfgWr(req));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ConfigHeader, SimFunction, Type0Header},
+        TlpType,
+    };
+
+    fn bridge() -> SimFunction {
+        let mut hdr = Type1Header::new(0x8086, 0x1);
+        // 64-bit prefetchable window
+        hdr.prefetch_base = 0x1;
+        hdr.prefetch_limit = 0x1;
+        SimFunction::new(ConfigHeader::Type1(hdr))
+    }
+
+    fn endpoint(device_id: u16) -> SimFunction {
+        SimFunction::new(ConfigHeader::Type0(Type0Header::new(0x10EE, device_id)))
+    }
+
+    /// Root port with a switch below it, whose two downstream ports each have
+    /// an endpoint, and a multi-function endpoint on the root bus
+    fn hierarchy() -> Hierarchy {
+        let mut h = Hierarchy::new();
+        let rp = h.add(None, 1, 0, bridge()).unwrap();
+        let usp = h.add(Some(rp), 0, 0, bridge()).unwrap();
+        let dsp0 = h.add(Some(usp), 0, 0, bridge()).unwrap();
+        let dsp1 = h.add(Some(usp), 1, 0, bridge()).unwrap();
+
+        let ep0 = endpoint(0x10)
+            .with_bar(0, BarKind::Memory64, true, 0x10_0000)
+            .and_then(|f| f.with_bar(2, BarKind::Memory32, false, 0x1000))
+            .unwrap();
+        h.add(Some(dsp0), 0, 0, ep0).unwrap();
+        let ep1 = endpoint(0x11)
+            .with_bar(0, BarKind::Io, false, 0x100)
+            .and_then(|f| f.with_bar(1, BarKind::Memory32, false, 0x4000))
+            .unwrap();
+        h.add(Some(dsp1), 0, 0, ep1).unwrap();
+
+        let mut mf = endpoint(0x20);
+        mf.space_mut()[0x0E] |= 0x80;
+        h.add(None, 2, 0, mf).unwrap();
+        h.add(None, 2, 3, endpoint(0x21)).unwrap();
+        h
+    }
+
+    #[test]
+    fn enumerator_builds_tree() {
+        let mut h = hierarchy();
+        let e = Enumerator::new(DeviceID::default())
+            .enumerate(&mut h)
+            .unwrap();
+
+        let ids: Vec<_> = e.devices.iter().map(|d| (d.id, d.device_id)).collect();
+        let id = |bus, device, function| DeviceID::new(bus, device, function).unwrap();
+        assert_eq!(
+            vec![(id(0, 1, 0), 0x1), (id(0, 2, 0), 0x20), (id(0, 2, 3), 0x21)],
+            ids
+        );
+
+        let rp = &e.devices[0];
+        assert_eq!(Some((1, 4)), rp.buses);
+        let usp = &rp.children[0];
+        assert_eq!((id(1, 0, 0), Some((2, 4))), (usp.id, usp.buses));
+        let dsp: Vec<_> = usp.children.iter().map(|d| (d.id, d.buses)).collect();
+        assert_eq!(
+            vec![(id(2, 0, 0), Some((3, 3))), (id(2, 1, 0), Some((4, 4)))],
+            dsp
+        );
+
+        let ep0 = &usp.children[0].children[0];
+        assert_eq!(id(3, 0, 0), ep0.id);
+        let bars: Vec<_> = ep0.bars.iter().map(|b| (b.index, b.addr, b.size)).collect();
+        assert_eq!(
+            vec![(0, 0xC000_0000, 0x10_0000), (2, 0x8000_0000, 0x1000)],
+            bars
+        );
+
+        let ep1 = &usp.children[1].children[0];
+        let bars: Vec<_> = ep1.bars.iter().map(|b| (b.index, b.addr, b.size)).collect();
+        assert_eq!(vec![(0, 0x1000, 0x100), (1, 0x8010_0000, 0x4000)], bars);
+    }
+
+    #[test]
+    fn enumerator_programs_hierarchy() {
+        let mut h = hierarchy();
+        let e = Enumerator::new(DeviceID::default())
+            .enumerate(&mut h)
+            .unwrap();
+
+        // The endpoints are now reachable through the programmed bridges and
+        // decode their assigned addresses
+        let mut read = |bus, device, reg| {
+            let target = DeviceID::new(bus, device, 0).unwrap();
+            let req = CfgRd::new(DeviceID::default(), 0, target, reg, bus != 0).unwrap();
+            let cpl = h.handle(&Tlp::CfgRd(req).to_vec().unwrap()).unwrap();
+            u32::from_le_bytes(Cpl::from_bytes(&cpl).unwrap().data.try_into().unwrap())
+        };
+        assert_eq!(0x0010_10EE, read(3, 0, 0x00));
+        assert_eq!(0xC000_000C, read(3, 0, 0x10));
+        assert_eq!(0x6, read(3, 0, 0x04) & 0x7);
+        assert_eq!(0x7, read(4, 0, 0x04) & 0x7);
+
+        // Downstream port 0 forwards its endpoint's memory and no I/O
+        assert_eq!(0x8000_8000, read(2, 0, 0x20));
+        assert_eq!(0xC001_C001, read(2, 0, 0x24));
+        assert_eq!(0x0010, read(2, 0, 0x1C) & 0xFFFF);
+        assert_eq!(0x04_0100, read(0, 1, 0x18) & 0xFF_FFFF);
+
+        // Type 0 requests only go to the root bus
+        let first = CfgRd::from_bytes(&e.sent[0]).unwrap();
+        assert_eq!(TlpType::CfgRd0, first.hdr.hdr.tlp_type);
+        assert!(e.sent.iter().all(|tlp| {
+            let hdr = Tlp::from_bytes(tlp).unwrap();
+            match hdr {
+                Tlp::CfgRd(r) => r.is_type1() == (r.target.bus != 0),
+                Tlp::CfgWr(w) => w.is_type1() == (w.target.bus != 0),
+                _ => false,
+            }
+        }));
+    }
+
+    #[test]
+    fn enumerator_out_of_space() {
+        let mut h = hierarchy();
+        let e = Enumerator::new(DeviceID::default())
+            .with_memory(Window::new(0x8000_0000, 0x8000_0FFF))
+            .enumerate(&mut h);
+        assert_eq!(Some(Field::Bar), e.unwrap_err().field());
+    }
+}
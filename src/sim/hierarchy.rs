@@ -0,0 +1,192 @@
+use crate::{
+    config::{ConfigAccess, HeaderLayout, HeaderType, SimFunction, HEADER_TYPE_OFFSET},
+    CompletionStatus, DeviceID, DeviceIDError, Field, Tlp, TlpError,
+};
+use alloc::vec::Vec;
+
+/// Handle to a function of a [`Hierarchy`]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FunctionId(usize);
+
+#[derive(Clone, Debug)]
+struct Slot {
+    /// Bridge whose secondary bus the function sits on, None for the root bus
+    parent: Option<FunctionId>,
+    device: u8,
+    function: u8,
+    config: SimFunction,
+}
+
+/// Set of simulated functions and bridges that answers config requests
+///
+/// Functions are placed by device and function number on the secondary bus
+/// of a bridge, or on the root bus. Bus numbers are not fixed: requests are
+/// routed through the bus numbers currently programmed into the bridges, as
+/// they would be in hardware. The root bus is bus 0, where type 0 requests
+/// are answered; type 1 requests are turned into type 0 by the bridge whose
+/// secondary bus they target.
+#[derive(Clone, Debug, Default)]
+pub struct Hierarchy {
+    slots: Vec<Slot>,
+}
+
+impl Hierarchy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a function below the bridge `parent`, or on the root bus
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` is not a bridge of this hierarchy.
+    pub fn add(
+        &mut self,
+        parent: Option<FunctionId>,
+        device: u8,
+        function: u8,
+        config: SimFunction,
+    ) -> Result<FunctionId, DeviceIDError> {
+        DeviceID::new(0, device, function)?;
+        if let Some(p) = parent {
+            assert!(self.is_bridge(p), "functions can only sit below bridges");
+        }
+        self.slots.push(Slot {
+            parent,
+            device,
+            function,
+            config,
+        });
+        Ok(FunctionId(self.slots.len() - 1))
+    }
+
+    pub fn function(&self, id: FunctionId) -> Option<&SimFunction> {
+        self.slots.get(id.0).map(|s| &s.config)
+    }
+
+    pub fn function_mut(&mut self, id: FunctionId) -> Option<&mut SimFunction> {
+        self.slots.get_mut(id.0).map(|s| &mut s.config)
+    }
+
+    /// Handles an encoded config request, returning the encoded completion
+    pub fn handle(&mut self, tlp: &[u8]) -> Result<Vec<u8>, TlpError> {
+        match Tlp::from_bytes(tlp)? {
+            Tlp::CfgRd(req) => {
+                let (status, data) = match self.lookup(req.target, req.is_type1()) {
+                    Some(slot) => (
+                        CompletionStatus::SuccessfulCompletion,
+                        self.slots[slot.0].config.read(req.reg).to_le_bytes(),
+                    ),
+                    None => (CompletionStatus::UnsupportedRequest, [0; 4]),
+                };
+                Tlp::Cpl(req.completion(req.target, status, &data)?).to_vec()
+            }
+            Tlp::CfgWr(req) => {
+                let status = match self.lookup(req.target, req.is_type1()) {
+                    Some(slot) => {
+                        let value = req.value();
+                        self.slots[slot.0]
+                            .config
+                            .write(req.reg, value, req.hdr.first_be);
+                        CompletionStatus::SuccessfulCompletion
+                    }
+                    None => CompletionStatus::UnsupportedRequest,
+                };
+                Tlp::Cpl(req.completion(req.target, status)?).to_vec()
+            }
+            tlp => Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: u8::from(tlp.header().tlp_type).into(),
+                offset: 0,
+            }),
+        }
+    }
+
+    fn is_bridge(&self, id: FunctionId) -> bool {
+        self.slots.get(id.0).is_some_and(|s| {
+            HeaderType::from(s.config.space()[HEADER_TYPE_OFFSET]).layout == HeaderLayout::Type1
+        })
+    }
+
+    /// Follows the programmed bus numbers to the function `target` refers to
+    fn lookup(&self, target: DeviceID, type1: bool) -> Option<FunctionId> {
+        // Type 0 requests only reach the root bus, type 1 requests never do
+        if type1 == (target.bus == 0) {
+            return None;
+        }
+
+        let (mut parent, mut bus) = (None, 0);
+        loop {
+            let mut below = self
+                .slots
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.parent == parent)
+                .map(|(i, s)| (FunctionId(i), s));
+            if bus == target.bus {
+                return below
+                    .find(|(_, s)| s.device == target.device && s.function == target.function)
+                    .map(|(id, _)| id);
+            }
+
+            // Bridges only forward to buses numbered above their own, which
+            // also rules out loops through unprogrammed bridges
+            let (id, secondary) = below.find_map(|(id, s)| {
+                let space = s.config.space();
+                let (secondary, subordinate) = (space[0x19], space[0x1A]);
+                (self.is_bridge(id)
+                    && secondary > bus
+                    && (secondary..=subordinate).contains(&target.bus))
+                .then_some((id, secondary))
+            })?;
+            (parent, bus) = (Some(id), secondary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ConfigHeader, Type0Header, Type1Header},
+        CfgRd, CfgWr, Cpl,
+    };
+
+    fn read(h: &mut Hierarchy, target: DeviceID, reg: u16) -> Option<u32> {
+        let req = CfgRd::new(DeviceID::default(), 0, target, reg, target.bus != 0).unwrap();
+        let cpl = h.handle(&Tlp::CfgRd(req).to_vec().unwrap()).unwrap();
+        let cpl = Cpl::from_bytes(&cpl).unwrap();
+        assert_eq!(target, cpl.hdr.cpl_id);
+        (cpl.hdr.status == CompletionStatus::SuccessfulCompletion)
+            .then(|| u32::from_le_bytes(cpl.data.try_into().unwrap()))
+    }
+
+    #[test]
+    fn hierarchy_routes_through_bridges() {
+        let mut h = Hierarchy::new();
+        let bridge = SimFunction::new(ConfigHeader::Type1(Type1Header::new(0x8086, 0x1)));
+        let ep = SimFunction::new(ConfigHeader::Type0(Type0Header::new(0x10EE, 0x2)));
+        let rp = h.add(None, 1, 0, bridge).unwrap();
+        h.add(Some(rp), 0, 0, ep).unwrap();
+
+        let id = |bus, device| DeviceID::new(bus, device, 0).unwrap();
+        assert_eq!(Some(0x0001_8086), read(&mut h, id(0, 1), 0));
+        assert_eq!(None, read(&mut h, id(0, 2), 0));
+        assert_eq!(None, read(&mut h, id(1, 0), 0));
+
+        let data = 0x0001_0100u32.to_le_bytes();
+        let wr = CfgWr::new(DeviceID::default(), 0, id(0, 1), 0x18, false, &data).unwrap();
+        h.handle(&Tlp::CfgWr(wr).to_vec().unwrap()).unwrap();
+        assert_eq!(Some(0x0002_10EE), read(&mut h, id(1, 0), 0));
+        assert_eq!(None, read(&mut h, id(2, 0), 0));
+        assert!(h.function(rp).is_some());
+    }
+
+    #[test]
+    fn hierarchy_rejects_other_tlps() {
+        let mut h = Hierarchy::new();
+        let req = crate::MRd::new(DeviceID::default(), 0, 0x1000, 4).unwrap();
+        let e = h.handle(&Tlp::MRd(req).to_vec().unwrap());
+        assert_eq!(Some(Field::FmtType), e.unwrap_err().field());
+    }
+}
@@ -0,0 +1,59 @@
+use alloc::{boxed::Box, collections::BTreeMap};
+
+/// Size of the pages memory is allocated in
+const PAGE_LEN: usize = 4096;
+
+/// Byte-addressable memory that only allocates the pages written to, with
+/// unwritten bytes reading as 0
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SparseMemory {
+    pages: BTreeMap<u64, Box<[u8; PAGE_LEN]>>,
+}
+
+impl SparseMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fills `buf` with the bytes starting at `addr`
+    pub fn read(&self, addr: u64, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let a = addr.wrapping_add(i as u64);
+            *byte = self
+                .pages
+                .get(&(a / PAGE_LEN as u64))
+                .map_or(0, |p| p[(a % PAGE_LEN as u64) as usize]);
+        }
+    }
+
+    /// Writes `data` starting at `addr`
+    pub fn write(&mut self, addr: u64, data: &[u8]) {
+        self.write_masked(addr, data, |_| true);
+    }
+
+    /// Writes the bytes of `data` for which `enabled` returns true, given
+    /// their index in `data`
+    pub fn write_masked<F>(&mut self, addr: u64, data: &[u8], enabled: F)
+    where
+        F: Fn(usize) -> bool,
+    {
+        for (i, &byte) in data.iter().enumerate().filter(|(i, _)| enabled(*i)) {
+            let a = addr.wrapping_add(i as u64);
+            let page = self
+                .pages
+                .entry(a / PAGE_LEN as u64)
+                .or_insert_with(|| Box::new([0; PAGE_LEN]));
+            page[(a % PAGE_LEN as u64) as usize] = byte;
+        }
+    }
+
+    /// Number of pages allocated
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Frees all memory
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
@@ -0,0 +1,14 @@
+//! In-process simulators that answer the TLPs built by the crate, so that
+//! requester-side code can be tested without hardware
+
+mod endpoint;
+mod enumerator;
+mod hierarchy;
+mod memory;
+mod topology;
+
+pub use endpoint::Endpoint;
+pub use enumerator::{DeviceNode, Enumeration, Enumerator};
+pub use hierarchy::{FunctionId, Hierarchy};
+pub use memory::SparseMemory;
+pub use topology::{Node, PortId, Route, RouteError, Topology};
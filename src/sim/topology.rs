@@ -0,0 +1,498 @@
+use crate::{
+    config::{Bar, Type1Header},
+    Address, DeviceID, MsgRouting, Tlp, TlpError, TlpType,
+};
+use alloc::vec::Vec;
+use byteorder::{BigEndian, ByteOrder};
+use core::fmt;
+
+/// Handle to a port of a [`Topology`]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PortId(usize);
+
+impl PortId {
+    /// Position of the port in the order it was added, starting at 0 for the
+    /// root complex
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Function at a port of a PCIe hierarchy
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Node {
+    /// Host side of the hierarchy, owning all memory not claimed below it
+    RootComplex { id: DeviceID },
+    /// Root port or switch port, forwarding TLPs through its bus numbers and
+    /// windows
+    Bridge { id: DeviceID, hdr: Type1Header },
+    /// Function claiming memory and I/O requests that hit its BARs
+    Endpoint { id: DeviceID, bars: Vec<Bar> },
+}
+
+impl Node {
+    pub fn id(&self) -> DeviceID {
+        match self {
+            Node::RootComplex { id } | Node::Bridge { id, .. } | Node::Endpoint { id, .. } => *id,
+        }
+    }
+}
+
+/// Destination of a routed TLP
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Route {
+    /// Delivered to a single port
+    Port(PortId),
+    /// Delivered to every port below the ingress port
+    Broadcast(Vec<PortId>),
+}
+
+/// Errors raised while routing a TLP
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RouteError {
+    /// The TLP could not be decoded
+    Decode(TlpError),
+    /// No function claims the TLP, which `port` rejects as an unsupported
+    /// request
+    UnsupportedRequest { port: PortId },
+}
+
+impl From<TlpError> for RouteError {
+    fn from(e: TlpError) -> Self {
+        RouteError::Decode(e)
+    }
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::Decode(e) => write!(f, "undecodable TLP: {}", e),
+            RouteError::UnsupportedRequest { port } => {
+                write!(f, "unsupported request at port {}", port.0)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RouteError {}
+
+/// What a TLP is routed by
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Target {
+    Memory(u64, u64),
+    Io(u64, u64),
+    Id(DeviceID),
+    RootComplex,
+    Broadcast,
+    Local,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    node: Node,
+    parent: Option<PortId>,
+    children: Vec<PortId>,
+}
+
+/// Model of a PCIe hierarchy that routes TLPs between its ports
+///
+/// The hierarchy is a tree rooted at the root complex. Bridges forward
+/// requests downstream when they fall inside their bus range or enabled
+/// windows, and upstream otherwise. Config requests are routed by ID
+/// whether they are type 0 or type 1.
+#[derive(Clone, Debug)]
+pub struct Topology {
+    ports: Vec<Entry>,
+}
+
+impl Topology {
+    /// Port of the root complex
+    pub const ROOT: PortId = PortId(0);
+
+    pub fn new<T>(rc_id: T) -> Self
+    where
+        T: Into<DeviceID>,
+    {
+        Self {
+            ports: alloc::vec![Entry {
+                node: Node::RootComplex { id: rc_id.into() },
+                parent: None,
+                children: Vec::new(),
+            }],
+        }
+    }
+
+    /// Adds a root or switch port below `parent`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` is an endpoint or not a port of this topology.
+    pub fn add_bridge<T>(&mut self, parent: PortId, id: T, hdr: Type1Header) -> PortId
+    where
+        T: Into<DeviceID>,
+    {
+        self.add(parent, Node::Bridge { id: id.into(), hdr })
+    }
+
+    /// Adds an endpoint below `parent`, claiming requests that hit `bars`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` is an endpoint or not a port of this topology.
+    pub fn add_endpoint<T, B>(&mut self, parent: PortId, id: T, bars: B) -> PortId
+    where
+        T: Into<DeviceID>,
+        B: IntoIterator<Item = Bar>,
+    {
+        self.add(
+            parent,
+            Node::Endpoint {
+                id: id.into(),
+                bars: bars.into_iter().collect(),
+            },
+        )
+    }
+
+    fn add(&mut self, parent: PortId, node: Node) -> PortId {
+        assert!(
+            !matches!(self.ports[parent.0].node, Node::Endpoint { .. }),
+            "endpoints cannot have ports below them"
+        );
+        let port = PortId(self.ports.len());
+        self.ports.push(Entry {
+            node,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        self.ports[parent.0].children.push(port);
+        port
+    }
+
+    pub fn node(&self, port: PortId) -> Option<&Node> {
+        self.ports.get(port.0).map(|e| &e.node)
+    }
+
+    /// Gives access to a node, for instance to reprogram a bridge's windows
+    pub fn node_mut(&mut self, port: PortId) -> Option<&mut Node> {
+        self.ports.get_mut(port.0).map(|e| &mut e.node)
+    }
+
+    pub fn parent(&self, port: PortId) -> Option<PortId> {
+        self.ports.get(port.0)?.parent
+    }
+
+    pub fn children(&self, port: PortId) -> &[PortId] {
+        self.ports.get(port.0).map_or(&[], |e| &e.children)
+    }
+
+    /// Returns the port that answers to `id`, if any
+    pub fn find(&self, id: DeviceID) -> Option<PortId> {
+        self.ports
+            .iter()
+            .position(|e| e.node.id() == id)
+            .map(PortId)
+    }
+
+    /// Routes an encoded TLP received at `ingress` to its destination
+    ///
+    /// Memory and I/O requests are routed by address, config requests and
+    /// completions by ID, and messages by their routing type. Local messages
+    /// terminate at `ingress`.
+    pub fn route(&self, ingress: PortId, tlp: &[u8]) -> Result<Route, RouteError> {
+        let target = Self::target(&Tlp::from_bytes(tlp)?);
+        match target {
+            Target::Local => Ok(Route::Port(ingress)),
+            Target::Broadcast => Ok(Route::Broadcast(self.below(ingress))),
+            Target::RootComplex if ingress != Self::ROOT => Ok(Route::Port(Self::ROOT)),
+            Target::RootComplex => Err(RouteError::UnsupportedRequest { port: ingress }),
+            t => self.walk(ingress, t).map(Route::Port),
+        }
+    }
+
+    /// Returns what a decoded TLP is routed by
+    fn target(tlp: &Tlp) -> Target {
+        match tlp {
+            Tlp::MRd(req) => Target::Memory(req.addr.into(), req.hdr.hdr.data_len().into()),
+            Tlp::MWr(req) => Target::Memory(req.addr.into(), req.data.len() as u64),
+            Tlp::DMWr(req) => Target::Memory(req.addr.into(), req.data.len() as u64),
+            Tlp::CfgRd(req) => Target::Id(req.target),
+            Tlp::CfgWr(req) => Target::Id(req.target),
+            Tlp::Cpl(cpl) => Target::Id(cpl.hdr.req_id),
+            Tlp::Msg(msg) => match msg.hdr.routing() {
+                Some(MsgRouting::ToRootComplex) | Some(MsgRouting::Gathered) => Target::RootComplex,
+                Some(MsgRouting::ByAddress) => {
+                    Target::Memory(BigEndian::read_u64(&msg.hdr.body), 1)
+                }
+                // SAFETY: Messages routed by ID always have a target ID
+                Some(MsgRouting::ById) => Target::Id(msg.hdr.target_id().unwrap()),
+                Some(MsgRouting::Broadcast) => Target::Broadcast,
+                Some(MsgRouting::Local) | None => Target::Local,
+            },
+            Tlp::Other(hdr, bytes) => match hdr.tlp_type {
+                TlpType::IORdT | TlpType::IOWrtT => {
+                    let addr = BigEndian::read_u32(&bytes[8..12]) & !0x3;
+                    Target::Io(addr.into(), hdr.data_len().into())
+                }
+                TlpType::MRdLk3 => {
+                    let addr = BigEndian::read_u32(&bytes[8..12]) & !0x3;
+                    Target::Memory(addr.into(), hdr.data_len().into())
+                }
+                TlpType::MRdLk4 => {
+                    let addr = BigEndian::read_u64(&bytes[8..16]) & !0x3;
+                    Target::Memory(addr, hdr.data_len().into())
+                }
+                // Anything else cannot be routed, so leave it at the receiver
+                _ => Target::Local,
+            },
+        }
+    }
+
+    /// Walks the tree from `ingress` to the port that claims `target`
+    fn walk(&self, ingress: PortId, target: Target) -> Result<PortId, RouteError> {
+        let (mut cur, mut from, mut down) = (ingress, None, false);
+        loop {
+            let entry = &self.ports[cur.0];
+            if cur != ingress && Self::claims(&entry.node, target) {
+                return Ok(cur);
+            }
+
+            let next = entry.children.iter().copied().find(|&c| {
+                let node = &self.ports[c.0].node;
+                Some(c) != from && (Self::claims(node, target) || Self::forwards(node, target))
+            });
+            if let Some(c) = next {
+                (cur, from, down) = (c, None, true);
+                continue;
+            }
+
+            match entry.parent {
+                Some(p) if !down && !Self::forwards(&entry.node, target) => {
+                    (cur, from) = (p, Some(cur));
+                }
+                // Requests from below that nothing else claims go to host memory
+                None if !down && cur != ingress && matches!(target, Target::Memory(..)) => {
+                    return Ok(cur);
+                }
+                _ => return Err(RouteError::UnsupportedRequest { port: cur }),
+            }
+        }
+    }
+
+    /// Returns true if `node` is the destination of `target`
+    fn claims(node: &Node, target: Target) -> bool {
+        match (node, target) {
+            (node, Target::Id(id)) => node.id() == id,
+            (Node::Endpoint { bars, .. }, Target::Memory(addr, len)) => {
+                bars.iter().any(|b| !b.is_io() && Self::hits(b, addr, len))
+            }
+            (Node::Endpoint { bars, .. }, Target::Io(addr, len)) => {
+                bars.iter().any(|b| b.is_io() && Self::hits(b, addr, len))
+            }
+            _ => false,
+        }
+    }
+
+    fn hits(bar: &Bar, addr: u64, len: u64) -> bool {
+        Address::try_from(addr).is_ok_and(|a| bar.hit(a, len).is_some())
+    }
+
+    /// Returns true if `node` is a bridge forwarding `target` downstream
+    fn forwards(node: &Node, target: Target) -> bool {
+        let Node::Bridge { hdr, .. } = node else {
+            return false;
+        };
+        let command = hdr.common.command;
+        match target {
+            Target::Id(id) => hdr.forwards_bus(id.bus),
+            Target::Memory(addr, _) => {
+                command.memory
+                    && [hdr.memory_window(), hdr.prefetch_window()]
+                        .iter()
+                        .flatten()
+                        .any(|w| w.contains(addr))
+            }
+            Target::Io(addr, _) => command.io && hdr.io_window().is_some_and(|w| w.contains(addr)),
+            _ => false,
+        }
+    }
+
+    /// Returns every port below `port`, in depth-first order
+    fn below(&self, port: PortId) -> Vec<PortId> {
+        let mut ports = Vec::new();
+        let mut stack: Vec<PortId> = self.children(port).iter().rev().copied().collect();
+        while let Some(p) = stack.pop() {
+            ports.push(p);
+            stack.extend(self.children(p).iter().rev());
+        }
+        ports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BarKind, Window},
+        CfgRd, Cpl, CplHeader, MRd, MWr, Msg, MsgHeader,
+    };
+    use alloc::{vec, vec::Vec};
+
+    fn id(bus: u8, device: u8, function: u8) -> DeviceID {
+        DeviceID::new(bus, device, function).unwrap()
+    }
+
+    fn bridge(secondary: u8, subordinate: u8, base: u64, limit: u64) -> Type1Header {
+        let mut hdr = Type1Header::new(0x8086, 0x1234)
+            .with_buses(secondary.saturating_sub(1), secondary, subordinate)
+            .with_memory_window(Some(Window::new(base, limit)))
+            .and_then(|h| h.with_prefetch_window(None))
+            .and_then(|h| h.with_io_window(None))
+            .unwrap();
+        hdr.common.command.memory = true;
+        hdr
+    }
+
+    fn bar(addr: u64, size: u64) -> Bar {
+        Bar {
+            index: 0,
+            kind: BarKind::Memory64,
+            prefetchable: false,
+            addr,
+            size,
+        }
+    }
+
+    /// Root port above a switch with two downstream ports, each with an
+    /// endpoint
+    fn topology() -> (Topology, [PortId; 6]) {
+        let mut t = Topology::new(id(0, 0, 0));
+        let rp = t.add_bridge(
+            Topology::ROOT,
+            id(0, 1, 0),
+            bridge(1, 4, 0x8000_0000, 0x80FF_FFFF),
+        );
+        let usp = t.add_bridge(rp, id(1, 0, 0), bridge(2, 4, 0x8000_0000, 0x80FF_FFFF));
+        let dsp0 = t.add_bridge(usp, id(2, 0, 0), bridge(3, 3, 0x8000_0000, 0x800F_FFFF));
+        let dsp1 = t.add_bridge(usp, id(2, 1, 0), bridge(4, 4, 0x8010_0000, 0x801F_FFFF));
+        let ep0 = t.add_endpoint(dsp0, id(3, 0, 0), [bar(0x8000_0000, 0x10000)]);
+        let ep1 = t.add_endpoint(dsp1, id(4, 0, 0), [bar(0x8010_0000, 0x1000)]);
+        (t, [rp, usp, dsp0, dsp1, ep0, ep1])
+    }
+
+    fn mrd(addr: u64) -> Vec<u8> {
+        Tlp::MRd(MRd::new(DeviceID::default(), 0, addr, 4).unwrap())
+            .to_vec()
+            .unwrap()
+    }
+
+    #[test]
+    fn topology_memory_routing() {
+        let (t, [_, _, dsp0, _, ep0, ep1]) = topology();
+        assert_eq!(
+            Ok(Route::Port(ep0)),
+            t.route(Topology::ROOT, &mrd(0x8000_0100))
+        );
+        // Peer to peer through the switch
+        assert_eq!(Ok(Route::Port(ep1)), t.route(ep0, &mrd(0x8010_0FFC)));
+        // Upstream to host memory
+        assert_eq!(Ok(Route::Port(Topology::ROOT)), t.route(ep1, &mrd(0x1000)));
+
+        // Inside the window of a downstream port but outside of any BAR
+        let e = t.route(Topology::ROOT, &mrd(0x8001_0000));
+        assert_eq!(Err(RouteError::UnsupportedRequest { port: dsp0 }), e);
+        let e = t.route(Topology::ROOT, &mrd(0x9000_0000));
+        let root = Topology::ROOT;
+        assert_eq!(Err(RouteError::UnsupportedRequest { port: root }), e);
+
+        // Larger than the BAR
+        let data = [0; 8];
+        let wr = Tlp::MWr(MWr::new(id(3, 0, 0), 0, 0x8010_0FFC, &data).unwrap())
+            .to_vec()
+            .unwrap();
+        assert!(t.route(ep0, &wr).is_err());
+    }
+
+    #[test]
+    fn topology_memory_disabled() {
+        let (mut t, [_, _, dsp0, _, ep0, _]) = topology();
+        assert_eq!(
+            Ok(Route::Port(ep0)),
+            t.route(Topology::ROOT, &mrd(0x8000_0000))
+        );
+        if let Some(Node::Bridge { hdr, .. }) = t.node_mut(dsp0) {
+            hdr.common.command.memory = false;
+        }
+        let e = t.route(Topology::ROOT, &mrd(0x8000_0000));
+        assert!(matches!(e, Err(RouteError::UnsupportedRequest { .. })));
+    }
+
+    #[test]
+    fn topology_id_routing() {
+        let (t, [_, usp, _, dsp1, ep0, ep1]) = topology();
+        let cfg = |target| {
+            Tlp::CfgRd(CfgRd::new(id(0, 0, 0), 0, target, 0, true).unwrap())
+                .to_vec()
+                .unwrap()
+        };
+        assert_eq!(
+            Ok(Route::Port(ep1)),
+            t.route(Topology::ROOT, &cfg(id(4, 0, 0)))
+        );
+        assert_eq!(
+            Ok(Route::Port(dsp1)),
+            t.route(Topology::ROOT, &cfg(id(2, 1, 0)))
+        );
+        assert_eq!(
+            Ok(Route::Port(usp)),
+            t.route(Topology::ROOT, &cfg(id(1, 0, 0)))
+        );
+        let e = t.route(Topology::ROOT, &cfg(id(4, 0, 1)));
+        assert_eq!(Err(RouteError::UnsupportedRequest { port: dsp1 }), e);
+        let e = t.route(Topology::ROOT, &cfg(id(9, 0, 0)));
+        let root = Topology::ROOT;
+        assert_eq!(Err(RouteError::UnsupportedRequest { port: root }), e);
+
+        let cpl = |req_id| {
+            let hdr = CplHeader::new().with_req_id(req_id);
+            Tlp::Cpl(Cpl::new(hdr, &[]).unwrap()).to_vec().unwrap()
+        };
+        assert_eq!(
+            Ok(Route::Port(Topology::ROOT)),
+            t.route(ep1, &cpl(id(0, 0, 0)))
+        );
+        assert_eq!(Ok(Route::Port(ep0)), t.route(ep1, &cpl(id(3, 0, 0))));
+        assert_eq!(Some(ep0), t.find(id(3, 0, 0)));
+    }
+
+    #[test]
+    fn topology_message_routing() {
+        let (t, ports) = topology();
+        let msg = |routing, target| {
+            let hdr = MsgHeader::new()
+                .with_hdr(crate::TlpHeader::new().with_type(TlpType::msg(routing, false)))
+                .with_target_id(target);
+            Tlp::Msg(Msg {
+                prefix: None,
+                hdr,
+                data: &[],
+            })
+            .to_vec()
+            .unwrap()
+        };
+        let [_, usp, _, dsp1, ep0, ep1] = ports;
+        let root = Topology::ROOT;
+        let to_rc = msg(MsgRouting::ToRootComplex, id(0, 0, 0));
+        assert_eq!(Ok(Route::Port(root)), t.route(ep0, &to_rc));
+        let by_id = msg(MsgRouting::ById, id(4, 0, 0));
+        assert_eq!(Ok(Route::Port(ep1)), t.route(ep0, &by_id));
+        let local = msg(MsgRouting::Local, id(0, 0, 0));
+        assert_eq!(Ok(Route::Port(ep0)), t.route(ep0, &local));
+
+        let bcast = msg(MsgRouting::Broadcast, id(0, 0, 0));
+        let all = vec![ports[0], usp, ports[2], ep0, dsp1, ep1];
+        assert_eq!(Ok(Route::Broadcast(all)), t.route(root, &bcast));
+        assert_eq!(Ok(Route::Broadcast(vec![ep1])), t.route(dsp1, &bcast));
+        assert_eq!(&[ports[2], dsp1], t.children(usp));
+    }
+}
@@ -0,0 +1,526 @@
+//! Proptest strategies generating valid headers and TLPs, for the property
+//! tests of crates built on this one
+//!
+//! Every strategy takes a [`TlpParams`] that constrains what it generates,
+//! such as the address range, the max payload size or 3DW headers only.
+//! Packets with a payload borrow it, so packet strategies generate encoded
+//! TLPs that decode with [`Tlp::from_bytes`]:
+//!
+//! ```
+//! # use rust_pcie_tlp::{strategy::{self, TlpParams}, Tlp};
+//! use proptest::{strategy::{Strategy, ValueTree}, test_runner::TestRunner};
+//!
+//! let params = TlpParams::new().with_max_payload(256).unwrap().with_3dw_only(true).unwrap();
+//! let bytes = strategy::tlp(&params)
+//!     .new_tree(&mut TestRunner::default())
+//!     .unwrap()
+//!     .current();
+//! assert!(Tlp::from_bytes(&bytes).unwrap().data().len() <= 256);
+//! ```
+
+use crate::{
+    CfgRd, CfgWr, CompletionStatus, Cpl, CplHeader, DMWr, DeviceID, Field, MRd, MWr, Msg,
+    MsgHeader, MsgRouting, PasidPrefix, Tlp, TlpError, TlpHeader, TlpType, TrafficClass, DWORD_LEN,
+    MAX_DATA_LEN,
+};
+use alloc::{vec, vec::Vec};
+use core::ops::RangeInclusive;
+use num_traits::FromPrimitive;
+use proptest::{collection, option, prelude::*, sample::select};
+
+/// Bytes that a memory request must not cross
+const PAGE_LEN: u64 = 4096;
+
+/// Largest config space register of a config request
+const MAX_REG: u16 = 0xFFC;
+
+/// Constraints on the headers and TLPs generated by the strategies
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TlpParams {
+    addr: RangeInclusive<u64>,
+    max_payload: u16,
+    max_read: u16,
+    three_dw: bool,
+}
+
+impl Default for TlpParams {
+    fn default() -> Self {
+        Self {
+            addr: 0..=u64::MAX,
+            max_payload: MAX_DATA_LEN as u16,
+            max_read: MAX_DATA_LEN as u16,
+            three_dw: false,
+        }
+    }
+}
+
+impl TlpParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the bytes accessed by memory requests to `addr`, which must
+    /// hold at least one aligned dword
+    pub fn with_addr_range(mut self, addr: RangeInclusive<u64>) -> Result<Self, TlpError> {
+        self.addr = addr;
+        self.check_addr()?;
+        Ok(self)
+    }
+
+    /// Sets the largest payload of writes, completions and messages in bytes,
+    /// a power of two from 128 to 4096
+    pub fn with_max_payload(mut self, max_payload: u16) -> Result<Self, TlpError> {
+        self.max_payload = check_size(max_payload)?;
+        Ok(self)
+    }
+
+    /// Sets the largest length of memory reads in bytes, a power of two from
+    /// 128 to 4096
+    pub fn with_max_read(mut self, max_read: u16) -> Result<Self, TlpError> {
+        self.max_read = check_size(max_read)?;
+        Ok(self)
+    }
+
+    /// Restricts generated TLPs to 3DW headers, limiting memory requests to
+    /// 32 bit addresses and leaving messages out of [`tlp`]
+    pub fn with_3dw_only(mut self, three_dw: bool) -> Result<Self, TlpError> {
+        self.three_dw = three_dw;
+        self.check_addr()?;
+        Ok(self)
+    }
+
+    /// Range of dword addresses that memory requests may start at
+    fn dwords(&self) -> Option<RangeInclusive<u64>> {
+        let end = match self.three_dw {
+            true => (*self.addr.end()).min(u32::MAX.into()),
+            false => *self.addr.end(),
+        };
+        let first = self
+            .addr
+            .start()
+            .checked_next_multiple_of(DWORD_LEN as u64)?
+            / 4;
+        let last = (end.checked_sub(3)?) / 4;
+        (first <= last).then_some(first..=last)
+    }
+
+    fn check_addr(&self) -> Result<(), TlpError> {
+        match self.dwords() {
+            Some(_) => Ok(()),
+            None => Err(TlpError::OutOfRange {
+                field: Field::Address,
+                value: *self.addr.start(),
+                min: 0,
+                max: self.addr.end().saturating_sub(3) & !3,
+                offset: 8,
+            }),
+        }
+    }
+}
+
+fn check_size(size: u16) -> Result<u16, TlpError> {
+    if size.is_power_of_two() && (128..=MAX_DATA_LEN as u16).contains(&size) {
+        Ok(size)
+    } else {
+        Err(TlpError::OutOfRange {
+            field: Field::Length,
+            value: size.into(),
+            min: 128,
+            max: MAX_DATA_LEN as u64,
+            offset: 2,
+        })
+    }
+}
+
+pub fn device_id() -> impl Strategy<Value = DeviceID> {
+    // SAFETY: Device and function are within the range of their fields
+    (any::<u8>(), 0u8..=31, 0u8..=7)
+        .prop_map(|(bus, device, function)| DeviceID::new(bus, device, function).unwrap())
+}
+
+pub fn traffic_class() -> impl Strategy<Value = TrafficClass> {
+    // SAFETY: Every 3 bit value is a traffic class
+    (0u8..8).prop_map(|tc| TrafficClass::from_u8(tc).unwrap())
+}
+
+pub fn pasid_prefix() -> impl Strategy<Value = PasidPrefix> {
+    (0..=PasidPrefix::MAX_PASID, any::<bool>(), any::<bool>()).prop_map(
+        |(pasid, exe, privileged)| {
+            // SAFETY: PASID is within the range of the field
+            PasidPrefix::new()
+                .with_pasid(pasid)
+                .unwrap()
+                .with_exe(exe)
+                .with_privileged(privileged)
+        },
+    )
+}
+
+/// Traffic class, relaxed ordering, no-snoop and ID-based ordering of a memory
+/// request or completion
+fn attrs() -> impl Strategy<Value = (TrafficClass, bool, bool, bool)> {
+    (traffic_class(), any::<bool>(), any::<bool>(), any::<bool>())
+}
+
+fn with_attrs(hdr: TlpHeader, (tc, ro, ns, ibo): (TrafficClass, bool, bool, bool)) -> TlpHeader {
+    hdr.with_tc(tc).with_ro(ro).with_ns(ns).with_ibo(ibo)
+}
+
+/// Address and length in bytes of a memory request of up to `max_len` bytes
+/// that stays within the address range and does not cross a 4KB boundary
+fn mem_range(params: &TlpParams, max_len: u16) -> impl Strategy<Value = (u64, u16)> {
+    // SAFETY: Address range is checked by the setters of the parameters
+    let dwords = params.dwords().unwrap();
+    let (first, last) = (*dwords.start(), *dwords.end());
+    let split = u64::from(u32::MAX) / 4;
+    // Favour 32 bit addresses when the range spans both sizes
+    let addr = if first <= split && last > split {
+        prop_oneof![first..=split, split + 1..=last].boxed()
+    } else {
+        dwords.boxed()
+    };
+
+    addr.prop_flat_map(move |dw| {
+        let addr = dw * 4;
+        let to_page = (PAGE_LEN - addr % PAGE_LEN) / 4;
+        let to_end = last - dw + 1;
+        let max = u64::from(max_len / 4).min(to_page).min(to_end);
+        (Just(addr), (1..=max).prop_map(|n| n as u16 * 4))
+    })
+}
+
+fn payload(len: u16) -> impl Strategy<Value = Vec<u8>> {
+    collection::vec(any::<u8>(), usize::from(len))
+}
+
+/// Memory read of up to the max read size
+pub fn mrd(params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    (
+        mem_range(params, params.max_read),
+        device_id(),
+        any::<u8>(),
+        attrs(),
+    )
+        .prop_map(|((addr, len), req_id, tag, attrs)| {
+            // SAFETY: Address and length are valid for a request
+            let mut mrd = MRd::new(req_id, tag, addr, len).unwrap();
+            mrd.hdr.hdr = with_attrs(mrd.hdr.hdr, attrs);
+            Tlp::MRd(mrd).to_vec().unwrap()
+        })
+}
+
+/// Memory write of up to the max payload size
+pub fn mwr(params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    (
+        mem_range(params, params.max_payload),
+        device_id(),
+        any::<u8>(),
+        attrs(),
+    )
+        .prop_flat_map(|((addr, len), req_id, tag, attrs)| {
+            (Just((addr, req_id, tag, attrs)), payload(len))
+        })
+        .prop_map(|((addr, req_id, tag, attrs), data)| {
+            // SAFETY: Address and payload are valid for a request
+            let mut mwr = MWr::new(req_id, tag, addr, &data).unwrap();
+            mwr.hdr.hdr = with_attrs(mwr.hdr.hdr, attrs);
+            Tlp::MWr(mwr).to_vec().unwrap()
+        })
+}
+
+/// Deferrable memory write of up to the max payload size
+pub fn dmwr(params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    (
+        mem_range(params, params.max_payload),
+        device_id(),
+        any::<u8>(),
+        attrs(),
+    )
+        .prop_flat_map(|((addr, len), req_id, tag, attrs)| {
+            (Just((addr, req_id, tag, attrs)), payload(len))
+        })
+        .prop_map(|((addr, req_id, tag, attrs), data)| {
+            // SAFETY: Address and payload are valid for a request
+            let mut dmwr = DMWr::new(req_id, tag, addr, &data).unwrap();
+            dmwr.hdr.hdr = with_attrs(dmwr.hdr.hdr, attrs);
+            Tlp::DMWr(dmwr).to_vec().unwrap()
+        })
+}
+
+/// Type 0 or type 1 config read
+pub fn cfg_rd(_params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    (
+        device_id(),
+        any::<u8>(),
+        device_id(),
+        0..=MAX_REG / 4,
+        any::<bool>(),
+    )
+        .prop_map(|(req_id, tag, target, reg, type1)| {
+            // SAFETY: Register is aligned and within config space
+            let cfg = CfgRd::new(req_id, tag, target, reg * 4, type1).unwrap();
+            Tlp::CfgRd(cfg).to_vec().unwrap()
+        })
+}
+
+/// Type 0 or type 1 config write of any byte enables
+pub fn cfg_wr(_params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    (
+        (device_id(), any::<u8>(), device_id()),
+        (0..=MAX_REG / 4, any::<bool>(), any::<[u8; 4]>(), 0u8..16),
+    )
+        .prop_map(|((req_id, tag, target), (reg, type1, data, first_be))| {
+            // SAFETY: Register is aligned and within config space, and the
+            // byte enables fit in their field
+            let cfg = CfgWr::new(req_id, tag, target, reg * 4, type1, &data)
+                .and_then(|cfg| cfg.with_first_be(first_be))
+                .unwrap();
+            Tlp::CfgWr(cfg).to_vec().unwrap()
+        })
+}
+
+/// Completion header without its type and length, which [`Cpl::new`] sets
+/// from the payload
+pub fn cpl_header(_params: &TlpParams) -> impl Strategy<Value = CplHeader> {
+    (
+        (device_id(), device_id(), any::<u8>(), attrs()),
+        (
+            select(vec![
+                CompletionStatus::SuccessfulCompletion,
+                CompletionStatus::UnsupportedRequest,
+                CompletionStatus::ConfigurationRequestRetry,
+                CompletionStatus::CompleterAbort,
+            ]),
+            0u16..4096,
+            0u8..128,
+        ),
+    )
+        .prop_map(|((cpl_id, req_id, tag, attrs), (status, bc, addr_low))| {
+            // SAFETY: Byte count and lower address fit in their fields
+            CplHeader::new()
+                .with_hdr(with_attrs(TlpHeader::new(), attrs))
+                .with_cpl_id(cpl_id)
+                .with_req_id(req_id)
+                .with_tag(tag)
+                .with_status(status)
+                .with_bc(bc)
+                .unwrap()
+                .with_addr(addr_low)
+                .unwrap()
+        })
+}
+
+/// Completion, carrying up to the max payload size when successful, with a
+/// byte count covering its payload
+pub fn cpl(params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    let max_dw = params.max_payload / 4;
+    cpl_header(params)
+        .prop_flat_map(move |hdr| {
+            let max_dw = match hdr.status {
+                CompletionStatus::SuccessfulCompletion => max_dw,
+                _ => 0,
+            };
+            (Just(hdr), (0..=max_dw).prop_flat_map(|n| payload(n * 4)))
+        })
+        .prop_flat_map(|(hdr, data)| {
+            // Remaining bytes cover at least this payload, less the bytes
+            // before the lower address and up to 3 trailing bytes
+            let min_bc = match data.len() as u16 {
+                0 => 0,
+                len => len.saturating_sub(u16::from(hdr.addr_low & 0x3) + 3).max(1),
+            };
+            (Just(hdr), Just(data), min_bc..=MAX_DATA_LEN as u16)
+        })
+        .prop_map(|(hdr, data, bc)| {
+            // SAFETY: A byte count of 4096 is encoded as 0, and the payload is
+            // within the max payload size
+            let hdr = hdr.with_bc(bc & 0xFFF).unwrap();
+            Tlp::Cpl(Cpl::new(hdr, &data).unwrap()).to_vec().unwrap()
+        })
+}
+
+fn msg_routing() -> impl Strategy<Value = MsgRouting> {
+    select(vec![
+        MsgRouting::ToRootComplex,
+        MsgRouting::ByAddress,
+        MsgRouting::ById,
+        MsgRouting::Broadcast,
+        MsgRouting::Local,
+        MsgRouting::Gathered,
+    ])
+}
+
+/// Message header of any routing and code, with or without data
+pub fn msg_header(_params: &TlpParams) -> impl Strategy<Value = MsgHeader> {
+    (
+        msg_routing(),
+        any::<bool>(),
+        device_id(),
+        any::<u8>(),
+        any::<u8>(),
+        any::<[u8; 8]>(),
+    )
+        .prop_map(|(routing, with_data, req_id, tag, code, body)| {
+            MsgHeader::new()
+                .with_hdr(TlpHeader::new().with_type(TlpType::msg(routing, with_data)))
+                .with_req_id(req_id)
+                .with_tag(tag)
+                .with_code(code)
+                .with_body(body)
+        })
+}
+
+/// Message with an optional PASID prefix, carrying up to the max payload size
+///
+/// Messages always have 4DW headers, whatever [`TlpParams::with_3dw_only`].
+pub fn msg(params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    let max_dw = params.max_payload / 4;
+    (option::of(pasid_prefix()), msg_header(params))
+        .prop_flat_map(move |(prefix, hdr)| {
+            let data = match hdr.hdr.tlp_type.has_data() {
+                true => (1..=max_dw).prop_flat_map(|n| payload(n * 4)).boxed(),
+                false => Just(Vec::new()).boxed(),
+            };
+            (Just((prefix, hdr)), data)
+        })
+        .prop_map(|((prefix, mut hdr), data)| {
+            if !data.is_empty() {
+                // SAFETY: Payload is within the max payload size
+                hdr.hdr = hdr.hdr.with_length(data.len() as u16).unwrap();
+            }
+            Tlp::Msg(Msg {
+                prefix,
+                hdr,
+                data: &data,
+            })
+            .to_vec()
+            .unwrap()
+        })
+}
+
+/// Any TLP with a typed decoder, leaving out messages when restricted to 3DW
+/// headers
+pub fn tlp(params: &TlpParams) -> impl Strategy<Value = Vec<u8>> {
+    let mut tlps = vec![
+        mrd(params).boxed(),
+        mwr(params).boxed(),
+        dmwr(params).boxed(),
+        cfg_rd(params).boxed(),
+        cfg_wr(params).boxed(),
+        cpl(params).boxed(),
+    ];
+    if !params.three_dw {
+        tlps.push(msg(params).boxed());
+    }
+    proptest::strategy::Union::new(tlps)
+}
+
+/// First header dword of any TLP generated by [`tlp`]
+pub fn tlp_header(params: &TlpParams) -> impl Strategy<Value = TlpHeader> {
+    // SAFETY: Generated TLPs always decode
+    tlp(params).prop_map(|bytes| Tlp::from_bytes(&bytes).unwrap().header())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Address;
+
+    fn params() -> impl Strategy<Value = TlpParams> {
+        let size = || select(vec![128u16, 256, 512, 1024, 2048, 4096]);
+        (size(), size(), any::<bool>(), any::<u64>(), 4u64..0x10_0000).prop_filter_map(
+            "Address range without a dword",
+            |(max_payload, max_read, three_dw, start, len)| {
+                let start = match three_dw {
+                    true => start % u64::from(u32::MAX),
+                    false => start,
+                };
+                TlpParams::new()
+                    .with_max_payload(max_payload)
+                    .and_then(|p| p.with_max_read(max_read))
+                    .and_then(|p| p.with_addr_range(start..=start.saturating_add(len)))
+                    .and_then(|p| p.with_3dw_only(three_dw))
+                    .ok()
+            },
+        )
+    }
+
+    fn check_mem_req(params: &TlpParams, addr: Address, len: usize) {
+        let addr = u64::from(addr);
+        let end = addr + len as u64 - 1;
+        assert!(params.addr.contains(&addr) && params.addr.contains(&end));
+        assert_eq!(addr / PAGE_LEN, end / PAGE_LEN);
+    }
+
+    proptest! {
+        /// Tests that generated TLPs decode and respect their parameters
+        #[test]
+        fn tlp_params(
+            (params, bytes) in params().prop_flat_map(|p| (Just(p.clone()), tlp(&p)))
+        ) {
+            let tlp = Tlp::from_bytes(&bytes).unwrap();
+            assert_eq!(bytes.len(), tlp.encoded_len());
+            assert!(tlp.data().len() <= usize::from(params.max_payload));
+            if params.three_dw {
+                assert_eq!(Some(3 * DWORD_LEN), tlp.header().tlp_type.header_len());
+            }
+
+            match tlp {
+                Tlp::MRd(t) => {
+                    let len = usize::from(t.hdr.hdr.data_len());
+                    assert!(len <= usize::from(params.max_read));
+                    check_mem_req(&params, t.addr, len);
+                }
+                Tlp::MWr(t) => check_mem_req(&params, t.addr, t.data.len()),
+                Tlp::DMWr(t) => check_mem_req(&params, t.addr, t.data.len()),
+                Tlp::Cpl(t) => {
+                    assert!(t.data.is_empty() || t.hdr.status == CompletionStatus::SuccessfulCompletion);
+                    if !t.data.is_empty() {
+                        let bc = match t.hdr.bc {
+                            0 => 4096,
+                            bc => usize::from(bc),
+                        };
+                        let skipped = usize::from(t.hdr.addr_low & 0x3) + 3;
+                        assert!(bc + skipped >= t.data.len());
+                    }
+                }
+                Tlp::Other(..) => panic!("Generated TLP without a typed decoder"),
+                _ => {}
+            }
+        }
+
+        /// Tests that message headers decode to the same header
+        #[test]
+        fn msg_header_roundtrip(hdr in msg_header(&TlpParams::new())) {
+            assert_eq!(Ok(hdr), MsgHeader::from_bytes(hdr.to_bytes()));
+            assert!(hdr.routing().is_some());
+        }
+
+        /// Tests that memory requests favour 32 bit addresses
+        #[test]
+        fn mrd_addr_sizes(bytes in collection::vec(mrd(&TlpParams::new()), 32)) {
+            let short = bytes
+                .iter()
+                .filter(|b| Tlp::from_bytes(b).unwrap().header().tlp_type == TlpType::MRd3)
+                .count();
+            assert!(short > 0 && short < bytes.len());
+        }
+    }
+
+    #[test]
+    fn bad_params() {
+        assert!(TlpParams::new().with_max_payload(64).is_err());
+        assert!(TlpParams::new().with_max_read(384).is_err());
+        assert!(TlpParams::new().with_addr_range(1..=6).is_err());
+        assert!(TlpParams::new().with_addr_range(4..=7).is_ok());
+
+        let high = TlpParams::new()
+            .with_addr_range(1 << 32..=u64::MAX)
+            .unwrap();
+        assert!(high.clone().with_3dw_only(true).is_err());
+        assert!(TlpParams::new()
+            .with_3dw_only(true)
+            .and_then(|p| p.with_addr_range(1 << 32..=u64::MAX))
+            .is_err());
+    }
+}
@@ -0,0 +1,545 @@
+//! Serialization of TLPs into the beats of a streaming data bus, and back
+//!
+//! Beats carry TLP bytes in order from bus byte 0 (bits 7:0), as AXI-Stream
+//! and Avalon-ST buses do. A [`Beat`] marks its valid dwords and the dwords
+//! that start and end a TLP, from which both the tkeep/tlast signals of
+//! AXI-Stream and the SOP/EOP/empty signals of Avalon-ST derive.
+//!
+//! With straddling, a TLP may start in the beat the previous one ends in, at
+//! the next 128-bit boundary. Any stream of whole dwords can be serialized,
+//! such as encoded TLPs or the bus bytes of [`crate::intel::to_avst`].
+
+use crate::{
+    packets::{check_len, check_min_len},
+    Field, TlpError, DWORD_LEN, MAX_DATA_LEN,
+};
+use alloc::vec::Vec;
+use core::{fmt, mem};
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+/// Dwords between the boundaries a straddled TLP may start at
+const SEGMENT_DWORDS: usize = 4;
+
+/// Longest TLP rebuilt from beats: four prefixes, a 4DW header, the gap dword
+/// of an Avalon-ST bus, the largest payload and a digest
+const MAX_TLP_LEN: usize = 10 * DWORD_LEN + MAX_DATA_LEN;
+
+/// Width of the data bus
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum BusWidth {
+    Bits64,
+    Bits128,
+    Bits256,
+    Bits512,
+}
+
+impl BusWidth {
+    /// Width in bytes
+    pub fn bytes(self) -> usize {
+        match self {
+            BusWidth::Bits64 => 8,
+            BusWidth::Bits128 => 16,
+            BusWidth::Bits256 => 32,
+            BusWidth::Bits512 => 64,
+        }
+    }
+
+    /// Width in dwords
+    pub fn dwords(self) -> usize {
+        self.bytes() / DWORD_LEN
+    }
+}
+
+/// One transfer of the data bus
+///
+/// `keep`, `sop` and `eop` hold one bit per bus dword, bit 0 being dword 0.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Beat {
+    pub width: BusWidth,
+    /// Bus bytes, of which the first `width.bytes()` are used
+    pub data: [u8; Beat::MAX_LEN],
+    /// Dwords holding TLP bytes
+    pub keep: u16,
+    /// Dwords starting a TLP
+    pub sop: u16,
+    /// Dwords ending a TLP
+    pub eop: u16,
+}
+
+impl Beat {
+    /// Bytes of the widest bus
+    pub const MAX_LEN: usize = 64;
+
+    /// Returns an idle beat
+    pub fn new(width: BusWidth) -> Self {
+        Self {
+            width,
+            data: [0; Self::MAX_LEN],
+            keep: 0,
+            sop: 0,
+            eop: 0,
+        }
+    }
+
+    /// Builds a beat from AXI-Stream signals
+    ///
+    /// A dword is kept when any of its `tkeep` bits is set, and `tlast` ends a
+    /// TLP at the last kept dword. The beat has no start bits, which
+    /// [`Deserializer`] reads as continuing the stream.
+    pub fn from_axis(
+        width: BusWidth,
+        data: &[u8],
+        tkeep: u64,
+        tlast: bool,
+    ) -> Result<Self, TlpError> {
+        let mut beat = Self::from_data(width, data)?;
+        beat.keep = (0..width.dwords())
+            .filter(|i| (tkeep >> (i * DWORD_LEN)) & 0xF != 0)
+            .fold(0, |keep, i| keep | 1 << i);
+        if tlast {
+            beat.eop = last_bit(beat.keep);
+        }
+        Ok(beat)
+    }
+
+    /// Builds a beat from Avalon-ST signals, `empty` counting the unused bytes
+    /// after the end of a TLP
+    ///
+    /// A start lies at dword 0 and an end at the last dword before the empty
+    /// bytes, every dword up to it being kept. An end needs at least one dword
+    /// before the empty bytes.
+    pub fn from_avst(
+        width: BusWidth,
+        data: &[u8],
+        sop: bool,
+        eop: bool,
+        empty: usize,
+    ) -> Result<Self, TlpError> {
+        let mut beat = Self::from_data(width, data)?;
+        let max_empty = width.bytes() - DWORD_LEN;
+        if eop && empty > max_empty {
+            return Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: empty as u64,
+                min: 0,
+                max: max_empty as u64,
+                offset: 0,
+            });
+        }
+        let dwords = match eop {
+            true => (width.bytes() - empty) / DWORD_LEN,
+            false => width.dwords(),
+        };
+        beat.keep = ((1u32 << dwords) - 1) as u16;
+        beat.sop = match sop {
+            true => beat.keep & 1,
+            false => 0,
+        };
+        if eop {
+            beat.eop = last_bit(beat.keep);
+        }
+        Ok(beat)
+    }
+
+    fn from_data(width: BusWidth, data: &[u8]) -> Result<Self, TlpError> {
+        check_len(data.len(), width.bytes())?;
+        let mut beat = Self::new(width);
+        beat.data[..data.len()].clone_from_slice(data);
+        Ok(beat)
+    }
+
+    /// Returns the used bus bytes
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.width.bytes()]
+    }
+
+    /// Returns the AXI-Stream tkeep signal, one bit per bus byte
+    pub fn tkeep(&self) -> u64 {
+        (0..self.width.dwords())
+            .filter(|i| self.keep & 1 << i != 0)
+            .fold(0, |tkeep, i| tkeep | 0xF << (i * DWORD_LEN))
+    }
+
+    /// Returns the AXI-Stream tlast signal, set when a TLP ends in the beat
+    pub fn tlast(&self) -> bool {
+        self.eop != 0
+    }
+
+    /// Returns the Avalon-ST empty signal, the number of unused bytes after
+    /// the last TLP ending in the beat
+    pub fn empty(&self) -> usize {
+        match self.eop {
+            0 => 0,
+            eop => (self.width.dwords() - (u16::BITS - eop.leading_zeros()) as usize) * DWORD_LEN,
+        }
+    }
+}
+
+/// Returns the highest bit set in `bits`
+fn last_bit(bits: u16) -> u16 {
+    match bits {
+        0 => 0,
+        _ => 1 << (u16::BITS - 1 - bits.leading_zeros()),
+    }
+}
+
+/// Cuts TLPs into the beats of a data bus
+#[derive(Clone, Debug)]
+pub struct Serializer {
+    straddle: bool,
+    beat: Beat,
+    pos: usize,
+}
+
+impl Serializer {
+    pub fn new(width: BusWidth) -> Self {
+        Self {
+            straddle: false,
+            beat: Beat::new(width),
+            pos: 0,
+        }
+    }
+
+    /// Lets TLPs start in the beat the previous TLP ends in
+    ///
+    /// The last beat of a TLP is then held back until the next TLP or
+    /// `flush`.
+    pub fn with_straddle(mut self, straddle: bool) -> Self {
+        self.straddle = straddle;
+        self
+    }
+
+    /// Appends a TLP to the stream, returning the beats it completes
+    pub fn push(&mut self, tlp: &[u8]) -> Result<Vec<Beat>, TlpError> {
+        check_min_len(tlp.len(), DWORD_LEN)?;
+        if !tlp.len().is_multiple_of(DWORD_LEN) {
+            return Err(TlpError::NotAligned {
+                field: Field::Length,
+                value: tlp.len() as u64,
+                align: DWORD_LEN as u64,
+                offset: 0,
+            });
+        }
+
+        let width = self.beat.width;
+        let mut beats = Vec::new();
+        if self.pos > 0 {
+            self.pos = self.pos.next_multiple_of(SEGMENT_DWORDS);
+            if self.pos >= width.dwords() {
+                beats.extend(self.flush());
+            }
+        }
+        let last = tlp.len() / DWORD_LEN - 1;
+        for (i, dword) in tlp.chunks(DWORD_LEN).enumerate() {
+            let bit = 1 << self.pos;
+            self.beat.data[self.pos * DWORD_LEN..][..DWORD_LEN].clone_from_slice(dword);
+            self.beat.keep |= bit;
+            if i == 0 {
+                self.beat.sop |= bit;
+            }
+            if i == last {
+                self.beat.eop |= bit;
+            }
+            self.pos += 1;
+            if self.pos == width.dwords() {
+                beats.extend(self.flush());
+            }
+        }
+        if !self.straddle {
+            beats.extend(self.flush());
+        }
+        Ok(beats)
+    }
+
+    /// Returns the partly filled beat, if any
+    pub fn flush(&mut self) -> Option<Beat> {
+        match self.pos {
+            0 => None,
+            _ => {
+                self.pos = 0;
+                let width = self.beat.width;
+                Some(mem::replace(&mut self.beat, Beat::new(width)))
+            }
+        }
+    }
+}
+
+/// Errors raised while rebuilding TLPs from beats, with the bus dword they
+/// were found at
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FramingError {
+    /// A TLP starts before the previous one ends
+    Start { dword: usize },
+    /// A kept dword lies outside of any TLP, or a start or end marks a dword
+    /// that is not kept
+    Outside { dword: usize },
+    /// A TLP grows past the largest TLP
+    TooLong { dword: usize },
+}
+
+impl fmt::Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramingError::Start { dword } => {
+                write!(
+                    f,
+                    "TLP starts at dword {} before the previous one ends",
+                    dword
+                )
+            }
+            FramingError::Outside { dword } => write!(f, "dword {} lies outside of a TLP", dword),
+            FramingError::TooLong { dword } => {
+                write!(
+                    f,
+                    "TLP is longer than {} bytes at dword {}",
+                    MAX_TLP_LEN, dword
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FramingError {}
+
+/// Rebuilds TLPs from the beats of a data bus
+#[derive(Clone, Debug, Default)]
+pub struct Deserializer {
+    tlp: Option<Vec<u8>>,
+}
+
+impl Deserializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether no TLP is in progress
+    pub fn is_idle(&self) -> bool {
+        self.tlp.is_none()
+    }
+
+    /// Consumes a beat, returning the TLPs that end in it
+    ///
+    /// In beats without any start, such as those of AXI-Stream, the first
+    /// kept dword after the end of a TLP starts the next one. On errors the
+    /// TLP in progress is dropped.
+    pub fn push(&mut self, beat: &Beat) -> Result<Vec<Vec<u8>>, FramingError> {
+        let res = self.read(beat);
+        if res.is_err() {
+            self.tlp = None;
+        }
+        res
+    }
+
+    fn read(&mut self, beat: &Beat) -> Result<Vec<Vec<u8>>, FramingError> {
+        let mut tlps = Vec::new();
+        for dword in 0..beat.width.dwords() {
+            let bit = 1 << dword;
+            if beat.keep & bit == 0 {
+                if (beat.sop | beat.eop) & bit != 0 {
+                    return Err(FramingError::Outside { dword });
+                }
+                continue;
+            }
+
+            if beat.sop & bit != 0 {
+                if self.tlp.is_some() {
+                    return Err(FramingError::Start { dword });
+                }
+                self.tlp = Some(Vec::new());
+            } else if beat.sop == 0 && self.tlp.is_none() {
+                self.tlp = Some(Vec::new());
+            }
+            let Some(tlp) = self.tlp.as_mut() else {
+                return Err(FramingError::Outside { dword });
+            };
+            if tlp.len() == MAX_TLP_LEN {
+                return Err(FramingError::TooLong { dword });
+            }
+            tlp.extend_from_slice(&beat.data[dword * DWORD_LEN..][..DWORD_LEN]);
+            if beat.eop & bit != 0 {
+                tlps.extend(self.tlp.take());
+            }
+        }
+        Ok(tlps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        strategy::{self, TlpParams},
+        DeviceID, MRd, Tlp,
+    };
+    use proptest::{collection::vec, prelude::*};
+
+    fn serialize(tlps: &[Vec<u8>], width: BusWidth, straddle: bool) -> Vec<Beat> {
+        let mut ser = Serializer::new(width).with_straddle(straddle);
+        let mut beats = Vec::new();
+        for tlp in tlps {
+            beats.extend(ser.push(tlp).unwrap());
+        }
+        beats.extend(ser.flush());
+        beats
+    }
+
+    fn deserialize(beats: &[Beat]) -> Vec<Vec<u8>> {
+        let mut de = Deserializer::new();
+        let mut tlps = Vec::new();
+        for beat in beats {
+            tlps.extend(de.push(beat).unwrap());
+        }
+        assert!(de.is_idle());
+        tlps
+    }
+
+    fn mrd() -> Vec<u8> {
+        let mrd = MRd::new(DeviceID::default(), 0, 0x1000, 4).unwrap();
+        Tlp::MRd(mrd).to_vec().unwrap()
+    }
+
+    proptest! {
+        /// Tests that TLP streams survive beats of any width and back
+        #[test]
+        fn stream_roundtrip(
+            tlps in vec(strategy::tlp(&TlpParams::new()), 1..8),
+            width: BusWidth,
+            straddle: bool,
+        ) {
+            let beats = serialize(&tlps, width, straddle);
+            assert_eq!(tlps, deserialize(&beats));
+
+            if !straddle {
+                // Without straddling, either set of signals frames the TLPs
+                let axis: Vec<_> = beats
+                    .iter()
+                    .map(|b| Beat::from_axis(width, b.bytes(), b.tkeep(), b.tlast()).unwrap())
+                    .collect();
+                assert_eq!(tlps, deserialize(&axis));
+
+                let avst: Vec<_> = beats
+                    .iter()
+                    .map(|b| {
+                        Beat::from_avst(width, b.bytes(), b.sop != 0, b.tlast(), b.empty()).unwrap()
+                    })
+                    .collect();
+                assert_eq!(beats, avst);
+            }
+        }
+    }
+
+    #[test]
+    fn beat_signals() {
+        let beats = serialize(&[mrd()], BusWidth::Bits64, false);
+        assert_eq!(2, beats.len());
+        assert_eq!(
+            (0b11, 0b01, 0b00),
+            (beats[0].keep, beats[0].sop, beats[0].eop)
+        );
+        assert_eq!((0xFF, false), (beats[0].tkeep(), beats[0].tlast()));
+        assert_eq!(
+            (0b01, 0b00, 0b01),
+            (beats[1].keep, beats[1].sop, beats[1].eop)
+        );
+        assert_eq!(
+            (0x0F, true, 4),
+            (beats[1].tkeep(), beats[1].tlast(), beats[1].empty())
+        );
+        assert_eq!(mrd()[8..], beats[1].bytes()[..4]);
+    }
+
+    #[test]
+    fn straddling() {
+        let tlps = [mrd(), mrd(), mrd()];
+        let beats = serialize(&tlps, BusWidth::Bits256, true);
+        assert_eq!(2, beats.len());
+        assert_eq!(0b0111_0111, beats[0].keep);
+        assert_eq!(0b0001_0001, beats[0].sop);
+        assert_eq!(0b0100_0100, beats[0].eop);
+        assert_eq!(0b0111, beats[1].keep);
+        assert_eq!(20, beats[1].empty());
+
+        // Without straddling every TLP starts a beat
+        assert_eq!(3, serialize(&tlps, BusWidth::Bits256, false).len());
+        // Narrow buses have no second boundary to start at
+        assert_eq!(3, serialize(&tlps, BusWidth::Bits128, true).len());
+    }
+
+    #[test]
+    fn bad_tlps() {
+        let mut ser = Serializer::new(BusWidth::Bits128);
+        assert!(matches!(ser.push(&[]), Err(TlpError::TooShort { .. })));
+        assert!(matches!(
+            ser.push(&[0; 6]),
+            Err(TlpError::NotAligned {
+                field: Field::Length,
+                value: 6,
+                ..
+            })
+        ));
+        assert_eq!(None, ser.flush());
+        assert_eq!(
+            Err(TlpError::TooShort {
+                expected: 16,
+                actual: 8
+            }),
+            Beat::from_axis(BusWidth::Bits128, &[0; 8], 0xFF, true)
+        );
+        // An end with every byte empty has no dword to end at
+        for empty in [16, 20] {
+            assert_eq!(
+                Err(TlpError::OutOfRange {
+                    field: Field::Length,
+                    value: empty,
+                    min: 0,
+                    max: 12,
+                    offset: 0
+                }),
+                Beat::from_avst(BusWidth::Bits128, &[0; 16], true, true, empty as usize)
+            );
+        }
+        assert!(Beat::from_avst(BusWidth::Bits128, &[0; 16], true, false, 20).is_ok());
+    }
+
+    #[test]
+    fn bad_framing() {
+        let mut beat = Beat::new(BusWidth::Bits128);
+        beat.keep = 0b1111;
+        beat.sop = 0b0101;
+        beat.eop = 0b1000;
+        let mut de = Deserializer::new();
+        assert_eq!(Err(FramingError::Start { dword: 2 }), de.push(&beat));
+        assert!(de.is_idle());
+
+        beat.sop = 0b0010;
+        assert_eq!(Err(FramingError::Outside { dword: 0 }), de.push(&beat));
+
+        beat.sop = 0b0001;
+        beat.eop = 0;
+        beat.keep = 0b0111;
+        assert_eq!(Ok(vec![]), de.push(&beat));
+        assert!(!de.is_idle());
+        beat.sop = 0;
+        beat.eop = 0b1000;
+        assert_eq!(Err(FramingError::Outside { dword: 3 }), de.push(&beat));
+    }
+
+    #[test]
+    fn too_long() {
+        let mut beat = Beat::new(BusWidth::Bits512);
+        beat.keep = 0xFFFF;
+        let mut de = Deserializer::new();
+        let beats = MAX_TLP_LEN / Beat::MAX_LEN;
+        for _ in 0..beats {
+            assert_eq!(Ok(vec![]), de.push(&beat));
+        }
+        // The dwords left fit, the next one does not
+        let dword = (MAX_TLP_LEN % Beat::MAX_LEN) / DWORD_LEN;
+        assert_eq!(Err(FramingError::TooLong { dword }), de.push(&beat));
+        assert!(de.is_idle());
+    }
+}
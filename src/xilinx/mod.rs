@@ -0,0 +1,830 @@
+//! Descriptors and sideband of the AXI4-Stream interfaces of the Xilinx
+//! UltraScale+ integrated block for PCI Express
+//!
+//! The block exchanges TLPs with the user application on four interfaces,
+//! each of which carries a descriptor in place of the TLP header at the start
+//! of `tdata`:
+//!
+//! - CQ: requests received from the link, as [`CqDescriptor`]
+//! - CC: completions to CQ requests, as [`CcDescriptor`]
+//! - RQ: requests sent by the user application, as [`RqDescriptor`]
+//! - RC: completions to RQ requests, as [`RcDescriptor`]
+//!
+//! Descriptors are little-endian, bit 0 of the first dword being bit 0 of the
+//! first byte. Byte enables and other per-TLP fields travel in `tuser`, whose
+//! layouts here are those of the 64 to 256 bit interfaces, with the parity
+//! bits left zero.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    packets::check_len, AddressType, CfgRd, CfgWr, CompletionStatus, Cpl, CplHeader, DeviceID,
+    Field, MRd, MWr, RequestHeader, Tlp, TlpError, TlpHeader, TlpType, TrafficClass, DWORD_LEN,
+};
+use num_traits::FromPrimitive;
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+/// Most dwords a descriptor can describe
+const MAX_DWORDS: u16 = 1024;
+
+/// Largest byte count of a completion descriptor
+const MAX_BYTE_COUNT: u16 = 4096;
+
+lossless_enum! {
+    /// Request type of CQ and RQ descriptors
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    #[cfg_attr(test, derive(Arbitrary))]
+    pub enum RequestType {
+        #[default]
+        MemRead = 0b0000,
+        MemWrite = 0b0001,
+        IoRead = 0b0010,
+        IoWrite = 0b0011,
+        FetchAdd = 0b0100,
+        Swap = 0b0101,
+        CompareSwap = 0b0110,
+        LockedRead = 0b0111,
+        CfgRead0 = 0b1000,
+        CfgRead1 = 0b1001,
+        CfgWrite0 = 0b1010,
+        CfgWrite1 = 0b1011,
+        /// Message other than vendor-defined and ATS messages
+        Msg = 0b1100,
+        VendorMsg = 0b1101,
+        AtsMsg = 0b1110,
+    }
+    /// Reserved request type
+    #[cfg_attr(test, proptest(skip))]
+    Reserved(ReservedRequestType, 0xF)
+}
+
+/// Returns `width` bits of `x` starting at bit `lo`
+fn bits(x: u128, lo: u32, width: u32) -> u128 {
+    (x >> lo) & ((1 << width) - 1)
+}
+
+fn bit(x: u128, lo: u32) -> bool {
+    bits(x, lo, 1) > 0
+}
+
+/// Attribute bits of a descriptor, with no-snoop in bit 0, relaxed ordering in
+/// bit 1 and ID-based ordering in bit 2
+fn attr(ro: bool, ns: bool, ido: bool) -> u128 {
+    (ido as u128) << 2 | (ro as u128) << 1 | ns as u128
+}
+
+fn traffic_class(x: u128, lo: u32) -> TrafficClass {
+    // SAFETY: Every 3 bit value is a traffic class
+    TrafficClass::from_u8(bits(x, lo, 3) as u8).unwrap()
+}
+
+/// Fields shared by CQ and RQ descriptors
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReqDescriptor {
+    pub at: AddressType,
+    /// Address with bits 1:0 clear, or the register of a config request
+    pub addr: u64,
+    /// Length of the request in dwords
+    pub dword_count: u16,
+    pub req_type: RequestType,
+    pub req_id: DeviceID,
+    pub tag: u8,
+    pub tc: TrafficClass,
+    /// Relaxed ordering
+    pub ro: bool,
+    /// No-snoop
+    pub ns: bool,
+    /// ID-based ordering
+    pub ido: bool,
+}
+
+impl ReqDescriptor {
+    fn to_bits(self) -> u128 {
+        self.at as u128
+            | u128::from(self.addr & !0x3)
+            | u128::from(self.dword_count & 0x7FF) << 64
+            | u128::from(u8::from(self.req_type) & 0xF) << 75
+            | u128::from(u16::from(self.req_id)) << 80
+            | u128::from(self.tag) << 96
+            | (self.tc as u128) << 121
+            | attr(self.ro, self.ns, self.ido) << 124
+    }
+
+    fn from_bits(x: u128) -> Self {
+        Self {
+            // SAFETY: Every 2 bit value is an address type
+            at: AddressType::from_u8(bits(x, 0, 2) as u8).unwrap(),
+            addr: x as u64 & !0x3,
+            dword_count: bits(x, 64, 11) as u16,
+            // SAFETY: Every 4 bit value is a request type
+            req_type: RequestType::from_u8(bits(x, 75, 4) as u8).unwrap(),
+            req_id: DeviceID::from(bits(x, 80, 16) as u16),
+            tag: bits(x, 96, 8) as u8,
+            tc: traffic_class(x, 121),
+            ro: bit(x, 125),
+            ns: bit(x, 124),
+            ido: bit(x, 126),
+        }
+    }
+
+    /// Splits a memory or config request into its descriptor fields, the
+    /// target of a config request and its request header
+    fn split(tlp: &Tlp) -> Result<(Self, DeviceID, RequestHeader), TlpError> {
+        use RequestType::*;
+
+        let (hdr, req_type, addr, target) = match tlp {
+            Tlp::MRd(t) => (t.hdr, MemRead, t.addr.into(), DeviceID::default()),
+            Tlp::MWr(t) => (t.hdr, MemWrite, t.addr.into(), DeviceID::default()),
+            Tlp::CfgRd(t) => {
+                let req_type = if t.is_type1() { CfgRead1 } else { CfgRead0 };
+                (t.hdr, req_type, t.reg.into(), t.target)
+            }
+            Tlp::CfgWr(t) => {
+                let req_type = if t.is_type1() { CfgWrite1 } else { CfgWrite0 };
+                (t.hdr, req_type, t.reg.into(), t.target)
+            }
+            t => {
+                return Err(TlpError::InvalidType {
+                    field: Field::FmtType,
+                    value: u8::from(t.header().tlp_type).into(),
+                    offset: 0,
+                })
+            }
+        };
+
+        let desc = Self {
+            at: hdr.hdr.at,
+            addr,
+            dword_count: hdr.hdr.data_len() / DWORD_LEN as u16,
+            req_type,
+            req_id: hdr.req_id,
+            tag: hdr.tag,
+            tc: hdr.hdr.tc,
+            ro: hdr.hdr.ro,
+            ns: hdr.hdr.ns,
+            ido: hdr.hdr.ibo,
+        };
+        Ok((desc, target, hdr))
+    }
+
+    /// Builds the memory or config request described, taking its byte
+    /// enables from the sideband and the target of config requests from
+    /// `target`
+    fn join<'a>(
+        &self,
+        target: DeviceID,
+        first_be: u8,
+        last_be: u8,
+        ep: bool,
+        data: &'a [u8],
+    ) -> Result<Tlp<'a>, TlpError> {
+        use RequestType::*;
+
+        let max = match self.req_type {
+            CfgRead0 | CfgRead1 | CfgWrite0 | CfgWrite1 => 1,
+            _ => MAX_DWORDS,
+        };
+        if !(1..=max).contains(&self.dword_count) {
+            return Err(TlpError::OutOfRange {
+                field: Field::Length,
+                value: self.dword_count.into(),
+                min: 1,
+                max: max.into(),
+                offset: 8,
+            });
+        }
+        let len = usize::from(self.dword_count) * DWORD_LEN;
+        let reg = (self.addr & 0xFFC) as u16;
+        match self.req_type {
+            MemWrite | CfgWrite0 | CfgWrite1 => check_len(data.len(), len)?,
+            _ => check_len(data.len(), 0)?,
+        }
+
+        let (req_id, tag) = (self.req_id, self.tag);
+        let mut tlp = match self.req_type {
+            MemRead => Tlp::MRd(MRd::new(req_id, tag, self.addr, len as u16)?),
+            MemWrite => Tlp::MWr(MWr::new(req_id, tag, self.addr, data)?),
+            CfgRead0 | CfgRead1 => {
+                let type1 = self.req_type == CfgRead1;
+                Tlp::CfgRd(CfgRd::new(req_id, tag, target, reg, type1)?)
+            }
+            CfgWrite0 | CfgWrite1 => {
+                let type1 = self.req_type == CfgWrite1;
+                // SAFETY: Payload length is already confirmed to be a dword
+                let data = data.try_into().unwrap();
+                Tlp::CfgWr(CfgWr::new(req_id, tag, target, reg, type1, data)?)
+            }
+            t => {
+                return Err(TlpError::InvalidType {
+                    field: Field::RequestType,
+                    value: u8::from(t).into(),
+                    offset: 9,
+                })
+            }
+        };
+
+        let hdr = match &mut tlp {
+            Tlp::MRd(t) => &mut t.hdr,
+            Tlp::MWr(t) => &mut t.hdr,
+            Tlp::CfgRd(t) => &mut t.hdr,
+            Tlp::CfgWr(t) => &mut t.hdr,
+            // SAFETY: Only requests are built above
+            _ => unreachable!(),
+        };
+        hdr.hdr = hdr
+            .hdr
+            .with_at(self.at)
+            .with_tc(self.tc)
+            .with_ro(self.ro)
+            .with_ns(self.ns)
+            .with_ibo(self.ido)
+            .with_ep(ep);
+        *hdr = hdr.with_first_be(first_be)?.with_last_be(last_be)?;
+        Ok(tlp)
+    }
+}
+
+/// Completer request descriptor, starting the requests the block delivers on
+/// the CQ interface
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CqDescriptor {
+    pub desc: ReqDescriptor,
+    /// Function targeted by the request
+    pub target_function: u8,
+    /// BAR the address matched, 6 for the expansion ROM
+    pub bar_id: u8,
+    /// Log2 of the size of the BAR
+    pub bar_aperture: u8,
+}
+
+impl CqDescriptor {
+    pub const LENGTH: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let x = self.desc.to_bits()
+            | u128::from(self.target_function) << 104
+            | u128::from(self.bar_id & 0x7) << 112
+            | u128::from(self.bar_aperture & 0x3F) << 115;
+        x.to_le_bytes()
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Self {
+        let x = u128::from_le_bytes(bytes);
+        Self {
+            desc: ReqDescriptor::from_bits(x),
+            target_function: bits(x, 104, 8) as u8,
+            bar_id: bits(x, 112, 3) as u8,
+            bar_aperture: bits(x, 115, 6) as u8,
+        }
+    }
+
+    /// Describes a memory read or write, returning the descriptor and the
+    /// sideband of its first beat
+    ///
+    /// The target function and BAR fields are left zero, as they are not
+    /// part of the TLP.
+    pub fn from_tlp(tlp: &Tlp) -> Result<(Self, CqUser), TlpError> {
+        if !matches!(tlp, Tlp::MRd(_) | Tlp::MWr(_)) {
+            return Err(TlpError::InvalidType {
+                field: Field::FmtType,
+                value: u8::from(tlp.header().tlp_type).into(),
+                offset: 0,
+            });
+        }
+
+        let (desc, _, hdr) = ReqDescriptor::split(tlp)?;
+        let user = CqUser {
+            first_be: hdr.first_be,
+            last_be: hdr.last_be,
+            sop: true,
+            ..Default::default()
+        };
+        Ok((
+            Self {
+                desc,
+                ..Default::default()
+            },
+            user,
+        ))
+    }
+
+    /// Builds the memory request described, with the byte enables of `user`
+    /// and the payload `data`
+    pub fn to_tlp<'a>(&self, user: &CqUser, data: &'a [u8]) -> Result<Tlp<'a>, TlpError> {
+        match self.desc.req_type {
+            RequestType::MemRead | RequestType::MemWrite => self.desc.join(
+                DeviceID::default(),
+                user.first_be,
+                user.last_be,
+                false,
+                data,
+            ),
+            t => Err(TlpError::InvalidType {
+                field: Field::RequestType,
+                value: u8::from(t).into(),
+                offset: 9,
+            }),
+        }
+    }
+}
+
+/// `m_axis_cq_tuser` of a CQ beat
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct CqUser {
+    /// Byte enables of the first dword
+    #[cfg_attr(test, proptest(strategy = "0u8..16"))]
+    pub first_be: u8,
+    /// Byte enables of the last dword
+    #[cfg_attr(test, proptest(strategy = "0u8..16"))]
+    pub last_be: u8,
+    /// Byte enables of the payload bytes of the beat
+    pub byte_en: u32,
+    /// Beat starts a TLP
+    pub sop: bool,
+    /// TLP is aborted
+    pub discontinue: bool,
+    /// TLP processing hints are present
+    pub tph_present: bool,
+    #[cfg_attr(test, proptest(strategy = "0u8..4"))]
+    pub tph_type: u8,
+    pub tph_st_tag: u8,
+}
+
+impl CqUser {
+    /// Width of `m_axis_cq_tuser` in bits
+    pub const WIDTH: u32 = 88;
+
+    pub fn to_bits(&self) -> u128 {
+        u128::from(self.first_be & 0xF)
+            | u128::from(self.last_be & 0xF) << 4
+            | u128::from(self.byte_en) << 8
+            | (self.sop as u128) << 40
+            | (self.discontinue as u128) << 41
+            | (self.tph_present as u128) << 42
+            | u128::from(self.tph_type & 0x3) << 43
+            | u128::from(self.tph_st_tag) << 45
+    }
+
+    pub fn from_bits(x: u128) -> Self {
+        Self {
+            first_be: bits(x, 0, 4) as u8,
+            last_be: bits(x, 4, 4) as u8,
+            byte_en: bits(x, 8, 32) as u32,
+            sop: bit(x, 40),
+            discontinue: bit(x, 41),
+            tph_present: bit(x, 42),
+            tph_type: bits(x, 43, 2) as u8,
+            tph_st_tag: bits(x, 45, 8) as u8,
+        }
+    }
+}
+
+/// Requester request descriptor, starting the requests the user application
+/// sends on the RQ interface
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RqDescriptor {
+    pub desc: ReqDescriptor,
+    /// Request is poisoned
+    pub poisoned: bool,
+    /// Target of a config request
+    pub completer_id: DeviceID,
+    /// Use `req_id` instead of the ID of the function sending the request
+    pub req_id_en: bool,
+    /// Have the block append an ECRC
+    pub force_ecrc: bool,
+}
+
+impl RqDescriptor {
+    pub const LENGTH: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let x = self.desc.to_bits()
+            | (self.poisoned as u128) << 79
+            | u128::from(u16::from(self.completer_id)) << 104
+            | (self.req_id_en as u128) << 120
+            | (self.force_ecrc as u128) << 127;
+        x.to_le_bytes()
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Self {
+        let x = u128::from_le_bytes(bytes);
+        Self {
+            desc: ReqDescriptor::from_bits(x),
+            poisoned: bit(x, 79),
+            completer_id: DeviceID::from(bits(x, 104, 16) as u16),
+            req_id_en: bit(x, 120),
+            force_ecrc: bit(x, 127),
+        }
+    }
+
+    /// Describes a memory or config request, returning the descriptor and its
+    /// sideband
+    ///
+    /// The requester ID of the TLP is kept by setting `req_id_en`.
+    pub fn from_tlp(tlp: &Tlp) -> Result<(Self, RqUser), TlpError> {
+        let (desc, completer_id, hdr) = ReqDescriptor::split(tlp)?;
+        let user = RqUser {
+            first_be: hdr.first_be,
+            last_be: hdr.last_be,
+            ..Default::default()
+        };
+        Ok((
+            Self {
+                desc,
+                poisoned: hdr.hdr.ep,
+                completer_id,
+                req_id_en: true,
+                force_ecrc: false,
+            },
+            user,
+        ))
+    }
+
+    /// Builds the memory or config request described, with the byte enables
+    /// of `user` and the payload `data`
+    pub fn to_tlp<'a>(&self, user: &RqUser, data: &'a [u8]) -> Result<Tlp<'a>, TlpError> {
+        self.desc.join(
+            self.completer_id,
+            user.first_be,
+            user.last_be,
+            self.poisoned,
+            data,
+        )
+    }
+}
+
+/// `s_axis_rq_tuser` of an RQ beat
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct RqUser {
+    /// Byte enables of the first dword
+    #[cfg_attr(test, proptest(strategy = "0u8..16"))]
+    pub first_be: u8,
+    /// Byte enables of the last dword
+    #[cfg_attr(test, proptest(strategy = "0u8..16"))]
+    pub last_be: u8,
+    /// Dword offset of the payload in the first beat, with address aligned
+    /// mode
+    #[cfg_attr(test, proptest(strategy = "0u8..8"))]
+    pub addr_offset: u8,
+    /// TLP is aborted
+    pub discontinue: bool,
+    /// TLP processing hints are present
+    pub tph_present: bool,
+    #[cfg_attr(test, proptest(strategy = "0u8..4"))]
+    pub tph_type: u8,
+    /// Steering tag is an index into the steering tag table
+    pub tph_indirect_tag_en: bool,
+    pub tph_st_tag: u8,
+    /// Sequence number reported back on `pcie_rq_seq_num`
+    #[cfg_attr(test, proptest(strategy = "0u8..64"))]
+    pub seq_num: u8,
+}
+
+impl RqUser {
+    /// Width of `s_axis_rq_tuser` in bits
+    pub const WIDTH: u32 = 62;
+
+    pub fn to_bits(&self) -> u64 {
+        u64::from(self.first_be & 0xF)
+            | u64::from(self.last_be & 0xF) << 4
+            | u64::from(self.addr_offset & 0x7) << 8
+            | (self.discontinue as u64) << 11
+            | (self.tph_present as u64) << 12
+            | u64::from(self.tph_type & 0x3) << 13
+            | (self.tph_indirect_tag_en as u64) << 15
+            | u64::from(self.tph_st_tag) << 16
+            | u64::from(self.seq_num & 0xF) << 24
+            | u64::from(self.seq_num >> 4 & 0x3) << 60
+    }
+
+    pub fn from_bits(x: u64) -> Self {
+        let x = u128::from(x);
+        Self {
+            first_be: bits(x, 0, 4) as u8,
+            last_be: bits(x, 4, 4) as u8,
+            addr_offset: bits(x, 8, 3) as u8,
+            discontinue: bit(x, 11),
+            tph_present: bit(x, 12),
+            tph_type: bits(x, 13, 2) as u8,
+            tph_indirect_tag_en: bit(x, 15),
+            tph_st_tag: bits(x, 16, 8) as u8,
+            seq_num: (bits(x, 24, 4) | bits(x, 60, 2) << 4) as u8,
+        }
+    }
+}
+
+/// Fields shared by CC and RC descriptors
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CplDescriptor {
+    /// Bytes left to complete the request, including this completion
+    pub byte_count: u16,
+    /// Completion of a locked read
+    pub locked: bool,
+    /// Length of the payload in dwords
+    pub dword_count: u16,
+    pub status: CompletionStatus,
+    /// Completion is poisoned
+    pub poisoned: bool,
+    pub req_id: DeviceID,
+    pub tag: u8,
+    pub completer_id: DeviceID,
+    pub tc: TrafficClass,
+    /// Relaxed ordering
+    pub ro: bool,
+    /// No-snoop
+    pub ns: bool,
+    /// ID-based ordering
+    pub ido: bool,
+}
+
+impl CplDescriptor {
+    fn to_bits(self) -> u128 {
+        u128::from(self.byte_count & 0x1FFF) << 16
+            | (self.locked as u128) << 29
+            | u128::from(self.dword_count & 0x7FF) << 32
+            | u128::from(u8::from(self.status) & 0x7) << 43
+            | (self.poisoned as u128) << 46
+            | u128::from(u16::from(self.req_id)) << 48
+            | u128::from(self.tag) << 64
+            | u128::from(u16::from(self.completer_id)) << 72
+            | (self.tc as u128) << 89
+            | attr(self.ro, self.ns, self.ido) << 92
+    }
+
+    fn from_bits(x: u128) -> Self {
+        Self {
+            byte_count: bits(x, 16, 13) as u16,
+            locked: bit(x, 29),
+            dword_count: bits(x, 32, 11) as u16,
+            // SAFETY: Every 3 bit value is a completion status
+            status: CompletionStatus::from_u8(bits(x, 43, 3) as u8).unwrap(),
+            poisoned: bit(x, 46),
+            req_id: DeviceID::from(bits(x, 48, 16) as u16),
+            tag: bits(x, 64, 8) as u8,
+            completer_id: DeviceID::from(bits(x, 72, 16) as u16),
+            tc: traffic_class(x, 89),
+            ro: bit(x, 93),
+            ns: bit(x, 92),
+            ido: bit(x, 94),
+        }
+    }
+
+    fn from_cpl(cpl: &Cpl) -> Self {
+        let hdr = &cpl.hdr;
+        Self {
+            byte_count: match hdr.bc {
+                0 => MAX_BYTE_COUNT,
+                bc => bc,
+            },
+            locked: matches!(hdr.hdr.tlp_type, TlpType::CplLk | TlpType::CplLkD),
+            dword_count: (cpl.data.len() / DWORD_LEN) as u16,
+            status: hdr.status,
+            poisoned: hdr.hdr.ep,
+            req_id: hdr.req_id,
+            tag: hdr.tag,
+            completer_id: hdr.cpl_id,
+            tc: hdr.hdr.tc,
+            ro: hdr.hdr.ro,
+            ns: hdr.hdr.ns,
+            ido: hdr.hdr.ibo,
+        }
+    }
+
+    fn to_cpl<'a>(
+        self,
+        lower_addr: u8,
+        at: AddressType,
+        data: &'a [u8],
+    ) -> Result<Cpl<'a>, TlpError> {
+        check_len(data.len(), usize::from(self.dword_count) * DWORD_LEN)?;
+        let bc = match self.byte_count {
+            MAX_BYTE_COUNT => 0,
+            bc if bc < MAX_BYTE_COUNT => bc,
+            bc => {
+                return Err(TlpError::OutOfRange {
+                    field: Field::ByteCount,
+                    value: bc.into(),
+                    min: 0,
+                    max: MAX_BYTE_COUNT.into(),
+                    offset: 2,
+                })
+            }
+        };
+
+        let tlp_hdr = TlpHeader::new()
+            .with_type(if self.locked {
+                TlpType::CplLk
+            } else {
+                TlpType::CplE
+            })
+            .with_at(at)
+            .with_tc(self.tc)
+            .with_ro(self.ro)
+            .with_ns(self.ns)
+            .with_ibo(self.ido)
+            .with_ep(self.poisoned);
+        let hdr = CplHeader::new()
+            .with_hdr(tlp_hdr)
+            .with_cpl_id(self.completer_id)
+            .with_bc(bc)?
+            .with_status(self.status)
+            .with_req_id(self.req_id)
+            .with_tag(self.tag)
+            .with_addr(lower_addr)?;
+        Cpl::new(hdr, data)
+    }
+}
+
+/// Completer completion descriptor, starting the completions the user
+/// application sends on the CC interface
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CcDescriptor {
+    pub desc: CplDescriptor,
+    /// Bits 6:0 of the address of the first byte returned
+    pub lower_addr: u8,
+    /// Address type of the request
+    pub at: AddressType,
+    /// Use `completer_id` instead of the ID of the function completing
+    pub completer_id_en: bool,
+    /// Have the block append an ECRC
+    pub force_ecrc: bool,
+}
+
+impl CcDescriptor {
+    pub const LENGTH: usize = 12;
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let x = self.desc.to_bits()
+            | u128::from(self.lower_addr & 0x7F)
+            | (self.at as u128) << 8
+            | (self.completer_id_en as u128) << 88
+            | (self.force_ecrc as u128) << 95;
+        let mut ret = [0; Self::LENGTH];
+        ret.clone_from_slice(&x.to_le_bytes()[..Self::LENGTH]);
+        ret
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Self {
+        let mut x = [0; 16];
+        x[..Self::LENGTH].clone_from_slice(&bytes);
+        let x = u128::from_le_bytes(x);
+        Self {
+            desc: CplDescriptor::from_bits(x),
+            lower_addr: bits(x, 0, 7) as u8,
+            // SAFETY: Every 2 bit value is an address type
+            at: AddressType::from_u8(bits(x, 8, 2) as u8).unwrap(),
+            completer_id_en: bit(x, 88),
+            force_ecrc: bit(x, 95),
+        }
+    }
+
+    /// Describes a completion, keeping its completer ID by setting
+    /// `completer_id_en`
+    pub fn from_cpl(cpl: &Cpl) -> Self {
+        Self {
+            desc: CplDescriptor::from_cpl(cpl),
+            lower_addr: cpl.hdr.addr_low,
+            at: cpl.hdr.hdr.at,
+            completer_id_en: true,
+            force_ecrc: false,
+        }
+    }
+
+    /// Builds the completion described, with the payload `data`
+    pub fn to_cpl<'a>(&self, data: &'a [u8]) -> Result<Cpl<'a>, TlpError> {
+        self.desc.to_cpl(self.lower_addr & 0x7F, self.at, data)
+    }
+}
+
+/// `s_axis_cc_tuser` of a CC beat
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct CcUser {
+    /// TLP is aborted
+    pub discontinue: bool,
+}
+
+impl CcUser {
+    /// Width of `s_axis_cc_tuser` in bits
+    pub const WIDTH: u32 = 33;
+
+    pub fn to_bits(&self) -> u64 {
+        self.discontinue as u64
+    }
+
+    pub fn from_bits(x: u64) -> Self {
+        Self {
+            discontinue: x & 0x1 > 0,
+        }
+    }
+}
+
+/// Requester completion descriptor, starting the completions the block
+/// delivers on the RC interface
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RcDescriptor {
+    pub desc: CplDescriptor,
+    /// Bits 11:0 of the address of the first byte returned
+    pub lower_addr: u16,
+    /// Error detected by the block while checking the completion
+    pub error_code: u8,
+    /// Last completion of the request
+    pub request_completed: bool,
+}
+
+impl RcDescriptor {
+    pub const LENGTH: usize = 12;
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let x = self.desc.to_bits()
+            | u128::from(self.lower_addr & 0xFFF)
+            | u128::from(self.error_code & 0xF) << 12
+            | (self.request_completed as u128) << 30;
+        let mut ret = [0; Self::LENGTH];
+        ret.clone_from_slice(&x.to_le_bytes()[..Self::LENGTH]);
+        ret
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Self {
+        let mut x = [0; 16];
+        x[..Self::LENGTH].clone_from_slice(&bytes);
+        let x = u128::from_le_bytes(x);
+        Self {
+            desc: CplDescriptor::from_bits(x),
+            lower_addr: bits(x, 0, 12) as u16,
+            error_code: bits(x, 12, 4) as u8,
+            request_completed: bit(x, 30),
+        }
+    }
+
+    /// Describes a completion
+    ///
+    /// The error code and request completed fields are left clear, as they
+    /// are computed by the block.
+    pub fn from_cpl(cpl: &Cpl) -> Self {
+        Self {
+            desc: CplDescriptor::from_cpl(cpl),
+            lower_addr: cpl.hdr.addr_low.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds the completion described, with the payload `data`
+    ///
+    /// The completion keeps bits 6:0 of the lower address.
+    pub fn to_cpl<'a>(&self, data: &'a [u8]) -> Result<Cpl<'a>, TlpError> {
+        self.desc
+            .to_cpl((self.lower_addr & 0x7F) as u8, AddressType::default(), data)
+    }
+}
+
+/// `m_axis_rc_tuser` of an RC beat
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct RcUser {
+    /// Byte enables of the payload bytes of the beat
+    pub byte_en: u32,
+    /// A TLP starts in the beat
+    pub is_sof_0: bool,
+    /// A second TLP starts in the beat, when straddling
+    pub is_sof_1: bool,
+    /// A TLP ends in the beat, in bit 0, with the offset of its last dword
+    /// in bits 3:1
+    #[cfg_attr(test, proptest(strategy = "0u8..16"))]
+    pub is_eof_0: u8,
+    /// A second TLP ends in the beat, when straddling
+    #[cfg_attr(test, proptest(strategy = "0u8..16"))]
+    pub is_eof_1: u8,
+    /// TLP is aborted
+    pub discontinue: bool,
+}
+
+impl RcUser {
+    /// Width of `m_axis_rc_tuser` in bits
+    pub const WIDTH: u32 = 75;
+
+    pub fn to_bits(&self) -> u128 {
+        u128::from(self.byte_en)
+            | (self.is_sof_0 as u128) << 32
+            | (self.is_sof_1 as u128) << 33
+            | u128::from(self.is_eof_0 & 0xF) << 34
+            | u128::from(self.is_eof_1 & 0xF) << 38
+            | (self.discontinue as u128) << 42
+    }
+
+    pub fn from_bits(x: u128) -> Self {
+        Self {
+            byte_en: bits(x, 0, 32) as u32,
+            is_sof_0: bit(x, 32),
+            is_sof_1: bit(x, 33),
+            is_eof_0: bits(x, 34, 4) as u8,
+            is_eof_1: bits(x, 38, 4) as u8,
+            discontinue: bit(x, 42),
+        }
+    }
+}
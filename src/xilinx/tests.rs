@@ -0,0 +1,217 @@
+use super::*;
+use crate::{
+    strategy::{self, TlpParams},
+    MsgHeader,
+};
+use proptest::prelude::*;
+
+prop_compose! {
+    fn req_desc()(
+        at: AddressType,
+        addr: u64,
+        dword_count in 0u16..2048,
+        req_type: RequestType,
+        req_id: DeviceID,
+        tag: u8,
+        tc: TrafficClass,
+        (ro, ns, ido) in any::<(bool, bool, bool)>(),
+    ) -> ReqDescriptor {
+        ReqDescriptor { at, addr: addr & !0x3, dword_count, req_type, req_id, tag, tc, ro, ns, ido }
+    }
+}
+
+prop_compose! {
+    fn cpl_desc()(
+        byte_count in 0u16..8192,
+        locked: bool,
+        dword_count in 0u16..2048,
+        status in (0u8..8).prop_map(|s| CompletionStatus::from_u8(s).unwrap()),
+        poisoned: bool,
+        (req_id, tag, completer_id) in any::<(DeviceID, u8, DeviceID)>(),
+        tc: TrafficClass,
+        (ro, ns, ido) in any::<(bool, bool, bool)>(),
+    ) -> CplDescriptor {
+        CplDescriptor {
+            byte_count, locked, dword_count, status, poisoned, req_id, tag, completer_id, tc, ro,
+            ns, ido,
+        }
+    }
+}
+
+proptest! {
+    /// Roundtrip testing of CQ and RQ descriptors
+    #[test]
+    fn req_desc_roundtrip(
+        desc in req_desc(),
+        (target_function, bar_id, bar_aperture) in (any::<u8>(), 0u8..8, 0u8..64),
+        (poisoned, completer_id, req_id_en, force_ecrc) in any::<(bool, DeviceID, bool, bool)>(),
+    ) {
+        let cq = CqDescriptor { desc, target_function, bar_id, bar_aperture };
+        assert_eq!(cq, CqDescriptor::from_bytes(cq.to_bytes()));
+
+        let rq = RqDescriptor { desc, poisoned, completer_id, req_id_en, force_ecrc };
+        assert_eq!(rq, RqDescriptor::from_bytes(rq.to_bytes()));
+    }
+
+    /// Roundtrip testing of CC and RC descriptors
+    #[test]
+    fn cpl_desc_roundtrip(
+        desc in cpl_desc(),
+        (lower_addr, at, completer_id_en, force_ecrc) in (0u8..128, any::<AddressType>(), any::<bool>(), any::<bool>()),
+        (rc_lower_addr, error_code, request_completed) in (0u16..4096, 0u8..16, any::<bool>()),
+    ) {
+        let cc = CcDescriptor { desc, lower_addr, at, completer_id_en, force_ecrc };
+        assert_eq!(cc, CcDescriptor::from_bytes(cc.to_bytes()));
+
+        let rc = RcDescriptor { desc, lower_addr: rc_lower_addr, error_code, request_completed };
+        assert_eq!(rc, RcDescriptor::from_bytes(rc.to_bytes()));
+    }
+
+    /// Roundtrip testing of the tuser sideband
+    #[test]
+    fn user_roundtrip(cq: CqUser, rq: RqUser, cc: CcUser, rc: RcUser) {
+        assert_eq!(cq, CqUser::from_bits(cq.to_bits()));
+        assert!(cq.to_bits() < 1 << CqUser::WIDTH);
+        assert_eq!(rq, RqUser::from_bits(rq.to_bits()));
+        assert!(rq.to_bits() < 1 << RqUser::WIDTH);
+        assert_eq!(cc, CcUser::from_bits(cc.to_bits()));
+        assert_eq!(rc, RcUser::from_bits(rc.to_bits()));
+        assert!(rc.to_bits() < 1 << RcUser::WIDTH);
+    }
+
+    /// Tests that requests survive conversion to RQ descriptors and back
+    #[test]
+    fn rq_tlp_roundtrip(bytes in prop_oneof![
+        strategy::mrd(&TlpParams::new()),
+        strategy::mwr(&TlpParams::new()),
+        strategy::cfg_rd(&TlpParams::new()),
+        strategy::cfg_wr(&TlpParams::new()),
+    ]) {
+        let tlp = Tlp::from_bytes(&bytes).unwrap();
+        let (desc, user) = RqDescriptor::from_tlp(&tlp).unwrap();
+        let desc = RqDescriptor::from_bytes(desc.to_bytes());
+        let user = RqUser::from_bits(user.to_bits());
+        assert_eq!(Ok(tlp), desc.to_tlp(&user, tlp.data()));
+    }
+
+    /// Tests that memory requests survive conversion to CQ descriptors and back
+    #[test]
+    fn cq_tlp_roundtrip(bytes in prop_oneof![
+        strategy::mrd(&TlpParams::new()),
+        strategy::mwr(&TlpParams::new()),
+    ]) {
+        let tlp = Tlp::from_bytes(&bytes).unwrap();
+        let (desc, user) = CqDescriptor::from_tlp(&tlp).unwrap();
+        let desc = CqDescriptor::from_bytes(desc.to_bytes());
+        let user = CqUser::from_bits(user.to_bits());
+        assert!(user.sop);
+        assert_eq!(Ok(tlp), desc.to_tlp(&user, tlp.data()));
+    }
+
+    /// Tests that completions survive conversion to CC and RC descriptors and
+    /// back
+    #[test]
+    fn cpl_roundtrip(bytes in strategy::cpl(&TlpParams::new())) {
+        let Tlp::Cpl(cpl) = Tlp::from_bytes(&bytes).unwrap() else {
+            panic!("Completion strategy generated another TLP");
+        };
+        let cc = CcDescriptor::from_bytes(CcDescriptor::from_cpl(&cpl).to_bytes());
+        assert_eq!(Ok(cpl), cc.to_cpl(cpl.data));
+
+        // The RC interface does not carry the address type
+        let mut expect = cpl;
+        expect.hdr.hdr.at = AddressType::default();
+        let rc = RcDescriptor::from_bytes(RcDescriptor::from_cpl(&cpl).to_bytes());
+        assert_eq!(Ok(expect), rc.to_cpl(cpl.data));
+    }
+}
+
+#[test]
+fn rq_layout() {
+    let req_id = DeviceID::new(1, 0, 0).unwrap();
+    let mwr = MWr::new(req_id, 0x12, 0x1_2345_6788, &[0; 8]).unwrap();
+    let (desc, user) = RqDescriptor::from_tlp(&Tlp::MWr(mwr)).unwrap();
+    assert_eq!(
+        [0x88, 0x67, 0x45, 0x23, 0x01, 0, 0, 0, 0x02, 0x08, 0x00, 0x01, 0x12, 0, 0, 0x01],
+        desc.to_bytes()
+    );
+    assert_eq!(0xFF, user.to_bits());
+
+    let target = DeviceID::new(2, 3, 4).unwrap();
+    let cfg = CfgRd::new(req_id, 1, target, 0x104, true).unwrap();
+    let (desc, user) = RqDescriptor::from_tlp(&Tlp::CfgRd(cfg)).unwrap();
+    assert_eq!(
+        [0x04, 0x01, 0, 0, 0, 0, 0, 0, 0x01, 0x48, 0x00, 0x01, 0x01, 0x1C, 0x02, 0x01],
+        desc.to_bytes()
+    );
+    assert_eq!(0x0F, user.to_bits());
+}
+
+#[test]
+fn cc_layout() {
+    let hdr = CplHeader::new()
+        .with_cpl_id(DeviceID::new(1, 0, 0).unwrap())
+        .with_req_id(DeviceID::new(0, 0, 0).unwrap())
+        .with_tag(5)
+        .with_bc(0)
+        .unwrap()
+        .with_addr(0x40)
+        .unwrap();
+    let data = [0; 4];
+    let cpl = Cpl::new(hdr, &data).unwrap();
+    assert_eq!(
+        [0x40, 0x00, 0x00, 0x10, 0x01, 0, 0, 0, 0x05, 0x00, 0x01, 0x01],
+        CcDescriptor::from_cpl(&cpl).to_bytes()
+    );
+}
+
+#[test]
+fn unsupported_requests() {
+    let msg = Tlp::Msg(crate::Msg {
+        prefix: None,
+        hdr: MsgHeader::new(),
+        data: &[],
+    });
+    assert!(matches!(
+        RqDescriptor::from_tlp(&msg),
+        Err(TlpError::InvalidType {
+            field: Field::FmtType,
+            ..
+        })
+    ));
+
+    let cfg = CfgRd::new(DeviceID::default(), 0, DeviceID::default(), 0, false).unwrap();
+    assert!(CqDescriptor::from_tlp(&Tlp::CfgRd(cfg)).is_err());
+
+    let mut desc = RqDescriptor::default();
+    desc.desc.req_type = RequestType::IoRead;
+    desc.desc.dword_count = 1;
+    assert_eq!(
+        Err(TlpError::InvalidType {
+            field: Field::RequestType,
+            value: 0b0010,
+            offset: 9
+        }),
+        desc.to_tlp(&RqUser::default(), &[])
+    );
+
+    desc.desc.req_type = RequestType::MemRead;
+    desc.desc.dword_count = 0;
+    assert!(matches!(
+        desc.to_tlp(&RqUser::default(), &[]),
+        Err(TlpError::OutOfRange {
+            field: Field::Length,
+            ..
+        })
+    ));
+
+    desc.desc.req_type = RequestType::MemWrite;
+    desc.desc.dword_count = 2;
+    assert_eq!(
+        Err(TlpError::TooShort {
+            expected: 8,
+            actual: 4
+        }),
+        desc.to_tlp(&RqUser::default(), &[0; 4])
+    );
+}